@@ -1,9 +1,14 @@
+use std::collections::HashSet;
 use std::error::Error;
+use std::fmt;
 
 use std::str::FromStr;
 
 use convert_case::{Case, Casing};
 
+mod metadata;
+pub use metadata::generate_profile_metadata_as_string;
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct FitMessage {
     pub name: String,
@@ -33,6 +38,10 @@ pub struct FitMessageField {
     pub ref_fields: Vec<FitRefField>,
     pub comment: Option<String>,
     pub example: Option<u8>,
+    /// Alternative interpretations of the field, selected by the values of
+    /// the subfield's `ref_fields`. Subfields share the parent's definition
+    /// number.
+    pub subfields: Vec<FitMessageField>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -42,7 +51,53 @@ pub enum FitMessageArrayType {
     VariableSizeArray,
 }
 
+/// Progress events emitted while reading the profile CSV files.
+///
+/// The readers are silent by default; pass a callback to
+/// [`read_messages_with_progress`] or [`read_profile_types_with_progress`]
+/// to observe what is being processed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProfileProgress<'a> {
+    CategoryStarted(&'a str),
+    MessageStarted(&'a str),
+    TypeStarted(&'a str),
+    TypeValueAdded {
+        type_name: &'a str,
+        value_name: &'a str,
+        value: u32,
+    },
+}
+
+impl fmt::Display for ProfileProgress<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CategoryStarted(category) => write!(f, "Starting category {}", category),
+            Self::MessageStarted(message) => write!(f, "Starting message for {}", message),
+            Self::TypeStarted(type_name) => write!(f, "Starting value for {}", type_name),
+            Self::TypeValueAdded {
+                type_name,
+                value_name,
+                value,
+            } => write!(
+                f,
+                "Adding to type {} a value name {} with value {}",
+                type_name, value_name, value
+            ),
+        }
+    }
+}
+
 pub fn read_messages(csv_content: String) -> Result<Vec<FitMessage>, Box<dyn Error>> {
+    read_messages_with_progress(csv_content, |_| {})
+}
+
+pub fn read_messages_with_progress<F>(
+    csv_content: String,
+    mut progress: F,
+) -> Result<Vec<FitMessage>, Box<dyn Error>>
+where
+    F: FnMut(ProfileProgress),
+{
     const MESSAGE_NAME_IDX: usize = 0;
     const FIELD_DEF_NUMBER_IDX: usize = 1;
     const FIELD_NAME_IDX: usize = 2;
@@ -63,7 +118,6 @@ pub fn read_messages(csv_content: String) -> Result<Vec<FitMessage>, Box<dyn Err
     let mut rdr = csv::Reader::from_reader(csv_content.as_bytes());
     let mut fit_messages: Vec<FitMessage> = Vec::new();
     let records = rdr.records();
-    let mut first_message = true;
     let mut curr_message = FitMessage {
         name: String::new(),
         comment: None,
@@ -79,27 +133,36 @@ pub fn read_messages(csv_content: String) -> Result<Vec<FitMessage>, Box<dyn Err
             && !rec[FIELD_TYPE_IDX].is_empty()
         {
             curr_category = rec[FIELD_TYPE_IDX].to_string();
-            println!("Starting category {}", curr_category)
+            progress(ProfileProgress::CategoryStarted(&curr_category));
         }
         if matches!(rec.get(MESSAGE_NAME_IDX), Some(mn) if !mn.is_empty()) {
-            if first_message {
+            if !curr_message.name.is_empty() || !curr_message.fields.is_empty() {
                 fit_messages.push(curr_message.clone());
-                first_message = false;
             }
-            fit_messages.push(curr_message.clone());
             curr_message = FitMessage {
                 name: rec[MESSAGE_NAME_IDX].to_string(),
                 comment: Some(rec[COMMENT_IDX].to_string()).filter(|s| !s.is_empty()),
                 fields: Vec::new(),
             };
-            println!("Starting message for {}", curr_message.name);
+            progress(ProfileProgress::MessageStarted(&curr_message.name));
         } else {
             let field_def_number_str = rec[FIELD_DEF_NUMBER_IDX].to_string();
-            if field_def_number_str.is_empty() {
-                continue; // let's not handle fields such as product subfields favero_product and garmin_product yet
-            }
-            let definition_number = field_def_number_str.parse::<u8>()?;
             let name = rec[FIELD_NAME_IDX].clone();
+            if name.is_empty() {
+                continue; // category and separator rows
+            }
+            // rows without a definition number are subfields of the preceding
+            // field, e.g. favero_product and garmin_product of product
+            let is_subfield = field_def_number_str.is_empty();
+            let definition_number = if is_subfield {
+                curr_message
+                    .fields
+                    .last()
+                    .map(|parent| parent.definition_number)
+                    .ok_or_else(|| format!("Subfield {} has no parent field", name))?
+            } else {
+                field_def_number_str.parse::<u8>()?
+            };
             let field_type = rec[FIELD_TYPE_IDX].clone();
             let array = parse_fit_message_array(&rec[ARRAY_IDX]);
             let components = parse_comma_delimited_string_list(&rec[COMPONENTS_IDX]);
@@ -142,8 +205,8 @@ pub fn read_messages(csv_content: String) -> Result<Vec<FitMessage>, Box<dyn Err
                 .iter()
                 .zip(ref_field_values.iter())
                 .map(|(name, value)| FitRefField {
-                    name: name.to_string(),
-                    value: value.to_string(),
+                    name: name.trim().to_string(),
+                    value: value.trim().to_string(),
                 })
                 .collect::<Vec<FitRefField>>();
             let comment = Some(rec[COMMENT_IDX].clone()).filter(|s| !s.is_empty());
@@ -166,12 +229,16 @@ pub fn read_messages(csv_content: String) -> Result<Vec<FitMessage>, Box<dyn Err
                 ref_fields,
                 comment,
                 example,
+                subfields: Vec::new(),
             };
-            curr_message.fields.push(fit_msg_field);
+            match curr_message.fields.last_mut() {
+                Some(parent) if is_subfield => parent.subfields.push(fit_msg_field),
+                _ => curr_message.fields.push(fit_msg_field),
+            }
         }
     }
     fit_messages.push(curr_message);
-    Ok(fit_messages)
+    Ok(normalize_messages(fit_messages)?)
 }
 
 fn validate_components_with_scale(
@@ -311,7 +378,7 @@ fn parse_comma_delimited_string_list(input: &str) -> Vec<String> {
 // `[N]` => variable length array
 fn parse_fit_message_array(array_def: &str) -> FitMessageArrayType {
     match array_def.trim() {
-        s if s.is_empty() => FitMessageArrayType::NotArray,
+        "" => FitMessageArrayType::NotArray,
         s if s.eq_ignore_ascii_case("[N]") => FitMessageArrayType::VariableSizeArray,
         s if s.starts_with('[') && s.ends_with(']') => {
             let trimmed = &s[1..s.len() - 1];
@@ -342,6 +409,22 @@ fn test_parse_variable_size_fit_message_array_definition() {
     assert_eq!(res, FitMessageArrayType::VariableSizeArray);
 }
 
+#[test]
+fn test_read_profile_types_reports_progress() {
+    let csv = "Type Name,Base Type,Value Name,Value,Comment\n\
+               file,enum,,,\n\
+               ,,device,1,\n";
+    let mut events = Vec::new();
+    read_profile_types_with_progress(csv.to_string(), |p| events.push(p.to_string())).unwrap();
+    assert_eq!(
+        events,
+        vec![
+            "Starting value for file",
+            "Adding to type file a value name device with value 1"
+        ]
+    );
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FitTypeValue {
     pub value_name: String,
@@ -353,10 +436,21 @@ pub struct FitTypeValue {
 pub struct FitType {
     pub type_name: String,
     pub base_type: String,
+    pub comment: Option<String>,
     pub values: Vec<FitTypeValue>,
 }
 
 pub fn read_profile_types(csv_content: String) -> Result<Vec<FitType>, Box<dyn Error>> {
+    read_profile_types_with_progress(csv_content, |_| {})
+}
+
+pub fn read_profile_types_with_progress<F>(
+    csv_content: String,
+    mut progress: F,
+) -> Result<Vec<FitType>, Box<dyn Error>>
+where
+    F: FnMut(ProfileProgress),
+{
     const TYPE_NAME_RECORD_IDX: usize = 0;
     const BASE_TYPE_RECORD_IDX: usize = 1;
     const VALUE_NAME_RECORD_IDX: usize = 2;
@@ -369,40 +463,39 @@ pub fn read_profile_types(csv_content: String) -> Result<Vec<FitType>, Box<dyn E
     let mut curr_fit_type: FitType = FitType {
         type_name: String::new(),
         base_type: String::new(),
+        comment: None,
         values: vec![],
     };
-    let mut first_type = true;
     let records = rdr.records();
     for res in records {
         let rec = res?;
         if matches!(rec.get(TYPE_NAME_RECORD_IDX), Some(tn) if !tn.is_empty()) {
             // starting new fit type definitions
-            if !first_type {
-                // not first item in CSV file
+            if !curr_fit_type.type_name.is_empty() || !curr_fit_type.values.is_empty() {
                 fit_types.push(curr_fit_type.clone());
-                first_type = false;
             }
-            fit_types.push(curr_fit_type.clone());
             curr_fit_type = FitType {
                 type_name: rec[TYPE_NAME_RECORD_IDX].to_string(),
                 base_type: rec[BASE_TYPE_RECORD_IDX].to_string(),
+                comment: Some(rec[COMMENT_RECORD_IDX].to_string()).filter(|s| !s.is_empty()),
                 values: Vec::new(),
             };
-            println!("Starting value for {}", curr_fit_type.type_name);
+            progress(ProfileProgress::TypeStarted(&curr_fit_type.type_name));
         } else {
             // continuing to add fit type values to current fit type
             let value_name = &rec[VALUE_NAME_RECORD_IDX];
             let value_str = &rec[VALUE_RECORD_IDX].trim();
             let comment = &rec[COMMENT_RECORD_IDX];
-            println!(
-                "Adding to type {} a value name {} with value {}",
-                curr_fit_type.type_name, value_name, value_str
-            );
             let value = if value_str.to_lowercase().starts_with("0x") {
                 u32::from_str_radix(value_str.to_lowercase().trim_start_matches("0x"), 16)?
             } else {
                 value_str.parse::<u32>()?
             };
+            progress(ProfileProgress::TypeValueAdded {
+                type_name: &curr_fit_type.type_name,
+                value_name,
+                value,
+            });
             curr_fit_type.values.push(FitTypeValue {
                 value_name: value_name.to_string(),
                 value,
@@ -411,77 +504,1020 @@ pub fn read_profile_types(csv_content: String) -> Result<Vec<FitType>, Box<dyn E
         }
     }
     fit_types.push(curr_fit_type);
-    Ok(fit_types)
+    Ok(normalize_profile_types(fit_types)?)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProfileValidationError {
+    EmptyMessageName {
+        field_count: usize,
+    },
+    ConflictingMessage(String),
+    DuplicateFieldDefinitionNumber {
+        message: String,
+        definition_number: u8,
+    },
+    EmptyTypeName {
+        value_count: usize,
+    },
+    ConflictingType(String),
+    DuplicateTypeValueName {
+        type_name: String,
+        value_name: String,
+    },
+    DuplicateTypeValue {
+        type_name: String,
+        value: u32,
+    },
+}
+
+impl fmt::Display for ProfileValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EmptyMessageName { field_count } => {
+                write!(f, "Message with {} fields has no name", field_count)
+            }
+            Self::ConflictingMessage(name) => {
+                write!(f, "Message {} is defined more than once", name)
+            }
+            Self::DuplicateFieldDefinitionNumber {
+                message,
+                definition_number,
+            } => write!(
+                f,
+                "Field definition number {} is used more than once in message {}",
+                definition_number, message
+            ),
+            Self::EmptyTypeName { value_count } => {
+                write!(f, "Type with {} values has no name", value_count)
+            }
+            Self::ConflictingType(name) => write!(f, "Type {} is defined more than once", name),
+            Self::DuplicateTypeValueName {
+                type_name,
+                value_name,
+            } => write!(
+                f,
+                "Value name {} is used more than once in type {}",
+                value_name, type_name
+            ),
+            Self::DuplicateTypeValue { type_name, value } => write!(
+                f,
+                "Value {} is used by more than one non-deprecated value of type {}",
+                value, type_name
+            ),
+        }
+    }
+}
+
+impl Error for ProfileValidationError {}
+
+/// Drops empty placeholder entries and exact duplicates from the messages
+/// read from the profile, and checks that message names are present and
+/// field definition numbers are unique within each message.
+pub fn normalize_messages(
+    messages: Vec<FitMessage>,
+) -> Result<Vec<FitMessage>, ProfileValidationError> {
+    let mut normalized: Vec<FitMessage> = Vec::with_capacity(messages.len());
+    for message in messages {
+        if message.name.is_empty() {
+            if message.fields.is_empty() && message.comment.is_none() {
+                continue;
+            }
+            return Err(ProfileValidationError::EmptyMessageName {
+                field_count: message.fields.len(),
+            });
+        }
+        if let Some(existing) = normalized.iter().find(|m| m.name == message.name) {
+            if *existing == message {
+                continue;
+            }
+            return Err(ProfileValidationError::ConflictingMessage(message.name));
+        }
+        let mut seen_definition_numbers = HashSet::new();
+        for field in &message.fields {
+            if !seen_definition_numbers.insert(field.definition_number) {
+                return Err(ProfileValidationError::DuplicateFieldDefinitionNumber {
+                    message: message.name.clone(),
+                    definition_number: field.definition_number,
+                });
+            }
+        }
+        normalized.push(message);
+    }
+    Ok(normalized)
+}
+
+/// Drops empty placeholder entries and exact duplicates from the types read
+/// from the profile, and checks that value names are unique within each type.
+/// Deprecated values are allowed to alias the value of their replacement.
+pub fn normalize_profile_types(
+    types: Vec<FitType>,
+) -> Result<Vec<FitType>, ProfileValidationError> {
+    let mut normalized: Vec<FitType> = Vec::with_capacity(types.len());
+    for fit_type in types {
+        if fit_type.type_name.is_empty() {
+            if fit_type.values.is_empty() {
+                continue;
+            }
+            return Err(ProfileValidationError::EmptyTypeName {
+                value_count: fit_type.values.len(),
+            });
+        }
+        if let Some(existing) = normalized
+            .iter()
+            .find(|t| t.type_name == fit_type.type_name)
+        {
+            if *existing == fit_type {
+                continue;
+            }
+            return Err(ProfileValidationError::ConflictingType(fit_type.type_name));
+        }
+        let mut seen_value_names = HashSet::new();
+        let mut seen_values = HashSet::new();
+        for val in &fit_type.values {
+            if !seen_value_names.insert(val.value_name.as_str()) {
+                return Err(ProfileValidationError::DuplicateTypeValueName {
+                    type_name: fit_type.type_name.clone(),
+                    value_name: val.value_name.clone(),
+                });
+            }
+            if !is_deprecated(&val.comment) && !seen_values.insert(val.value) {
+                return Err(ProfileValidationError::DuplicateTypeValue {
+                    type_name: fit_type.type_name.clone(),
+                    value: val.value,
+                });
+            }
+        }
+        normalized.push(fit_type);
+    }
+    Ok(normalized)
+}
+
+pub(crate) fn is_deprecated(comment: &str) -> bool {
+    comment.trim().to_lowercase().starts_with("deprecated")
+}
+
+#[cfg(test)]
+fn shipped_profile_csv(file_name: &str) -> String {
+    std::fs::read_to_string(format!("../fit_definitions/{}", file_name)).unwrap()
+}
+
+#[test]
+fn test_read_shipped_messages_has_no_placeholders_or_duplicates() {
+    let messages = read_messages(shipped_profile_csv("profile_messages.csv")).unwrap();
+    assert!(messages.iter().all(|m| !m.name.is_empty()));
+    let names = messages.iter().map(|m| &m.name).collect::<HashSet<_>>();
+    assert_eq!(names.len(), messages.len());
+    assert_eq!(messages[0].name, "file_id");
+}
+
+#[test]
+fn test_read_shipped_profile_types_has_no_placeholders_or_duplicates() {
+    let types = read_profile_types(shipped_profile_csv("profile_types.csv")).unwrap();
+    assert!(types.iter().all(|t| !t.type_name.is_empty()));
+    let names = types.iter().map(|t| &t.type_name).collect::<HashSet<_>>();
+    assert_eq!(names.len(), types.len());
+    assert_eq!(types[0].type_name, "file");
+}
+
+#[test]
+fn test_read_shipped_messages_attaches_subfields_to_parent() {
+    let messages = read_messages(shipped_profile_csv("profile_messages.csv")).unwrap();
+    let file_id = messages.iter().find(|m| m.name == "file_id").unwrap();
+    let product = file_id.fields.iter().find(|f| f.name == "product").unwrap();
+    let subfield_names = product
+        .subfields
+        .iter()
+        .map(|f| f.name.as_str())
+        .collect::<Vec<&str>>();
+    assert_eq!(subfield_names, vec!["favero_product", "garmin_product"]);
+    let garmin_product = &product.subfields[1];
+    assert_eq!(garmin_product.definition_number, 2);
+    assert_eq!(garmin_product.ref_fields.len(), 4);
+    assert_eq!(garmin_product.ref_fields[3].value, "tacx");
+    assert!(file_id.fields.iter().all(|f| f.name != "garmin_product"));
+}
+
+#[test]
+fn test_normalize_messages_rejects_duplicate_definition_numbers() {
+    let field = FitMessageField {
+        category: String::new(),
+        definition_number: 1,
+        name: String::from("a"),
+        field_type: String::from("uint8"),
+        array: FitMessageArrayType::NotArray,
+        scale: vec![1.0],
+        offset: 0,
+        components: vec![],
+        units: vec![],
+        bits: vec![],
+        accumulate: vec![],
+        ref_fields: vec![],
+        comment: None,
+        example: None,
+        subfields: vec![],
+    };
+    let message = FitMessage {
+        name: String::from("test"),
+        comment: None,
+        fields: vec![
+            field.clone(),
+            FitMessageField {
+                name: String::from("b"),
+                ..field
+            },
+        ],
+    };
+    assert_eq!(
+        normalize_messages(vec![message]),
+        Err(ProfileValidationError::DuplicateFieldDefinitionNumber {
+            message: String::from("test"),
+            definition_number: 1
+        })
+    );
+}
+
+#[test]
+fn test_normalize_messages_drops_placeholders_and_exact_duplicates() {
+    let placeholder = FitMessage {
+        name: String::new(),
+        comment: None,
+        fields: vec![],
+    };
+    let message = FitMessage {
+        name: String::from("test"),
+        comment: None,
+        fields: vec![],
+    };
+    let normalized = normalize_messages(vec![
+        placeholder.clone(),
+        placeholder,
+        message.clone(),
+        message.clone(),
+    ])
+    .unwrap();
+    assert_eq!(normalized, vec![message]);
 }
 
 fn fit_type_to_rust_type(fit_type: &str) -> &str {
     match fit_type {
+        "enum" => "u8",
+        "bool" => "bool",
+        "sint8" => "i8",
         "uint8" => "u8",
         "uint8z" => "u8",
+        "byte" => "u8",
+        "sint16" => "i16",
         "uint16" => "u16",
         "uint16z" => "u16",
+        "sint32" => "i32",
         "uint32" => "u32",
         "uint32z" => "u32",
+        "sint64" => "i64",
+        "uint64" => "u64",
+        "uint64z" => "u64",
+        "float32" => "f32",
+        "float64" => "f64",
+        "string" => "String",
         _ => panic!("Unknown FIT type: {}", fit_type),
     }
 }
 
-pub fn generate_enum_type_as_string(t: FitType) -> String {
+/// Options controlling the generated code.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CodegenOptions {
+    /// Emit serde `Serialize`/`Deserialize` derives on generated enums and
+    /// message structs, gated behind the consuming crate's `serde` feature.
+    pub serde: bool,
+    /// Gate optional profile categories behind the consuming crate's cargo
+    /// features, see [`message_feature`] and [`type_feature`].
+    pub feature_gates: bool,
+    /// Version of the FIT SDK profile the code is generated from, encoded
+    /// the same way as the file header `profile_version` field.
+    pub profile_version: Option<u16>,
+    /// Emit a `from_message` constructor on each message struct converting a
+    /// decoded `fit_parser` `DataMessage`, for code generated into that crate.
+    pub message_conversions: bool,
+}
+
+impl CodegenOptions {
+    pub(crate) fn feature_gate_attribute(&self, feature: Option<&str>) -> String {
+        match feature {
+            Some(feature) if self.feature_gates => format!("#[cfg(feature = \"{}\")]\n", feature),
+            _ => String::new(),
+        }
+    }
+
+    fn derive_attributes(&self) -> String {
+        if self.serde {
+            String::from(
+                "#[cfg_attr(feature = \"serde\", derive(serde::Serialize, serde::Deserialize))]\n",
+            )
+        } else {
+            String::new()
+        }
+    }
+}
+
+const DIVE_MESSAGES: [&str; 4] = ["dive_settings", "dive_alarm", "dive_gas", "dive_summary"];
+const GARMIN_INTERNAL_MESSAGES: [&str; 6] = [
+    "memo_glob",
+    "ant_rx",
+    "ant_tx",
+    "exd_screen_configuration",
+    "exd_data_field_configuration",
+    "exd_data_concept_configuration",
+];
+const SENSOR_DATA_MESSAGES: [&str; 9] = [
+    "gyroscope_data",
+    "accelerometer_data",
+    "magnetometer_data",
+    "barometer_data",
+    "three_d_sensor_calibration",
+    "one_d_sensor_calibration",
+    "obdii_data",
+    "nmea_sentence",
+    "aviation_attitude",
+];
+const VIDEO_MESSAGES: [&str; 6] = [
+    "camera_event",
+    "video_frame",
+    "video",
+    "video_title",
+    "video_description",
+    "video_clip",
+];
+const STRENGTH_TRAINING_MESSAGES: [&str; 2] = ["set", "exercise_title"];
+
+/// Cargo feature of the consuming crate the given message is gated behind,
+/// or `None` for messages that are always generated.
+pub fn message_feature(message: &FitMessage) -> Option<&'static str> {
+    let name = message.name.as_str();
+    if DIVE_MESSAGES.contains(&name) {
+        Some("dive")
+    } else if GARMIN_INTERNAL_MESSAGES.contains(&name) {
+        Some("garmin-internal")
+    } else if SENSOR_DATA_MESSAGES.contains(&name) {
+        Some("sensor-data")
+    } else if VIDEO_MESSAGES.contains(&name) {
+        Some("video")
+    } else if STRENGTH_TRAINING_MESSAGES.contains(&name) {
+        Some("strength-training")
+    } else if message
+        .fields
+        .first()
+        .is_some_and(|f| f.category == "MONITORING FILE MESSAGES")
+    {
+        Some("monitoring")
+    } else {
+        None
+    }
+}
+
+/// Cargo feature of the consuming crate the given type is gated behind, or
+/// `None` for types that are always generated.
+pub fn type_feature(fit_type: &FitType) -> Option<&'static str> {
+    let name = fit_type.type_name.as_str();
+    if name.starts_with("dive_") || name == "water_type" || name == "tissue_model_type" {
+        Some("dive")
+    } else if name.starts_with("exd_") || name == "supported_exd_screen_layouts" {
+        Some("garmin-internal")
+    } else if name.ends_with("_exercise_name") || name == "exercise_category" {
+        Some("strength-training")
+    } else {
+        None
+    }
+}
+
+// Turns free form profile comment text into `///` doc comment lines with the
+// given indentation. Brackets are escaped so rustdoc does not treat them as
+// intra-doc links.
+fn doc_comment(comment: &str, indent: &str) -> String {
+    comment
+        .lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty())
+        .map(|l| l.replace('[', "\\[").replace(']', "\\]"))
+        .map(|l| format!("{}/// {}\n", indent, l))
+        .collect::<String>()
+}
+
+pub fn generate_enum_type_as_string(t: FitType, options: &CodegenOptions) -> String {
     let mut s = String::new();
     if t.base_type != "enum" {
         return s;
     }
+    if let Some(comment) = &t.comment {
+        s.push_str(&doc_comment(comment, ""));
+    }
+    s.push_str(&options.feature_gate_attribute(type_feature(&t)));
+    s.push_str(&options.derive_attributes());
     s.push_str(&format!(
         "pub enum {} {{\n",
         t.type_name.to_case(Case::UpperCamel)
     ));
     for val in t.values {
-        if val.comment.trim().to_lowercase().starts_with("deprecated") {
+        if is_deprecated(&val.comment) {
             continue;
         }
-        let comment = if val.comment.is_empty() {
-            String::new()
-        } else {
-            format!(" // {}", val.comment)
-        };
+        s.push_str(&doc_comment(&val.comment, "    "));
         s.push_str(&format!(
-            "    {} = {},{}\n",
+            "    {} = {},\n",
             val.value_name.to_case(Case::UpperCamel),
             val.value,
-            comment
         ));
     }
     s.push_str("}\n");
     s
 }
 
-pub fn generate_fit_trait_as_string(t: FitType) -> String {
+pub fn generate_fit_trait_as_string(t: FitType, options: &CodegenOptions) -> String {
     let mut s = String::new();
     let type_name_cased = t.type_name.to_case(Case::UpperCamel);
+    let feature_gate = options.feature_gate_attribute(type_feature(&t));
+    let type_doc = t
+        .comment
+        .as_deref()
+        .map(|c| doc_comment(c, ""))
+        .unwrap_or_default();
+    s.push_str(&type_doc);
+    s.push_str(&feature_gate);
     s.push_str(&format!("trait {}Trait {{\n", type_name_cased));
     let rust_type = fit_type_to_rust_type(&t.base_type);
     for val in t.values {
-        let comment = if val.comment.is_empty() {
-            String::new()
-        } else {
-            format!(" // {}", val.comment)
-        };
         let value_name_cased = if val.value_name.chars().next().unwrap().is_ascii_digit() {
             format!("_{}", val.value_name.to_case(Case::UpperSnake))
         } else {
             val.value_name.to_case(Case::UpperSnake)
         };
 
+        s.push_str(&doc_comment(&val.comment, "    "));
         s.push_str(&format!(
-            "    const {}: {} = {};{}\n",
-            value_name_cased, rust_type, val.value, comment
+            "    const {}: {} = {};\n",
+            value_name_cased, rust_type, val.value
         ))
     }
     s.push_str("}\n");
+    s.push_str(&type_doc);
+    s.push_str(&feature_gate);
     s.push_str(&format!("struct {};\n", type_name_cased));
+    s.push_str(&feature_gate);
     s.push_str(&format!(
         "impl {}Trait for {}{{}}\n\n",
         type_name_cased, type_name_cased
     ));
     s
 }
+
+// Rust keywords that show up as profile field names
+const RUST_KEYWORDS: [&str; 4] = ["type", "mod", "match", "move"];
+
+fn message_field_name(field_name: &str) -> String {
+    let snake = field_name.to_case(Case::Snake);
+    if RUST_KEYWORDS.contains(&snake.as_str()) {
+        format!("r#{}", snake)
+    } else {
+        snake
+    }
+}
+
+// Resolves the Rust type a message field value is represented with. Scaled
+// or offset values are represented as `f64`, other values by the Rust
+// counterpart of their (profile type's) base type.
+fn message_field_rust_type(field: &FitMessageField, types: &[FitType]) -> String {
+    let base_type = types
+        .iter()
+        .find(|t| t.type_name == field.field_type)
+        .map(|t| t.base_type.as_str())
+        .unwrap_or(&field.field_type);
+    // Fields with several components list the scales of the components, the
+    // field value itself is then not scaled.
+    let is_scaled = field.scale.len() == 1 && (field.scale[0] != 1.0 || field.offset != 0);
+    let rust_type = if is_scaled {
+        "f64"
+    } else {
+        fit_type_to_rust_type(base_type)
+    };
+    match field.array {
+        FitMessageArrayType::NotArray => rust_type.to_string(),
+        FitMessageArrayType::FixedSizeArray(_) | FitMessageArrayType::VariableSizeArray => {
+            format!("Vec<{}>", rust_type)
+        }
+    }
+}
+
+pub fn generate_message_struct_as_string(
+    m: &FitMessage,
+    types: &[FitType],
+    options: &CodegenOptions,
+) -> String {
+    let mut s = String::new();
+    if let Some(comment) = &m.comment {
+        s.push_str(&doc_comment(comment, ""));
+    }
+    s.push_str(&options.feature_gate_attribute(message_feature(m)));
+    s.push_str("#[derive(Debug, Clone, Default, PartialEq)]\n");
+    s.push_str(&options.derive_attributes());
+    s.push_str(&format!(
+        "pub struct {} {{\n",
+        m.name.to_case(Case::UpperCamel)
+    ));
+    for field in &m.fields {
+        if let Some(comment) = &field.comment {
+            s.push_str(&doc_comment(comment, "    "));
+        }
+        let units = field
+            .units
+            .iter()
+            .map(|u| u.trim())
+            .filter(|u| !u.is_empty())
+            .collect::<Vec<&str>>();
+        if !units.is_empty() {
+            if field.comment.is_some() {
+                s.push_str("    ///\n");
+            }
+            s.push_str(&format!("    /// Units: {}\n", units.join(", ")));
+        }
+        s.push_str(&format!(
+            "    pub {}: Option<{}>,\n",
+            message_field_name(&field.name),
+            message_field_rust_type(field, types)
+        ));
+    }
+    s.push_str("}\n");
+    if options.message_conversions {
+        s.push_str(&generate_message_conversion_as_string(m, types, options));
+    }
+    s
+}
+
+// Conversions of a message struct from and, when the global message number is
+// known, to a data message, mapping each field by its definition number.
+fn generate_message_conversion_as_string(
+    m: &FitMessage,
+    types: &[FitType],
+    options: &CodegenOptions,
+) -> String {
+    let mut s = String::new();
+    s.push_str(&options.feature_gate_attribute(message_feature(m)));
+    s.push_str(&format!("impl {} {{\n", m.name.to_case(Case::UpperCamel)));
+    s.push_str("    pub fn from_message(message: &crate::fit_messages::DataMessage) -> Self {\n");
+    s.push_str("        Self {\n");
+    for field in &m.fields {
+        s.push_str(&format!(
+            "            {}: message.typed_field({}),\n",
+            message_field_name(&field.name),
+            field.definition_number
+        ));
+    }
+    s.push_str("        }\n    }\n");
+    if let Some(number) = mesg_num_value(types, &m.name) {
+        s.push_str("    pub fn to_message(&self) -> crate::fit_messages::DataMessage {\n");
+        s.push_str(&format!(
+            "        let mut message = crate::fit_messages::DataMessage::new({});\n",
+            number
+        ));
+        for field in &m.fields {
+            s.push_str(&format!(
+                "        message.set_typed_field({}, \"{}\", &self.{});\n",
+                field.definition_number,
+                field.name,
+                message_field_name(&field.name)
+            ));
+        }
+        s.push_str("        message\n    }\n");
+    }
+    s.push_str("}\n");
+    s
+}
+
+/// Global message number of the named message from the `mesg_num` type.
+pub fn mesg_num_value(types: &[FitType], message_name: &str) -> Option<u32> {
+    types
+        .iter()
+        .find(|t| t.type_name == "mesg_num")
+        .and_then(|t| t.values.iter().find(|v| v.value_name == message_name))
+        .map(|v| v.value)
+}
+
+// Resolves a ref field value name such as `garmin` to its numeric value using
+// the type of the referenced field, e.g. `manufacturer`.
+fn ref_field_value_number(
+    message: &FitMessage,
+    ref_field: &FitRefField,
+    types: &[FitType],
+) -> (u8, u32) {
+    let referenced = message
+        .fields
+        .iter()
+        .find(|f| f.name == ref_field.name)
+        .unwrap_or_else(|| {
+            panic!(
+                "Unknown ref field {} in message {}",
+                ref_field.name, message.name
+            )
+        });
+    let value_name = ref_field.value.as_str();
+    let value = value_name.parse::<u32>().unwrap_or_else(|_| {
+        types
+            .iter()
+            .find(|t| t.type_name == referenced.field_type)
+            .and_then(|t| t.values.iter().find(|v| v.value_name == value_name))
+            .map(|v| v.value)
+            .unwrap_or_else(|| {
+                panic!(
+                    "Unknown ref field value {} for {}.{}",
+                    value_name, message.name, referenced.name
+                )
+            })
+    });
+    (referenced.definition_number, value)
+}
+
+/// Generates `subfield_name`, which selects the active subfield of a field
+/// from the values of its reference fields. The reference field values are
+/// looked up by field definition number through the given closure.
+pub fn generate_subfield_dispatch_as_string(
+    messages: &[FitMessage],
+    types: &[FitType],
+    options: &CodegenOptions,
+) -> String {
+    let has_subfields = messages
+        .iter()
+        .any(|m| m.fields.iter().any(|f| !f.subfields.is_empty()));
+    let mut s = String::new();
+    s.push_str("/// Name of the subfield active for the given message field, resolved from\n");
+    s.push_str("/// the raw values of the subfield's reference fields.\n");
+    if !has_subfields {
+        s.push_str("#[allow(unused_variables, clippy::match_single_binding)]\n");
+    }
+    s.push_str("pub fn subfield_name<F>(\n");
+    s.push_str("    global_message_number: u16,\n");
+    s.push_str("    field_definition_number: u8,\n");
+    s.push_str("    ref_field_value: F,\n");
+    s.push_str(") -> Option<&'static str>\n");
+    s.push_str("where\n");
+    s.push_str("    F: Fn(u8) -> Option<u32>,\n");
+    s.push_str("{\n");
+    s.push_str("    match (global_message_number, field_definition_number) {\n");
+    for m in messages {
+        let mesg_num = match mesg_num_value(types, &m.name) {
+            Some(mesg_num) => mesg_num,
+            None => continue,
+        };
+        for field in m.fields.iter().filter(|f| !f.subfields.is_empty()) {
+            let feature_gate = options.feature_gate_attribute(message_feature(m));
+            if !feature_gate.is_empty() {
+                s.push_str("        ");
+                s.push_str(&feature_gate);
+            }
+            s.push_str(&format!(
+                "        ({}, {}) => {{\n",
+                mesg_num, field.definition_number
+            ));
+            for subfield in &field.subfields {
+                let conditions = subfield
+                    .ref_fields
+                    .iter()
+                    .map(|rf| ref_field_value_number(m, rf, types))
+                    .map(|(num, value)| format!("ref_field_value({}) == Some({})", num, value))
+                    .collect::<Vec<String>>();
+                if conditions.is_empty() {
+                    continue;
+                }
+                s.push_str(&format!(
+                    "            if {} {{\n                return Some(\"{}\");\n            }}\n",
+                    conditions.join(" || "),
+                    subfield.name
+                ));
+            }
+            s.push_str("            None\n");
+            s.push_str("        }\n");
+        }
+    }
+    s.push_str("        _ => None,\n");
+    s.push_str("    }\n");
+    s.push_str("}\n");
+    s
+}
+
+/// Generates the complete profile module source: message structs in a
+/// nested `messages` module followed by the profile types.
+///
+/// Messages and types are emitted sorted by name and the output is formatted
+/// with `prettyplease`, so the generated code only changes when the profile
+/// does.
+pub fn generate_profile_as_string(
+    types: Vec<FitType>,
+    messages: &[FitMessage],
+    options: &CodegenOptions,
+) -> Result<String, Box<dyn Error>> {
+    let mut types = types;
+    types.sort_by(|a, b| a.type_name.cmp(&b.type_name));
+    let mut messages = messages.to_vec();
+    messages.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut s = String::new();
+    s.push_str("//! Generated by fit_profile_typegen from the FIT SDK profile.\n");
+    s.push_str("//! Do not edit, regenerate with the `regen_profile` binary instead.\n");
+    s.push_str("#![allow(dead_code)]\n\n");
+    if let Some(profile_version) = options.profile_version {
+        s.push_str("/// Version of the FIT SDK profile this module is generated from, encoded\n");
+        s.push_str("/// as major * 100 + minor like the file header `profile_version`.\n");
+        s.push_str(&format!(
+            "pub const PROFILE_VERSION: u16 = {};\n\n",
+            profile_version
+        ));
+    }
+    s.push_str("pub mod messages {\n");
+    for m in &messages {
+        s.push_str(&generate_message_struct_as_string(m, &types, options));
+        s.push('\n')
+    }
+    s.push_str("}\n\n");
+    s.push_str(&generate_subfield_dispatch_as_string(
+        &messages, &types, options,
+    ));
+    s.push('\n');
+    s.push_str(&generate_profile_metadata_as_string(
+        &messages, &types, options,
+    ));
+    s.push('\n');
+    for t in types {
+        if t.type_name.is_empty() {
+            continue;
+        } else if t.base_type == "enum" {
+            s.push_str(&generate_enum_type_as_string(t, options));
+        } else {
+            s.push_str(&generate_fit_trait_as_string(t, options));
+        }
+        s.push('\n')
+    }
+    format_generated_code(&s)
+}
+
+/// Parses a FIT SDK profile version such as `21.60` into the encoding used by
+/// the file header `profile_version` field (major * 100 + minor).
+pub fn parse_profile_version(version: &str) -> Result<u16, Box<dyn Error>> {
+    let (major, minor) = version
+        .trim()
+        .split_once('.')
+        .ok_or_else(|| format!("Profile version {} is not of form major.minor", version))?;
+    let major = major.parse::<u16>()?;
+    let minor = minor.parse::<u16>()?;
+    if minor >= 100 {
+        return Err(format!("Profile version {} minor part exceeds 99", version).into());
+    }
+    Ok(major * 100 + minor)
+}
+
+/// Formats generated Rust source with `prettyplease`.
+pub fn format_generated_code(source: &str) -> Result<String, Box<dyn Error>> {
+    let file = syn::parse_file(source)?;
+    Ok(prettyplease::unparse(&file))
+}
+
+#[test]
+fn test_generate_enum_type_with_doc_comments() {
+    let t = FitType {
+        type_name: String::from("timer_trigger"),
+        base_type: String::from("enum"),
+        comment: Some(String::from("timer event data")),
+        values: vec![FitTypeValue {
+            value_name: String::from("manual"),
+            value: 0,
+            comment: String::from("started manually"),
+        }],
+    };
+    assert_eq!(
+        generate_enum_type_as_string(t, &CodegenOptions::default()),
+        "/// timer event data\n\
+         pub enum TimerTrigger {\n    \
+             /// started manually\n    \
+             Manual = 0,\n\
+         }\n"
+    );
+}
+
+#[test]
+fn test_generate_message_struct_with_doc_comments() {
+    let m = FitMessage {
+        name: String::from("file_id"),
+        comment: Some(String::from("Must be first message in file.")),
+        fields: vec![FitMessageField {
+            category: String::new(),
+            definition_number: 0,
+            name: String::from("type"),
+            field_type: String::from("uint16"),
+            array: FitMessageArrayType::NotArray,
+            scale: vec![100.0],
+            offset: 0,
+            components: vec![],
+            units: vec![String::from("s")],
+            bits: vec![],
+            accumulate: vec![],
+            ref_fields: vec![],
+            comment: Some(String::from("Kind of file")),
+            example: None,
+            subfields: vec![],
+        }],
+    };
+    assert_eq!(
+        generate_message_struct_as_string(&m, &[], &CodegenOptions::default()),
+        "/// Must be first message in file.\n\
+         #[derive(Debug, Clone, Default, PartialEq)]\n\
+         pub struct FileId {\n    \
+             /// Kind of file\n    \
+             ///\n    \
+             /// Units: s\n    \
+             pub r#type: Option<f64>,\n\
+         }\n"
+    );
+}
+
+#[test]
+fn test_generate_message_struct_with_serde_derives() {
+    let m = FitMessage {
+        name: String::from("file_creator"),
+        comment: None,
+        fields: vec![],
+    };
+    let options = CodegenOptions {
+        serde: true,
+        ..CodegenOptions::default()
+    };
+    assert_eq!(
+        generate_message_struct_as_string(&m, &[], &options),
+        "#[derive(Debug, Clone, Default, PartialEq)]\n\
+         #[cfg_attr(feature = \"serde\", derive(serde::Serialize, serde::Deserialize))]\n\
+         pub struct FileCreator {\n\
+         }\n"
+    );
+}
+
+#[test]
+fn test_generate_message_struct_with_feature_gate() {
+    let m = FitMessage {
+        name: String::from("dive_gas"),
+        comment: None,
+        fields: vec![],
+    };
+    let options = CodegenOptions {
+        feature_gates: true,
+        ..CodegenOptions::default()
+    };
+    assert_eq!(
+        generate_message_struct_as_string(&m, &[], &options),
+        "#[cfg(feature = \"dive\")]\n\
+         #[derive(Debug, Clone, Default, PartialEq)]\n\
+         pub struct DiveGas {\n\
+         }\n"
+    );
+}
+
+#[test]
+fn test_generate_message_struct_with_conversion() {
+    let m = FitMessage {
+        name: String::from("hrv"),
+        comment: None,
+        fields: vec![test_field("type", 0, "uint8")],
+    };
+    let options = CodegenOptions {
+        message_conversions: true,
+        ..CodegenOptions::default()
+    };
+    let types = vec![FitType {
+        type_name: String::from("mesg_num"),
+        base_type: String::from("uint16"),
+        comment: None,
+        values: vec![FitTypeValue {
+            value_name: String::from("hrv"),
+            value: 78,
+            comment: String::new(),
+        }],
+    }];
+    assert_eq!(
+        generate_message_struct_as_string(&m, &types, &options),
+        "#[derive(Debug, Clone, Default, PartialEq)]\n\
+         pub struct Hrv {\n\
+         \x20   pub r#type: Option<u8>,\n\
+         }\n\
+         impl Hrv {\n\
+         \x20   pub fn from_message(message: &crate::fit_messages::DataMessage) -> Self {\n\
+         \x20       Self {\n\
+         \x20           r#type: message.typed_field(0),\n\
+         \x20       }\n\
+         \x20   }\n\
+         \x20   pub fn to_message(&self) -> crate::fit_messages::DataMessage {\n\
+         \x20       let mut message = crate::fit_messages::DataMessage::new(78);\n\
+         \x20       message.set_typed_field(0, \"type\", &self.r#type);\n\
+         \x20       message\n\
+         \x20   }\n\
+         }\n"
+    );
+}
+
+#[cfg(test)]
+pub(crate) fn test_field(name: &str, definition_number: u8, field_type: &str) -> FitMessageField {
+    FitMessageField {
+        category: String::new(),
+        definition_number,
+        name: String::from(name),
+        field_type: String::from(field_type),
+        array: FitMessageArrayType::NotArray,
+        scale: vec![1.0],
+        offset: 0,
+        components: vec![],
+        units: vec![],
+        bits: vec![],
+        accumulate: vec![],
+        ref_fields: vec![],
+        comment: None,
+        example: None,
+        subfields: vec![],
+    }
+}
+
+#[test]
+fn test_generate_subfield_dispatch() {
+    let mut product = test_field("product", 2, "uint16");
+    product.subfields.push(FitMessageField {
+        ref_fields: vec![
+            FitRefField {
+                name: String::from("manufacturer"),
+                value: String::from("garmin"),
+            },
+            FitRefField {
+                name: String::from("manufacturer"),
+                value: String::from("dynastream"),
+            },
+        ],
+        ..test_field("garmin_product", 2, "garmin_product")
+    });
+    let message = FitMessage {
+        name: String::from("file_id"),
+        comment: None,
+        fields: vec![test_field("manufacturer", 1, "manufacturer"), product],
+    };
+    let value = |name: &str, value: u32| FitTypeValue {
+        value_name: String::from(name),
+        value,
+        comment: String::new(),
+    };
+    let types = vec![
+        FitType {
+            type_name: String::from("mesg_num"),
+            base_type: String::from("uint16"),
+            comment: None,
+            values: vec![value("file_id", 0)],
+        },
+        FitType {
+            type_name: String::from("manufacturer"),
+            base_type: String::from("uint16"),
+            comment: None,
+            values: vec![value("garmin", 1), value("dynastream", 15)],
+        },
+    ];
+    let generated =
+        generate_subfield_dispatch_as_string(&[message], &types, &CodegenOptions::default());
+    assert!(generated.contains(
+        "        (0, 2) => {\n            \
+         if ref_field_value(1) == Some(1) || ref_field_value(1) == Some(15) {\n                \
+         return Some(\"garmin_product\");\n"
+    ));
+    assert!(generated.ends_with("        _ => None,\n    }\n}\n"));
+}
+
+#[test]
+fn test_generate_profile_is_formatted_and_sorted() {
+    let message = |name: &str| FitMessage {
+        name: String::from(name),
+        comment: None,
+        fields: vec![test_field("timestamp", 253, "uint32")],
+    };
+    let generated = generate_profile_as_string(
+        vec![],
+        &[message("session"), message("lap")],
+        &CodegenOptions::default(),
+    )
+    .unwrap();
+    let lap_idx = generated.find("pub struct Lap {").unwrap();
+    let session_idx = generated.find("pub struct Session {").unwrap();
+    assert!(lap_idx < session_idx);
+    assert!(
+        generated.contains("    pub struct Lap {\n        pub timestamp: Option<u32>,\n    }\n")
+    );
+    assert_eq!(
+        generated,
+        format_generated_code(&generated).unwrap(),
+        "formatting should be idempotent"
+    );
+}
+
+#[test]
+fn test_parse_profile_version() {
+    assert_eq!(parse_profile_version("21.60\n").unwrap(), 2160);
+    assert_eq!(parse_profile_version("20.8").unwrap(), 2008);
+    assert!(parse_profile_version("21").is_err());
+    assert!(parse_profile_version("21.100").is_err());
+}
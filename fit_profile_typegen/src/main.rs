@@ -5,12 +5,19 @@ use codegen::{Scope, Variant};
 use convert_case::Case;
 use convert_case::Casing;
 use fit_profile_typegen::{
-    generate_enum_type_as_string, generate_fit_trait_as_string, read_messages, read_profile_types,
+    generate_enum_type_as_string, generate_fit_trait_as_string, generate_message_struct_as_string,
+    read_messages_with_progress, read_profile_types_with_progress, CodegenOptions,
 };
 
 fn main() -> Result<(), Box<dyn Error>> {
     // println!("cargo:rerun-if-changed=fit_definitions/profile_messages.csv");
     // println!("cargo:rerun-if-changed=fit_definitions/profile_types.csv");
+    let options = CodegenOptions {
+        serde: std::env::args().any(|arg| arg == "--serde"),
+        feature_gates: std::env::args().any(|arg| arg == "--feature-gates"),
+        profile_version: None,
+        message_conversions: std::env::args().any(|arg| arg == "--message-conversions"),
+    };
     let mut codegen_scope = Scope::new();
     let mut profiles_csv_content = String::new();
     let mut messages_csv_content = String::new();
@@ -21,21 +28,24 @@ fn main() -> Result<(), Box<dyn Error>> {
     std::fs::File::open("../fit_definitions/profile_messages.csv")
         .unwrap()
         .read_to_string(&mut messages_csv_content)?;
-    let types = read_profile_types(profiles_csv_content)?;
-    let messages = read_messages(messages_csv_content)?;
+    let types = read_profile_types_with_progress(profiles_csv_content, |p| println!("{}", p))?;
+    let messages = read_messages_with_progress(messages_csv_content, |p| println!("{}", p))?;
     println!("profile_types : {:#?}", types);
     println!("messages: {:#?}", messages);
-    let test_enum = codegen_scope.new_enum(&"test_enum".to_case(Case::UpperCamel));
-    let mut variant = Variant::new(&"test_variant".to_case(Case::UpperCamel));
+    let test_enum = codegen_scope.new_enum("test_enum".to_case(Case::UpperCamel));
+    let mut variant = Variant::new("test_variant".to_case(Case::UpperCamel));
     variant.tuple("i64");
     test_enum.push_variant(variant);
+    for m in &messages {
+        println!("{}", generate_message_struct_as_string(m, &types, &options))
+    }
     for t in types {
         if t.type_name.is_empty() {
             continue;
         } else if t.base_type == "enum" {
-            println!("{}", generate_enum_type_as_string(t))
+            println!("{}", generate_enum_type_as_string(t, &options))
         } else {
-            println!("{}", generate_fit_trait_as_string(t))
+            println!("{}", generate_fit_trait_as_string(t, &options))
         }
     }
     //println!("{}\n", codegen_scope.to_string());
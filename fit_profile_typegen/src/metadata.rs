@@ -0,0 +1,345 @@
+use convert_case::{Case, Casing};
+
+use crate::{
+    is_deprecated, mesg_num_value, message_feature, type_feature, CodegenOptions, FitMessage,
+    FitMessageArrayType, FitMessageField, FitType,
+};
+
+// Definitions of the metadata types the generated statics are built from.
+const METADATA_TYPES: &str = r#"
+/// Layout of a field value array as declared in the profile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldArray {
+    NotArray,
+    Fixed(usize),
+    Variable,
+}
+
+/// A value packed into the bits of a field, expanded to the field `name`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ComponentInfo {
+    pub name: &'static str,
+    pub bits: u8,
+    pub scale: f64,
+    pub offset: f64,
+    pub units: &'static str,
+    pub accumulate: bool,
+}
+
+/// Alternative interpretation of a field, see [`subfield_name`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SubfieldInfo {
+    pub name: &'static str,
+    pub field_type: &'static str,
+    pub scale: f64,
+    pub offset: f64,
+    pub units: &'static str,
+    pub components: &'static [ComponentInfo],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FieldInfo {
+    pub name: &'static str,
+    pub number: u8,
+    /// Profile type name such as `date_time`, or the base type name.
+    pub field_type: &'static str,
+    pub array: FieldArray,
+    pub scale: f64,
+    pub offset: f64,
+    pub units: &'static str,
+    pub components: &'static [ComponentInfo],
+    pub subfields: &'static [SubfieldInfo],
+}
+
+impl FieldInfo {
+    pub fn subfield(&self, name: &str) -> Option<&SubfieldInfo> {
+        self.subfields.iter().find(|s| s.name == name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MessageInfo {
+    pub name: &'static str,
+    pub number: u16,
+    pub fields: &'static [FieldInfo],
+}
+
+impl MessageInfo {
+    pub fn field(&self, number: u8) -> Option<&FieldInfo> {
+        self.fields.iter().find(|f| f.number == number)
+    }
+
+    /// The field with the given name or with a subfield of the name.
+    pub fn field_by_name(&self, name: &str) -> Option<&FieldInfo> {
+        self.fields
+            .iter()
+            .find(|f| f.name == name)
+            .or_else(|| self.fields.iter().find(|f| f.subfield(name).is_some()))
+    }
+}
+
+/// Global message number of the message with the given profile name.
+pub fn mesg_num_by_name(name: &str) -> Option<u16> {
+    message_info_by_name(name).map(|m| m.number)
+}
+
+/// Field definition number of the field of a message with the given profile
+/// name or subfield name.
+pub fn field_num_by_name(global_message_number: u16, field_name: &str) -> Option<u8> {
+    message_info(global_message_number)?
+        .field_by_name(field_name)
+        .map(|f| f.number)
+}
+
+/// Named values of a profile type, sorted by value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TypeInfo {
+    pub name: &'static str,
+    pub base_type: &'static str,
+    pub values: &'static [(u32, &'static str)],
+}
+
+impl TypeInfo {
+    pub fn value_name(&self, value: u32) -> Option<&'static str> {
+        self.values
+            .binary_search_by_key(&value, |(v, _)| *v)
+            .ok()
+            .map(|idx| self.values[idx].1)
+    }
+}
+"#;
+
+fn float_literal(value: f32) -> String {
+    format!("{:?}_f64", value)
+}
+
+fn units_literal(units: &[String], idx: usize) -> String {
+    format!("{:?}", units.get(idx).map(|u| u.trim()).unwrap_or(""))
+}
+
+fn array_literal(array: &FitMessageArrayType) -> String {
+    match array {
+        FitMessageArrayType::NotArray => String::from("FieldArray::NotArray"),
+        FitMessageArrayType::FixedSizeArray(size) => format!("FieldArray::Fixed({})", size),
+        FitMessageArrayType::VariableSizeArray => String::from("FieldArray::Variable"),
+    }
+}
+
+// Scale and offset of the field value itself. Fields with several components
+// list per component scales, the field value is then not scaled.
+fn field_scale_and_offset(field: &FitMessageField) -> (String, String) {
+    if field.scale.len() == 1 {
+        (
+            float_literal(field.scale[0]),
+            float_literal(field.offset as f32),
+        )
+    } else {
+        (float_literal(1.0), float_literal(0.0))
+    }
+}
+
+fn components_literal(field: &FitMessageField) -> String {
+    let components = field
+        .components
+        .iter()
+        .enumerate()
+        .map(|(idx, name)| {
+            let scale = if field.scale.len() == field.components.len() {
+                field.scale[idx]
+            } else {
+                1.0
+            };
+            format!(
+                "ComponentInfo {{ name: {:?}, bits: {}, scale: {}, offset: {}, units: {}, accumulate: {} }}",
+                name.trim(),
+                field.bits.get(idx).copied().unwrap_or(0),
+                float_literal(scale),
+                float_literal(field.offset as f32),
+                units_literal(&field.units, idx),
+                field.accumulate.get(idx).copied().unwrap_or(0) == 1
+            )
+        })
+        .collect::<Vec<String>>();
+    format!("&[{}]", components.join(", "))
+}
+
+fn subfields_literal(field: &FitMessageField) -> String {
+    let subfields = field
+        .subfields
+        .iter()
+        .map(|subfield| {
+            let (scale, offset) = field_scale_and_offset(subfield);
+            format!(
+                "SubfieldInfo {{ name: {:?}, field_type: {:?}, scale: {}, offset: {}, units: {}, components: {} }}",
+                subfield.name,
+                subfield.field_type,
+                scale,
+                offset,
+                units_literal(&subfield.units, 0),
+                components_literal(subfield)
+            )
+        })
+        .collect::<Vec<String>>();
+    format!("&[{}]", subfields.join(", "))
+}
+
+fn field_literal(field: &FitMessageField) -> String {
+    let (scale, offset) = field_scale_and_offset(field);
+    let units = if field.components.len() > 1 {
+        String::from("\"\"")
+    } else {
+        units_literal(&field.units, 0)
+    };
+    format!(
+        "FieldInfo {{ name: {:?}, number: {}, field_type: {:?}, array: {}, scale: {}, offset: {}, units: {}, components: {}, subfields: {} }}",
+        field.name,
+        field.definition_number,
+        field.field_type,
+        array_literal(&field.array),
+        scale,
+        offset,
+        units,
+        components_literal(field),
+        subfields_literal(field)
+    )
+}
+
+fn message_static_name(message: &FitMessage) -> String {
+    format!("{}_MESSAGE", message.name.to_case(Case::UpperSnake))
+}
+
+fn type_static_name(fit_type: &FitType) -> String {
+    format!("{}_TYPE", fit_type.type_name.to_case(Case::UpperSnake))
+}
+
+/// Generates runtime profile metadata: a `MessageInfo` static per message, a
+/// `TypeInfo` static per type, and the `message_info`,
+/// `message_info_by_name` and `type_info` lookup functions.
+pub fn generate_profile_metadata_as_string(
+    messages: &[FitMessage],
+    types: &[FitType],
+    options: &CodegenOptions,
+) -> String {
+    let mut s = String::from(METADATA_TYPES);
+    s.push('\n');
+    let mut message_arms = String::new();
+    let mut message_name_arms = String::new();
+    for m in messages {
+        let number = match mesg_num_value(types, &m.name) {
+            Some(number) => number,
+            None => continue,
+        };
+        let feature_gate = options.feature_gate_attribute(message_feature(m));
+        let fields = m.fields.iter().map(field_literal).collect::<Vec<String>>();
+        s.push_str(&feature_gate);
+        s.push_str(&format!(
+            "pub static {}: MessageInfo = MessageInfo {{ name: {:?}, number: {}, fields: &[{}] }};\n",
+            message_static_name(m),
+            m.name,
+            number,
+            fields.join(", ")
+        ));
+        message_arms.push_str(&feature_gate);
+        message_arms.push_str(&format!(
+            "{} => Some(&{}),\n",
+            number,
+            message_static_name(m)
+        ));
+        message_name_arms.push_str(&feature_gate);
+        message_name_arms.push_str(&format!(
+            "{:?} => Some(&{}),\n",
+            m.name,
+            message_static_name(m)
+        ));
+    }
+    let mut type_arms = String::new();
+    for t in types {
+        let feature_gate = options.feature_gate_attribute(type_feature(t));
+        let mut values = t
+            .values
+            .iter()
+            .filter(|v| !is_deprecated(&v.comment))
+            .map(|v| (v.value, v.value_name.as_str()))
+            .collect::<Vec<(u32, &str)>>();
+        values.sort();
+        let values = values
+            .iter()
+            .map(|(value, name)| format!("({}, {:?})", value, name))
+            .collect::<Vec<String>>();
+        s.push_str(&feature_gate);
+        s.push_str(&format!(
+            "pub static {}: TypeInfo = TypeInfo {{ name: {:?}, base_type: {:?}, values: &[{}] }};\n",
+            type_static_name(t),
+            t.type_name,
+            t.base_type,
+            values.join(", ")
+        ));
+        type_arms.push_str(&feature_gate);
+        type_arms.push_str(&format!(
+            "{:?} => Some(&{}),\n",
+            t.type_name,
+            type_static_name(t)
+        ));
+    }
+    s.push_str("\n/// Profile metadata of the message with the given global message number.\n");
+    s.push_str(
+        "pub fn message_info(global_message_number: u16) -> Option<&'static MessageInfo> {\n",
+    );
+    s.push_str("match global_message_number {\n");
+    s.push_str(&message_arms);
+    s.push_str("_ => None,\n}\n}\n");
+    s.push_str("\n/// Profile metadata of the message with the given name.\n");
+    s.push_str("pub fn message_info_by_name(name: &str) -> Option<&'static MessageInfo> {\n");
+    s.push_str("match name {\n");
+    s.push_str(&message_name_arms);
+    s.push_str("_ => None,\n}\n}\n");
+    s.push_str("\n/// Profile metadata of the type with the given name.\n");
+    s.push_str("pub fn type_info(type_name: &str) -> Option<&'static TypeInfo> {\n");
+    s.push_str("match type_name {\n");
+    s.push_str(&type_arms);
+    s.push_str("_ => None,\n}\n}\n");
+    s
+}
+
+#[cfg(test)]
+use crate::{test_field, FitTypeValue};
+
+#[test]
+fn test_generate_profile_metadata() {
+    let mut speed = test_field("speed", 6, "uint16");
+    speed.scale = vec![1000.0];
+    speed.units = vec![String::from("m/s")];
+    speed.components = vec![String::from("enhanced_speed")];
+    speed.bits = vec![16];
+    let message = FitMessage {
+        name: String::from("record"),
+        comment: None,
+        fields: vec![speed],
+    };
+    let types = vec![FitType {
+        type_name: String::from("mesg_num"),
+        base_type: String::from("uint16"),
+        comment: None,
+        values: vec![FitTypeValue {
+            value_name: String::from("record"),
+            value: 20,
+            comment: String::new(),
+        }],
+    }];
+    let generated =
+        generate_profile_metadata_as_string(&[message], &types, &CodegenOptions::default());
+    assert!(generated.contains(
+        "pub static RECORD_MESSAGE: MessageInfo = MessageInfo { name: \"record\", number: 20, fields: &[\
+         FieldInfo { name: \"speed\", number: 6, field_type: \"uint16\", array: FieldArray::NotArray, \
+         scale: 1000.0_f64, offset: 0.0_f64, units: \"m/s\", components: &[\
+         ComponentInfo { name: \"enhanced_speed\", bits: 16, scale: 1000.0_f64, offset: 0.0_f64, \
+         units: \"m/s\", accumulate: false }], subfields: &[] }] };\n"
+    ));
+    assert!(generated.contains("20 => Some(&RECORD_MESSAGE),\n"));
+    assert!(generated.contains("\"record\" => Some(&RECORD_MESSAGE),\n"));
+    assert!(generated.contains(
+        "pub static MESG_NUM_TYPE: TypeInfo = TypeInfo { name: \"mesg_num\", base_type: \"uint16\", values: &[(20, \"record\")] };\n"
+    ));
+    // generated code must parse
+    crate::format_generated_code(&generated).unwrap();
+}
@@ -0,0 +1,36 @@
+//! Regenerates the profile module of the parser from the FIT SDK profile
+//! CSVs. Run in this directory, optionally with the output path:
+//! `cargo run --bin regen_profile [../parser/src/profile.rs]`.
+
+use std::error::Error;
+
+use fit_profile_typegen::{
+    generate_profile_as_string, parse_profile_version, read_messages, read_profile_types,
+    CodegenOptions,
+};
+
+const DEFAULT_OUTPUT: &str = "../parser/src/profile.rs";
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let output = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| DEFAULT_OUTPUT.to_string());
+    let types = read_profile_types(std::fs::read_to_string(
+        "../fit_definitions/profile_types.csv",
+    )?)?;
+    let messages = read_messages(std::fs::read_to_string(
+        "../fit_definitions/profile_messages.csv",
+    )?)?;
+    let profile_version = std::fs::read_to_string("../fit_definitions/profile_version.txt")?;
+
+    let options = CodegenOptions {
+        serde: true,
+        feature_gates: true,
+        profile_version: Some(parse_profile_version(&profile_version)?),
+        message_conversions: true,
+    };
+    let codegen_str = generate_profile_as_string(types, &messages, &options)?;
+    std::fs::write(&output, codegen_str)?;
+    println!("Wrote {}", output);
+    Ok(())
+}
@@ -0,0 +1,62 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use fit_parser::crc::FitCrc;
+use fit_parser::fit_file::FitFile;
+use fit_parser::fit_header::try_parse_fit_header_from_data;
+use fit_parser::raw::RecordIterator;
+
+const ACTIVITY_FILE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../7427193981_ACTIVITY.fit");
+
+fn activity_data() -> Vec<u8> {
+    std::fs::read(ACTIVITY_FILE).unwrap()
+}
+
+fn bench_header(c: &mut Criterion) {
+    let data = activity_data();
+    c.bench_function("header", |b| {
+        b.iter(|| try_parse_fit_header_from_data(black_box(&data)).unwrap())
+    });
+}
+
+fn bench_records(c: &mut Criterion) {
+    let data = activity_data();
+    let header = try_parse_fit_header_from_data(&data).unwrap();
+    let data_start = header.header_size() as usize;
+    let data_end = data_start + header.data_size() as usize;
+    let mut group = c.benchmark_group("records");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.bench_function("activity", |b| {
+        b.iter(|| RecordIterator::new(black_box(&data[..data_end]), data_start).count())
+    });
+    group.finish();
+}
+
+fn bench_decode(c: &mut Criterion) {
+    let data = activity_data();
+    let mut group = c.benchmark_group("decode");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.bench_function("activity", |b| {
+        b.iter(|| FitFile::parse(black_box(&data)).unwrap())
+    });
+    group.finish();
+}
+
+fn bench_crc(c: &mut Criterion) {
+    let data = activity_data();
+    let mut group = c.benchmark_group("crc");
+    for size in [1024, data.len()] {
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &data[..size], |b, d| {
+            b.iter(|| FitCrc::checksum(black_box(d), 0))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_header,
+    bench_records,
+    bench_decode,
+    bench_crc
+);
+criterion_main!(benches);
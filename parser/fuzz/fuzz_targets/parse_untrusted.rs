@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = fit_parser::parse_untrusted(data);
+    let _ = fit_parser::debug::annotate(data);
+});
@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use fit_parser::raw::RecordIterator;
+
+// Exercises definition and data record parsing without a file header.
+fuzz_target!(|data: &[u8]| {
+    for record in RecordIterator::new(data, 0) {
+        if record.is_err() {
+            break;
+        }
+    }
+});
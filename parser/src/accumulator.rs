@@ -0,0 +1,76 @@
+//! Accumulation of values that count up in a few bits and roll over, like
+//! the accumulated components of the profile, such as the distance packed
+//! in `compressed_speed_distance`. The decoder accumulates components with
+//! it; developer fields and custom pipelines can use it the same way.
+
+use std::collections::HashMap;
+
+use crate::fit_messages::low_bits_mask;
+
+/// Accumulated values by global message number and field definition
+/// number.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Accumulator {
+    values: HashMap<(u16, u8), u64>,
+}
+
+impl Accumulator {
+    pub fn new() -> Accumulator {
+        Accumulator::default()
+    }
+
+    /// Adds the `bits` bit value of the field to its accumulated value,
+    /// counting a rollover when the value is below the low bits of the
+    /// accumulated value, and returns the accumulated value. Accumulation
+    /// starts from zero.
+    pub fn accumulate(
+        &mut self,
+        global_message_number: u16,
+        field_definition_number: u8,
+        value: u64,
+        bits: u8,
+    ) -> u64 {
+        let mask = low_bits_mask(bits);
+        let last = self
+            .values
+            .entry((global_message_number, field_definition_number))
+            .or_insert(0);
+        *last += (value & mask).wrapping_sub(*last & mask) & mask;
+        *last
+    }
+
+    /// Accumulated value of the field, `None` before its first value.
+    pub fn value(&self, global_message_number: u16, field_definition_number: u8) -> Option<u64> {
+        self.values
+            .get(&(global_message_number, field_definition_number))
+            .copied()
+    }
+
+    /// Sets the accumulated value of the field, such as from a full value
+    /// of it, to accumulate on from.
+    pub fn set(&mut self, global_message_number: u16, field_definition_number: u8, value: u64) {
+        self.values
+            .insert((global_message_number, field_definition_number), value);
+    }
+
+    /// Forgets the accumulated values of all fields.
+    pub fn clear(&mut self) {
+        self.values.clear();
+    }
+}
+
+#[test]
+fn test_accumulator() {
+    let mut accumulator = Accumulator::new();
+    assert_eq!(accumulator.value(20, 5), None);
+    assert_eq!(accumulator.accumulate(20, 5, 250, 8), 250);
+    // rolls over from 250 to 4
+    assert_eq!(accumulator.accumulate(20, 5, 4, 8), 260);
+    assert_eq!(accumulator.accumulate(20, 5, 4, 8), 260);
+    assert_eq!(accumulator.accumulate(0xFF00, 5, 3, 8), 3);
+    accumulator.set(20, 5, 1000);
+    assert_eq!(accumulator.accumulate(20, 5, 0xF0, 8), 1008);
+    assert_eq!(accumulator.value(20, 5), Some(1008));
+    accumulator.clear();
+    assert_eq!(accumulator.value(20, 5), None);
+}
@@ -0,0 +1,312 @@
+//! Typed access to the activity structure of a file: sessions, their laps
+//! and the records.
+
+use std::collections::BTreeMap;
+
+use crate::fit_file::FitFile;
+use crate::profile::messages::{Lap, Record, Session};
+use crate::profile::{Sport, LAP_MESSAGE, RECORD_MESSAGE, SESSION_MESSAGE};
+
+/// A session with the laps that belong to it, both in chronological order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SessionLaps {
+    pub session: Session,
+    pub laps: Vec<Lap>,
+}
+
+impl SessionLaps {
+    /// Whether the lap at `lap_index` among all laps of the file belongs to
+    /// the session. Uses the session's lap index range when present and its
+    /// time range otherwise.
+    fn contains(&self, lap_index: usize, lap: &Lap) -> bool {
+        let session = &self.session;
+        if let (Some(first), Some(count)) = (session.first_lap_index, session.num_laps) {
+            let index = lap.message_index.map(|i| i as usize).unwrap_or(lap_index);
+            return (first as usize..first as usize + count as usize).contains(&index);
+        }
+        match (session.start_time, lap.start_time) {
+            (Some(session_start), Some(lap_start)) => {
+                let session_end = session_start as f64 + session.total_elapsed_time.unwrap_or(0.0);
+                lap_start >= session_start && (lap_start as f64) < session_end
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FitFile {
+    pub(crate) fn typed_messages<'a, T: 'a>(
+        &'a self,
+        global_message_number: u16,
+        convert: fn(&crate::fit_messages::DataMessage) -> T,
+    ) -> impl Iterator<Item = T> + 'a {
+        self.messages
+            .iter()
+            .filter(move |m| m.global_message_number == global_message_number)
+            .map(convert)
+    }
+
+    /// Record messages ordered by timestamp. Records without a timestamp come
+    /// first, in file order.
+    pub fn records(&self) -> Vec<Record> {
+        let mut records = self
+            .typed_messages(RECORD_MESSAGE.number, Record::from_message)
+            .collect::<Vec<Record>>();
+        records.sort_by_key(|r| r.timestamp);
+        records
+    }
+
+    /// Lap messages ordered by start time.
+    pub fn laps(&self) -> Vec<Lap> {
+        let mut laps = self
+            .typed_messages(LAP_MESSAGE.number, Lap::from_message)
+            .collect::<Vec<Lap>>();
+        laps.sort_by_key(|l| l.start_time);
+        laps
+    }
+
+    /// Session messages ordered by start time, each with its laps.
+    pub fn sessions(&self) -> Vec<SessionLaps> {
+        let mut sessions = self
+            .typed_messages(SESSION_MESSAGE.number, Session::from_message)
+            .map(|session| SessionLaps {
+                session,
+                laps: vec![],
+            })
+            .collect::<Vec<SessionLaps>>();
+        sessions.sort_by_key(|s| s.session.start_time);
+        let laps = self
+            .typed_messages(LAP_MESSAGE.number, Lap::from_message)
+            .collect::<Vec<Lap>>();
+        for (lap_index, lap) in laps.into_iter().enumerate() {
+            if let Some(session) = sessions.iter_mut().find(|s| s.contains(lap_index, &lap)) {
+                session.laps.push(lap);
+            }
+        }
+        for session in &mut sessions {
+            session.laps.sort_by_key(|l| l.start_time);
+        }
+        sessions
+    }
+}
+
+/// Whether a leg of an activity is a sport or a transition between sports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LegKind {
+    /// A sport, with its `sport` and `sub_sport` values.
+    Sport {
+        sport: u8,
+        sub_sport: Option<u8>,
+    },
+    Transition,
+}
+
+/// One session of an activity, such as the swim or T1 of a triathlon.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Leg {
+    pub kind: LegKind,
+    pub session: SessionLaps,
+}
+
+/// An activity split into its sessions. Single sport activities have one
+/// leg, multisport activities a leg per sport and transition.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Activity {
+    sessions: Vec<SessionLaps>,
+}
+
+impl Activity {
+    pub fn from_file(file: &FitFile) -> Activity {
+        Activity {
+            sessions: file.sessions(),
+        }
+    }
+
+    /// Sessions in chronological order as sport and transition legs.
+    pub fn legs(&self) -> Vec<Leg> {
+        self.sessions
+            .iter()
+            .map(|session| Leg {
+                kind: match session.session.sport {
+                    Some(sport) if sport == Sport::Transition as u8 => LegKind::Transition,
+                    sport => LegKind::Sport {
+                        sport: sport.unwrap_or(Sport::Generic as u8),
+                        sub_sport: session.session.sub_sport,
+                    },
+                },
+                session: session.clone(),
+            })
+            .collect()
+    }
+
+    /// Transition legs, such as T1 and T2 of a triathlon.
+    pub fn transitions(&self) -> Vec<Leg> {
+        self.legs()
+            .into_iter()
+            .filter(|leg| leg.kind == LegKind::Transition)
+            .collect()
+    }
+
+    /// Sessions grouped by their `sport` value, transitions included.
+    pub fn sessions_by_sport(&self) -> BTreeMap<u8, Vec<&SessionLaps>> {
+        let mut by_sport: BTreeMap<u8, Vec<&SessionLaps>> = BTreeMap::new();
+        for session in &self.sessions {
+            let sport = session.session.sport.unwrap_or(Sport::Generic as u8);
+            by_sport.entry(sport).or_default().push(session);
+        }
+        by_sport
+    }
+
+    /// Whether the activity has sessions of more than one sport.
+    pub fn is_multisport(&self) -> bool {
+        self.legs()
+            .iter()
+            .filter_map(|leg| match leg.kind {
+                LegKind::Sport { sport, .. } => Some(sport),
+                LegKind::Transition => None,
+            })
+            .collect::<std::collections::BTreeSet<u8>>()
+            .len()
+            > 1
+    }
+}
+
+#[cfg(test)]
+use crate::fit_file::{test_fit_file, test_message};
+#[cfg(test)]
+use crate::fit_messages::Value;
+
+#[test]
+fn test_sessions_resolve_laps() {
+    // lap: message_index 254, start_time 2; session: start_time 2,
+    // total_elapsed_time 7, first_lap_index 25, num_laps 26
+    let fit_file = test_fit_file(vec![
+        test_message(
+            19,
+            vec![
+                (254, "message_index", Value::U16(1)),
+                (2, "start_time", Value::U32(150)),
+            ],
+        ),
+        test_message(
+            19,
+            vec![
+                (254, "message_index", Value::U16(0)),
+                (2, "start_time", Value::U32(100)),
+            ],
+        ),
+        test_message(
+            19,
+            vec![
+                (254, "message_index", Value::U16(2)),
+                (2, "start_time", Value::U32(300)),
+            ],
+        ),
+        test_message(
+            18,
+            vec![
+                (2, "start_time", Value::U32(300)),
+                (25, "first_lap_index", Value::U16(2)),
+                (26, "num_laps", Value::U16(1)),
+            ],
+        ),
+        test_message(
+            18,
+            vec![
+                (2, "start_time", Value::U32(100)),
+                (7, "total_elapsed_time", Value::F64(200.0)),
+            ],
+        ),
+    ]);
+    let laps = fit_file.laps();
+    assert_eq!(
+        laps.iter()
+            .map(|l| l.start_time)
+            .collect::<Vec<Option<u32>>>(),
+        vec![Some(100), Some(150), Some(300)]
+    );
+    let sessions = fit_file.sessions();
+    assert_eq!(sessions.len(), 2);
+    assert_eq!(sessions[0].session.start_time, Some(100));
+    assert_eq!(
+        sessions[0]
+            .laps
+            .iter()
+            .map(|l| l.message_index)
+            .collect::<Vec<Option<u16>>>(),
+        vec![Some(0), Some(1)]
+    );
+    assert_eq!(sessions[1].laps.len(), 1);
+    assert_eq!(sessions[1].laps[0].message_index, Some(2));
+}
+
+#[test]
+fn test_records_in_timestamp_order() {
+    let fit_file = test_fit_file(vec![
+        test_message(
+            20,
+            vec![
+                (253, "timestamp", Value::U32(20)),
+                (3, "heart_rate", Value::U8(130)),
+            ],
+        ),
+        test_message(
+            20,
+            vec![
+                (253, "timestamp", Value::U32(10)),
+                (3, "heart_rate", Value::U8(120)),
+            ],
+        ),
+    ]);
+    let records = fit_file.records();
+    assert_eq!(records[0].timestamp, Some(10));
+    assert_eq!(records[0].heart_rate, Some(120));
+    assert_eq!(records[1].heart_rate, Some(130));
+}
+
+#[test]
+fn test_multisport_legs() {
+    // session: start_time 2, sport 5
+    let session = |start: u32, sport: Sport| {
+        test_message(
+            18,
+            vec![
+                (2, "start_time", Value::U32(start)),
+                (5, "sport", Value::U8(sport as u8)),
+            ],
+        )
+    };
+    let fit_file = test_fit_file(vec![
+        session(300, Sport::Running),
+        session(100, Sport::Swimming),
+        session(200, Sport::Transition),
+        session(250, Sport::Cycling),
+        session(280, Sport::Transition),
+    ]);
+    let activity = Activity::from_file(&fit_file);
+    let kinds = activity
+        .legs()
+        .iter()
+        .map(|leg| leg.kind)
+        .collect::<Vec<LegKind>>();
+    let sport = |sport: Sport| LegKind::Sport {
+        sport: sport as u8,
+        sub_sport: None,
+    };
+    assert_eq!(
+        kinds,
+        vec![
+            sport(Sport::Swimming),
+            LegKind::Transition,
+            sport(Sport::Cycling),
+            LegKind::Transition,
+            sport(Sport::Running)
+        ]
+    );
+    assert_eq!(activity.transitions().len(), 2);
+    assert_eq!(
+        activity.sessions_by_sport()[&(Sport::Transition as u8)].len(),
+        2
+    );
+    assert!(activity.is_multisport());
+}
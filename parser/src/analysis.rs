@@ -0,0 +1,614 @@
+//! Derived activity metrics computed from the decoded messages.
+
+use crate::elevation::{elevation_gain, ElevationOptions};
+use crate::fit_file::FitFile;
+use crate::profile::messages::{Event as EventMessage, Lap, Session};
+use crate::profile::{Event, EventType, EVENT_MESSAGE};
+use crate::series::RecordSeries;
+
+/// Thresholds for telling moving from stopped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MovingTimeOptions {
+    /// Speed in meters per second below which a sample counts as stopped.
+    pub min_moving_speed: f64,
+    /// Longest time in seconds between two records that counts as moving.
+    /// Longer gaps are pauses, such as auto pause without timer events.
+    pub max_record_gap: u32,
+}
+
+impl Default for MovingTimeOptions {
+    fn default() -> Self {
+        MovingTimeOptions {
+            min_moving_speed: 0.5,
+            max_record_gap: 10,
+        }
+    }
+}
+
+/// A period in which the activity was not recorded, from timer stop to
+/// start or a gap between records. Times are FIT timestamps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pause {
+    pub start: u32,
+    pub end: u32,
+}
+
+impl Pause {
+    pub fn duration(&self) -> u32 {
+        self.end - self.start
+    }
+}
+
+/// Timer and movement analysis of an activity.
+#[derive(Debug, Clone)]
+pub struct ActivityAnalysis {
+    series: RecordSeries,
+    /// Periods in which the timer was running, as start and end timestamps.
+    timer_periods: Vec<(u32, u32)>,
+    options: MovingTimeOptions,
+}
+
+fn is_timer_stop(event_type: u8) -> bool {
+    [
+        EventType::Stop as u8,
+        EventType::StopAll as u8,
+        EventType::StopDisable as u8,
+        EventType::StopDisableAll as u8,
+    ]
+    .contains(&event_type)
+}
+
+// Periods between timer start and stop events. Without timer events the
+// timer is taken to run from the first to the last record.
+fn timer_periods(file: &FitFile, first: u32, last: u32) -> Vec<(u32, u32)> {
+    let mut events = file
+        .messages
+        .iter()
+        .filter(|m| m.global_message_number == EVENT_MESSAGE.number)
+        .map(EventMessage::from_message)
+        .filter(|e| e.event == Some(Event::Timer as u8))
+        .filter_map(|e| Some((e.timestamp?, e.event_type?)))
+        .collect::<Vec<(u32, u8)>>();
+    if events.is_empty() {
+        return vec![(first, last)];
+    }
+    events.sort_by_key(|(timestamp, _)| *timestamp);
+    let mut periods = Vec::new();
+    let mut started: Option<u32> = None;
+    for (timestamp, event_type) in events {
+        match started {
+            None if event_type == EventType::Start as u8 => started = Some(timestamp),
+            Some(start) if is_timer_stop(event_type) => {
+                periods.push((start, timestamp));
+                started = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(start) = started {
+        periods.push((start, last.max(start)));
+    }
+    periods
+}
+
+impl ActivityAnalysis {
+    pub fn new(file: &FitFile) -> ActivityAnalysis {
+        ActivityAnalysis::with_options(file, MovingTimeOptions::default())
+    }
+
+    pub fn with_options(file: &FitFile, options: MovingTimeOptions) -> ActivityAnalysis {
+        let series = file.record_series();
+        let timestamps = series.timestamp.iter().flatten();
+        let first = timestamps.clone().min().copied().unwrap_or(0);
+        let last = timestamps.max().copied().unwrap_or(0);
+        ActivityAnalysis {
+            timer_periods: timer_periods(file, first, last),
+            series,
+            options,
+        }
+    }
+
+    // Timestamped samples in time order with their speeds.
+    fn samples(&self) -> Vec<(u32, f64)> {
+        let mut samples = self
+            .series
+            .timestamp
+            .iter()
+            .zip(&self.series.speed)
+            .filter_map(|(t, speed)| t.map(|t| (t, *speed)))
+            .collect::<Vec<(u32, f64)>>();
+        samples.sort_by_key(|(t, _)| *t);
+        samples
+    }
+
+    fn in_timer_period(&self, start: u32, end: u32) -> bool {
+        self.timer_periods
+            .iter()
+            .any(|(period_start, period_end)| *period_start <= start && end <= *period_end)
+    }
+
+    /// Seconds from the first to the last record, pauses included.
+    pub fn elapsed_time(&self) -> u32 {
+        let samples = self.samples();
+        match (samples.first(), samples.last()) {
+            (Some((first, _)), Some((last, _))) => last - first,
+            _ => 0,
+        }
+    }
+
+    /// Seconds the timer was running.
+    pub fn timer_time(&self) -> u32 {
+        self.timer_periods
+            .iter()
+            .map(|(start, end)| end - start)
+            .sum()
+    }
+
+    /// Timer stops and record gaps longer than the maximum record gap while
+    /// the timer was running, in time order.
+    pub fn pauses(&self) -> Vec<Pause> {
+        let mut pauses = self
+            .timer_periods
+            .windows(2)
+            .map(|periods| Pause {
+                start: periods[0].1,
+                end: periods[1].0,
+            })
+            .filter(|p| p.end > p.start)
+            .collect::<Vec<Pause>>();
+        for pair in self.samples().windows(2) {
+            let (start, end) = (pair[0].0, pair[1].0);
+            if end - start > self.options.max_record_gap && self.in_timer_period(start, end) {
+                pauses.push(Pause { start, end });
+            }
+        }
+        pauses.sort_by_key(|p| p.start);
+        pauses
+    }
+
+    /// Seconds spent moving: time between consecutive records while the
+    /// timer was running, excluding record gaps and samples slower than the
+    /// minimum moving speed. Samples without speed count as moving.
+    pub fn moving_time(&self) -> u32 {
+        self.samples()
+            .windows(2)
+            .filter(|pair| {
+                let (start, end, speed) = (pair[0].0, pair[1].0, pair[1].1);
+                end - start <= self.options.max_record_gap
+                    && self.in_timer_period(start, end)
+                    && (speed.is_nan() || speed >= self.options.min_moving_speed)
+            })
+            .map(|pair| pair[1].0 - pair[0].0)
+            .sum()
+    }
+}
+
+/// Summary values of a session or lap, either as stored in the file or
+/// computed from its records.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SummaryStats {
+    /// Beats per minute.
+    pub avg_heart_rate: Option<f64>,
+    pub max_heart_rate: Option<f64>,
+    /// Watts.
+    pub avg_power: Option<f64>,
+    pub max_power: Option<f64>,
+    /// Meters per second.
+    pub avg_speed: Option<f64>,
+    pub max_speed: Option<f64>,
+    /// Meters.
+    pub total_ascent: Option<f64>,
+    pub total_descent: Option<f64>,
+    pub total_distance: Option<f64>,
+}
+
+// Session and lap messages have the same summary fields.
+macro_rules! stored_summary {
+    ($message:expr) => {
+        SummaryStats {
+            avg_heart_rate: $message.avg_heart_rate.map(f64::from),
+            max_heart_rate: $message.max_heart_rate.map(f64::from),
+            avg_power: $message.avg_power.map(f64::from),
+            max_power: $message.max_power.map(f64::from),
+            avg_speed: $message.enhanced_avg_speed.or($message.avg_speed),
+            max_speed: $message.enhanced_max_speed.or($message.max_speed),
+            total_ascent: $message.total_ascent.map(f64::from),
+            total_descent: $message.total_descent.map(f64::from),
+            total_distance: $message.total_distance,
+        }
+    };
+}
+
+fn mean(values: &[f64]) -> Option<f64> {
+    let values = values.iter().filter(|v| !v.is_nan()).collect::<Vec<&f64>>();
+    if values.is_empty() {
+        return None;
+    }
+    Some(values.iter().copied().sum::<f64>() / values.len() as f64)
+}
+
+fn max(values: &[f64]) -> Option<f64> {
+    values
+        .iter()
+        .filter(|v| !v.is_nan())
+        .copied()
+        .reduce(f64::max)
+}
+
+/// A summary value that differs between the stored and computed summary by
+/// more than the tolerance.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Discrepancy {
+    pub metric: &'static str,
+    pub stored: f64,
+    pub computed: f64,
+}
+
+impl SummaryStats {
+    pub fn from_session(session: &Session) -> SummaryStats {
+        stored_summary!(session)
+    }
+
+    pub fn from_lap(lap: &Lap) -> SummaryStats {
+        stored_summary!(lap)
+    }
+
+    /// Computes the summary of the samples of `series`. Averages are sample
+    /// averages, ascent and descent are the [`elevation_gain`] with the
+    /// default options and distance is the distance covered from the first
+    /// to the last sample.
+    pub fn from_series(series: &RecordSeries) -> SummaryStats {
+        let has_altitude = series.altitude.iter().any(|a| !a.is_nan());
+        let gain = elevation_gain(&series.altitude, &ElevationOptions::default());
+        let distances = series
+            .distance
+            .iter()
+            .filter(|d| !d.is_nan())
+            .collect::<Vec<&f64>>();
+        SummaryStats {
+            avg_heart_rate: mean(&series.heart_rate),
+            max_heart_rate: max(&series.heart_rate),
+            avg_power: mean(&series.power),
+            max_power: max(&series.power),
+            avg_speed: mean(&series.speed),
+            max_speed: max(&series.speed),
+            total_ascent: has_altitude.then_some(gain.ascent),
+            total_descent: has_altitude.then_some(gain.descent),
+            total_distance: match (distances.first(), distances.last()) {
+                (Some(first), Some(last)) => Some(**last - **first),
+                _ => None,
+            },
+        }
+    }
+
+    fn values(&self) -> [(&'static str, Option<f64>); 9] {
+        [
+            ("avg_heart_rate", self.avg_heart_rate),
+            ("max_heart_rate", self.max_heart_rate),
+            ("avg_power", self.avg_power),
+            ("max_power", self.max_power),
+            ("avg_speed", self.avg_speed),
+            ("max_speed", self.max_speed),
+            ("total_ascent", self.total_ascent),
+            ("total_descent", self.total_descent),
+            ("total_distance", self.total_distance),
+        ]
+    }
+
+    /// Values present in both summaries that differ by more than `tolerance`
+    /// relative to the stored value, or to 1 for stored values below 1.
+    pub fn compare(&self, computed: &SummaryStats, tolerance: f64) -> Vec<Discrepancy> {
+        self.values()
+            .iter()
+            .zip(computed.values())
+            .filter_map(
+                |((metric, stored), (_, computed))| match (stored, computed) {
+                    (Some(stored), Some(computed))
+                        if (stored - computed).abs() > tolerance * stored.abs().max(1.0) =>
+                    {
+                        Some(Discrepancy {
+                            metric,
+                            stored: *stored,
+                            computed,
+                        })
+                    }
+                    _ => None,
+                },
+            )
+            .collect()
+    }
+}
+
+/// Whether a cross-checked summary is of a session or a lap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SummaryKind {
+    Session,
+    Lap,
+}
+
+/// Result of cross-checking one stored session or lap summary.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SummaryCheck {
+    pub kind: SummaryKind,
+    /// Index of the session or lap in chronological order.
+    pub index: usize,
+    pub stored: SummaryStats,
+    pub computed: SummaryStats,
+    pub discrepancies: Vec<Discrepancy>,
+}
+
+// Records of the series from the first to the last record within the time
+// window of a session or lap.
+fn window(series: &RecordSeries, start_time: Option<u32>, elapsed: Option<f64>) -> RecordSeries {
+    let (start, end) = match (start_time, elapsed) {
+        (Some(start), Some(elapsed)) => (start as f64, start as f64 + elapsed),
+        _ => return RecordSeries::default(),
+    };
+    let in_window = |t: &Option<u32>| t.is_some_and(|t| t as f64 >= start && t as f64 <= end);
+    match (
+        series.timestamp.iter().position(in_window),
+        series.timestamp.iter().rposition(in_window),
+    ) {
+        (Some(first), Some(last)) => series.slice(first..last + 1),
+        _ => RecordSeries::default(),
+    }
+}
+
+/// Recomputes the summaries of all sessions and laps from the records within
+/// their time ranges and compares them with the stored values, reporting
+/// values that differ by more than the relative `tolerance`.
+pub fn cross_check(file: &FitFile, tolerance: f64) -> Vec<SummaryCheck> {
+    let series = file.record_series();
+    let check = |kind, index, stored: SummaryStats, records: RecordSeries| {
+        let computed = SummaryStats::from_series(&records);
+        SummaryCheck {
+            kind,
+            index,
+            discrepancies: stored.compare(&computed, tolerance),
+            stored,
+            computed,
+        }
+    };
+    let mut checks = Vec::new();
+    for (index, session) in file.sessions().iter().enumerate() {
+        let session = &session.session;
+        let records = window(&series, session.start_time, session.total_elapsed_time);
+        checks.push(check(
+            SummaryKind::Session,
+            index,
+            SummaryStats::from_session(session),
+            records,
+        ));
+    }
+    for (index, lap) in file.laps().iter().enumerate() {
+        let records = window(&series, lap.start_time, lap.total_elapsed_time);
+        checks.push(check(
+            SummaryKind::Lap,
+            index,
+            SummaryStats::from_lap(lap),
+            records,
+        ));
+    }
+    checks
+}
+
+/// Overview of an activity: its sport, start and duration and the summary
+/// values computed from its records.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ActivitySummary {
+    /// The `sport` value of the first session.
+    pub sport: Option<u8>,
+    /// Seconds since the FIT epoch of the first record.
+    pub start_time: Option<u32>,
+    /// Seconds.
+    pub elapsed_time: u32,
+    pub timer_time: u32,
+    pub stats: SummaryStats,
+}
+
+// Sum of the present values, `None` if none are present.
+fn sum_present(values: impl Iterator<Item = Option<f64>>) -> Option<f64> {
+    values.flatten().reduce(|a, b| a + b)
+}
+
+impl ActivitySummary {
+    pub fn from_file(file: &FitFile) -> ActivitySummary {
+        let analysis = ActivityAnalysis::new(file);
+        ActivitySummary {
+            sport: file.sessions().iter().find_map(|s| s.session.sport),
+            start_time: analysis.series.timestamp.iter().flatten().min().copied(),
+            elapsed_time: analysis.elapsed_time(),
+            timer_time: analysis.timer_time(),
+            stats: SummaryStats::from_series(&analysis.series),
+        }
+    }
+
+    /// Summary of several activities: the earliest start, the sport if all
+    /// have the same, summed times, distances, ascents and descents, the
+    /// highest maximums and averages weighted by timer time.
+    pub fn total(summaries: &[ActivitySummary]) -> ActivitySummary {
+        let weighted_mean = |value: fn(&SummaryStats) -> Option<f64>| {
+            let weighted = summaries
+                .iter()
+                .filter_map(|s| Some((value(&s.stats)?, s.timer_time as f64)))
+                .collect::<Vec<(f64, f64)>>();
+            let weight = weighted.iter().map(|(_, w)| w).sum::<f64>();
+            (weight > 0.0).then(|| weighted.iter().map(|(v, w)| v * w).sum::<f64>() / weight)
+        };
+        let highest = |value: fn(&SummaryStats) -> Option<f64>| {
+            summaries
+                .iter()
+                .filter_map(|s| value(&s.stats))
+                .reduce(f64::max)
+        };
+        let total = |value: fn(&SummaryStats) -> Option<f64>| {
+            sum_present(summaries.iter().map(|s| value(&s.stats)))
+        };
+        let sport = summaries.first().and_then(|s| s.sport);
+        ActivitySummary {
+            sport: sport.filter(|_| summaries.iter().all(|s| s.sport == sport)),
+            start_time: summaries.iter().filter_map(|s| s.start_time).min(),
+            elapsed_time: summaries.iter().map(|s| s.elapsed_time).sum(),
+            timer_time: summaries.iter().map(|s| s.timer_time).sum(),
+            stats: SummaryStats {
+                avg_heart_rate: weighted_mean(|s| s.avg_heart_rate),
+                max_heart_rate: highest(|s| s.max_heart_rate),
+                avg_power: weighted_mean(|s| s.avg_power),
+                max_power: highest(|s| s.max_power),
+                avg_speed: weighted_mean(|s| s.avg_speed),
+                max_speed: highest(|s| s.max_speed),
+                total_ascent: total(|s| s.total_ascent),
+                total_descent: total(|s| s.total_descent),
+                total_distance: total(|s| s.total_distance),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+use crate::fit_file::{test_fit_file, test_message};
+#[cfg(test)]
+use crate::fit_messages::{DataMessage, Value};
+
+#[cfg(test)]
+fn test_timer_event(timestamp: u32, event_type: EventType) -> DataMessage {
+    test_message(
+        21,
+        vec![
+            (253, "timestamp", Value::U32(timestamp)),
+            (0, "event", Value::U8(Event::Timer as u8)),
+            (1, "event_type", Value::U8(event_type as u8)),
+        ],
+    )
+}
+
+#[cfg(test)]
+fn test_record(timestamp: u32, speed: f64) -> DataMessage {
+    test_message(
+        20,
+        vec![
+            (253, "timestamp", Value::U32(timestamp)),
+            (6, "speed", Value::F64(speed)),
+        ],
+    )
+}
+
+#[test]
+fn test_moving_time() {
+    let mut messages = vec![test_timer_event(0, EventType::Start)];
+    // moving for 10 s, standing still for 5 s
+    messages.extend((0..=10).map(|t| test_record(t, 3.0)));
+    messages.extend((11..=15).map(|t| test_record(t, 0.0)));
+    // timer stopped for 30 s
+    messages.push(test_timer_event(15, EventType::StopAll));
+    messages.push(test_timer_event(45, EventType::Start));
+    messages.extend((45..=50).map(|t| test_record(t, 3.0)));
+    // 20 s gap without timer events, then moving again
+    messages.extend((70..=72).map(|t| test_record(t, 3.0)));
+    messages.push(test_timer_event(72, EventType::StopAll));
+    let analysis = ActivityAnalysis::new(&test_fit_file(messages));
+    assert_eq!(analysis.elapsed_time(), 72);
+    assert_eq!(analysis.timer_time(), 15 + 27);
+    assert_eq!(analysis.moving_time(), 10 + 5 + 2);
+    assert_eq!(
+        analysis.pauses(),
+        vec![Pause { start: 15, end: 45 }, Pause { start: 50, end: 70 }]
+    );
+}
+
+#[test]
+fn test_cross_check_reports_discrepancies() {
+    let record = |t: u32, hr: u8, altitude: f64, distance: f64| {
+        test_message(
+            20,
+            vec![
+                (253, "timestamp", Value::U32(t)),
+                (3, "heart_rate", Value::U8(hr)),
+                (2, "altitude", Value::F64(altitude)),
+                (5, "distance", Value::F64(distance)),
+            ],
+        )
+    };
+    // session: start_time 2, total_elapsed_time 7, total_distance 9,
+    // avg_heart_rate 16, max_heart_rate 17, total_ascent 22
+    let session = test_message(
+        18,
+        vec![
+            (2, "start_time", Value::U32(0)),
+            (7, "total_elapsed_time", Value::F64(3.0)),
+            (9, "total_distance", Value::F64(30.0)),
+            (16, "avg_heart_rate", Value::U8(110)),
+            (17, "max_heart_rate", Value::U8(160)),
+            (22, "total_ascent", Value::U16(10)),
+        ],
+    );
+    let fit_file = test_fit_file(vec![
+        record(0, 100, 10.0, 0.0),
+        record(1, 110, 20.0, 10.0),
+        record(2, 120, 30.0, 20.0),
+        record(3, 110, 40.0, 30.0),
+        session,
+    ]);
+    let checks = cross_check(&fit_file, 0.01);
+    assert_eq!(checks.len(), 1);
+    // smoothed altitudes 20, 25, 25 and 30
+    assert_eq!(checks[0].computed.total_ascent, Some(10.0));
+    assert_eq!(checks[0].computed.total_descent, Some(0.0));
+    assert_eq!(checks[0].computed.total_distance, Some(30.0));
+    assert_eq!(
+        checks[0].discrepancies,
+        vec![Discrepancy {
+            metric: "max_heart_rate",
+            stored: 160.0,
+            computed: 120.0
+        }]
+    );
+
+    // altitude noise is not ascent
+    let noisy = test_fit_file(
+        (0..10)
+            .map(|t| record(t, 100, 100.0 + (t % 2) as f64, 0.0))
+            .collect(),
+    );
+    let stats = SummaryStats::from_series(&noisy.record_series());
+    assert_eq!(stats.total_ascent, Some(0.0));
+    assert_eq!(stats.total_descent, Some(0.0));
+}
+
+#[test]
+fn test_activity_summary_total() {
+    let record = |t: u32, hr: u8, distance: f64| {
+        test_message(
+            20,
+            vec![
+                (253, "timestamp", Value::U32(t)),
+                (3, "heart_rate", Value::U8(hr)),
+                (5, "distance", Value::F64(distance)),
+            ],
+        )
+    };
+    let session = test_message(18, vec![(5, "sport", Value::U8(2))]);
+    let first = ActivitySummary::from_file(&test_fit_file(vec![
+        record(100, 100, 0.0),
+        record(110, 120, 50.0),
+        session.clone(),
+    ]));
+    assert_eq!(first.sport, Some(2));
+    assert_eq!(first.start_time, Some(100));
+    assert_eq!(first.elapsed_time, 10);
+    assert_eq!(first.stats.avg_heart_rate, Some(110.0));
+    assert_eq!(first.stats.total_distance, Some(50.0));
+    let second = ActivitySummary::from_file(&test_fit_file(vec![
+        record(0, 140, 0.0),
+        record(30, 140, 100.0),
+    ]));
+    let total = ActivitySummary::total(&[first, second]);
+    assert_eq!(total.sport, None);
+    assert_eq!(total.start_time, Some(0));
+    assert_eq!(total.timer_time, 40);
+    // 10 s at 110 bpm and 30 s at 140 bpm
+    assert_eq!(total.stats.avg_heart_rate, Some(132.5));
+    assert_eq!(total.stats.max_heart_rate, Some(140.0));
+    assert_eq!(total.stats.total_distance, Some(150.0));
+    assert_eq!(total.stats.avg_power, None);
+}
@@ -0,0 +1,324 @@
+//! Parallel parsing of many FIT files, for bulk imports.
+//!
+//! With the `compression` feature, gzip files and zip archives are read
+//! too, see [`crate::compression`].
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+
+#[cfg(feature = "compression")]
+use crate::compression::{self, is_fit_path};
+use crate::decoder::FitDecoder;
+use crate::error::FitError;
+use crate::fit_file::FitFile;
+use crate::profile::messages::{Activity, Session};
+use crate::profile::{ACTIVITY_MESSAGE, SESSION_MESSAGE};
+use crate::time::{civil_from_days, days_from_civil, FIT_EPOCH_UNIX_DAYS, SECONDS_PER_DAY};
+
+/// Why a single file of a batch could not be parsed.
+#[derive(Debug)]
+pub enum BatchError {
+    Io(io::Error),
+    Parse(FitError),
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "Reading file failed: {}", e),
+            Self::Parse(e) => write!(f, "Parsing file failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for BatchError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Parse(e) => Some(e),
+        }
+    }
+}
+
+/// Result of parsing one file of a batch.
+#[derive(Debug)]
+pub struct FileResult {
+    pub path: PathBuf,
+    pub result: Result<FitFile, BatchError>,
+}
+
+#[cfg(not(feature = "compression"))]
+fn read_path(path: PathBuf) -> Vec<(PathBuf, io::Result<Vec<u8>>)> {
+    let data = fs::read(&path);
+    vec![(path, data)]
+}
+
+// The FIT files of a zip archive get the path of the archive joined with
+// their path within it.
+#[cfg(feature = "compression")]
+fn read_path(path: PathBuf) -> Vec<(PathBuf, io::Result<Vec<u8>>)> {
+    match compression::read_fit_files(&path) {
+        Ok(files) => files
+            .into_iter()
+            .map(|file| {
+                let path = file
+                    .name
+                    .map_or_else(|| path.clone(), |name| path.join(name));
+                (path, Ok(file.data))
+            })
+            .collect(),
+        Err(e) => vec![(path, Err(e))],
+    }
+}
+
+fn parse_path(path: PathBuf, decoder: &FitDecoder) -> Vec<FileResult> {
+    read_path(path)
+        .into_iter()
+        .map(|(path, data)| FileResult {
+            path,
+            result: data
+                .map_err(BatchError::Io)
+                .and_then(|data| decoder.decode(&data).map_err(BatchError::Parse)),
+        })
+        .collect()
+}
+
+#[cfg(not(feature = "compression"))]
+fn is_fit_path(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("fit"))
+}
+
+/// Parses the given files in parallel. Results are in the order of `paths`,
+/// with a result for each FIT file of a zip archive.
+pub fn parse_files(paths: Vec<PathBuf>) -> Vec<FileResult> {
+    parse_files_with(paths, &FitDecoder::new())
+}
+
+/// Parses the given files in parallel like [`parse_files`], with one
+/// decoder shared by all threads.
+pub fn parse_files_with(paths: Vec<PathBuf>, decoder: &FitDecoder) -> Vec<FileResult> {
+    paths
+        .into_par_iter()
+        .flat_map_iter(|path| parse_path(path, decoder))
+        .collect()
+}
+
+/// Parses the `.fit` files directly in `dir` in parallel, sorted by path,
+/// and the `.fit.gz` and `.zip` files with the `compression` feature.
+/// Fails only if the directory can not be listed, errors of single files are
+/// reported in their results.
+pub fn parse_dir<P: AsRef<Path>>(dir: P) -> io::Result<Vec<FileResult>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if is_fit_path(&path) && path.is_file() {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(parse_files(paths))
+}
+
+/// Totals of sessions.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Totals {
+    pub sessions: usize,
+    /// Meters.
+    pub distance: f64,
+    /// Timer time in seconds.
+    pub duration: f64,
+    /// Meters.
+    pub ascent: f64,
+    pub training_stress_score: f64,
+}
+
+impl Totals {
+    fn add(&mut self, session: &Session) {
+        self.sessions += 1;
+        self.distance += session.total_distance.unwrap_or_default();
+        self.duration += session.total_timer_time.unwrap_or_default();
+        self.ascent += session.total_ascent.unwrap_or_default() as f64;
+        self.training_stress_score += session.training_stress_score.unwrap_or_default();
+    }
+}
+
+/// Totals of the sessions starting in a week or a month, overall and by
+/// `sport` value.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PeriodTotals {
+    /// Days since the FIT epoch of the first day of the period, the Monday
+    /// of a week or the first of a month.
+    pub first_day: i64,
+    pub totals: Totals,
+    pub by_sport: BTreeMap<u8, Totals>,
+}
+
+/// Weekly and monthly totals of the sessions of many files.
+#[derive(Debug, Default)]
+pub struct Aggregate {
+    /// Weeks with sessions in time order.
+    pub weekly: Vec<PeriodTotals>,
+    /// Months with sessions in time order.
+    pub monthly: Vec<PeriodTotals>,
+    /// Files that could not be read or parsed.
+    pub errors: Vec<(PathBuf, BatchError)>,
+}
+
+// Week and month of a FIT epoch day as their first days.
+fn period_first_days(day: i64) -> (i64, i64) {
+    // the FIT epoch is a Sunday
+    let monday = day - (day + 6).rem_euclid(7);
+    let (year, month, _) = civil_from_days(day + FIT_EPOCH_UNIX_DAYS);
+    (
+        monday,
+        days_from_civil(year, month, 1) - FIT_EPOCH_UNIX_DAYS,
+    )
+}
+
+fn add_session(periods: &mut BTreeMap<i64, PeriodTotals>, first_day: i64, session: &Session) {
+    let period = periods.entry(first_day).or_insert_with(|| PeriodTotals {
+        first_day,
+        ..PeriodTotals::default()
+    });
+    period.totals.add(session);
+    if let Some(sport) = session.sport {
+        period.by_sport.entry(sport).or_default().add(session);
+    }
+}
+
+/// Weekly and monthly totals of the sessions of the files, read in parallel
+/// with [`FitFile::trailing_summary`] without decoding the records. Sessions
+/// count in the period of their start time, in the local time of the file
+/// when its activity message has a local timestamp and in UTC otherwise.
+pub fn aggregate(paths: Vec<PathBuf>) -> Aggregate {
+    let summaries = paths
+        .into_par_iter()
+        .flat_map_iter(read_path)
+        .map(|(path, data)| {
+            let summary = data
+                .map_err(BatchError::Io)
+                .and_then(|data| FitFile::trailing_summary(&data).map_err(BatchError::Parse));
+            (path, summary)
+        })
+        .collect::<Vec<_>>();
+    let mut weekly = BTreeMap::new();
+    let mut monthly = BTreeMap::new();
+    let mut errors = Vec::new();
+    for (path, summary) in summaries {
+        let summary = match summary {
+            Ok(summary) => summary,
+            Err(e) => {
+                errors.push((path, e));
+                continue;
+            }
+        };
+        let utc_offset = summary
+            .iter()
+            .filter(|m| m.global_message_number == ACTIVITY_MESSAGE.number)
+            .map(Activity::from_message)
+            .find_map(|a| Some(a.local_timestamp? as i64 - a.timestamp? as i64))
+            .unwrap_or(0);
+        let sessions = summary
+            .iter()
+            .filter(|m| m.global_message_number == SESSION_MESSAGE.number)
+            .map(Session::from_message);
+        for session in sessions {
+            let start = match session.start_time.or(session.timestamp) {
+                Some(start) => start as i64 + utc_offset,
+                None => continue,
+            };
+            let (week, month) = period_first_days(start.div_euclid(SECONDS_PER_DAY));
+            add_session(&mut weekly, week, &session);
+            add_session(&mut monthly, month, &session);
+        }
+    }
+    Aggregate {
+        weekly: weekly.into_values().collect(),
+        monthly: monthly.into_values().collect(),
+        errors,
+    }
+}
+
+#[cfg(test)]
+use crate::fit_file::test_file;
+
+#[test]
+fn test_parse_dir() {
+    let dir = std::env::temp_dir().join(format!("fit_parser_batch_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let valid = test_file(&[]);
+    fs::write(dir.join("a.fit"), &valid).unwrap();
+    fs::write(dir.join("b.FIT"), [4, 0, 0]).unwrap();
+    fs::write(dir.join("c.txt"), &valid).unwrap();
+    let results = parse_dir(&dir).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].path, dir.join("a.fit"));
+    assert!(results[0].result.is_ok());
+    assert!(matches!(
+        results[1].result,
+        Err(BatchError::Parse(FitError::InvalidHeaderSize {
+            header_size: 4
+        }))
+    ));
+}
+
+#[cfg(test)]
+use crate::encode::encode;
+
+#[test]
+fn test_aggregate() {
+    // 2024-01-01, a Monday, is day 12419 of the FIT epoch
+    let day = 12419 * SECONDS_PER_DAY as u32;
+    let session = |start: u32, sport: u8, distance: f64| Session {
+        start_time: Some(start),
+        sport: Some(sport),
+        total_distance: Some(distance),
+        total_timer_time: Some(3600.0),
+        total_ascent: Some(100),
+        ..Session::default()
+    };
+    let dir = std::env::temp_dir().join(format!("fit_parser_aggregate_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let files = [
+        // Sunday 2023-12-31 and Monday 2024-01-01
+        vec![
+            session(day - 3600, 1, 10000.0),
+            session(day + 3600, 2, 30000.0),
+        ],
+        vec![session(day + 2 * 86400, 1, 5000.0)],
+    ];
+    let mut paths = Vec::new();
+    for (i, sessions) in files.iter().enumerate() {
+        let messages = sessions.iter().map(Session::to_message).collect::<Vec<_>>();
+        let path = dir.join(format!("{}.fit", i));
+        fs::write(&path, encode(&messages).unwrap()).unwrap();
+        paths.push(path);
+    }
+    paths.push(dir.join("missing.fit"));
+    let aggregate = aggregate(paths);
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(aggregate.errors.len(), 1);
+    assert_eq!(
+        aggregate
+            .weekly
+            .iter()
+            .map(|p| (p.first_day, p.totals.sessions))
+            .collect::<Vec<_>>(),
+        vec![(12419 - 7, 1), (12419, 2)]
+    );
+    let january = &aggregate.monthly[1];
+    assert_eq!(january.first_day, 12419);
+    assert_eq!(january.totals.distance, 35000.0);
+    assert_eq!(january.totals.duration, 7200.0);
+    assert_eq!(january.by_sport[&1].distance, 5000.0);
+    assert_eq!(january.by_sport[&2].ascent, 100.0);
+    assert_eq!(aggregate.monthly[0].first_day, 12419 - 31);
+}
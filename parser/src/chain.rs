@@ -0,0 +1,109 @@
+//! Splitting chained FIT files, several files written one after another like
+//! devices do for long recordings, into their files, and joining files into
+//! one chained file.
+
+use crate::crc::FitCrc;
+use crate::error::FitError;
+use crate::fit_header::try_parse_fit_header_from_data;
+
+// Header size of headers with a header CRC.
+const HEADER_SIZE_WITH_CRC: usize = 14;
+
+// Length of the file starting at `start` of `fit_data`: header, data and
+// the file CRC.
+fn file_len(fit_data: &[u8], start: usize) -> Result<usize, FitError> {
+    let header = try_parse_fit_header_from_data(&fit_data[start..]).map_err(|e| match e {
+        FitError::UnexpectedEndOfData { offset } => FitError::UnexpectedEndOfData {
+            offset: start + offset,
+        },
+        e => e,
+    })?;
+    let len = header.header_size() as usize + header.data_size() as usize + 2;
+    if start + len > fit_data.len() {
+        return Err(FitError::UnexpectedEndOfData {
+            offset: fit_data.len(),
+        });
+    }
+    Ok(len)
+}
+
+/// The files of a chained file, each with its header and file CRC, in file
+/// order. A file that is not chained is its only file.
+pub fn split_chained(fit_data: &[u8]) -> Result<Vec<&[u8]>, FitError> {
+    let mut files = Vec::new();
+    let mut start = 0;
+    while start < fit_data.len() {
+        let len = file_len(fit_data, start)?;
+        files.push(&fit_data[start..start + len]);
+        start += len;
+    }
+    Ok(files)
+}
+
+/// Joins files into one chained file. Bytes after the file CRC of a file
+/// are dropped, and the header CRC and file CRC of each file are recomputed,
+/// so files without CRCs can be joined too.
+pub fn join_chained<'a, I>(files: I) -> Result<Vec<u8>, FitError>
+where
+    I: IntoIterator<Item = &'a [u8]>,
+{
+    let mut chained = Vec::new();
+    for file in files {
+        let start = chained.len();
+        let len = file_len(file, 0)?;
+        chained.extend_from_slice(&file[..len]);
+        let segment = &mut chained[start..];
+        if segment[0] as usize >= HEADER_SIZE_WITH_CRC {
+            let header_crc = FitCrc::checksum(&segment[..12], 0);
+            segment[12..14].copy_from_slice(&header_crc.to_le_bytes());
+        }
+        let crc = FitCrc::checksum(&segment[..len - 2], 0);
+        segment[len - 2..].copy_from_slice(&crc.to_le_bytes());
+    }
+    Ok(chained)
+}
+
+#[cfg(test)]
+use crate::decoder::{CrcPolicy, FitDecoder};
+#[cfg(test)]
+use crate::encode::encode;
+#[cfg(test)]
+use crate::fit_file::test_file;
+#[cfg(test)]
+use crate::profile::messages::Record;
+
+#[test]
+fn test_split_and_join_chained() {
+    let first = test_file(&[
+        0x40, 0x00, 0x00, 0x14, 0x00, 0x01, // record, 1 field
+        0x03, 0x01, 0x02, // heart_rate
+        0x00, 0x96, // heart_rate 150
+    ]);
+    let mut second = encode(&[Record {
+        heart_rate: Some(151),
+        ..Record::default()
+    }
+    .to_message()])
+    .unwrap();
+    second.extend_from_slice(&[0xFF; 3]);
+    let chained = join_chained([first.as_slice(), second.as_slice()]).unwrap();
+    assert_eq!(chained.len(), first.len() + second.len() - 3);
+
+    let files = split_chained(&chained).unwrap();
+    assert_eq!(files.len(), 2);
+    // the file CRC missing from the first file is computed
+    assert_eq!(files[0][..first.len() - 2], first[..first.len() - 2]);
+    assert_eq!(files[1], &second[..second.len() - 3]);
+    let decoder = FitDecoder::builder().crc_policy(CrcPolicy::Enforce).build();
+    for file in &files {
+        assert_eq!(decoder.decode(file).unwrap().messages.len(), 1);
+    }
+    assert_eq!(decoder.decode_chained(&chained).unwrap().len(), 2);
+
+    assert_eq!(
+        split_chained(&chained[..chained.len() - 1]),
+        Err(FitError::UnexpectedEndOfData {
+            offset: chained.len() - 1
+        })
+    );
+}
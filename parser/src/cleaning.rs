@@ -0,0 +1,213 @@
+//! Detection and removal of device glitches in record series.
+
+use crate::geo::haversine_distance;
+use crate::series::RecordSeries;
+
+/// Thresholds above which values are taken to be glitches.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CleaningOptions {
+    /// Meters per second, for both the speed column and the speed implied by
+    /// consecutive positions.
+    pub max_speed: f64,
+    /// Beats per minute.
+    pub min_heart_rate: f64,
+    pub max_heart_rate: f64,
+    /// Beats per minute per second between consecutive heart rates.
+    pub max_heart_rate_change: f64,
+    /// Whether removed values are replaced by linear interpolation between
+    /// the surrounding values. Otherwise they are set to NaN.
+    pub interpolate: bool,
+}
+
+impl Default for CleaningOptions {
+    fn default() -> Self {
+        CleaningOptions {
+            max_speed: 50.0,
+            min_heart_rate: 25.0,
+            max_heart_rate: 250.0,
+            max_heart_rate_change: 30.0,
+            interpolate: false,
+        }
+    }
+}
+
+/// Column of a detected glitch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GlitchColumn {
+    Position,
+    Speed,
+    HeartRate,
+}
+
+/// A value taken to be a glitch, by sample index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Glitch {
+    pub index: usize,
+    pub column: GlitchColumn,
+}
+
+// Seconds between two samples, at least one so that samples with the same or
+// no timestamps do not divide by zero.
+fn seconds_between(timestamps: &[Option<u32>], from: usize, to: usize) -> f64 {
+    match (timestamps[from], timestamps[to]) {
+        (Some(from), Some(to)) => (to.saturating_sub(from)).max(1) as f64,
+        _ => 1.0,
+    }
+}
+
+// Replaces the values at `indices` by linear interpolation over time between
+// the nearest values that are not NaN. Values without a neighbor on both
+// sides stay NaN.
+fn interpolate(timestamps: &[Option<u32>], column: &mut [f64], indices: &[usize]) {
+    for idx in indices {
+        let before = (0..*idx).rev().find(|i| !column[*i].is_nan());
+        let after = (idx + 1..column.len()).find(|i| !column[*i].is_nan());
+        if let (Some(before), Some(after)) = (before, after) {
+            let fraction = seconds_between(timestamps, before, *idx)
+                / seconds_between(timestamps, before, after);
+            column[*idx] = column[before] + (column[after] - column[before]) * fraction.min(1.0);
+        }
+    }
+}
+
+impl RecordSeries {
+    /// Removes positions implying impossible speeds, speeds above the maximum
+    /// and heart rates out of range or changing impossibly fast, returning
+    /// the removed values. Each value is compared with the latest value that
+    /// was kept, so a glitch does not also flag the value after it.
+    pub fn clean(&mut self, options: &CleaningOptions) -> Vec<Glitch> {
+        let mut glitches = Vec::new();
+        let mut last_position: Option<usize> = None;
+        let mut last_heart_rate: Option<usize> = None;
+        for idx in 0..self.len() {
+            let (lat, long) = (self.position_lat[idx], self.position_long[idx]);
+            if !lat.is_nan() && !long.is_nan() {
+                let jump = last_position.is_some_and(|last| {
+                    let distance = haversine_distance(
+                        self.position_lat[last],
+                        self.position_long[last],
+                        lat,
+                        long,
+                    );
+                    distance / seconds_between(&self.timestamp, last, idx) > options.max_speed
+                });
+                if jump {
+                    glitches.push(Glitch {
+                        index: idx,
+                        column: GlitchColumn::Position,
+                    });
+                } else {
+                    last_position = Some(idx);
+                }
+            }
+            if self.speed[idx] > options.max_speed {
+                glitches.push(Glitch {
+                    index: idx,
+                    column: GlitchColumn::Speed,
+                });
+            }
+            let heart_rate = self.heart_rate[idx];
+            if !heart_rate.is_nan() {
+                let out_of_range =
+                    heart_rate < options.min_heart_rate || heart_rate > options.max_heart_rate;
+                let jump = last_heart_rate.is_some_and(|last| {
+                    (heart_rate - self.heart_rate[last]).abs()
+                        / seconds_between(&self.timestamp, last, idx)
+                        > options.max_heart_rate_change
+                });
+                if out_of_range || jump {
+                    glitches.push(Glitch {
+                        index: idx,
+                        column: GlitchColumn::HeartRate,
+                    });
+                } else {
+                    last_heart_rate = Some(idx);
+                }
+            }
+        }
+        let indices = |column| {
+            glitches
+                .iter()
+                .filter(|g| g.column == column)
+                .map(|g| g.index)
+                .collect::<Vec<usize>>()
+        };
+        let (positions, speeds, heart_rates) = (
+            indices(GlitchColumn::Position),
+            indices(GlitchColumn::Speed),
+            indices(GlitchColumn::HeartRate),
+        );
+        for idx in &positions {
+            self.position_lat[*idx] = f64::NAN;
+            self.position_long[*idx] = f64::NAN;
+        }
+        for idx in &speeds {
+            self.speed[*idx] = f64::NAN;
+        }
+        for idx in &heart_rates {
+            self.heart_rate[*idx] = f64::NAN;
+        }
+        if options.interpolate {
+            interpolate(&self.timestamp, &mut self.position_lat, &positions);
+            interpolate(&self.timestamp, &mut self.position_long, &positions);
+            interpolate(&self.timestamp, &mut self.speed, &speeds);
+            interpolate(&self.timestamp, &mut self.heart_rate, &heart_rates);
+        }
+        glitches
+    }
+}
+
+#[cfg(test)]
+use crate::series::test_series;
+
+// A series with a heart rate, a position and a speed glitch.
+#[cfg(test)]
+fn glitched_series() -> RecordSeries {
+    RecordSeries {
+        position_lat: vec![60.0, 60.0001, 61.0, 60.0003],
+        position_long: vec![24.0; 4],
+        heart_rate: vec![120.0, 240.0, 124.0, 126.0],
+        speed: vec![3.0, 3.0, 300.0, 3.0],
+        ..test_series(&[0, 1, 2, 3])
+    }
+}
+
+#[test]
+fn test_clean_removes_glitches() {
+    let mut series = glitched_series();
+    let glitches = series.clean(&CleaningOptions::default());
+    assert_eq!(
+        glitches,
+        vec![
+            Glitch {
+                index: 1,
+                column: GlitchColumn::HeartRate
+            },
+            Glitch {
+                index: 2,
+                column: GlitchColumn::Position
+            },
+            Glitch {
+                index: 2,
+                column: GlitchColumn::Speed
+            },
+        ]
+    );
+    assert!(series.position_lat[2].is_nan());
+    assert!(series.speed[2].is_nan());
+    assert!(series.heart_rate[1].is_nan());
+    assert_eq!(series.heart_rate[2], 124.0);
+}
+
+#[test]
+fn test_clean_interpolates() {
+    let mut series = glitched_series();
+    series.clean(&CleaningOptions {
+        interpolate: true,
+        ..CleaningOptions::default()
+    });
+    assert_eq!(series.heart_rate[1], 122.0);
+    assert_eq!(series.speed[2], 3.0);
+    assert!((series.position_lat[2] - 60.0002).abs() < 1e-9);
+    assert_eq!(series.position_long[2], 24.0);
+}
@@ -0,0 +1,245 @@
+//! Reading FIT files from gzip files and zip archives, such as the `.fit.gz`
+//! files and zipped archives of Garmin Connect bulk exports.
+//!
+//! Gzip files are read with `flate2` and zip archives, zip64 archives of
+//! large exports included, with `zip`. Only the `.fit` entries of archives
+//! are decompressed, and decompressed sizes are capped per file and in total
+//! to guard against compression bombs.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, Cursor, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use flate2::read::GzDecoder;
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+// Decompressed files larger than this are rejected. Far larger than any
+// activity file.
+const MAX_DECOMPRESSED_SIZE: u64 = 1 << 30;
+// Decompressed files of one gzip file or zip archive together larger than
+// this are rejected.
+const MAX_TOTAL_DECOMPRESSED_SIZE: u64 = 4 << 30;
+
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+const ZIP_LOCAL_HEADER_MAGIC: [u8; 4] = [0x50, 0x4B, 0x03, 0x04];
+// Empty archives start with the end of central directory record.
+const ZIP_END_OF_CENTRAL_DIRECTORY_MAGIC: [u8; 4] = [0x50, 0x4B, 0x05, 0x06];
+
+/// Why compressed data could not be read.
+#[derive(Debug)]
+pub enum DecompressError {
+    /// Reading or decompressing the data failed, such as for malformed or
+    /// truncated data or data not matching its CRC-32.
+    Io(io::Error),
+    /// The zip archive is malformed or uses an unsupported compression
+    /// method.
+    Zip(ZipError),
+    /// A decompressed file, or the files together, are larger than the
+    /// supported size.
+    TooLarge,
+}
+
+impl fmt::Display for DecompressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "Decompressing failed: {}", e),
+            Self::Zip(e) => write!(f, "Invalid zip archive: {}", e),
+            Self::TooLarge => write!(f, "Decompressed data is too large"),
+        }
+    }
+}
+
+impl std::error::Error for DecompressError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Zip(e) => Some(e),
+            Self::TooLarge => None,
+        }
+    }
+}
+
+impl From<io::Error> for DecompressError {
+    fn from(e: io::Error) -> Self {
+        DecompressError::Io(e)
+    }
+}
+
+impl From<ZipError> for DecompressError {
+    fn from(e: ZipError) -> Self {
+        DecompressError::Zip(e)
+    }
+}
+
+// Reads `reader` to the end, failing when the data is larger than a file
+// may be or than the `budget` of decompressed bytes left, which is reduced
+// by the size of the data.
+fn read_limited<R: Read>(reader: R, budget: &mut u64) -> Result<Vec<u8>, DecompressError> {
+    let limit = MAX_DECOMPRESSED_SIZE.min(*budget);
+    let mut data = Vec::new();
+    reader.take(limit + 1).read_to_end(&mut data)?;
+    if data.len() as u64 > limit {
+        return Err(DecompressError::TooLarge);
+    }
+    *budget -= data.len() as u64;
+    Ok(data)
+}
+
+/// Decompresses a gzip file. Only the first member of multi-member files is
+/// read.
+pub fn gunzip(data: &[u8]) -> Result<Vec<u8>, DecompressError> {
+    let mut budget = MAX_TOTAL_DECOMPRESSED_SIZE;
+    read_limited(GzDecoder::new(data), &mut budget)
+}
+
+/// A FIT file read from plain, gzip or zip data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtractedFile {
+    /// Path of the file within a zip archive, `None` for other data.
+    pub name: Option<String>,
+    pub data: Vec<u8>,
+}
+
+fn is_fit_name(name: &str) -> bool {
+    name.to_ascii_lowercase().ends_with(".fit")
+}
+
+// The `.fit` files of a zip archive in the order of its central directory.
+// Other files are not decompressed.
+fn unzip_fit_files<R: Read + Seek>(
+    reader: R,
+    budget: &mut u64,
+) -> Result<Vec<ExtractedFile>, DecompressError> {
+    let mut archive = ZipArchive::new(reader)?;
+    let mut files = Vec::new();
+    for index in 0..archive.len() {
+        let name = match archive.name_for_index(index) {
+            Some(name) => name?.into_owned(),
+            None => continue,
+        };
+        if !is_fit_name(&name) {
+            continue;
+        }
+        let data = read_limited(archive.by_index(index)?, budget)?;
+        files.push(ExtractedFile {
+            name: Some(name),
+            data,
+        });
+    }
+    Ok(files)
+}
+
+// The FIT files of the data of `reader`, see [`extract_fit_files`], at most
+// `budget` bytes of them decompressed.
+fn extract<R: Read + Seek>(
+    mut reader: R,
+    mut budget: u64,
+) -> Result<Vec<ExtractedFile>, DecompressError> {
+    let mut magic = Vec::with_capacity(4);
+    reader.by_ref().take(4).read_to_end(&mut magic)?;
+    reader.seek(SeekFrom::Start(0))?;
+    if magic.starts_with(&GZIP_MAGIC) {
+        Ok(vec![ExtractedFile {
+            name: None,
+            data: read_limited(GzDecoder::new(reader), &mut budget)?,
+        }])
+    } else if magic == ZIP_LOCAL_HEADER_MAGIC || magic == ZIP_END_OF_CENTRAL_DIRECTORY_MAGIC {
+        unzip_fit_files(reader, &mut budget)
+    } else {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        Ok(vec![ExtractedFile { name: None, data }])
+    }
+}
+
+/// The FIT files in `data`: the data itself, the decompressed data of a
+/// gzip file, or the `.fit` files of a zip archive, recognized by their
+/// magic bytes.
+pub fn extract_fit_files(data: &[u8]) -> Result<Vec<ExtractedFile>, DecompressError> {
+    extract(Cursor::new(data), MAX_TOTAL_DECOMPRESSED_SIZE)
+}
+
+/// Reads the FIT files of a plain, gzip or zip file, see
+/// [`extract_fit_files`]. Zip archives are read from the file rather than
+/// loaded whole. Decompression errors are returned as
+/// [`io::ErrorKind::InvalidData`] errors.
+pub fn read_fit_files<P: AsRef<Path>>(path: P) -> io::Result<Vec<ExtractedFile>> {
+    let file = BufReader::new(File::open(path)?);
+    extract(file, MAX_TOTAL_DECOMPRESSED_SIZE).map_err(|e| match e {
+        DecompressError::Io(e) => e,
+        e => io::Error::new(io::ErrorKind::InvalidData, e),
+    })
+}
+
+/// Whether a path names a file [`read_fit_files`] reads FIT files from:
+/// `.fit`, `.fit.gz` and `.zip` files.
+pub fn is_fit_path(path: &Path) -> bool {
+    let name = match path.file_name() {
+        Some(name) => name.to_string_lossy().to_ascii_lowercase(),
+        None => return false,
+    };
+    name.ends_with(".fit") || name.ends_with(".fit.gz") || name.ends_with(".zip")
+}
+
+#[cfg(test)]
+use crate::fit_file::test_file;
+
+#[test]
+fn test_extract_gzip() {
+    let gzip = [
+        0x1F, 0x8B, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xE3, 0x11, 0xC8, 0xE7, 0x60,
+        0x00, 0x02, 0x3D, 0x37, 0xCF, 0x10, 0x06, 0x06, 0x00, 0x78, 0x04, 0xF0, 0xC8, 0x0E, 0x00,
+        0x00, 0x00,
+    ];
+    let files = extract_fit_files(&gzip).unwrap();
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].name, None);
+    assert_eq!(files[0].data, test_file(&[]));
+    let mut corrupted = gzip.to_vec();
+    corrupted[24] ^= 1;
+    assert!(matches!(gunzip(&corrupted), Err(DecompressError::Io(_))));
+    assert!(matches!(
+        extract(Cursor::new(&gzip), 10),
+        Err(DecompressError::TooLarge)
+    ));
+    assert_eq!(
+        extract_fit_files(&test_file(&[])).unwrap()[0].data,
+        test_file(&[])
+    );
+}
+
+#[test]
+fn test_extract_zip() {
+    // a.fit compressed and readme.txt stored
+    let zip = [
+        0x50, 0x4B, 0x03, 0x04, 0x14, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x21, 0x00, 0x78,
+        0x04, 0xF0, 0xC8, 0x0E, 0x00, 0x00, 0x00, 0x0E, 0x00, 0x00, 0x00, 0x05, 0x00, 0x00, 0x00,
+        0x61, 0x2E, 0x66, 0x69, 0x74, 0xE3, 0x11, 0xC8, 0xE7, 0x60, 0x00, 0x02, 0x3D, 0x37, 0xCF,
+        0x10, 0x06, 0x06, 0x00, 0x50, 0x4B, 0x03, 0x04, 0x14, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x21, 0x00, 0xAC, 0x2A, 0x93, 0xD8, 0x02, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00,
+        0x0A, 0x00, 0x00, 0x00, 0x72, 0x65, 0x61, 0x64, 0x6D, 0x65, 0x2E, 0x74, 0x78, 0x74, 0x68,
+        0x69, 0x50, 0x4B, 0x01, 0x02, 0x14, 0x03, 0x14, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00,
+        0x21, 0x00, 0x78, 0x04, 0xF0, 0xC8, 0x0E, 0x00, 0x00, 0x00, 0x0E, 0x00, 0x00, 0x00, 0x05,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80, 0x01, 0x00, 0x00,
+        0x00, 0x00, 0x61, 0x2E, 0x66, 0x69, 0x74, 0x50, 0x4B, 0x01, 0x02, 0x14, 0x03, 0x14, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x21, 0x00, 0xAC, 0x2A, 0x93, 0xD8, 0x02, 0x00, 0x00,
+        0x00, 0x02, 0x00, 0x00, 0x00, 0x0A, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x80, 0x01, 0x31, 0x00, 0x00, 0x00, 0x72, 0x65, 0x61, 0x64, 0x6D, 0x65, 0x2E,
+        0x74, 0x78, 0x74, 0x50, 0x4B, 0x05, 0x06, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x02, 0x00,
+        0x6B, 0x00, 0x00, 0x00, 0x5B, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+    let files = extract_fit_files(&zip).unwrap();
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].name.as_deref(), Some("a.fit"));
+    assert_eq!(files[0].data, test_file(&[]));
+    // only a.fit counts against the total size
+    assert_eq!(extract(Cursor::new(&zip), 14).unwrap().len(), 1);
+    assert!(matches!(
+        extract(Cursor::new(&zip), 13),
+        Err(DecompressError::TooLarge)
+    ));
+    assert!(is_fit_path(Path::new("export/activity.FIT.gz")));
+    assert!(!is_fit_path(Path::new("export/activity.gz")));
+}
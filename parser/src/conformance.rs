@@ -0,0 +1,378 @@
+//! Checking the decoded messages of a file against the profile: enum values
+//! must be values of their type, arrays must have the declared number of
+//! values, the file must start with a `file_id` message and have only
+//! messages belonging to its file type.
+//!
+//! Messages not in the profile, such as manufacturer specific messages, are
+//! not checked.
+
+use std::fmt;
+
+use crate::diagnostics::Severity;
+use crate::file_type::FileType;
+use crate::fit_file::FitFile;
+use crate::fit_messages::{DataMessage, FieldValue, Value};
+use crate::profile::{message_info, type_info, FieldArray, FILE_ID_MESSAGE};
+
+// Messages that can be in files of any type.
+const COMMON_MESSAGES: [&str; 7] = [
+    "file_id",
+    "file_creator",
+    "software",
+    "slave_device",
+    "device_info",
+    "developer_data_id",
+    "field_description",
+];
+
+// Messages of the other file types that are out of place in activity files,
+// which can have any other message.
+const NOT_IN_ACTIVITY: [&str; 6] = [
+    "course",
+    "course_point",
+    "goal",
+    "schedule",
+    "segment_id",
+    "segment_point",
+];
+
+// Messages besides the common ones that files of a type can have, `None` for
+// types that are not checked.
+fn file_type_messages(file_type: FileType) -> Option<&'static [&'static str]> {
+    match file_type {
+        FileType::Course => Some(&["course", "lap", "record", "event", "course_point"]),
+        FileType::Workout => Some(&[
+            "workout",
+            "workout_session",
+            "workout_step",
+            "exercise_title",
+        ]),
+        FileType::Settings => Some(&[
+            "user_profile",
+            "hrm_profile",
+            "sdm_profile",
+            "bike_profile",
+            "device_settings",
+            "connectivity",
+            "watchface_settings",
+            "ohr_settings",
+        ]),
+        FileType::Sport => Some(&[
+            "zones_target",
+            "sport",
+            "hr_zone",
+            "power_zone",
+            "met_zone",
+            "speed_zone",
+            "cadence_zone",
+        ]),
+        FileType::Goals => Some(&["goal"]),
+        FileType::Schedules => Some(&["schedule"]),
+        FileType::MonitoringA | FileType::MonitoringB | FileType::MonitoringDaily => Some(&[
+            "monitoring_info",
+            "monitoring",
+            "hr",
+            "stress_level",
+            "event",
+        ]),
+        FileType::Segment => Some(&["segment_id", "segment_leaderboard_entry", "segment_point"]),
+        FileType::Activity | FileType::Other(_) => None,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConformanceIssueKind {
+    /// The file has no `file_id` message.
+    MissingFileId,
+    /// Messages come before the `file_id` message.
+    FileIdNotFirst,
+    /// Value of an enum typed field is not a value of the enum.
+    UnknownEnumValue {
+        global_message_number: u16,
+        field_definition_number: u8,
+        value: u32,
+    },
+    /// Number of values of a field differs from the number declared in the
+    /// profile, one for fields that are not arrays.
+    ArrayLengthMismatch {
+        global_message_number: u16,
+        field_definition_number: u8,
+        expected: usize,
+        actual: usize,
+    },
+    /// The message does not belong in files of the file's type.
+    UnexpectedMessage {
+        global_message_number: u16,
+        file_type: FileType,
+    },
+}
+
+impl ConformanceIssueKind {
+    /// Code of the kind, such as `FIT1001`, stable like the codes of
+    /// [`crate::diagnostics::DiagnosticKind::code`].
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::MissingFileId => "FIT1001",
+            Self::FileIdNotFirst => "FIT1002",
+            Self::UnknownEnumValue { .. } => "FIT1003",
+            Self::ArrayLengthMismatch { .. } => "FIT1004",
+            Self::UnexpectedMessage { .. } => "FIT1005",
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        match self {
+            Self::MissingFileId => Severity::Error,
+            Self::UnknownEnumValue { .. } => Severity::Info,
+            Self::FileIdNotFirst
+            | Self::ArrayLengthMismatch { .. }
+            | Self::UnexpectedMessage { .. } => Severity::Warning,
+        }
+    }
+}
+
+impl fmt::Display for ConformanceIssueKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingFileId => write!(f, "The file has no file_id message"),
+            Self::FileIdNotFirst => write!(f, "The file_id message is not the first message"),
+            Self::UnknownEnumValue {
+                global_message_number,
+                field_definition_number,
+                value,
+            } => write!(
+                f,
+                "Value {} of field {} of message {} is not a value of its enum",
+                value, field_definition_number, global_message_number
+            ),
+            Self::ArrayLengthMismatch {
+                global_message_number,
+                field_definition_number,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Field {} of message {} has {} values instead of {}",
+                field_definition_number, global_message_number, actual, expected
+            ),
+            Self::UnexpectedMessage {
+                global_message_number,
+                file_type,
+            } => write!(
+                f,
+                "Message {} does not belong in {} files",
+                global_message_number,
+                file_type.name().unwrap_or("these")
+            ),
+        }
+    }
+}
+
+/// A conformance issue with the byte offset of the record of the message it
+/// was found in, `None` for issues of the whole file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConformanceIssue {
+    pub offset: Option<usize>,
+    pub kind: ConformanceIssueKind,
+}
+
+impl fmt::Display for ConformanceIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.offset {
+            Some(offset) => write!(f, "{} (offset {:#x})", self.kind, offset),
+            None => write!(f, "{}", self.kind),
+        }
+    }
+}
+
+/// The conformance issues of a file in file order, file level issues first.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConformanceReport {
+    pub issues: Vec<ConformanceIssue>,
+}
+
+impl ConformanceReport {
+    pub fn is_conformant(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Issues of at least the severity.
+    pub fn at_least(&self, severity: Severity) -> impl Iterator<Item = &ConformanceIssue> {
+        self.issues
+            .iter()
+            .filter(move |i| i.kind.severity() >= severity)
+    }
+}
+
+// Number of values of a decoded field.
+fn value_count(value: &Value) -> usize {
+    match value {
+        Value::Array(values) => values.len(),
+        Value::Bytes(bytes) => bytes.len(),
+        _ => 1,
+    }
+}
+
+fn check_field(message: &DataMessage, field: &FieldValue, issues: &mut Vec<ConformanceIssueKind>) {
+    let info = match message_info(message.global_message_number)
+        .and_then(|m| m.field(field.field_definition_number))
+    {
+        Some(info) => info,
+        None => return,
+    };
+    // fields expanded from components have the names of their target fields
+    if field
+        .name
+        .is_some_and(|name| name != info.name && info.subfield(name).is_none())
+    {
+        return;
+    }
+    let field_type = field
+        .name
+        .and_then(|name| info.subfield(name))
+        .map_or(info.field_type, |s| s.field_type);
+    let expected = match info.array {
+        FieldArray::NotArray => Some(1),
+        FieldArray::Fixed(len) => Some(len),
+        FieldArray::Variable => None,
+    };
+    let actual = value_count(&field.value);
+    if let Some(expected) = expected.filter(|expected| *expected != actual) {
+        issues.push(ConformanceIssueKind::ArrayLengthMismatch {
+            global_message_number: message.global_message_number,
+            field_definition_number: field.field_definition_number,
+            expected,
+            actual,
+        });
+    }
+    let enum_type = match type_info(field_type) {
+        Some(t) if t.base_type == "enum" => t,
+        _ => return,
+    };
+    let values = match &field.value {
+        Value::Array(values) => values.iter().collect::<Vec<&Value>>(),
+        value => vec![value],
+    };
+    for value in values.iter().filter_map(|v| v.as_u64()) {
+        if enum_type.value_name(value as u32).is_none() {
+            issues.push(ConformanceIssueKind::UnknownEnumValue {
+                global_message_number: message.global_message_number,
+                field_definition_number: field.field_definition_number,
+                value: value as u32,
+            });
+        }
+    }
+}
+
+impl FitFile {
+    /// Checks the messages of the file against the profile.
+    pub fn conformance_report(&self) -> ConformanceReport {
+        let mut issues = Vec::new();
+        match self
+            .messages
+            .iter()
+            .position(|m| m.global_message_number == FILE_ID_MESSAGE.number)
+        {
+            None => issues.push(ConformanceIssue {
+                offset: None,
+                kind: ConformanceIssueKind::MissingFileId,
+            }),
+            Some(0) => {}
+            Some(_) => issues.push(ConformanceIssue {
+                offset: None,
+                kind: ConformanceIssueKind::FileIdNotFirst,
+            }),
+        }
+        let file_type = self.file_type();
+        for message in &self.messages {
+            let name = match message_info(message.global_message_number) {
+                Some(info) => info.name,
+                None => continue,
+            };
+            let mut kinds = Vec::new();
+            let belongs = match file_type {
+                _ if COMMON_MESSAGES.contains(&name) => true,
+                Some(FileType::Activity) => !NOT_IN_ACTIVITY.contains(&name),
+                Some(file_type) => file_type_messages(file_type).is_none_or(|m| m.contains(&name)),
+                None => true,
+            };
+            if let (false, Some(file_type)) = (belongs, file_type) {
+                kinds.push(ConformanceIssueKind::UnexpectedMessage {
+                    global_message_number: message.global_message_number,
+                    file_type,
+                });
+            }
+            for field in &message.fields {
+                check_field(message, field, &mut kinds);
+            }
+            issues.extend(kinds.into_iter().map(|kind| ConformanceIssue {
+                offset: Some(message.offset),
+                kind,
+            }));
+        }
+        ConformanceReport { issues }
+    }
+}
+
+#[cfg(test)]
+use crate::fit_file::test_file;
+
+#[test]
+fn test_conformance_report() {
+    let data = test_file(&[
+        0x40, 0x00, 0x00, 0x00, 0x00, 0x01, // file_id, 1 field
+        0x00, 0x01, 0x00, // type
+        0x00, 0x06, // course
+        0x41, 0x00, 0x00, 0x15, 0x00, 0x02, // event, 2 fields
+        0x00, 0x01, 0x00, // event
+        0x01, 0x01, 0x00, // event_type
+        0x01, 0xC8, 0x00, // event 200, start
+        0x42, 0x00, 0x00, 0x0F, 0x00, 0x01, // goal, 1 field
+        0x00, 0x02, 0x00, // sport, 2 values
+        0x02, 0x01, 0x02, // running, cycling
+    ]);
+    let file = FitFile::parse(&data).unwrap();
+    let report = file.conformance_report();
+    let kinds = report
+        .issues
+        .iter()
+        .map(|i| i.kind.clone())
+        .collect::<Vec<ConformanceIssueKind>>();
+    assert_eq!(
+        kinds,
+        vec![
+            ConformanceIssueKind::UnknownEnumValue {
+                global_message_number: 21,
+                field_definition_number: 0,
+                value: 200
+            },
+            ConformanceIssueKind::UnexpectedMessage {
+                global_message_number: 15,
+                file_type: FileType::Course
+            },
+            ConformanceIssueKind::ArrayLengthMismatch {
+                global_message_number: 15,
+                field_definition_number: 0,
+                expected: 1,
+                actual: 2
+            },
+        ]
+    );
+    assert_eq!(report.issues[1].offset, Some(file.messages[2].offset));
+    assert_eq!(
+        report.issues[1].to_string(),
+        format!(
+            "Message 15 does not belong in course files (offset {:#x})",
+            file.messages[2].offset
+        )
+    );
+
+    assert_eq!(report.at_least(Severity::Warning).count(), 2);
+    assert_eq!(report.issues[2].kind.code(), "FIT1004");
+
+    let without_file_id = FitFile::parse(&test_file(&data[23..data.len() - 2])).unwrap();
+    assert_eq!(
+        without_file_id.conformance_report().issues[0].kind,
+        ConformanceIssueKind::MissingFileId
+    );
+}
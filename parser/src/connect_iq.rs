@@ -0,0 +1,247 @@
+//! A registry of the developer fields of well-known Connect IQ apps, giving
+//! their values a canonical meaning and unit instead of the name and units
+//! chosen by each app.
+//!
+//! Fields are matched by name and by the application id of their developer.
+//! Fields without an application id are matched by name alone.
+
+use crate::developer::{DeveloperFieldInfo, DeveloperFields};
+use crate::fit_file::FitFile;
+use crate::fit_messages::{DataMessage, Value};
+
+/// Canonical meaning of a known developer field, with the unit of its values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KnownField {
+    /// Running power, watts.
+    RunningPower,
+    /// Power spent on vertical oscillation, watts.
+    FormPower,
+    /// Power spent against the air, watts.
+    AirPower,
+    /// kN/m.
+    LegSpringStiffness,
+    /// Degrees Celsius.
+    CoreTemperature,
+    /// Degrees Celsius.
+    SkinTemperature,
+    /// Heat strain index, 0 to 10.
+    HeatStrainIndex,
+    /// Maximal power available, watts.
+    MaximalPowerAvailable,
+}
+
+/// A Connect IQ app and the names of its developer fields.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KnownApp {
+    pub name: &'static str,
+    /// Application ids of the app. When empty, only fields without an
+    /// application id are matched.
+    pub application_ids: &'static [[u8; 16]],
+    pub fields: &'static [(&'static str, KnownField)],
+}
+
+/// Apps recognized by [`DeveloperFieldRegistry::with_known_apps`]. They have
+/// no application ids, so only match fields without one; register an app
+/// with its ids to match the fields it writes with them.
+pub const KNOWN_APPS: [KnownApp; 3] = [
+    KnownApp {
+        name: "Stryd",
+        application_ids: &[],
+        fields: &[
+            ("Power", KnownField::RunningPower),
+            ("Form Power", KnownField::FormPower),
+            ("Air Power", KnownField::AirPower),
+            ("Leg Spring Stiffness", KnownField::LegSpringStiffness),
+        ],
+    },
+    KnownApp {
+        name: "CORE",
+        application_ids: &[],
+        fields: &[
+            ("core_temperature", KnownField::CoreTemperature),
+            ("skin_temperature", KnownField::SkinTemperature),
+            ("heat_strain_index", KnownField::HeatStrainIndex),
+        ],
+    },
+    KnownApp {
+        name: "Xert",
+        application_ids: &[],
+        fields: &[("MPA", KnownField::MaximalPowerAvailable)],
+    },
+];
+
+// Converts a value to the unit of the field from the units of its
+// description.
+fn convert(field: KnownField, value: f64, units: Option<&str>) -> f64 {
+    match (field, units.map(str::trim)) {
+        (KnownField::CoreTemperature | KnownField::SkinTemperature, Some("F" | "°F" | "degF")) => {
+            (value - 32.0) / 1.8
+        }
+        (KnownField::LegSpringStiffness, Some("N/m")) => value / 1000.0,
+        (_, _) => value,
+    }
+}
+
+/// Developer fields of known apps. Apps registered later take precedence.
+/// A registry is `Send + Sync` for sharing between threads.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DeveloperFieldRegistry {
+    apps: Vec<KnownApp>,
+}
+
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<DeveloperFieldRegistry>();
+};
+
+impl DeveloperFieldRegistry {
+    /// An empty registry.
+    pub fn new() -> DeveloperFieldRegistry {
+        DeveloperFieldRegistry::default()
+    }
+
+    /// A registry of the [`KNOWN_APPS`].
+    pub fn with_known_apps() -> DeveloperFieldRegistry {
+        DeveloperFieldRegistry {
+            apps: KNOWN_APPS.to_vec(),
+        }
+    }
+
+    pub fn register(&mut self, app: KnownApp) {
+        self.apps.push(app);
+    }
+
+    pub fn apps(&self) -> impl Iterator<Item = &KnownApp> {
+        self.apps.iter()
+    }
+
+    /// The app and canonical meaning of a developer field.
+    pub fn resolve(&self, info: &DeveloperFieldInfo) -> Option<(&KnownApp, KnownField)> {
+        self.apps.iter().rev().find_map(|app| {
+            let known_id = match info.application_id.as_deref() {
+                Some(id) => app.application_ids.iter().any(|a| a[..] == *id),
+                None => app.application_ids.is_empty(),
+            };
+            if !known_id {
+                return None;
+            }
+            app.fields
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(&info.name))
+                .map(|(_, field)| (app, *field))
+        })
+    }
+
+    /// Values of the known developer fields of the message in the unit of
+    /// their field.
+    pub fn values(
+        &self,
+        developer: &DeveloperFields,
+        message: &DataMessage,
+    ) -> Vec<(KnownField, f64)> {
+        developer
+            .values(message)
+            .filter_map(|(info, value)| {
+                let (_, field) = self.resolve(info)?;
+                let value = match value {
+                    Value::Array(_) | Value::String(_) | Value::Bytes(_) => return None,
+                    value => value.as_f64()?,
+                };
+                Some((field, convert(field, value, info.units.as_deref())))
+            })
+            .collect()
+    }
+
+    /// Value of a known developer field of the message.
+    pub fn value(
+        &self,
+        developer: &DeveloperFields,
+        message: &DataMessage,
+        field: KnownField,
+    ) -> Option<f64> {
+        self.values(developer, message)
+            .into_iter()
+            .find_map(|(f, value)| (f == field).then_some(value))
+    }
+}
+
+impl FitFile {
+    /// Timestamped values of a known developer field in file order.
+    pub fn known_developer_values(
+        &self,
+        registry: &DeveloperFieldRegistry,
+        field: KnownField,
+    ) -> Vec<(Option<u32>, f64)> {
+        let developer = DeveloperFields::from_file(self);
+        self.messages
+            .iter()
+            .filter(|m| !m.developer_fields.is_empty())
+            .filter_map(|m| Some((m.timestamp(), registry.value(&developer, m, field)?)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+use crate::fit_file::test_file;
+
+#[test]
+fn test_known_developer_fields() {
+    let mut records = vec![
+        0x40, 0x00, 0x00, 0xCF, 0x00, 0x02, // developer_data_id, 2 fields
+        0x01, 0x10, 0x0D, // application_id
+        0x03, 0x01, 0x02, // developer_data_index
+        0x00,
+    ];
+    records.extend_from_slice(&[0xA5; 16]);
+    records.extend_from_slice(&[
+        0x00, // developer_data_index 0
+        0x41, 0x00, 0x00, 0xCE, 0x00, 0x05, // field_description, 5 fields
+        0x00, 0x01, 0x02, // developer_data_index
+        0x01, 0x01, 0x02, // field_definition_number
+        0x02, 0x01, 0x02, // fit_base_type_id
+        0x03, 0x11, 0x07, // field_name
+        0x08, 0x02, 0x07, // units
+        0x01, 0x00, 0x00, 0x84,
+    ]);
+    records.extend_from_slice(b"skin_temperature\0F\0");
+    records.extend_from_slice(&[
+        0x62, 0x00, 0x00, 0x14, 0x00, 0x01, // record with developer fields
+        0xFD, 0x04, 0x86, // timestamp
+        0x01, 0x00, 0x02, 0x00, // developer field 0, 2 bytes, index 0
+        0x02, 0xE8, 0x03, 0x00, 0x00, 0x71, 0x00, // timestamp 1000, 113 F
+    ]);
+    let file = FitFile::parse(&test_file(&records)).unwrap();
+    let developer = DeveloperFields::from_file(&file);
+    let info = developer.iter().next().unwrap();
+    assert_eq!(info.application_id.as_deref(), Some(&[0xA5; 16][..]));
+
+    // fields with an application id need an app registered with it
+    let mut registry = DeveloperFieldRegistry::with_known_apps();
+    assert_eq!(registry.resolve(info), None);
+    let without_id = DeveloperFieldInfo {
+        application_id: None,
+        ..info.clone()
+    };
+    assert_eq!(registry.resolve(&without_id).unwrap().0.name, "CORE");
+
+    registry.register(KnownApp {
+        application_ids: &[[0xA5; 16]],
+        ..KNOWN_APPS[1]
+    });
+    assert_eq!(registry.resolve(info).unwrap().0.application_ids.len(), 1);
+    let values = file.known_developer_values(&registry, KnownField::SkinTemperature);
+    assert_eq!(values, vec![(Some(1000), 45.0)]);
+    assert!(file
+        .known_developer_values(&registry, KnownField::CoreTemperature)
+        .is_empty());
+
+    // an app registered with other ids does not match
+    let mut registry = DeveloperFieldRegistry::new();
+    registry.register(KnownApp {
+        name: "Other",
+        application_ids: &[[0x5A; 16]],
+        fields: &[("skin_temperature", KnownField::SkinTemperature)],
+    });
+    assert_eq!(registry.resolve(info), None);
+    assert_eq!(registry.resolve(&without_id), None);
+}
@@ -0,0 +1,261 @@
+//! Course files: the course, its laps, the track as records and the points
+//! of interest along it, with the distances of the points along the track
+//! for navigation cues.
+
+use crate::fit_file::FitFile;
+use crate::geo::{cumulative_distance, EARTH_RADIUS};
+use crate::profile::messages::{Course as CourseMessage, CoursePoint, FileId, Lap, Record};
+use crate::profile::{
+    CoursePoint as CoursePointType, COURSE_MESSAGE, COURSE_POINT_MESSAGE, FILE_ID_MESSAGE,
+    LAP_MESSAGE, RECORD_MESSAGE,
+};
+use crate::series::SEMICIRCLES_TO_DEGREES;
+
+// Course point types that are turn directions.
+const TURNS: [CoursePointType; 11] = [
+    CoursePointType::Left,
+    CoursePointType::Right,
+    CoursePointType::Straight,
+    CoursePointType::LeftFork,
+    CoursePointType::RightFork,
+    CoursePointType::MiddleFork,
+    CoursePointType::SlightLeft,
+    CoursePointType::SharpLeft,
+    CoursePointType::SlightRight,
+    CoursePointType::SharpRight,
+    CoursePointType::UTurn,
+];
+
+/// The messages of a course file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Course {
+    pub file_id: Option<FileId>,
+    pub course: Option<CourseMessage>,
+    pub laps: Vec<Lap>,
+    /// The track in file order.
+    pub records: Vec<Record>,
+    /// Ordered by message index.
+    pub points: Vec<CoursePoint>,
+}
+
+/// A position on the track of a course.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrackPosition {
+    /// Meters along the track.
+    pub distance: f64,
+    /// Meters from the track.
+    pub off_track: f64,
+}
+
+/// A course point at its distance along the track.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cue {
+    pub point: CoursePoint,
+    /// Meters along the track.
+    pub distance: f64,
+}
+
+impl Cue {
+    /// Whether the point is a turn direction, such as `left` or `u_turn`.
+    pub fn is_turn(&self) -> bool {
+        self.point
+            .r#type
+            .is_some_and(|t| TURNS.into_iter().any(|turn| turn as u8 == t))
+    }
+}
+
+/// The cues from `distance` to `lookahead` meters ahead of it, of cues
+/// ordered by distance as by [`Course::cues`].
+pub fn upcoming_cues(cues: &[Cue], distance: f64, lookahead: f64) -> &[Cue] {
+    let start = cues.partition_point(|c| c.distance < distance);
+    let end = cues.partition_point(|c| c.distance <= distance + lookahead);
+    &cues[start..end.max(start)]
+}
+
+// A track point: latitude and longitude in degrees and meters along the
+// track.
+type TrackPoint = (f64, f64, f64);
+
+impl Course {
+    /// The positioned records with their distances along the track. The
+    /// distances of the records are used when all of them have one,
+    /// otherwise distances are computed from the positions.
+    fn track(&self) -> Vec<TrackPoint> {
+        let positioned = self
+            .records
+            .iter()
+            .filter_map(|r| {
+                Some((
+                    r.position_lat? as f64 * SEMICIRCLES_TO_DEGREES,
+                    r.position_long? as f64 * SEMICIRCLES_TO_DEGREES,
+                    r.distance,
+                ))
+            })
+            .collect::<Vec<(f64, f64, Option<f64>)>>();
+        if let Some(distances) = positioned
+            .iter()
+            .map(|(_, _, d)| *d)
+            .collect::<Option<Vec<f64>>>()
+        {
+            return positioned
+                .iter()
+                .zip(distances)
+                .map(|((lat, long, _), d)| (*lat, *long, d))
+                .collect();
+        }
+        let lat = positioned.iter().map(|p| p.0).collect::<Vec<f64>>();
+        let long = positioned.iter().map(|p| p.1).collect::<Vec<f64>>();
+        cumulative_distance(&lat, &long)
+            .into_iter()
+            .zip(positioned)
+            .map(|(d, (lat, long, _))| (lat, long, d))
+            .collect()
+    }
+
+    /// The position on the track nearest to a position in degrees, or
+    /// `None` for a course without a track.
+    pub fn locate(&self, lat: f64, long: f64) -> Option<TrackPosition> {
+        locate(&self.track(), lat, long)
+    }
+
+    /// The course points ordered by distance along the track. Points without
+    /// a distance are placed at the nearest position on the track, points
+    /// without either are left out.
+    pub fn cues(&self) -> Vec<Cue> {
+        let track = self.track();
+        let mut cues = self
+            .points
+            .iter()
+            .filter_map(|point| {
+                let distance = point.distance.or_else(|| {
+                    let lat = point.position_lat? as f64 * SEMICIRCLES_TO_DEGREES;
+                    let long = point.position_long? as f64 * SEMICIRCLES_TO_DEGREES;
+                    Some(locate(&track, lat, long)?.distance)
+                })?;
+                Some(Cue {
+                    point: point.clone(),
+                    distance,
+                })
+            })
+            .collect::<Vec<Cue>>();
+        cues.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+        cues
+    }
+
+    pub fn from_file(file: &FitFile) -> Course {
+        let mut points = file
+            .typed_messages(COURSE_POINT_MESSAGE.number, CoursePoint::from_message)
+            .collect::<Vec<CoursePoint>>();
+        points.sort_by_key(|p| p.message_index);
+        Course {
+            file_id: file
+                .typed_messages(FILE_ID_MESSAGE.number, FileId::from_message)
+                .next(),
+            course: file
+                .typed_messages(COURSE_MESSAGE.number, CourseMessage::from_message)
+                .next(),
+            laps: file
+                .typed_messages(LAP_MESSAGE.number, Lap::from_message)
+                .collect(),
+            records: file
+                .typed_messages(RECORD_MESSAGE.number, Record::from_message)
+                .collect(),
+            points,
+        }
+    }
+}
+
+impl FitFile {
+    pub fn course(&self) -> Course {
+        Course::from_file(self)
+    }
+}
+
+// Projects the position on each track segment in a local flat projection
+// around it, which is accurate for the short segments of a track.
+fn locate(track: &[TrackPoint], lat: f64, long: f64) -> Option<TrackPosition> {
+    let meters_per_degree = EARTH_RADIUS.to_radians();
+    let scale_long = lat.to_radians().cos();
+    let to_local = |(p_lat, p_long, _): &TrackPoint| {
+        (
+            (p_long - long) * scale_long * meters_per_degree,
+            (p_lat - lat) * meters_per_degree,
+        )
+    };
+    let mut nearest: Option<TrackPosition> = None;
+    let segments = track
+        .windows(2)
+        .map(|w| (&w[0], &w[1]))
+        .chain(track.first().map(|p| (p, p)).filter(|_| track.len() == 1));
+    for (a, b) in segments {
+        let (ax, ay) = to_local(a);
+        let (bx, by) = to_local(b);
+        let (dx, dy) = (bx - ax, by - ay);
+        let length_squared = dx * dx + dy * dy;
+        let t = if length_squared > 0.0 {
+            (-(ax * dx + ay * dy) / length_squared).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let off_track = (ax + t * dx).hypot(ay + t * dy);
+        if nearest.is_none_or(|n| off_track < n.off_track) {
+            nearest = Some(TrackPosition {
+                distance: a.2 + t * (b.2 - a.2),
+                off_track,
+            });
+        }
+    }
+    nearest
+}
+
+#[cfg(test)]
+fn semicircles(degrees: f64) -> i32 {
+    (degrees / SEMICIRCLES_TO_DEGREES).round() as i32
+}
+
+#[test]
+fn test_course_cues() {
+    // a track north along a meridian, 0.001 degrees is about 111 m
+    let records = (0..5)
+        .map(|i| Record {
+            position_lat: Some(semicircles(60.0 + i as f64 * 0.001)),
+            position_long: Some(semicircles(24.0)),
+            ..Record::default()
+        })
+        .collect();
+    let point =
+        |index: u16, point_type: CoursePointType, lat: f64, distance: Option<f64>| CoursePoint {
+            message_index: Some(index),
+            position_lat: Some(semicircles(lat)),
+            position_long: Some(semicircles(24.0001)),
+            distance,
+            r#type: Some(point_type as u8),
+            ..CoursePoint::default()
+        };
+    let course = Course {
+        records,
+        points: vec![
+            point(0, CoursePointType::Summit, 60.0035, None),
+            point(1, CoursePointType::Left, 60.0015, None),
+            point(2, CoursePointType::Water, 0.0, Some(50.0)),
+        ],
+        ..Course::default()
+    };
+    let position = course.locate(60.002, 24.0).unwrap();
+    assert!((position.distance - 222.4).abs() < 0.1);
+    assert!(position.off_track < 0.01);
+
+    let cues = course.cues();
+    let distances = cues
+        .iter()
+        .map(|c| c.distance.round())
+        .collect::<Vec<f64>>();
+    assert_eq!(distances, vec![50.0, 167.0, 389.0]);
+    assert!(cues[1].is_turn());
+    assert!(!cues[2].is_turn());
+
+    let upcoming = upcoming_cues(&cues, 100.0, 300.0);
+    assert_eq!(upcoming.len(), 2);
+    assert_eq!(upcoming[0].point.message_index, Some(1));
+    assert!(upcoming_cues(&cues, 400.0, 100.0).is_empty());
+}
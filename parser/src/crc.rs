@@ -0,0 +1,143 @@
+//! The CRC-16 used by FIT files to check the header and the whole file.
+
+use std::hash::Hasher;
+
+const POLYNOMIAL: u16 = 0xA001;
+
+const fn crc_tables() -> [[u16; 256]; 8] {
+    let mut tables = [[0u16; 256]; 8];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u16;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ POLYNOMIAL
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        tables[0][i] = crc;
+        i += 1;
+    }
+    let mut table = 1;
+    while table < 8 {
+        let mut i = 0;
+        while i < 256 {
+            let prev = tables[table - 1][i];
+            tables[table][i] = (prev >> 8) ^ tables[0][(prev & 0xFF) as usize];
+            i += 1;
+        }
+        table += 1;
+    }
+    tables
+}
+
+// Slice-by-8 tables, table k gives the CRC of a byte followed by k zero bytes.
+static CRC_TABLES: [[u16; 256]; 8] = crc_tables();
+
+/// Incrementally computed FIT CRC. Feeding the data in any number of chunks
+/// gives the same CRC as feeding it at once.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FitCrc {
+    crc: u16,
+}
+
+impl FitCrc {
+    pub fn new() -> FitCrc {
+        FitCrc::default()
+    }
+
+    /// Continues a CRC computed over earlier data.
+    pub fn with_initial(crc: u16) -> FitCrc {
+        FitCrc { crc }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        let t = &CRC_TABLES;
+        let mut crc = self.crc;
+        let mut chunks = data.chunks_exact(8);
+        for b in &mut chunks {
+            let low = b[0] ^ crc as u8;
+            let high = b[1] ^ (crc >> 8) as u8;
+            crc = t[7][low as usize]
+                ^ t[6][high as usize]
+                ^ t[5][b[2] as usize]
+                ^ t[4][b[3] as usize]
+                ^ t[3][b[4] as usize]
+                ^ t[2][b[5] as usize]
+                ^ t[1][b[6] as usize]
+                ^ t[0][b[7] as usize];
+        }
+        for b in chunks.remainder() {
+            crc = (crc >> 8) ^ t[0][((crc ^ *b as u16) & 0xFF) as usize];
+        }
+        self.crc = crc;
+    }
+
+    pub fn value(&self) -> u16 {
+        self.crc
+    }
+
+    /// CRC of `data`, continuing from `crc_in`.
+    pub fn checksum(data: &[u8], crc_in: u16) -> u16 {
+        let mut crc = FitCrc::with_initial(crc_in);
+        crc.update(data);
+        crc.value()
+    }
+}
+
+impl Hasher for FitCrc {
+    fn write(&mut self, bytes: &[u8]) {
+        self.update(bytes);
+    }
+
+    fn finish(&self) -> u64 {
+        self.crc as u64
+    }
+}
+
+// Reference implementation from the FIT protocol description, computing the
+// CRC four bits at a time.
+#[cfg(test)]
+fn nibble_crc(data: &[u8], crc_in: u16) -> u16 {
+    const NIBBLE_TABLE: [u16; 16] = [
+        0x0000, 0xCC01, 0xD801, 0x1400, 0xF001, 0x3C00, 0x2800, 0xE401, 0xA001, 0x6C00, 0x7800,
+        0xB401, 0x5000, 0x9C01, 0x8801, 0x4400,
+    ];
+    let mut crc = crc_in;
+    for byte in data {
+        let tmp = NIBBLE_TABLE[(crc & 0xF) as usize];
+        crc = (crc >> 4) & 0x0FFF;
+        crc = crc ^ tmp ^ NIBBLE_TABLE[(byte & 0xF) as usize];
+        let tmp = NIBBLE_TABLE[(crc & 0xF) as usize];
+        crc = (crc >> 4) & 0x0FFF;
+        crc = crc ^ tmp ^ NIBBLE_TABLE[((byte >> 4) & 0xF) as usize];
+    }
+    crc
+}
+
+#[test]
+fn test_fit_crc_matches_reference() {
+    let data = (0..1000u32)
+        .map(|i| (i.wrapping_mul(2654435761) >> 13) as u8)
+        .collect::<Vec<u8>>();
+    for len in [0, 1, 7, 8, 9, 15, 16, 17, 1000] {
+        assert_eq!(
+            FitCrc::checksum(&data[..len], 0),
+            nibble_crc(&data[..len], 0)
+        );
+    }
+    assert_eq!(FitCrc::checksum(&data, 0x1234), nibble_crc(&data, 0x1234));
+}
+
+#[test]
+fn test_fit_crc_incremental() {
+    let data = b"14 bytes header.FIT and some data records";
+    let mut crc = FitCrc::new();
+    for chunk in data.chunks(5) {
+        crc.write(chunk);
+    }
+    assert_eq!(crc.finish(), FitCrc::checksum(data, 0) as u64);
+}
@@ -0,0 +1,170 @@
+//! Cycling dynamics from record messages: left/right balance, torque
+//! effectiveness, pedal smoothness, platform center offset and power phase.
+
+use crate::fit_file::FitFile;
+use crate::profile::messages::Record;
+use crate::profile::RECORD_MESSAGE;
+
+/// A left/right power balance value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Balance {
+    /// Percent of the total power.
+    pub percent: f64,
+    /// Whether `percent` is the contribution of the right leg. Otherwise the
+    /// device did not tell which leg it is.
+    pub right: bool,
+}
+
+impl Balance {
+    /// Decodes a `left_right_balance` value: the percentage in the low seven
+    /// bits and the right leg flag in the high bit.
+    pub fn from_left_right_balance(value: u8) -> Option<Balance> {
+        if value == 0xFF {
+            return None;
+        }
+        Some(Balance {
+            percent: (value & 0x7F) as f64,
+            right: value & 0x80 != 0,
+        })
+    }
+
+    /// Decodes a `left_right_balance_100` value: the percentage in hundredths
+    /// in the low 14 bits and the right leg flag in the high bit.
+    pub fn from_left_right_balance_100(value: u16) -> Option<Balance> {
+        if value == 0xFFFF {
+            return None;
+        }
+        Some(Balance {
+            percent: (value & 0x3FFF) as f64 / 100.0,
+            right: value & 0x8000 != 0,
+        })
+    }
+
+    /// Percent of the total power from the left leg, if known.
+    pub fn left_percent(&self) -> Option<f64> {
+        self.right.then_some(100.0 - self.percent)
+    }
+
+    /// Percent of the total power from the right leg, if known.
+    pub fn right_percent(&self) -> Option<f64> {
+        self.right.then_some(self.percent)
+    }
+}
+
+/// Crank angles in degrees where a leg produces positive power, or its peak
+/// power.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PowerPhase {
+    pub start: f64,
+    pub end: f64,
+}
+
+impl PowerPhase {
+    fn from_angles(angles: Option<Vec<f64>>) -> Option<PowerPhase> {
+        match angles?.as_slice() {
+            [start, end, ..] => Some(PowerPhase {
+                start: *start,
+                end: *end,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Cycling dynamics of one record. Missing values are `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CyclingDynamics {
+    pub timestamp: Option<u32>,
+    pub balance: Option<Balance>,
+    /// Percent.
+    pub left_torque_effectiveness: Option<f64>,
+    pub right_torque_effectiveness: Option<f64>,
+    /// Percent.
+    pub left_pedal_smoothness: Option<f64>,
+    pub right_pedal_smoothness: Option<f64>,
+    pub combined_pedal_smoothness: Option<f64>,
+    /// Millimeters.
+    pub left_platform_center_offset: Option<i8>,
+    pub right_platform_center_offset: Option<i8>,
+    pub left_power_phase: Option<PowerPhase>,
+    pub left_power_phase_peak: Option<PowerPhase>,
+    pub right_power_phase: Option<PowerPhase>,
+    pub right_power_phase_peak: Option<PowerPhase>,
+}
+
+impl CyclingDynamics {
+    pub fn from_record(record: Record) -> CyclingDynamics {
+        CyclingDynamics {
+            timestamp: record.timestamp,
+            balance: record
+                .left_right_balance
+                .and_then(Balance::from_left_right_balance),
+            left_torque_effectiveness: record.left_torque_effectiveness,
+            right_torque_effectiveness: record.right_torque_effectiveness,
+            left_pedal_smoothness: record.left_pedal_smoothness,
+            right_pedal_smoothness: record.right_pedal_smoothness,
+            combined_pedal_smoothness: record.combined_pedal_smoothness,
+            left_platform_center_offset: record.left_pco,
+            right_platform_center_offset: record.right_pco,
+            left_power_phase: PowerPhase::from_angles(record.left_power_phase),
+            left_power_phase_peak: PowerPhase::from_angles(record.left_power_phase_peak),
+            right_power_phase: PowerPhase::from_angles(record.right_power_phase),
+            right_power_phase_peak: PowerPhase::from_angles(record.right_power_phase_peak),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self
+            == CyclingDynamics {
+                timestamp: self.timestamp,
+                ..CyclingDynamics::default()
+            }
+    }
+}
+
+impl FitFile {
+    /// Cycling dynamics of the record messages that have any, in file order.
+    pub fn cycling_dynamics(&self) -> Vec<CyclingDynamics> {
+        self.typed_messages(RECORD_MESSAGE.number, Record::from_message)
+            .map(CyclingDynamics::from_record)
+            .filter(|d| !d.is_empty())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+use crate::fit_file::test_file;
+
+#[test]
+fn test_balance() {
+    let right = Balance::from_left_right_balance(0x80 | 52).unwrap();
+    assert_eq!(right.right_percent(), Some(52.0));
+    assert_eq!(right.left_percent(), Some(48.0));
+    let unknown = Balance::from_left_right_balance(52).unwrap();
+    assert_eq!(unknown.percent, 52.0);
+    assert_eq!(unknown.right_percent(), None);
+    assert_eq!(Balance::from_left_right_balance(0xFF), None);
+    let balance_100 = Balance::from_left_right_balance_100(0x8000 | 5025).unwrap();
+    assert_eq!(balance_100.right_percent(), Some(50.25));
+}
+
+#[test]
+fn test_cycling_dynamics() {
+    let file = FitFile::parse(&test_file(&[
+        0x40, 0x00, 0x00, 0x14, 0x00, 0x04, // record, 4 fields
+        0x1E, 0x01, 0x02, // left_right_balance
+        0x2B, 0x01, 0x02, // left_torque_effectiveness
+        0x43, 0x01, 0x01, // left_pco
+        0x45, 0x02, 0x02, // left_power_phase
+        0x00, 0xB2, 0xB4, 0xFD, 0x07, 0x72, // 50 % right, 90 %, -3 mm, 9.8..160.3 degrees
+    ]))
+    .unwrap();
+    let dynamics = file.cycling_dynamics();
+    assert_eq!(dynamics.len(), 1);
+    assert_eq!(dynamics[0].balance.unwrap().right_percent(), Some(50.0));
+    assert_eq!(dynamics[0].left_torque_effectiveness, Some(90.0));
+    assert_eq!(dynamics[0].left_platform_center_offset, Some(-3));
+    let phase = dynamics[0].left_power_phase.unwrap();
+    assert!((phase.start - 7.0 / 0.7111111).abs() < 1e-6);
+    assert!((phase.end - 114.0 / 0.7111111).abs() < 1e-6);
+}
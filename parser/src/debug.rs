@@ -0,0 +1,330 @@
+//! Debugging aids for inspecting the byte level layout of FIT files.
+
+use std::fmt::Write;
+use std::ops::Range;
+
+use crate::diagnostics::Diagnostics;
+use crate::fit_header::{format_profile_version, try_parse_fit_header_from_data, FitFileHeader};
+use crate::fit_messages::{DataMessage, MessageDecoder, Value};
+use crate::fit_records::{DefinitionRecord, Record, RecordIterator};
+use crate::profile::message_info;
+
+const BYTES_PER_LINE: usize = 16;
+
+// Collects labeled byte ranges and formats them as hexdump lines, the lines
+// overlapping `range` only.
+struct Annotator<'a> {
+    data: &'a [u8],
+    range: Range<usize>,
+    out: String,
+}
+
+impl Annotator<'_> {
+    fn label(&mut self, offset: usize, len: usize, label: &str) {
+        let end = self.data.len().min(offset + len);
+        if offset >= end {
+            return;
+        }
+        for (line, chunk) in self.data[offset..end].chunks(BYTES_PER_LINE).enumerate() {
+            let line_start = offset + line * BYTES_PER_LINE;
+            if line_start >= self.range.end || line_start + chunk.len() <= self.range.start {
+                continue;
+            }
+            let hex = chunk
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<Vec<String>>()
+                .join(" ");
+            let label = if line == 0 { label } else { "" };
+            let _ = writeln!(
+                self.out,
+                "{:08x}  {:<width$}  {}",
+                line_start,
+                hex,
+                label,
+                width = BYTES_PER_LINE * 3 - 1
+            );
+        }
+    }
+}
+
+fn message_name(global_message_number: u16) -> String {
+    message_info(global_message_number)
+        .map(|m| m.name.to_string())
+        .unwrap_or_else(|| format!("message {}", global_message_number))
+}
+
+// Strings are quoted to show their bounds in the dump.
+fn format_value(value: &Value) -> String {
+    match value {
+        Value::String(v) => format!("{:?}", v),
+        value => value.to_string(),
+    }
+}
+
+fn field_label(message: &DataMessage, field_definition_number: u8) -> String {
+    let profile_name = message_info(message.global_message_number)
+        .and_then(|m| m.field(field_definition_number))
+        .map(|f| f.name);
+    let name = |decoded_name: Option<&'static str>| match decoded_name.or(profile_name) {
+        Some(name) => name.to_string(),
+        None => format!("field {}", field_definition_number),
+    };
+    match message.field(field_definition_number) {
+        Some(field) if field.units.is_empty() => {
+            format!("{} = {}", name(field.name), format_value(&field.value))
+        }
+        Some(field) => format!(
+            "{} = {} {}",
+            name(field.name),
+            format_value(&field.value),
+            field.units
+        ),
+        None => format!("{} (invalid)", name(None)),
+    }
+}
+
+fn annotate_header(annotator: &mut Annotator, header: &FitFileHeader) {
+    annotator.label(0, 1, &format!("header size {}", header.header_size()));
+    annotator.label(
+        1,
+        1,
+        &format!("protocol version {:#04x}", header.protocol_version()),
+    );
+    annotator.label(
+        2,
+        2,
+        &format!(
+            "profile version {}",
+            format_profile_version(header.profile_version())
+        ),
+    );
+    annotator.label(4, 4, &format!("data size {}", header.data_size()));
+    annotator.label(8, 4, &format!("data type {:?}", header.data_type()));
+    if let Some(crc) = header.crc() {
+        annotator.label(12, 2, &format!("header CRC {:#06x}", crc));
+    }
+    let extension = header.extension();
+    if !extension.is_empty() {
+        annotator.label(
+            header.header_size() as usize - extension.len(),
+            extension.len(),
+            &format!("header extension, {} bytes", extension.len()),
+        );
+    }
+}
+
+fn annotate_definition(annotator: &mut Annotator, offset: usize, definition: &DefinitionRecord) {
+    annotator.label(
+        offset,
+        1,
+        &format!(
+            "definition header, local message type {}",
+            definition.header.local_message_type
+        ),
+    );
+    annotator.label(offset + 1, 1, "reserved");
+    annotator.label(
+        offset + 2,
+        1,
+        &format!("architecture {:?}", definition.architecture),
+    );
+    annotator.label(
+        offset + 3,
+        2,
+        &format!(
+            "global message number {} ({})",
+            definition.global_message_number,
+            message_name(definition.global_message_number)
+        ),
+    );
+    annotator.label(
+        offset + 5,
+        1,
+        &format!("{} fields", definition.field_definitions.len()),
+    );
+    let mut curr = offset + 6;
+    for field in &definition.field_definitions {
+        annotator.label(
+            curr,
+            3,
+            &format!(
+                "field {}, size {}, {}",
+                field.field_definition_number, field.field_size, field.base_type.type_name
+            ),
+        );
+        curr += 3;
+    }
+    if definition.header.contains_extended_definitions {
+        annotator.label(
+            curr,
+            1,
+            &format!(
+                "{} developer fields",
+                definition.developer_field_definitions.len()
+            ),
+        );
+        curr += 1;
+        for field in &definition.developer_field_definitions {
+            annotator.label(
+                curr,
+                3,
+                &format!(
+                    "developer field {}, size {}, developer data index {}",
+                    field.field_number, field.field_size, field.developer_data_index
+                ),
+            );
+            curr += 3;
+        }
+    }
+}
+
+/// Produces a hexdump of a FIT file where each byte range is labeled with its
+/// interpretation: header fields, record headers, definitions and decoded
+/// field values. Bytes that can not be parsed are dumped with the reason.
+pub fn annotate(fit_data: &[u8]) -> String {
+    annotate_range(fit_data, 0..fit_data.len())
+}
+
+/// The lines of [`annotate`] overlapping the byte range, for looking into a
+/// part of a large file. The records before the range are still read for
+/// their definitions, the records after it are not.
+pub fn annotate_range(fit_data: &[u8], range: Range<usize>) -> String {
+    let mut annotator = Annotator {
+        data: fit_data,
+        range,
+        out: String::new(),
+    };
+    let header = match try_parse_fit_header_from_data(fit_data) {
+        Ok(header) => header,
+        Err(e) => {
+            annotator.label(0, fit_data.len(), &format!("unparsed: {}", e));
+            return annotator.out;
+        }
+    };
+    annotate_header(&mut annotator, &header);
+    let data_start = header.header_size() as usize;
+    let data_end = fit_data.len().min(data_start + header.data_size() as usize);
+    let mut decoder = MessageDecoder::new();
+    let mut diagnostics = Diagnostics::new();
+    let mut records = RecordIterator::new(&fit_data[..data_end], data_start);
+    loop {
+        let record_offset = records.offset();
+        let record_index = records.record_index();
+        if record_offset >= annotator.range.end {
+            break;
+        }
+        match records.next() {
+            None => break,
+            Some(Ok(Record::Definition(definition))) => {
+                annotate_definition(&mut annotator, record_offset, &definition)
+            }
+            Some(Ok(Record::Data(record))) => {
+                let sizes = record
+                    .fields
+                    .iter()
+                    .map(|f| (f.field_definition_number, f.data.len()))
+                    .collect::<Vec<(u8, usize)>>();
+                let developer_fields = record.developer_fields.clone();
+                let message = decoder.decode(record, record_offset, record_index, &mut diagnostics);
+                let name = message_name(message.global_message_number);
+                annotator.label(record_offset, 1, &format!("data header, {}", name));
+                let mut curr = record_offset + 1;
+                for (field_definition_number, size) in sizes {
+                    let label = format!(
+                        "{}.{}",
+                        name,
+                        field_label(&message, field_definition_number)
+                    );
+                    annotator.label(curr, size, &label);
+                    curr += size;
+                }
+                for field in developer_fields {
+                    annotator.label(
+                        curr,
+                        field.data.len(),
+                        &format!(
+                            "developer field {}, developer data index {}",
+                            field.field_number, field.developer_data_index
+                        ),
+                    );
+                    curr += field.data.len();
+                }
+            }
+            Some(Err(e)) => {
+                annotator.label(
+                    record_offset,
+                    data_end - record_offset,
+                    &format!("unparsed: {}", e),
+                );
+                break;
+            }
+        }
+    }
+    annotator.label(data_end, 2, "file CRC");
+    annotator.label(
+        data_end + 2,
+        fit_data.len().saturating_sub(data_end + 2),
+        "trailing data",
+    );
+    annotator.out
+}
+
+#[test]
+fn test_annotate() {
+    let mut data = vec![12, 0x10, 0x6F, 0x08, 11, 0, 0, 0];
+    data.extend_from_slice(b".FIT");
+    data.extend_from_slice(&[
+        0x40, 0x00, 0x00, 0x14, 0x00, 0x01, 0x03, 0x01, 0x02, // record: heart_rate
+        0x00, 0x96, // heart_rate 150
+        0x34, 0x12, // file CRC
+    ]);
+    let annotated = annotate(&data);
+    let lines = annotated.lines().collect::<Vec<&str>>();
+    assert_eq!(lines[0], format!("00000000  0c{:45}  header size 12", ""));
+    assert!(lines.contains(
+        &format!(
+            "0000000c  40{:45}  definition header, local message type 0",
+            ""
+        )
+        .as_str()
+    ));
+    assert!(lines.contains(&format!("00000016  96{:45}  record.heart_rate = 150 bpm", "").as_str()));
+    assert_eq!(
+        *lines.last().unwrap(),
+        format!("00000017  34 12{:42}  file CRC", "")
+    );
+}
+
+#[test]
+fn test_annotate_truncated_header() {
+    assert_eq!(
+        annotate(&[14, 0x10]),
+        format!(
+            "00000000  0e 10{:42}  unparsed: Unexpected end of data in record at offset 0\n",
+            ""
+        )
+    );
+}
+
+#[test]
+fn test_annotate_range() {
+    let mut data = vec![12, 0x10, 0x6F, 0x08, 15, 0, 0, 0];
+    data.extend_from_slice(b".FIT");
+    data.extend_from_slice(&[
+        0x40, 0x00, 0x00, 0x14, 0x00, 0x01, 0x03, 0x01, 0x02, // record: heart_rate
+        0x00, 0x96, // heart_rate 150
+        0x00, 0x97, // heart_rate 151
+        0x00, 0x98, // heart_rate 152
+        0x34, 0x12, // file CRC
+    ]);
+    assert_eq!(
+        annotate_range(&data, 0x17..0x19),
+        format!(
+            "00000017  00{0:45}  data header, record\n\
+             00000018  97{0:45}  record.heart_rate = 151 bpm\n",
+            ""
+        )
+    );
+    assert_eq!(annotate_range(&data, 0..data.len()), annotate(&data));
+}
@@ -0,0 +1,501 @@
+//! Configurable parsing. [`FitDecoder`] parses files like
+//! [`FitFile::parse`] with the CRC policy, leniency, message filter, value
+//! decoding, cancellation and plausibility rules set in one
+//! [`ParserOptions`], built fluently with [`FitDecoder::builder`]:
+//!
+//! ```
+//! use fit_parser::decoder::{CrcPolicy, FitDecoder};
+//!
+//! let decoder = FitDecoder::builder()
+//!     .crc_policy(CrcPolicy::Warn)
+//!     .lenient(true)
+//!     .messages([20, 18])
+//!     .build();
+//! # let _ = decoder;
+//! ```
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::error::FitError;
+use crate::fit_file::FitFile;
+use crate::fit_header::try_parse_fit_header_from_data;
+use crate::fit_messages::DecodeState;
+use crate::fit_records::LocalMessageTable;
+use crate::limits::ParseLimits;
+use crate::plausibility::PlausibilityRules;
+
+/// What to do when the file CRC does not match. The header CRC is always
+/// checked.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CrcPolicy {
+    #[default]
+    Ignore,
+    /// Report a mismatch as a diagnostic.
+    Warn,
+    /// Fail the parse on a mismatch or a missing CRC.
+    Enforce,
+}
+
+/// How string fields are decoded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StringHandling {
+    /// Invalid UTF-8 is replaced with U+FFFD.
+    #[default]
+    Lossy,
+    /// Strings that are not valid UTF-8 are decoded as their bytes.
+    Bytes,
+}
+
+/// Progress of a parse through the data section, reported every 64 KiB and
+/// when the data section is done.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    pub bytes_processed: usize,
+    /// Size of the data section, as far as the data has it.
+    pub total_bytes: usize,
+}
+
+impl Progress {
+    /// Share of the data section processed, from 0 to 1.
+    pub fn fraction(&self) -> f64 {
+        if self.total_bytes == 0 {
+            1.0
+        } else {
+            self.bytes_processed as f64 / self.total_bytes as f64
+        }
+    }
+}
+
+/// Shared flag for cancelling parses from another thread. Clones share the
+/// flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Makes the parses checking this token fail with
+    /// [`FitError::Cancelled`] before their next record.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Tokens are equal when they share the flag.
+impl PartialEq for CancellationToken {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.cancelled, &other.cancelled)
+    }
+}
+
+/// Options of a parse. The default options parse like [`FitFile::parse`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParserOptions {
+    pub crc: CrcPolicy,
+    /// End the data section at a malformed record with a diagnostic instead
+    /// of failing the parse. Exceeding a limit still fails it.
+    pub lenient: bool,
+    /// Global message numbers of the messages to keep, all when `None`.
+    pub message_filter: Option<Vec<u16>>,
+    /// Keep the developer fields of the messages.
+    pub developer_fields: bool,
+    /// Apply the scale and offset of the profile to field values.
+    pub apply_scale: bool,
+    pub strings: StringHandling,
+    pub limits: ParseLimits,
+    pub cancellation: Option<CancellationToken>,
+    /// Rules checked after decoding, with a diagnostic for each implausible
+    /// value.
+    pub plausibility: Option<PlausibilityRules>,
+    /// Fail the parse on definitions using features that the protocol
+    /// version of the file does not allow, such as developer fields in
+    /// protocol version 1.0 files.
+    pub strict_protocol: bool,
+    /// Messages to reserve room for up front instead of estimating the count
+    /// from the data size, such as the count from an earlier parse or an
+    /// index. Clamped to the most messages the data section can hold.
+    pub capacity_hint: Option<usize>,
+}
+
+impl Default for ParserOptions {
+    fn default() -> Self {
+        ParserOptions {
+            crc: CrcPolicy::default(),
+            lenient: false,
+            message_filter: None,
+            developer_fields: true,
+            apply_scale: true,
+            strings: StringHandling::default(),
+            limits: ParseLimits::default(),
+            cancellation: None,
+            plausibility: None,
+            strict_protocol: false,
+            capacity_hint: None,
+        }
+    }
+}
+
+impl ParserOptions {
+    pub(crate) fn includes_message(&self, global_message_number: u16) -> bool {
+        self.message_filter
+            .as_ref()
+            .is_none_or(|filter| filter.contains(&global_message_number))
+    }
+}
+
+/// Snapshot of what a decoder carries from one file to the next: the local
+/// message type definitions, the last timestamp for compressed timestamps
+/// and the accumulated component values.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DecoderState {
+    pub(crate) local_messages: LocalMessageTable,
+    pub(crate) decode: DecodeState,
+}
+
+impl DecoderState {
+    /// The state before any file, with no local message types defined.
+    pub fn new() -> DecoderState {
+        DecoderState::default()
+    }
+
+    /// Whether the local message type has a definition.
+    pub fn is_defined(&self, local_message_type: u8) -> bool {
+        self.local_messages.get(local_message_type).is_some()
+    }
+}
+
+/// Parser of FIT files with fixed options. A decoder is `Send + Sync`, so
+/// one decoder, for example in an `Arc<FitDecoder>`, can decode many files
+/// concurrently.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FitDecoder {
+    options: ParserOptions,
+}
+
+impl FitDecoder {
+    /// A decoder with the default options.
+    pub fn new() -> FitDecoder {
+        FitDecoder::default()
+    }
+
+    pub fn builder() -> FitDecoderBuilder {
+        FitDecoderBuilder::default()
+    }
+
+    pub fn options(&self) -> &ParserOptions {
+        &self.options
+    }
+
+    pub fn decode(&self, fit_data: &[u8]) -> Result<FitFile, FitError> {
+        self.decode_with_progress(fit_data, |_| {})
+    }
+
+    /// Decodes like [`FitDecoder::decode`], reporting the progress to
+    /// `progress`.
+    pub fn decode_with_progress<F: FnMut(Progress)>(
+        &self,
+        fit_data: &[u8],
+        mut progress: F,
+    ) -> Result<FitFile, FitError> {
+        FitFile::parse_impl(
+            fit_data,
+            &self.options,
+            &mut progress,
+            &mut DecoderState::new(),
+        )
+    }
+
+    /// Decodes a file continuing from `state`, as left by earlier files from
+    /// the same device: data records may use the definitions of the earlier
+    /// files. On success `state` is updated to the state after the file, on
+    /// failure it is left as it was, so a clone of it serves as a snapshot
+    /// to restore.
+    pub fn decode_with_state(
+        &self,
+        fit_data: &[u8],
+        state: &mut DecoderState,
+    ) -> Result<FitFile, FitError> {
+        FitFile::parse_impl(fit_data, &self.options, &mut |_| {}, state)
+    }
+
+    /// Decodes the files chained one after another in `fit_data`, each
+    /// continuing from the state of the previous one. Offsets of the
+    /// messages are relative to the start of their file.
+    pub fn decode_chained(&self, fit_data: &[u8]) -> Result<Vec<FitFile>, FitError> {
+        let mut state = DecoderState::new();
+        let mut files = Vec::new();
+        let mut start = 0;
+        while start < fit_data.len() {
+            let header = try_parse_fit_header_from_data(&fit_data[start..])?;
+            // header, data and the file CRC
+            let len = header.header_size() as usize + header.data_size() as usize + 2;
+            let end = fit_data.len().min(start + len);
+            files.push(self.decode_with_state(&fit_data[start..end], &mut state)?);
+            start = end;
+        }
+        Ok(files)
+    }
+}
+
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<FitDecoder>();
+};
+
+impl From<ParserOptions> for FitDecoder {
+    fn from(options: ParserOptions) -> Self {
+        FitDecoder { options }
+    }
+}
+
+/// Builder of a [`FitDecoder`], starting from the default options.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FitDecoderBuilder {
+    options: ParserOptions,
+}
+
+impl FitDecoderBuilder {
+    pub fn crc_policy(mut self, crc: CrcPolicy) -> Self {
+        self.options.crc = crc;
+        self
+    }
+
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.options.lenient = lenient;
+        self
+    }
+
+    /// Keeps only the messages with these global message numbers.
+    pub fn messages<I: IntoIterator<Item = u16>>(mut self, global_message_numbers: I) -> Self {
+        self.options.message_filter = Some(global_message_numbers.into_iter().collect());
+        self
+    }
+
+    pub fn developer_fields(mut self, keep: bool) -> Self {
+        self.options.developer_fields = keep;
+        self
+    }
+
+    /// Whether to apply the scale and offset of the profile, or keep the
+    /// raw values.
+    pub fn scale(mut self, apply: bool) -> Self {
+        self.options.apply_scale = apply;
+        self
+    }
+
+    pub fn strings(mut self, strings: StringHandling) -> Self {
+        self.options.strings = strings;
+        self
+    }
+
+    pub fn limits(mut self, limits: ParseLimits) -> Self {
+        self.options.limits = limits;
+        self
+    }
+
+    /// Checks `token` before each record.
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.options.cancellation = Some(token);
+        self
+    }
+
+    pub fn plausibility(mut self, rules: PlausibilityRules) -> Self {
+        self.options.plausibility = Some(rules);
+        self
+    }
+
+    pub fn strict_protocol(mut self, strict: bool) -> Self {
+        self.options.strict_protocol = strict;
+        self
+    }
+
+    /// Reserves room for `messages` messages up front, see
+    /// [`ParserOptions::capacity_hint`].
+    pub fn capacity_hint(mut self, messages: usize) -> Self {
+        self.options.capacity_hint = Some(messages);
+        self
+    }
+
+    pub fn build(self) -> FitDecoder {
+        FitDecoder::from(self.options)
+    }
+}
+
+#[cfg(test)]
+use crate::diagnostics::DiagnosticKind;
+#[cfg(test)]
+use crate::fit_file::test_file;
+#[cfg(test)]
+use crate::fit_messages::Value;
+
+#[test]
+fn test_decoder_options() {
+    let mut data = test_file(&[
+        0x40, 0x00, 0x00, 0x14, 0x00, 0x02, // record, 2 fields
+        0x03, 0x01, 0x02, // heart_rate
+        0x06, 0x02, 0x84, // speed, uint16
+        0x00, 0x96, 0xE8, 0x03, // heart_rate 150, speed 1000
+        0x41, 0x00, 0x00, 0x12, 0x00, 0x01, // session, 1 field
+        0x05, 0x01, 0x00, // sport
+        0x01, 0x01, // running
+    ]);
+    assert_eq!(FitDecoder::new().decode(&data), FitFile::parse(&data));
+
+    let file = FitDecoder::builder()
+        .messages([20])
+        .scale(false)
+        .build()
+        .decode(&data)
+        .unwrap();
+    assert_eq!(file.messages.len(), 1);
+    assert_eq!(file.messages[0].field(6).unwrap().value, Value::U16(1000));
+
+    // the test file has a zero CRC
+    let crc_end = data.len();
+    assert!(matches!(
+        FitDecoder::builder()
+            .crc_policy(CrcPolicy::Enforce)
+            .build()
+            .decode(&data),
+        Err(FitError::FileCrcMismatch { expected: 0, .. })
+    ));
+    let warned = FitDecoder::builder()
+        .crc_policy(CrcPolicy::Warn)
+        .build()
+        .decode(&data)
+        .unwrap();
+    assert!(matches!(
+        warned.diagnostics.iter().next().unwrap().kind,
+        DiagnosticKind::FileCrcMismatch { expected: 0, .. }
+    ));
+
+    // a definition cut short by the end of the data section
+    data.truncate(crc_end - 6);
+    data[4..8].copy_from_slice(&22u32.to_le_bytes());
+    assert!(FitFile::parse(&data).is_err());
+    let lenient = FitDecoder::builder().lenient(true).build();
+    let file = lenient.decode(&data).unwrap();
+    assert_eq!(file.messages.len(), 1);
+    assert!(matches!(
+        file.diagnostics.iter().next().unwrap().kind,
+        DiagnosticKind::MalformedRecord { .. }
+    ));
+}
+
+#[test]
+fn test_decode_progress_and_cancellation() {
+    let mut records = Vec::new();
+    records.extend_from_slice(&[0x40, 0x00, 0x00, 0x14, 0x00, 0x01, 0x03, 0x01, 0x02]);
+    for _ in 0..40_000 {
+        records.extend_from_slice(&[0x00, 0x96]);
+    }
+    let data = test_file(&records);
+    let mut reports = Vec::new();
+    let file = FitDecoder::new()
+        .decode_with_progress(&data, |p| reports.push(p))
+        .unwrap();
+    assert_eq!(file.messages.len(), 40_000);
+    assert_eq!(reports.len(), 3);
+    assert_eq!(reports[0].bytes_processed, 0);
+    assert!(reports[1].bytes_processed >= 64 * 1024);
+    assert_eq!(reports[2].fraction(), 1.0);
+
+    let token = CancellationToken::new();
+    let decoder = FitDecoder::builder().cancellation(token.clone()).build();
+    let result = decoder.decode_with_progress(&data, |p| {
+        if p.fraction() > 0.5 {
+            token.cancel();
+        }
+    });
+    assert!(matches!(result, Err(FitError::Cancelled { .. })));
+}
+
+#[test]
+fn test_shared_decoder() {
+    let data = std::sync::Arc::new(test_file(&[
+        0x40, 0x00, 0x00, 0x14, 0x00, 0x01, // record, 1 field
+        0x03, 0x01, 0x02, // heart_rate
+        0x00, 0x96, // heart_rate 150
+    ]));
+    let decoder = std::sync::Arc::new(FitDecoder::builder().scale(false).build());
+    let threads = (0..4)
+        .map(|_| {
+            let (decoder, data) = (decoder.clone(), data.clone());
+            std::thread::spawn(move || decoder.decode(&data).unwrap().messages.len())
+        })
+        .collect::<Vec<_>>();
+    for thread in threads {
+        assert_eq!(thread.join().unwrap(), 1);
+    }
+}
+
+#[test]
+fn test_decode_with_state() {
+    let first = test_file(&[
+        0x40, 0x00, 0x00, 0x14, 0x00, 0x01, // record, 1 field
+        0x03, 0x01, 0x02, // heart_rate
+        0x00, 0x96, // heart_rate 150
+    ]);
+    // a file without definitions
+    let second = test_file(&[0x00, 0x97]);
+    let decoder = FitDecoder::new();
+    assert_eq!(decoder.decode(&second).unwrap().messages.len(), 0);
+
+    let mut state = DecoderState::new();
+    decoder.decode_with_state(&first, &mut state).unwrap();
+    assert!(state.is_defined(0));
+    let snapshot = state.clone();
+    let file = decoder.decode_with_state(&second, &mut state).unwrap();
+    assert_eq!(file.messages[0].field(3).unwrap().value, Value::U8(151));
+    assert_eq!(state, snapshot);
+    assert!(decoder.decode_with_state(&[0x01], &mut state).is_err());
+    assert_eq!(state, snapshot);
+
+    let chained = [first, second].concat();
+    let files = decoder.decode_chained(&chained).unwrap();
+    assert_eq!(files.len(), 2);
+    assert_eq!(files[1].messages.len(), 1);
+}
+
+#[cfg(test)]
+use crate::fit_header::ProtocolViolation;
+
+#[test]
+fn test_strict_protocol() {
+    let mut data = test_file(&[
+        0x40, 0x00, 0x00, 0xFF, 0xFF, 0x01, // message 65535, 1 field
+        0x00, 0x08, 0x8F, // uint64
+        0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ]);
+    let strict = FitDecoder::builder().strict_protocol(true).build();
+    let file = FitDecoder::new().decode(&data).unwrap();
+    assert_eq!(file.header.protocol_major_version(), 1);
+    assert_eq!(file.header.protocol_minor_version(), 0);
+    assert_eq!(file.messages.len(), 1);
+    let error = strict.decode(&data).unwrap_err();
+    assert_eq!(
+        error,
+        FitError::ProtocolViolation {
+            violation: ProtocolViolation::Base64Bit { base_type: 0x8F },
+            protocol_version: 0x10,
+            offset: 12
+        }
+    );
+    assert_eq!(
+        error.to_string(),
+        "Definition record at offset 12 has 64-bit base type 0x8f, \
+         not allowed in protocol version 1.0"
+    );
+    // protocol version 2.0
+    data[1] = 0x20;
+    assert!(strict.decode(&data).is_ok());
+}
@@ -0,0 +1,205 @@
+//! Fingerprints of activities for finding the same activity in several
+//! files, such as a ride exported by two platforms. Exports rarely match
+//! byte for byte, so fingerprints compare the start time, duration and
+//! distance of the activity and the serial number of the device that
+//! recorded it.
+
+use crate::fit_file::FitFile;
+use crate::profile::messages::{FileId, Record, Session};
+use crate::profile::{FILE_ID_MESSAGE, RECORD_MESSAGE, SESSION_MESSAGE};
+
+// FNV-1a parameters, for hashes stable across platforms and releases.
+const FNV_OFFSET_BASIS: u64 = 0xCBF2_9CE4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01B3;
+
+// Resolution of the values in hashes: seconds of the start time and the
+// duration, meters of the distance.
+const HASH_TIME_RESOLUTION: f64 = 60.0;
+const HASH_DISTANCE_RESOLUTION: f64 = 100.0;
+
+/// Summary values of an activity identifying it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fingerprint {
+    /// Seconds since the FIT epoch.
+    pub start_time: u32,
+    /// Elapsed seconds.
+    pub duration: f64,
+    /// Meters.
+    pub distance: f64,
+    /// Serial number of the device that created the file.
+    pub serial_number: Option<u32>,
+}
+
+/// Largest differences of fingerprints of the same activity.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuplicateTolerance {
+    /// Seconds.
+    pub start_time: u32,
+    /// Fraction of the longer duration.
+    pub duration: f64,
+    /// Fraction of the longer distance.
+    pub distance: f64,
+}
+
+impl Default for DuplicateTolerance {
+    fn default() -> Self {
+        DuplicateTolerance {
+            start_time: 60,
+            duration: 0.02,
+            distance: 0.02,
+        }
+    }
+}
+
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(hash, |hash, b| (hash ^ *b as u64).wrapping_mul(FNV_PRIME))
+}
+
+// Whether the values differ by at most the fraction of the larger one.
+fn within_fraction(a: f64, b: f64, fraction: f64) -> bool {
+    (a - b).abs() <= a.abs().max(b.abs()) * fraction
+}
+
+impl Fingerprint {
+    /// Hash of the values rounded to minutes and 100 meters, equal for
+    /// files of the same activity with the same serial number unless the
+    /// values round differently. Stable, so it can be stored.
+    pub fn hash(&self) -> u64 {
+        let start_time = (self.start_time as f64 / HASH_TIME_RESOLUTION).round() as u64;
+        let duration = (self.duration / HASH_TIME_RESOLUTION).round() as u64;
+        let distance = (self.distance / HASH_DISTANCE_RESOLUTION).round() as u64;
+        let serial_number = self.serial_number.map_or(u64::MAX, |s| s as u64);
+        [start_time, duration, distance, serial_number]
+            .iter()
+            .fold(FNV_OFFSET_BASIS, |hash, v| fnv1a(hash, &v.to_le_bytes()))
+    }
+
+    /// Whether the fingerprints are of the same activity: the values differ
+    /// at most by the tolerance and the serial numbers match when both are
+    /// known.
+    pub fn is_near_duplicate(&self, other: &Fingerprint, tolerance: &DuplicateTolerance) -> bool {
+        let serial_numbers_match = match (self.serial_number, other.serial_number) {
+            (Some(a), Some(b)) => a == b,
+            _ => true,
+        };
+        serial_numbers_match
+            && self.start_time.abs_diff(other.start_time) <= tolerance.start_time
+            && within_fraction(self.duration, other.duration, tolerance.duration)
+            && within_fraction(self.distance, other.distance, tolerance.distance)
+    }
+}
+
+/// Index pairs of the fingerprints of the same activity, lower index first,
+/// in order of the first index and then the second.
+pub fn find_duplicates(
+    fingerprints: &[Fingerprint],
+    tolerance: &DuplicateTolerance,
+) -> Vec<(usize, usize)> {
+    let mut by_start = (0..fingerprints.len()).collect::<Vec<usize>>();
+    by_start.sort_by_key(|i| fingerprints[*i].start_time);
+    let mut duplicates = Vec::new();
+    for (position, i) in by_start.iter().enumerate() {
+        let a = &fingerprints[*i];
+        for j in &by_start[position + 1..] {
+            let b = &fingerprints[*j];
+            if b.start_time - a.start_time > tolerance.start_time {
+                break;
+            }
+            if a.is_near_duplicate(b, tolerance) {
+                duplicates.push((*i.min(j), *i.max(j)));
+            }
+        }
+    }
+    duplicates.sort();
+    duplicates
+}
+
+impl FitFile {
+    /// Fingerprint of the activity from its sessions, or from its records
+    /// when it has no sessions. `None` without a start time.
+    pub fn fingerprint(&self) -> Option<Fingerprint> {
+        let serial_number = self
+            .typed_messages(FILE_ID_MESSAGE.number, FileId::from_message)
+            .next()
+            .and_then(|file_id| file_id.serial_number);
+        let sessions = self
+            .typed_messages(SESSION_MESSAGE.number, Session::from_message)
+            .collect::<Vec<Session>>();
+        if let Some(start_time) = sessions.iter().filter_map(|s| s.start_time).min() {
+            return Some(Fingerprint {
+                start_time,
+                duration: sessions.iter().filter_map(|s| s.total_elapsed_time).sum(),
+                distance: sessions.iter().filter_map(|s| s.total_distance).sum(),
+                serial_number,
+            });
+        }
+        let records = self
+            .typed_messages(RECORD_MESSAGE.number, Record::from_message)
+            .collect::<Vec<Record>>();
+        let start_time = records.iter().filter_map(|r| r.timestamp).min()?;
+        let end_time = records.iter().filter_map(|r| r.timestamp).max()?;
+        Some(Fingerprint {
+            start_time,
+            duration: (end_time - start_time) as f64,
+            distance: records
+                .iter()
+                .filter_map(|r| r.distance)
+                .fold(0.0, f64::max),
+            serial_number,
+        })
+    }
+}
+
+#[cfg(test)]
+use crate::fit_file::{test_fit_file, test_message};
+#[cfg(test)]
+use crate::fit_messages::Value;
+
+#[test]
+fn test_fingerprints_and_duplicates() {
+    let fingerprint = |start_time, duration, distance, serial_number| Fingerprint {
+        start_time,
+        duration,
+        distance,
+        serial_number,
+    };
+    let fingerprints = [
+        fingerprint(1000, 3600.0, 30000.0, Some(7)),
+        fingerprint(90000, 3600.0, 30000.0, Some(7)),
+        // the first one exported without the device
+        fingerprint(1005, 3590.0, 29950.0, None),
+        // another device at the same time
+        fingerprint(1000, 3600.0, 30000.0, Some(8)),
+    ];
+    let tolerance = DuplicateTolerance::default();
+    assert_eq!(
+        find_duplicates(&fingerprints, &tolerance),
+        vec![(0, 2), (2, 3)]
+    );
+    assert_eq!(
+        fingerprints[0].hash(),
+        fingerprint(1010, 3610.0, 30020.0, Some(7)).hash()
+    );
+    assert_ne!(fingerprints[0].hash(), fingerprints[3].hash());
+
+    let fit_file = test_fit_file(vec![
+        test_message(0, vec![(3, "serial_number", Value::U32(7))]),
+        test_message(
+            20,
+            vec![
+                (253, "timestamp", Value::U32(1000)),
+                (5, "distance", Value::F64(0.0)),
+            ],
+        ),
+        test_message(
+            20,
+            vec![
+                (253, "timestamp", Value::U32(4600)),
+                (5, "distance", Value::F64(30000.0)),
+            ],
+        ),
+    ]);
+    assert_eq!(fit_file.fingerprint(), Some(fingerprints[0].clone()));
+}
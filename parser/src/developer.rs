@@ -0,0 +1,227 @@
+//! Decoding of developer fields, such as the fields added by Connect IQ apps,
+//! using the `field_description` messages of the file.
+
+use crate::fit_file::FitFile;
+use crate::fit_messages::{apply_scale_and_offset, decode_value, DataMessage, Value};
+use crate::fit_records::{get_base_type_info, BaseType, BaseTypeInfo};
+use crate::profile::messages::{DeveloperDataId, FieldDescription};
+use crate::profile::{DEVELOPER_DATA_ID_MESSAGE, FIELD_DESCRIPTION_MESSAGE};
+
+/// Description of a developer field from a `field_description` message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeveloperFieldInfo {
+    pub developer_data_index: u8,
+    pub field_definition_number: u8,
+    pub name: String,
+    pub base_type: &'static BaseTypeInfo,
+    pub units: Option<String>,
+    /// Values are divided by the scale and then the offset is subtracted,
+    /// like profile fields.
+    pub scale: f64,
+    pub offset: f64,
+    /// The profile message and field the developer field stands in for.
+    pub native_message_number: Option<u16>,
+    pub native_field_number: Option<u8>,
+    /// Connect IQ application id of the developer, from the
+    /// `developer_data_id` message of the developer data index.
+    pub application_id: Option<Vec<u8>>,
+}
+
+impl DeveloperFieldInfo {
+    /// Number of the profile field of the message the developer field stands
+    /// in for, if any.
+    pub fn native_field(&self, global_message_number: u16) -> Option<u8> {
+        if self.native_message_number == Some(global_message_number) {
+            self.native_field_number
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn from_description(description: FieldDescription) -> Option<DeveloperFieldInfo> {
+        let base_type = BaseType::try_from(description.fit_base_type_id?).ok()?;
+        Some(DeveloperFieldInfo {
+            developer_data_index: description.developer_data_index?,
+            field_definition_number: description.field_definition_number?,
+            name: description.field_name?.into_iter().next()?,
+            base_type: get_base_type_info(base_type.into()),
+            units: description.units.and_then(|u| u.into_iter().next()),
+            scale: description.scale.filter(|s| *s != 0).map_or(1.0, f64::from),
+            offset: description.offset.map_or(0.0, f64::from),
+            native_message_number: description.native_mesg_num,
+            native_field_number: description.native_field_num,
+            application_id: None,
+        })
+    }
+}
+
+/// The developer fields described in a file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DeveloperFields {
+    fields: Vec<DeveloperFieldInfo>,
+}
+
+impl DeveloperFields {
+    /// Collects the `field_description` messages of the file with the
+    /// application ids of their developers. A later description of the same
+    /// field replaces an earlier one.
+    pub fn from_file(file: &FitFile) -> DeveloperFields {
+        let developers = file
+            .typed_messages(
+                DEVELOPER_DATA_ID_MESSAGE.number,
+                DeveloperDataId::from_message,
+            )
+            .collect::<Vec<DeveloperDataId>>();
+        let mut fields: Vec<DeveloperFieldInfo> = Vec::new();
+        let descriptions = file
+            .typed_messages(
+                FIELD_DESCRIPTION_MESSAGE.number,
+                FieldDescription::from_message,
+            )
+            .filter_map(DeveloperFieldInfo::from_description);
+        for mut info in descriptions {
+            info.application_id = developers
+                .iter()
+                .rev()
+                .find(|d| d.developer_data_index == Some(info.developer_data_index))
+                .and_then(|d| d.application_id.clone());
+            fields.retain(|f| {
+                (f.developer_data_index, f.field_definition_number)
+                    != (info.developer_data_index, info.field_definition_number)
+            });
+            fields.push(info);
+        }
+        DeveloperFields { fields }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &DeveloperFieldInfo> {
+        self.fields.iter()
+    }
+
+    pub fn get(
+        &self,
+        developer_data_index: u8,
+        field_definition_number: u8,
+    ) -> Option<&DeveloperFieldInfo> {
+        self.fields.iter().find(|f| {
+            f.developer_data_index == developer_data_index
+                && f.field_definition_number == field_definition_number
+        })
+    }
+
+    /// Decoded developer fields of the message with their descriptions, with
+    /// the scale and offset of the description applied. Fields without a
+    /// description or a valid value are skipped.
+    pub fn values<'a>(
+        &'a self,
+        message: &'a DataMessage,
+    ) -> impl Iterator<Item = (&'a DeveloperFieldInfo, Value)> + 'a {
+        message.developer_fields.iter().filter_map(|field| {
+            let info = self.get(field.developer_data_index, field.field_number)?;
+            if !field
+                .data
+                .len()
+                .is_multiple_of(info.base_type.size as usize)
+            {
+                return None;
+            }
+            let value = decode_value(info.base_type, &field.architecture, &field.data)?;
+            Some((info, apply_scale_and_offset(value, info.scale, info.offset)))
+        })
+    }
+
+    /// Decoded value of the developer field of the message with the given
+    /// name.
+    pub fn value<'a>(
+        &'a self,
+        message: &'a DataMessage,
+        name: &str,
+    ) -> Option<(&'a DeveloperFieldInfo, Value)> {
+        self.values(message).find(|(info, _)| info.name == name)
+    }
+
+    /// Value of a profile field of the message, or of the developer field
+    /// standing in for it when the message lacks the profile field.
+    pub fn native_value(
+        &self,
+        message: &DataMessage,
+        field_definition_number: u8,
+    ) -> Option<Value> {
+        if let Some(field) = message.field(field_definition_number) {
+            return Some(field.value.clone());
+        }
+        self.values(message)
+            .find(|(info, _)| {
+                info.native_field(message.global_message_number) == Some(field_definition_number)
+            })
+            .map(|(_, value)| value)
+    }
+}
+
+#[cfg(test)]
+use crate::fit_file::test_file;
+
+#[test]
+fn test_developer_fields() {
+    let mut records = vec![
+        0x40, 0x00, 0x00, 0xCE, 0x00, 0x05, // field_description, 5 fields
+        0x00, 0x01, 0x02, // developer_data_index
+        0x01, 0x01, 0x02, // field_definition_number
+        0x02, 0x01, 0x02, // fit_base_type_id
+        0x03, 0x0C, 0x07, // field_name
+        0x08, 0x04, 0x07, // units
+        0x00, 0x00, 0x00, 0x84,
+    ];
+    records.extend_from_slice(b"Ground Time\0ms\0\0");
+    records.extend_from_slice(&[
+        0x61, 0x00, 0x00, 0x14, 0x00, 0x01, // record with developer fields
+        0x03, 0x01, 0x02, // heart_rate
+        0x01, 0x00, 0x02, 0x00, // developer field 0, 2 bytes, index 0
+        0x01, 0x96, 0x0E, 0x01, // heart_rate 150, ground time 270
+    ]);
+    let file = FitFile::parse(&test_file(&records)).unwrap();
+    let developer = DeveloperFields::from_file(&file);
+    assert_eq!(developer.iter().count(), 1);
+    let (info, value) = developer.value(&file.messages[1], "Ground Time").unwrap();
+    assert_eq!(info.units.as_deref(), Some("ms"));
+    assert_eq!(value, Value::U16(270));
+    assert!(developer.value(&file.messages[1], "Form Power").is_none());
+}
+
+#[test]
+fn test_developer_field_scale_and_native_field() {
+    let mut records = vec![
+        0x40, 0x00, 0x00, 0xCE, 0x00, 0x09, // field_description, 9 fields
+        0x00, 0x01, 0x02, // developer_data_index
+        0x01, 0x01, 0x02, // field_definition_number
+        0x02, 0x01, 0x02, // fit_base_type_id
+        0x03, 0x06, 0x07, // field_name
+        0x06, 0x01, 0x02, // scale
+        0x07, 0x01, 0x01, // offset
+        0x08, 0x02, 0x07, // units
+        0x0E, 0x02, 0x84, // native_mesg_num
+        0x0F, 0x01, 0x02, // native_field_num
+        0x00, 0x00, 0x00, 0x84,
+    ];
+    records.extend_from_slice(b"Power\0");
+    records.extend_from_slice(&[0x0A, 0x05]); // scale 10, offset 5
+    records.extend_from_slice(b"W\0");
+    records.extend_from_slice(&[0x14, 0x00, 0x07]); // record power
+    records.extend_from_slice(&[
+        0x61, 0x00, 0x00, 0x14, 0x00, 0x00, // record with developer fields
+        0x01, 0x00, 0x02, 0x00, // developer field 0, 2 bytes, index 0
+        0x01, 0xC4, 0x09, // 2500
+    ]);
+    let file = FitFile::parse(&test_file(&records)).unwrap();
+    let developer = DeveloperFields::from_file(&file);
+    let info = developer.iter().next().unwrap();
+    assert_eq!((info.scale, info.offset), (10.0, 5.0));
+    assert_eq!(info.native_field(20), Some(7));
+    let (_, value) = developer.value(&file.messages[1], "Power").unwrap();
+    assert_eq!(value, Value::F64(245.0));
+    assert_eq!(
+        developer.native_value(&file.messages[1], 7),
+        Some(Value::F64(245.0))
+    );
+    assert_eq!(developer.native_value(&file.messages[1], 3), None);
+}
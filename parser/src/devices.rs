@@ -0,0 +1,425 @@
+//! The devices and sensors of a file, folded from its `device_info`
+//! messages, and a timeline of their battery and connection events.
+
+use crate::fit_file::FitFile;
+use crate::fit_messages::DataMessage;
+use crate::profile::messages::{DeviceInfo, Event as EventMessage};
+use crate::profile::{
+    type_info, Event, COMM_TIMEOUT_TYPE_TYPE, DEVICE_INFO_MESSAGE, EVENT_MESSAGE,
+};
+
+// Field numbers of `device_info` fields with names resolved from the profile.
+const DEVICE_TYPE_FIELD_NUMBER: u8 = 1;
+const MANUFACTURER_FIELD_NUMBER: u8 = 2;
+const PRODUCT_FIELD_NUMBER: u8 = 4;
+const BATTERY_STATUS_FIELD_NUMBER: u8 = 11;
+
+// `device_index` of the device that created the file.
+const CREATOR_DEVICE_INDEX: u8 = 0;
+
+// Profile name of the value of a `device_info` field, using the type of the
+// active subfield.
+fn value_name(message: &DataMessage, field_definition_number: u8) -> Option<&'static str> {
+    let field = message.field(field_definition_number)?;
+    let field_info = DEVICE_INFO_MESSAGE.field(field_definition_number)?;
+    let field_type = match field.name {
+        Some(name) if name != field_info.name => field_info.subfield(name)?.field_type,
+        _ => field_info.field_type,
+    };
+    type_info(field_type)?.value_name(u32::try_from(field.value.as_u64()?).ok()?)
+}
+
+/// Battery state of a device at a point in time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatteryReading {
+    /// Seconds since the FIT epoch.
+    pub timestamp: u32,
+    /// Volts.
+    pub voltage: Option<f64>,
+    /// `battery_status` value.
+    pub status: Option<u8>,
+    /// Profile name of the status, such as `good` or `low`.
+    pub status_name: Option<&'static str>,
+}
+
+/// A device or sensor with the latest values of its `device_info` messages.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Device {
+    pub device_index: Option<u8>,
+    pub device_type: Option<u8>,
+    /// Profile name of the device type, such as `heart_rate` for ANT+
+    /// sensors.
+    pub device_type_name: Option<&'static str>,
+    pub manufacturer: Option<u16>,
+    pub manufacturer_name: Option<&'static str>,
+    pub product: Option<u16>,
+    /// `product_name` of the messages, or the profile name of the product.
+    pub product_name: Option<String>,
+    pub serial_number: Option<u32>,
+    pub software_version: Option<f64>,
+    pub hardware_version: Option<u8>,
+    /// `source_type` value, such as ANT+ or Bluetooth Low Energy.
+    pub source_type: Option<u8>,
+    pub ant_device_number: Option<u16>,
+    pub descriptor: Option<String>,
+    /// Battery readings in time order.
+    pub battery: Vec<BatteryReading>,
+}
+
+impl Device {
+    /// Whether the device created the file.
+    pub fn is_creator(&self) -> bool {
+        self.device_index == Some(CREATOR_DEVICE_INDEX)
+    }
+
+    /// The last battery reading.
+    pub fn last_battery(&self) -> Option<&BatteryReading> {
+        self.battery.last()
+    }
+
+    // Whether the message describes this device: by serial number when both
+    // have one, otherwise by device index, type, manufacturer and product.
+    fn matches(&self, info: &DeviceInfo) -> bool {
+        if let (Some(serial), Some(other)) = (self.serial_number, info.serial_number) {
+            return serial == other;
+        }
+        let same = |a: Option<u16>, b: Option<u16>| a.is_none() || b.is_none() || a == b;
+        self.device_index == info.device_index
+            && (self.device_type.is_none()
+                || info.device_type.is_none()
+                || self.device_type == info.device_type)
+            && same(self.manufacturer, info.manufacturer)
+            && same(self.product, info.product)
+            && same(self.ant_device_number, info.ant_device_number)
+    }
+
+    // Takes the values of a later message, keeping values it lacks.
+    fn update(&mut self, message: &DataMessage, info: DeviceInfo) {
+        self.device_index = info.device_index.or(self.device_index);
+        if info.device_type.is_some() {
+            self.device_type = info.device_type;
+            self.device_type_name = value_name(message, DEVICE_TYPE_FIELD_NUMBER);
+        }
+        if info.manufacturer.is_some() {
+            self.manufacturer = info.manufacturer;
+            self.manufacturer_name = value_name(message, MANUFACTURER_FIELD_NUMBER);
+        }
+        if info.product.is_some() {
+            self.product = info.product;
+        }
+        let product_name = info
+            .product_name
+            .or_else(|| value_name(message, PRODUCT_FIELD_NUMBER).map(String::from));
+        self.product_name = product_name.or(self.product_name.take());
+        self.serial_number = info.serial_number.or(self.serial_number);
+        self.software_version = info.software_version.or(self.software_version);
+        self.hardware_version = info.hardware_version.or(self.hardware_version);
+        self.source_type = info.source_type.or(self.source_type);
+        self.ant_device_number = info.ant_device_number.or(self.ant_device_number);
+        self.descriptor = info.descriptor.or(self.descriptor.take());
+        if let Some(timestamp) = info.timestamp {
+            if info.battery_voltage.is_some() || info.battery_status.is_some() {
+                self.battery.push(BatteryReading {
+                    timestamp,
+                    voltage: info.battery_voltage,
+                    status: info.battery_status,
+                    status_name: value_name(message, BATTERY_STATUS_FIELD_NUMBER),
+                });
+            }
+        }
+    }
+}
+
+/// What happened to a device at a point of the timeline.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeviceEventKind {
+    /// Battery state from a `device_info` message or a `battery` event.
+    Battery {
+        /// Volts.
+        voltage: Option<f64>,
+        /// `battery_status` value.
+        status: Option<u8>,
+    },
+    /// A `battery_low` event.
+    BatteryLow,
+    /// A `comm_timeout` event with its `comm_timeout_type` value.
+    CommTimeout(u16),
+    /// The first `device_info` message of a device after a communication
+    /// timeout of it.
+    Reconnected,
+}
+
+/// A battery or connection event of a device.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceEvent {
+    /// Seconds since the FIT epoch.
+    pub timestamp: u32,
+    /// Index of the device in `device_info` messages, `None` for events that
+    /// do not name one.
+    pub device_index: Option<u8>,
+    pub kind: DeviceEventKind,
+}
+
+impl DeviceEvent {
+    /// Profile name of the timeout type of `CommTimeout` events, such as
+    /// `connection_lost`.
+    pub fn comm_timeout_name(&self) -> Option<&'static str> {
+        match self.kind {
+            DeviceEventKind::CommTimeout(timeout) => {
+                COMM_TIMEOUT_TYPE_TYPE.value_name(timeout.into())
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A period in which a device was not connected, from a communication
+/// timeout to its reconnection.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dropout {
+    pub device_index: Option<u8>,
+    /// Seconds since the FIT epoch.
+    pub start: u32,
+    /// `None` if the device did not reconnect.
+    pub end: Option<u32>,
+    /// `comm_timeout_type` value.
+    pub timeout_type: u16,
+}
+
+impl Dropout {
+    /// Seconds disconnected, `None` if the device did not reconnect.
+    pub fn duration(&self) -> Option<u32> {
+        Some(self.end? - self.start)
+    }
+}
+
+// Battery and timeout events of an `event` message.
+fn event_kind(message: &DataMessage, event: &EventMessage) -> Option<DeviceEventKind> {
+    match event.event? {
+        e if e == Event::Battery as u8 => Some(DeviceEventKind::Battery {
+            voltage: message.field_by_name("battery_level")?.value.as_f64(),
+            status: None,
+        }),
+        e if e == Event::BatteryLow as u8 => Some(DeviceEventKind::BatteryLow),
+        e if e == Event::CommTimeout as u8 => {
+            let timeout = message.field_by_name("comm_timeout")?.value.as_u64()?;
+            Some(DeviceEventKind::CommTimeout(u16::try_from(timeout).ok()?))
+        }
+        _ => None,
+    }
+}
+
+impl FitFile {
+    /// The devices of the `device_info` messages in order of first
+    /// appearance, one entry per device.
+    pub fn devices(&self) -> Vec<Device> {
+        let mut messages = self
+            .messages
+            .iter()
+            .filter(|m| m.global_message_number == DEVICE_INFO_MESSAGE.number)
+            .collect::<Vec<&DataMessage>>();
+        messages.sort_by_key(|m| m.timestamp());
+        let mut devices: Vec<Device> = Vec::new();
+        for message in messages {
+            let info = DeviceInfo::from_message(message);
+            match devices.iter_mut().find(|d| d.matches(&info)) {
+                Some(device) => device.update(message, info),
+                None => {
+                    let mut device = Device::default();
+                    device.update(message, info);
+                    devices.push(device);
+                }
+            }
+        }
+        devices
+    }
+
+    /// Battery and connection events of the devices in time order.
+    pub fn device_timeline(&self) -> Vec<DeviceEvent> {
+        let mut messages = self
+            .messages
+            .iter()
+            .filter(|m| {
+                m.global_message_number == DEVICE_INFO_MESSAGE.number
+                    || m.global_message_number == EVENT_MESSAGE.number
+            })
+            .filter(|m| m.timestamp().is_some())
+            .collect::<Vec<&DataMessage>>();
+        messages.sort_by_key(|m| m.timestamp());
+        let mut timed_out: Vec<Option<u8>> = Vec::new();
+        let mut timeline = Vec::new();
+        for message in messages {
+            let timestamp = message.timestamp().unwrap_or_default();
+            if message.global_message_number == EVENT_MESSAGE.number {
+                let event = EventMessage::from_message(message);
+                if let Some(kind) = event_kind(message, &event) {
+                    if let DeviceEventKind::CommTimeout(_) = kind {
+                        timed_out.push(event.device_index);
+                    }
+                    timeline.push(DeviceEvent {
+                        timestamp,
+                        device_index: event.device_index,
+                        kind,
+                    });
+                }
+                continue;
+            }
+            let info = DeviceInfo::from_message(message);
+            if let Some(idx) = timed_out.iter().position(|d| *d == info.device_index) {
+                timed_out.remove(idx);
+                timeline.push(DeviceEvent {
+                    timestamp,
+                    device_index: info.device_index,
+                    kind: DeviceEventKind::Reconnected,
+                });
+            }
+            if info.battery_voltage.is_some() || info.battery_status.is_some() {
+                timeline.push(DeviceEvent {
+                    timestamp,
+                    device_index: info.device_index,
+                    kind: DeviceEventKind::Battery {
+                        voltage: info.battery_voltage,
+                        status: info.battery_status,
+                    },
+                });
+            }
+        }
+        timeline
+    }
+
+    /// Periods in which devices were disconnected, from each communication
+    /// timeout to the next reconnection of the device.
+    pub fn sensor_dropouts(&self) -> Vec<Dropout> {
+        let mut dropouts: Vec<Dropout> = Vec::new();
+        for event in self.device_timeline() {
+            match event.kind {
+                DeviceEventKind::CommTimeout(timeout_type) => {
+                    let open = dropouts
+                        .iter()
+                        .any(|d| d.device_index == event.device_index && d.end.is_none());
+                    if !open {
+                        dropouts.push(Dropout {
+                            device_index: event.device_index,
+                            start: event.timestamp,
+                            end: None,
+                            timeout_type,
+                        });
+                    }
+                }
+                DeviceEventKind::Reconnected => {
+                    if let Some(dropout) = dropouts
+                        .iter_mut()
+                        .find(|d| d.device_index == event.device_index && d.end.is_none())
+                    {
+                        dropout.end = Some(event.timestamp);
+                    }
+                }
+                _ => {}
+            }
+        }
+        dropouts
+    }
+}
+
+#[cfg(test)]
+use crate::fit_file::{test_fit_file, test_message};
+#[cfg(test)]
+use crate::fit_messages::Value;
+
+#[test]
+fn test_devices() {
+    let info = |t: u32, index: u8, serial: u32, voltage: f64, status: u8| {
+        test_message(
+            DEVICE_INFO_MESSAGE.number,
+            vec![
+                (253, "timestamp", Value::U32(t)),
+                (0, "device_index", Value::U8(index)),
+                (1, "antplus_device_type", Value::U8(120)),
+                (2, "manufacturer", Value::U16(1)),
+                (3, "serial_number", Value::U32(serial)),
+                (4, "garmin_product", Value::U16(1)),
+                (5, "software_version", Value::F64(4.5)),
+                (10, "battery_voltage", Value::F64(voltage)),
+                (11, "battery_status", Value::U8(status)),
+            ],
+        )
+    };
+    let file = test_fit_file(vec![
+        info(1000, 1, 1234, 3.0, 2),
+        info(1000, 2, 5678, 2.9, 2),
+        info(5000, 1, 1234, 2.7, 4),
+    ]);
+    let devices = file.devices();
+    assert_eq!(devices.len(), 2);
+    assert!(!devices[0].is_creator());
+    assert_eq!(devices[0].serial_number, Some(1234));
+    assert_eq!(devices[0].manufacturer_name, Some("garmin"));
+    assert_eq!(devices[0].device_type_name, Some("heart_rate"));
+    assert_eq!(devices[0].product_name.as_deref(), Some("hrm1"));
+    assert_eq!(devices[0].battery.len(), 2);
+    let last = devices[0].last_battery().unwrap();
+    assert_eq!(last.timestamp, 5000);
+    assert_eq!(last.voltage, Some(2.7));
+    assert_eq!(last.status_name, Some("low"));
+    assert_eq!(devices[1].battery[0].status_name, Some("good"));
+}
+
+#[test]
+fn test_device_timeline() {
+    let info = |t: u32, voltage: f64| {
+        test_message(
+            DEVICE_INFO_MESSAGE.number,
+            vec![
+                (253, "timestamp", Value::U32(t)),
+                (0, "device_index", Value::U8(1)),
+                (10, "battery_voltage", Value::F64(voltage)),
+            ],
+        )
+    };
+    let timeout = |t: u32| {
+        test_message(
+            EVENT_MESSAGE.number,
+            vec![
+                (253, "timestamp", Value::U32(t)),
+                (0, "event", Value::U8(Event::CommTimeout as u8)),
+                (3, "comm_timeout", Value::U16(2)),
+                (13, "device_index", Value::U8(1)),
+            ],
+        )
+    };
+    let file = test_fit_file(vec![
+        info(1000, 3.0),
+        timeout(1500),
+        info(1600, 2.9),
+        test_message(
+            EVENT_MESSAGE.number,
+            vec![
+                (253, "timestamp", Value::U32(1700)),
+                (0, "event", Value::U8(Event::Battery as u8)),
+                (3, "battery_level", Value::F64(3.9)),
+            ],
+        ),
+        timeout(2000),
+    ]);
+    let timeline = file.device_timeline();
+    assert_eq!(timeline.len(), 6);
+    assert_eq!(timeline[1].kind, DeviceEventKind::CommTimeout(2));
+    assert_eq!(timeline[1].comm_timeout_name(), Some("connection_lost"));
+    assert_eq!(timeline[2].kind, DeviceEventKind::Reconnected);
+    assert_eq!(
+        timeline[4],
+        DeviceEvent {
+            timestamp: 1700,
+            device_index: None,
+            kind: DeviceEventKind::Battery {
+                voltage: Some(3.9),
+                status: None,
+            },
+        }
+    );
+    let dropouts = file.sensor_dropouts();
+    assert_eq!(dropouts.len(), 2);
+    assert_eq!(dropouts[0].duration(), Some(100));
+    assert_eq!(dropouts[1].start, 2000);
+    assert_eq!(dropouts[1].end, None);
+}
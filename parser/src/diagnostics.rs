@@ -0,0 +1,252 @@
+use std::fmt;
+
+use crate::error::FitError;
+use crate::fit_header::format_profile_version;
+use crate::profile::PROFILE_VERSION;
+
+/// How serious a diagnostic is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// The file is fine but uses something this crate does not know, such
+    /// as a newer profile.
+    Info,
+    /// A value is suspect or decoded differently than declared.
+    Warning,
+    /// Data is missing or corrupt.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Info => write!(f, "info"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// Kind of a non-fatal issue found while parsing.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiagnosticKind {
+    /// Value of an enum typed field is not a value of the enum in the profile.
+    UnknownEnumValue {
+        global_message_number: u16,
+        field_definition_number: u8,
+        value: u32,
+    },
+    /// Size of a field is not a multiple of the size of its base type. The
+    /// field is decoded as a byte array.
+    FieldSizeMismatch {
+        global_message_number: u16,
+        field_definition_number: u8,
+        field_size: usize,
+        base_type_size: u8,
+    },
+    /// Scaling a field value did not produce a finite number.
+    ScaledValueOutOfRange {
+        global_message_number: u16,
+        field_definition_number: u8,
+        value: f64,
+    },
+    /// A data record refers to a local message type that has no definition.
+    /// The rest of the data section can not be read after this.
+    UndefinedLocalMessageType { local_message_type: u8 },
+    /// File CRC does not match the header and data section.
+    FileCrcMismatch { expected: u16, calculated: u16 },
+    /// A malformed record ended a lenient parse. The rest of the data
+    /// section is skipped.
+    MalformedRecord { error: FitError },
+    /// A field value is outside the range of a plausibility rule.
+    ImplausibleValue {
+        global_message_number: u16,
+        field_definition_number: u8,
+        value: f64,
+    },
+    /// The file was written with a newer profile than the one of this crate
+    /// and may have messages, fields or enum values it does not know.
+    NewerProfile { profile_version: u16 },
+}
+
+impl DiagnosticKind {
+    /// Code of the kind, such as `FIT0001`, for filtering diagnostics.
+    /// Codes stay the same across versions and are never reused;
+    /// conformance issues have codes from `FIT1001`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::UnknownEnumValue { .. } => "FIT0001",
+            Self::FieldSizeMismatch { .. } => "FIT0002",
+            Self::ScaledValueOutOfRange { .. } => "FIT0003",
+            Self::UndefinedLocalMessageType { .. } => "FIT0004",
+            Self::FileCrcMismatch { .. } => "FIT0005",
+            Self::MalformedRecord { .. } => "FIT0006",
+            Self::ImplausibleValue { .. } => "FIT0007",
+            Self::NewerProfile { .. } => "FIT0008",
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        match self {
+            Self::UnknownEnumValue { .. } | Self::NewerProfile { .. } => Severity::Info,
+            Self::FieldSizeMismatch { .. }
+            | Self::ScaledValueOutOfRange { .. }
+            | Self::ImplausibleValue { .. } => Severity::Warning,
+            Self::UndefinedLocalMessageType { .. }
+            | Self::FileCrcMismatch { .. }
+            | Self::MalformedRecord { .. } => Severity::Error,
+        }
+    }
+}
+
+impl fmt::Display for DiagnosticKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownEnumValue {
+                global_message_number,
+                field_definition_number,
+                value,
+            } => write!(
+                f,
+                "Unknown enum value {} in field {} of message {}",
+                value, field_definition_number, global_message_number
+            ),
+            Self::FieldSizeMismatch {
+                global_message_number,
+                field_definition_number,
+                field_size,
+                base_type_size,
+            } => write!(
+                f,
+                "Size {} of field {} of message {} is not a multiple of base type size {}, decoded as bytes",
+                field_size, field_definition_number, global_message_number, base_type_size
+            ),
+            Self::ScaledValueOutOfRange {
+                global_message_number,
+                field_definition_number,
+                value,
+            } => write!(
+                f,
+                "Scaled value {} out of range in field {} of message {}",
+                value, field_definition_number, global_message_number
+            ),
+            Self::UndefinedLocalMessageType { local_message_type } => write!(
+                f,
+                "Data record refers to undefined local message type {}",
+                local_message_type
+            ),
+            Self::FileCrcMismatch {
+                expected,
+                calculated,
+            } => write!(
+                f,
+                "File CRC {:#06x} does not match calculated CRC {:#06x}",
+                expected, calculated
+            ),
+            Self::MalformedRecord { error } => {
+                write!(f, "Malformed record, rest of the data skipped: {}", error)
+            }
+            Self::ImplausibleValue {
+                global_message_number,
+                field_definition_number,
+                value,
+            } => write!(
+                f,
+                "Implausible value {} in field {} of message {}",
+                value, field_definition_number, global_message_number
+            ),
+            Self::NewerProfile { profile_version } => write!(
+                f,
+                "Profile version {} of the file is newer than supported profile version {}",
+                format_profile_version(*profile_version),
+                format_profile_version(PROFILE_VERSION)
+            ),
+        }
+    }
+}
+
+/// A non-fatal issue found while parsing, with the byte offset of the record
+/// it was found in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub offset: usize,
+    pub kind: DiagnosticKind,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (offset {:#x})", self.kind, self.offset)
+    }
+}
+
+/// Sink collecting the diagnostics of a parse, retrievable afterwards.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Diagnostics {
+    entries: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Diagnostics {
+        Diagnostics::default()
+    }
+
+    pub fn push(&mut self, offset: usize, kind: DiagnosticKind) {
+        self.entries.push(Diagnostic { offset, kind });
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Diagnostic> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl<'a> IntoIterator for &'a Diagnostics {
+    type Item = &'a Diagnostic;
+    type IntoIter = std::slice::Iter<'a, Diagnostic>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.iter()
+    }
+}
+
+#[test]
+fn test_diagnostic_codes() {
+    let kinds = [
+        DiagnosticKind::UnknownEnumValue {
+            global_message_number: 21,
+            field_definition_number: 0,
+            value: 200,
+        },
+        DiagnosticKind::FileCrcMismatch {
+            expected: 0,
+            calculated: 1,
+        },
+        DiagnosticKind::ImplausibleValue {
+            global_message_number: 20,
+            field_definition_number: 3,
+            value: 10.0,
+        },
+        DiagnosticKind::NewerProfile {
+            profile_version: 2200,
+        },
+    ];
+    let codes = kinds
+        .iter()
+        .map(|k| (k.code(), k.severity()))
+        .collect::<Vec<(&str, Severity)>>();
+    assert_eq!(
+        codes,
+        vec![
+            ("FIT0001", Severity::Info),
+            ("FIT0005", Severity::Error),
+            ("FIT0007", Severity::Warning),
+            ("FIT0008", Severity::Info)
+        ]
+    );
+}
@@ -0,0 +1,201 @@
+//! Dive logs of Garmin Descent devices: settings, gases, summaries, depth
+//! and tank pressures.
+
+use crate::fit_file::FitFile;
+use crate::fit_messages::DataMessage;
+use crate::profile::messages::{DiveGas, DiveSettings, DiveSummary, Record};
+use crate::profile::{
+    DIVE_GAS_MESSAGE, DIVE_SETTINGS_MESSAGE, DIVE_SUMMARY_MESSAGE, LAP_MESSAGE, RECORD_MESSAGE,
+    SESSION_MESSAGE,
+};
+
+/// Global message number of `tank_update`, which is newer than the bundled
+/// profile and decoded by its number.
+pub const TANK_UPDATE_MESSAGE_NUMBER: u16 = 319;
+/// Global message number of `tank_summary`, decoded like `tank_update`.
+pub const TANK_SUMMARY_MESSAGE_NUMBER: u16 = 323;
+
+// Tank pressures are stored in hundredths of a bar and volumes in hundredths
+// of a liter.
+const TANK_SCALE: f64 = 100.0;
+
+/// A pressure reading of a tank pressure sensor.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TankPressure {
+    /// Seconds since the FIT epoch.
+    pub timestamp: u32,
+    /// ANT channel id of the sensor.
+    pub sensor: u32,
+    /// Bar.
+    pub pressure: f64,
+}
+
+/// Gas used from a tank over a dive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TankSummary {
+    pub sensor: u32,
+    /// Bar.
+    pub start_pressure: Option<f64>,
+    pub end_pressure: Option<f64>,
+    /// Liters.
+    pub volume_used: Option<f64>,
+}
+
+fn scaled(message: &DataMessage, field_definition_number: u8) -> Option<f64> {
+    Some(message.field(field_definition_number)?.value.as_f64()? / TANK_SCALE)
+}
+
+fn sensor(message: &DataMessage) -> Option<u32> {
+    u32::try_from(message.field(0)?.value.as_u64()?).ok()
+}
+
+/// A depth sample of the dive profile.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DepthSample {
+    /// Seconds since the FIT epoch.
+    pub timestamp: u32,
+    /// Meters.
+    pub depth: f64,
+    /// Seconds of no decompression limit left.
+    pub ndl_time: Option<u32>,
+    /// Meters, when decompression stops are needed.
+    pub next_stop_depth: Option<f64>,
+}
+
+/// The dive messages of a file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DiveLog {
+    pub settings: Vec<DiveSettings>,
+    pub gases: Vec<DiveGas>,
+    pub summaries: Vec<DiveSummary>,
+    pub tank_pressures: Vec<TankPressure>,
+    pub tank_summaries: Vec<TankSummary>,
+    pub depth_profile: Vec<DepthSample>,
+}
+
+impl DiveLog {
+    pub fn from_file(file: &FitFile) -> DiveLog {
+        let messages = |number: u16| {
+            file.messages
+                .iter()
+                .filter(move |m| m.global_message_number == number)
+        };
+        DiveLog {
+            settings: file
+                .typed_messages(DIVE_SETTINGS_MESSAGE.number, DiveSettings::from_message)
+                .collect(),
+            gases: file
+                .typed_messages(DIVE_GAS_MESSAGE.number, DiveGas::from_message)
+                .collect(),
+            summaries: file
+                .typed_messages(DIVE_SUMMARY_MESSAGE.number, DiveSummary::from_message)
+                .collect(),
+            tank_pressures: messages(TANK_UPDATE_MESSAGE_NUMBER)
+                .filter_map(|m| {
+                    Some(TankPressure {
+                        timestamp: m.timestamp()?,
+                        sensor: sensor(m)?,
+                        pressure: scaled(m, 1)?,
+                    })
+                })
+                .collect(),
+            tank_summaries: messages(TANK_SUMMARY_MESSAGE_NUMBER)
+                .filter_map(|m| {
+                    Some(TankSummary {
+                        sensor: sensor(m)?,
+                        start_pressure: scaled(m, 1),
+                        end_pressure: scaled(m, 2),
+                        volume_used: scaled(m, 3),
+                    })
+                })
+                .collect(),
+            depth_profile: file
+                .typed_messages(RECORD_MESSAGE.number, Record::from_message)
+                .filter_map(|r| {
+                    Some(DepthSample {
+                        timestamp: r.timestamp?,
+                        depth: r.depth?,
+                        ndl_time: r.ndl_time,
+                        next_stop_depth: r.next_stop_depth,
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    /// Summary of the whole dive, referring to the session.
+    pub fn session_summary(&self) -> Option<&DiveSummary> {
+        self.summaries
+            .iter()
+            .find(|s| s.reference_mesg == Some(SESSION_MESSAGE.number))
+    }
+
+    /// Summaries of the laps of the dive, by lap index.
+    pub fn lap_summaries(&self) -> impl Iterator<Item = &DiveSummary> {
+        self.summaries
+            .iter()
+            .filter(|s| s.reference_mesg == Some(LAP_MESSAGE.number))
+    }
+
+    /// Deepest depth of the depth profile in meters.
+    pub fn max_depth(&self) -> Option<f64> {
+        self.depth_profile.iter().map(|s| s.depth).reduce(f64::max)
+    }
+}
+
+impl FitFile {
+    pub fn dive_log(&self) -> DiveLog {
+        DiveLog::from_file(self)
+    }
+}
+
+#[cfg(test)]
+use crate::fit_file::test_file;
+
+#[test]
+fn test_dive_settings_heart_rate_source_subfield() {
+    let file = FitFile::parse(&test_file(&[
+        0x40, 0x00, 0x00, 0x02, 0x01, 0x02, // dive_settings, 2 fields
+        0x13, 0x01, 0x00, // heart_rate_source_type
+        0x14, 0x01, 0x02, // heart_rate_source
+        0x00, 0x05, 0x0A, // local, wrist heart rate
+    ]))
+    .unwrap();
+    assert_eq!(
+        file.messages[0].field(20).unwrap().name,
+        Some("heart_rate_local_device_type")
+    );
+    assert_eq!(file.dive_log().settings[0].heart_rate_source, Some(10));
+}
+
+#[test]
+fn test_dive_log() {
+    let mut records = vec![
+        0x40, 0x00, 0x00, 0x0C, 0x01, 0x03, // dive_summary, 3 fields
+        0x00, 0x02, 0x84, // reference_mesg
+        0x03, 0x04, 0x86, // max_depth
+        0x0B, 0x04, 0x86, // bottom_time
+        0x00, 0x12, 0x00,
+    ];
+    records.extend_from_slice(&30500u32.to_le_bytes());
+    records.extend_from_slice(&2400000u32.to_le_bytes());
+    records.extend_from_slice(&[
+        0x41, 0x00, 0x00, 0x3F, 0x01, 0x03, // tank_update, 3 fields
+        0xFD, 0x04, 0x86, // timestamp
+        0x00, 0x04, 0x8C, // sensor
+        0x01, 0x02, 0x84, // pressure
+        0x01, 0x00, 0x10, 0x00, 0x00, 0x39, 0x30, 0x00, 0x00, 0x94, 0x4E,
+    ]);
+    let log = FitFile::parse(&test_file(&records)).unwrap().dive_log();
+    let summary = log.session_summary().unwrap();
+    assert_eq!(summary.max_depth, Some(30.5));
+    assert_eq!(summary.bottom_time, Some(2400.0));
+    assert_eq!(
+        log.tank_pressures,
+        vec![TankPressure {
+            timestamp: 4096,
+            sensor: 12345,
+            pressure: 201.16
+        }]
+    );
+}
@@ -0,0 +1,584 @@
+//! In-place edits of the field values of encoded FIT files.
+//!
+//! Edits keep the layout of the file: values can be changed or invalidated
+//! but fields can not be added or removed. The file CRC is recomputed after
+//! editing. Developer data is the exception, [`rewrite_developer_data`]
+//! removes its records and fields.
+//!
+//! Small fixes, such as of the `file_id` message, can be patched into the
+//! file in place with [`edit_records_in_place`] and [`patch_file_id`],
+//! without copying or decoding the file.
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+use crate::crc::FitCrc;
+use crate::error::FitError;
+use crate::fit_header::try_parse_fit_header_from_data;
+use crate::fit_messages::{decode_value, Value};
+use crate::fit_records::{
+    parse_record_header, BaseType, BaseTypeInfo, DefinitionRecord, Endianness, Record,
+    RecordHeader, RecordIterator, LOCAL_MESSAGE_TYPE_COUNT,
+};
+use crate::profile::{
+    DEVELOPER_DATA_ID_MESSAGE, FIELD_DESCRIPTION_MESSAGE, FILE_ID_MESSAGE, LAP_MESSAGE,
+    SESSION_MESSAGE, SPORT_MESSAGE,
+};
+
+// Field numbers of `developer_data_index` in the messages describing
+// developer data.
+const DEVELOPER_DATA_ID_INDEX_FIELD_NUMBER: u8 = 3;
+const FIELD_DESCRIPTION_INDEX_FIELD_NUMBER: u8 = 0;
+
+// Field numbers of `sport` and `sub_sport` in the messages that have them.
+const SPORT_FIELD_NUMBERS: [(u16, u8, u8); 3] = [
+    (SESSION_MESSAGE.number, 5, 6),
+    (LAP_MESSAGE.number, 25, 39),
+    (SPORT_MESSAGE.number, 0, 1),
+];
+
+// Bit of the definition record header telling that developer field
+// definitions follow the field definitions.
+const DEVELOPER_DATA_FLAG: u8 = 0x20;
+
+// Position of a field value within the bytes of its record.
+#[derive(Debug, Clone)]
+struct FieldSpan {
+    field_definition_number: u8,
+    base_type: &'static BaseTypeInfo,
+    start: usize,
+    len: usize,
+}
+
+/// A data record of a file being edited, with its raw, unscaled field
+/// values.
+#[derive(Debug)]
+pub struct EditableRecord<'a> {
+    pub global_message_number: u16,
+    architecture: Endianness,
+    fields: Vec<FieldSpan>,
+    // The record header byte followed by the field values.
+    bytes: &'a mut [u8],
+}
+
+pub(crate) fn write_bits(bytes: &mut [u8], architecture: &Endianness, bits: u64) {
+    match (bytes.len(), architecture) {
+        (1, _) => bytes[0] = bits as u8,
+        (2, Endianness::LittleEndian) => LittleEndian::write_u16(bytes, bits as u16),
+        (2, Endianness::BigEndian) => BigEndian::write_u16(bytes, bits as u16),
+        (4, Endianness::LittleEndian) => LittleEndian::write_u32(bytes, bits as u32),
+        (4, Endianness::BigEndian) => BigEndian::write_u32(bytes, bits as u32),
+        (8, Endianness::LittleEndian) => LittleEndian::write_u64(bytes, bits),
+        (8, Endianness::BigEndian) => BigEndian::write_u64(bytes, bits),
+        (size, _) => panic!("Unsupported base type size {}", size),
+    }
+}
+
+impl EditableRecord<'_> {
+    /// Field definition numbers of the fields of the record.
+    pub fn field_numbers(&self) -> Vec<u8> {
+        self.fields
+            .iter()
+            .map(|f| f.field_definition_number)
+            .collect()
+    }
+
+    /// Lowest five bits of the timestamp of a record with a compressed
+    /// timestamp header, `None` for records with a normal header.
+    pub fn compressed_time_offset(&self) -> Option<u8> {
+        match parse_record_header(self.bytes[0]) {
+            RecordHeader::CompressedTimestamp(header) => Some(header.time_offset),
+            _ => None,
+        }
+    }
+
+    /// Sets the lowest five bits of the timestamp of a record with a
+    /// compressed timestamp header. Returns whether the record has one.
+    pub fn set_compressed_time_offset(&mut self, time_offset: u8) -> bool {
+        if self.compressed_time_offset().is_none() {
+            return false;
+        }
+        self.bytes[0] = (self.bytes[0] & !0x1F) | (time_offset & 0x1F);
+        true
+    }
+
+    fn span(&self, field_definition_number: u8) -> Option<FieldSpan> {
+        self.fields
+            .iter()
+            .find(|f| f.field_definition_number == field_definition_number)
+            .cloned()
+    }
+
+    /// Raw value of a field, `None` if the record does not have the field or
+    /// its value is invalid.
+    pub fn field(&self, field_definition_number: u8) -> Option<Value> {
+        let span = self.span(field_definition_number)?;
+        if !span.len.is_multiple_of(span.base_type.size as usize) {
+            return None;
+        }
+        decode_value(
+            span.base_type,
+            &self.architecture,
+            &self.bytes[span.start..span.start + span.len],
+        )
+    }
+
+    /// Sets the raw value of a single value integer field. Returns whether
+    /// the record has such a field and the value fits its base type.
+    pub fn set_integer(&mut self, field_definition_number: u8, value: i64) -> bool {
+        let span = match self.span(field_definition_number) {
+            Some(span) if span.len == span.base_type.size as usize => span,
+            _ => return false,
+        };
+        let fits = match span.base_type.base_type {
+            BaseType::Enum | BaseType::Uint8 | BaseType::Uint8z => u8::try_from(value).is_ok(),
+            BaseType::Sint8 => i8::try_from(value).is_ok(),
+            BaseType::Uint16 | BaseType::Uint16z => u16::try_from(value).is_ok(),
+            BaseType::Sint16 => i16::try_from(value).is_ok(),
+            BaseType::Uint32 | BaseType::Uint32z => u32::try_from(value).is_ok(),
+            BaseType::Sint32 => i32::try_from(value).is_ok(),
+            BaseType::Uint64 | BaseType::Uint64z => value >= 0,
+            BaseType::Sint64 => true,
+            _ => false,
+        };
+        if fits {
+            write_bits(
+                &mut self.bytes[span.start..span.start + span.len],
+                &self.architecture,
+                value as u64,
+            );
+        }
+        fits
+    }
+
+    /// Sets a field to the invalid value of its base type, which readers
+    /// treat as the field not having a value. Returns whether the record has
+    /// the field.
+    pub fn invalidate(&mut self, field_definition_number: u8) -> bool {
+        let span = match self.span(field_definition_number) {
+            Some(span) => span,
+            None => return false,
+        };
+        let bytes = &mut self.bytes[span.start..span.start + span.len];
+        let size = span.base_type.size as usize;
+        match span.base_type.base_type {
+            BaseType::String => bytes.fill(0),
+            BaseType::Byte => bytes.fill(0xFF),
+            _ if !span.len.is_multiple_of(size) => bytes.fill(0xFF),
+            _ => {
+                for chunk in bytes.chunks_exact_mut(size) {
+                    write_bits(chunk, &self.architecture, span.base_type.invalid_value);
+                }
+            }
+        }
+        true
+    }
+}
+
+// A data record of the file: its offset, field values and length.
+struct RecordSpan {
+    offset: usize,
+    global_message_number: u16,
+    architecture: Endianness,
+    fields: Vec<FieldSpan>,
+    len: usize,
+}
+
+/// Copies the file and calls `edit` for each of its data records, then
+/// recomputes the file CRC. The header and definitions are kept as they are.
+pub fn edit_records<F>(fit_data: &[u8], edit: F) -> Result<Vec<u8>, FitError>
+where
+    F: FnMut(&mut EditableRecord),
+{
+    let mut edited = fit_data.to_vec();
+    edit_records_in_place(&mut edited, edit)?;
+    Ok(edited)
+}
+
+/// Calls `edit` for each data record of the file and recomputes the file
+/// CRC, changing only the edited bytes and the CRC. The file is left as it
+/// is if it is malformed.
+pub fn edit_records_in_place<F>(fit_data: &mut [u8], mut edit: F) -> Result<(), FitError>
+where
+    F: FnMut(&mut EditableRecord),
+{
+    let header = try_parse_fit_header_from_data(fit_data)?;
+    let data_start = header.header_size() as usize;
+    let data_end = data_start + header.data_size() as usize;
+    if data_end > fit_data.len() {
+        return Err(FitError::UnexpectedEndOfData {
+            offset: fit_data.len(),
+        });
+    }
+    // the records are read before editing as the file is borrowed by
+    // the reader
+    let mut spans = Vec::new();
+    let mut records = RecordIterator::new(&fit_data[..data_end], data_start);
+    loop {
+        let record_offset = records.offset();
+        let record = match records.next() {
+            None => break,
+            Some(record) => record?,
+        };
+        if let Record::Data(record) = record {
+            let mut start = 1;
+            let mut fields = Vec::with_capacity(record.fields.len());
+            for field in &record.fields {
+                fields.push(FieldSpan {
+                    field_definition_number: field.field_definition_number,
+                    base_type: field.base_type,
+                    start,
+                    len: field.data.len(),
+                });
+                start += field.data.len();
+            }
+            let len = start
+                + record
+                    .developer_fields
+                    .iter()
+                    .map(|f| f.data.len())
+                    .sum::<usize>();
+            spans.push(RecordSpan {
+                offset: record_offset,
+                global_message_number: record.global_message_number,
+                architecture: record.architecture,
+                fields,
+                len,
+            });
+        }
+    }
+    for span in spans {
+        edit(&mut EditableRecord {
+            global_message_number: span.global_message_number,
+            architecture: span.architecture,
+            fields: span.fields,
+            bytes: &mut fit_data[span.offset..span.offset + span.len],
+        });
+    }
+    if data_end + 2 <= fit_data.len() {
+        let crc = FitCrc::checksum(&fit_data[..data_end], 0);
+        fit_data[data_end..data_end + 2].copy_from_slice(&crc.to_le_bytes());
+    }
+    Ok(())
+}
+
+/// New values of the fields of the `file_id` message, `None` keeping the
+/// value of a field.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileIdPatch {
+    pub manufacturer: Option<u16>,
+    pub product: Option<u16>,
+    pub serial_number: Option<u32>,
+    /// Seconds since the FIT epoch.
+    pub time_created: Option<u32>,
+}
+
+/// Patches the fields of the `file_id` messages of the file in place,
+/// changing only their values and the file CRC. Returns whether every
+/// patched field was found: fields the file was recorded without can not
+/// be added in place.
+pub fn patch_file_id(fit_data: &mut [u8], patch: &FileIdPatch) -> Result<bool, FitError> {
+    let values = [
+        (1, patch.manufacturer.map(i64::from)),
+        (2, patch.product.map(i64::from)),
+        (3, patch.serial_number.map(i64::from)),
+        (4, patch.time_created.map(i64::from)),
+    ];
+    let mut patched = [false; 4];
+    let mut found = false;
+    edit_records_in_place(fit_data, |record| {
+        if record.global_message_number != FILE_ID_MESSAGE.number {
+            return;
+        }
+        found = true;
+        for ((number, value), patched) in values.iter().zip(patched.iter_mut()) {
+            if let Some(value) = value {
+                *patched |= record.set_integer(*number, *value);
+            }
+        }
+    })?;
+    Ok(found
+        && values
+            .iter()
+            .zip(patched)
+            .all(|((_, value), patched)| value.is_none() || patched))
+}
+
+/// Changes the sport and sub sport of the `session`, `lap` and `sport`
+/// messages of a file, such as for an activity recorded as a run that was a
+/// hike. Everything else is kept byte for byte. Messages recorded without a
+/// sport or sub sport field are left without one.
+pub fn set_sport(fit_data: &[u8], sport: u8, sub_sport: u8) -> Result<Vec<u8>, FitError> {
+    edit_records(fit_data, |record| {
+        if let Some((_, sport_field, sub_sport_field)) = SPORT_FIELD_NUMBERS
+            .iter()
+            .find(|(number, _, _)| *number == record.global_message_number)
+        {
+            record.set_integer(*sport_field, sport as i64);
+            record.set_integer(*sub_sport_field, sub_sport as i64);
+        }
+    })
+}
+
+// Definition record bytes keeping the developer fields with a new developer
+// data index, and whether each developer field is kept.
+fn rewrite_definition<F>(
+    bytes: &[u8],
+    definition: &DefinitionRecord,
+    remap: &mut F,
+) -> (Vec<u8>, Vec<bool>)
+where
+    F: FnMut(u8) -> Option<u8>,
+{
+    let fields_end = 6 + definition.field_definitions.len() * 3;
+    let mut rewritten = bytes[..fields_end].to_vec();
+    let mut developer_fields = Vec::new();
+    let mut kept = Vec::with_capacity(definition.developer_field_definitions.len());
+    for field in &definition.developer_field_definitions {
+        let index = remap(field.developer_data_index);
+        if let Some(index) = index {
+            developer_fields.extend_from_slice(&[field.field_number, field.field_size, index]);
+        }
+        kept.push(index.is_some());
+    }
+    if developer_fields.is_empty() {
+        rewritten[0] &= !DEVELOPER_DATA_FLAG;
+    } else {
+        rewritten.push((developer_fields.len() / 3) as u8);
+        rewritten.extend(developer_fields);
+    }
+    (rewritten, kept)
+}
+
+/// Rewrites the developer data of a file, such as the fields of Connect IQ
+/// apps. `remap` gives the new developer data index for each index of the
+/// file, or `None` to remove the developer's `developer_data_id` and
+/// `field_description` messages and its fields from definitions and data
+/// records. The header data size and the CRCs are updated.
+pub fn rewrite_developer_data<F>(fit_data: &[u8], mut remap: F) -> Result<Vec<u8>, FitError>
+where
+    F: FnMut(u8) -> Option<u8>,
+{
+    let mut header = try_parse_fit_header_from_data(fit_data)?;
+    let data_start = header.header_size() as usize;
+    let data_end = data_start + header.data_size() as usize;
+    if data_end > fit_data.len() {
+        return Err(FitError::UnexpectedEndOfData {
+            offset: fit_data.len(),
+        });
+    }
+    let mut rewritten = fit_data[..data_start].to_vec();
+    // Whether the developer fields of each local message type are kept.
+    let mut kept: [Vec<bool>; LOCAL_MESSAGE_TYPE_COUNT] = Default::default();
+    let mut records = RecordIterator::new(&fit_data[..data_end], data_start);
+    loop {
+        let record_offset = records.offset();
+        let record = match records.next() {
+            None => break,
+            Some(record) => record?,
+        };
+        let bytes = &fit_data[record_offset..records.offset()];
+        match record {
+            Record::Definition(definition) => {
+                let (definition_bytes, developer_fields) =
+                    rewrite_definition(bytes, &definition, &mut remap);
+                kept[definition.header.local_message_type as usize] = developer_fields;
+                rewritten.extend(definition_bytes);
+            }
+            Record::Data(record) => {
+                let index_field = match record.global_message_number {
+                    n if n == DEVELOPER_DATA_ID_MESSAGE.number => {
+                        Some(DEVELOPER_DATA_ID_INDEX_FIELD_NUMBER)
+                    }
+                    n if n == FIELD_DESCRIPTION_MESSAGE.number => {
+                        Some(FIELD_DESCRIPTION_INDEX_FIELD_NUMBER)
+                    }
+                    _ => None,
+                };
+                let mut start = 1;
+                let mut record_bytes = bytes[..1].to_vec();
+                for field in &record.fields {
+                    let mut data = field.data.clone();
+                    if Some(field.field_definition_number) == index_field && data.len() == 1 {
+                        match remap(data[0]) {
+                            Some(index) => data[0] = index,
+                            None => {
+                                record_bytes.clear();
+                                break;
+                            }
+                        }
+                    }
+                    record_bytes.extend(data);
+                    start += field.data.len();
+                }
+                if record_bytes.is_empty() {
+                    continue;
+                }
+                let local = record.header.local_message_type() as usize;
+                for (field, keep) in record.developer_fields.iter().zip(&kept[local]) {
+                    if *keep {
+                        record_bytes.extend_from_slice(&bytes[start..start + field.data.len()]);
+                    }
+                    start += field.data.len();
+                }
+                rewritten.extend(record_bytes);
+            }
+        }
+    }
+    header.set_data_size((rewritten.len() - data_start) as u32);
+    rewritten[..data_start].copy_from_slice(&header.to_bytes());
+    let crc = FitCrc::checksum(&rewritten, 0);
+    rewritten.extend_from_slice(&crc.to_le_bytes());
+    Ok(rewritten)
+}
+
+/// Removes all developer data from a file, see [`rewrite_developer_data`].
+pub fn strip_developer_data(fit_data: &[u8]) -> Result<Vec<u8>, FitError> {
+    rewrite_developer_data(fit_data, |_| None)
+}
+
+#[cfg(test)]
+use crate::developer::DeveloperFields;
+#[cfg(test)]
+use crate::fit_file::{test_file, FitFile};
+#[cfg(test)]
+use crate::profile::messages::FileId;
+#[cfg(test)]
+use crate::profile::{Sport, SubSport};
+
+#[test]
+fn test_edit_records() {
+    let data = test_file(&[
+        0x40, 0x00, 0x00, 0x14, 0x00, 0x02, // record, 2 fields
+        0x03, 0x01, 0x02, // heart_rate
+        0x06, 0x02, 0x84, // speed
+        0x00, 0x96, 0xE8, 0x03, // heart_rate 150, speed 1000
+    ]);
+    let edited = edit_records(&data, |record| {
+        assert_eq!(record.field(3), Some(Value::U8(150)));
+        assert!(record.set_integer(3, 160));
+        assert!(!record.set_integer(3, 256));
+        assert!(record.invalidate(6));
+        assert!(!record.invalidate(7));
+    })
+    .unwrap();
+    let fit_file = FitFile::parse(&edited).unwrap();
+    assert_eq!(fit_file.messages[0].field(3).unwrap().value, Value::U8(160));
+    assert!(fit_file.messages[0].field(6).is_none());
+    let data_end = edited.len() - 2;
+    assert_eq!(
+        FitCrc::checksum(&edited[..data_end], 0).to_le_bytes(),
+        edited[data_end..]
+    );
+}
+
+#[test]
+fn test_patch_file_id() {
+    let mut data = test_file(&[
+        0x40, 0x00, 0x00, 0x00, 0x00, 0x02, // file_id, 2 fields
+        0x03, 0x04, 0x8C, // serial_number
+        0x04, 0x04, 0x86, // time_created
+        0x00, 0x01, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00,
+    ]);
+    let original = data.clone();
+    let patch = FileIdPatch {
+        serial_number: Some(1234),
+        time_created: Some(1_000_000_000),
+        ..FileIdPatch::default()
+    };
+    assert!(patch_file_id(&mut data, &patch).unwrap());
+    let file_id = FileId::from_message(&FitFile::parse(&data).unwrap().messages[0]);
+    assert_eq!(file_id.serial_number, Some(1234));
+    assert_eq!(file_id.time_created, Some(1_000_000_000));
+    // only the values and the CRC change
+    assert_eq!(data[..25], original[..25]);
+    let data_end = data.len() - 2;
+    assert_eq!(
+        FitCrc::checksum(&data[..data_end], 0).to_le_bytes(),
+        data[data_end..]
+    );
+    let patch = FileIdPatch {
+        product: Some(1),
+        ..FileIdPatch::default()
+    };
+    assert!(!patch_file_id(&mut data, &patch).unwrap());
+}
+
+#[test]
+fn test_set_sport() {
+    let data = test_file(&[
+        0x40, 0x00, 0x00, 0x12, 0x00, 0x03, // session, 3 fields
+        0x05, 0x01, 0x00, // sport
+        0x06, 0x01, 0x00, // sub_sport
+        0x09, 0x04, 0x86, // total_distance
+        0x00, 0x01, 0x03, 0xA0, 0x86, 0x01, 0x00, // running, trail, 1000 m
+        0x41, 0x00, 0x00, 0x13, 0x00, 0x01, // lap, 1 field
+        0x19, 0x01, 0x00, // sport
+        0x01, 0x01, // running
+    ]);
+    let edited = set_sport(&data, Sport::Hiking as u8, SubSport::Generic as u8).unwrap();
+    let fit_file = FitFile::parse(&edited).unwrap();
+    let session = &fit_file.messages[0];
+    assert_eq!(session.enum_value(5).unwrap().value, Sport::Hiking as u32);
+    assert_eq!(session.enum_value(6).unwrap().value, 0);
+    assert_eq!(fit_file.messages[1].enum_value(25).unwrap().value, 17);
+    assert!(fit_file.messages[1].field(39).is_none());
+    // only the sport values and the CRC change
+    let changed = (0..data.len())
+        .filter(|idx| data[*idx] != edited[*idx])
+        .count();
+    assert_eq!(changed, 5);
+}
+
+#[cfg(test)]
+fn test_developer_file() -> Vec<u8> {
+    let mut records = vec![
+        0x40, 0x00, 0x00, 0xCF, 0x00, 0x01, // developer_data_id, 1 field
+        0x03, 0x01, 0x02, // developer_data_index
+        0x00, 0x00, // developer_data_index 0
+        0x41, 0x00, 0x00, 0xCE, 0x00, 0x04, // field_description, 4 fields
+        0x00, 0x01, 0x02, // developer_data_index
+        0x01, 0x01, 0x02, // field_definition_number
+        0x02, 0x01, 0x02, // fit_base_type_id
+        0x03, 0x06, 0x07, // field_name
+        0x01, 0x00, 0x00, 0x84,
+    ];
+    records.extend_from_slice(b"Power\0");
+    records.extend_from_slice(&[
+        0x62, 0x00, 0x00, 0x14, 0x00, 0x01, // record with developer fields
+        0x03, 0x01, 0x02, // heart_rate
+        0x01, 0x00, 0x02, 0x00, // developer field 0, 2 bytes, index 0
+        0x02, 0x96, 0xFA, 0x00, // heart_rate 150, power 250
+    ]);
+    test_file(&records)
+}
+
+#[test]
+fn test_strip_developer_data() {
+    let stripped = strip_developer_data(&test_developer_file()).unwrap();
+    let fit_file = FitFile::parse(&stripped).unwrap();
+    assert!(fit_file.diagnostics.is_empty());
+    assert_eq!(fit_file.messages.len(), 1);
+    assert_eq!(fit_file.messages[0].field(3).unwrap().value, Value::U8(150));
+    assert!(fit_file.messages[0].developer_fields.is_empty());
+    let data_end = stripped.len() - 2;
+    assert_eq!(
+        FitCrc::checksum(&stripped[..data_end], 0).to_le_bytes(),
+        stripped[data_end..]
+    );
+}
+
+#[test]
+fn test_remap_developer_data() {
+    let remapped = rewrite_developer_data(&test_developer_file(), |index| Some(index + 2)).unwrap();
+    let fit_file = FitFile::parse(&remapped).unwrap();
+    assert_eq!(fit_file.messages.len(), 3);
+    assert_eq!(fit_file.messages[0].field(3).unwrap().value, Value::U8(2));
+    assert_eq!(fit_file.messages[1].field(0).unwrap().value, Value::U8(2));
+    assert_eq!(
+        fit_file.messages[2].developer_fields[0].developer_data_index,
+        2
+    );
+    let developer = DeveloperFields::from_file(&fit_file);
+    let (_, value) = developer.value(&fit_file.messages[2], "Power").unwrap();
+    assert_eq!(value, Value::U16(250));
+}
@@ -0,0 +1,120 @@
+//! Mean-maximal curves: the best average power, speed and heart rate held
+//! over given durations.
+
+use std::time::Duration;
+
+use crate::fit_file::FitFile;
+use crate::series::{RecordSeries, ResampleError};
+
+/// Highest average of `window` consecutive values. Windows containing NaN
+/// values are skipped, `None` if there is no complete window.
+pub fn mean_maximal(values: &[f64], window: usize) -> Option<f64> {
+    if window == 0 || window > values.len() {
+        return None;
+    }
+    // Prefix sums of the values and of the number of missing values.
+    let mut sums = Vec::with_capacity(values.len() + 1);
+    let mut missing = Vec::with_capacity(values.len() + 1);
+    sums.push(0.0);
+    missing.push(0usize);
+    for value in values {
+        let (sum, count) = (sums[sums.len() - 1], missing[missing.len() - 1]);
+        if value.is_nan() {
+            sums.push(sum);
+            missing.push(count + 1);
+        } else {
+            sums.push(sum + value);
+            missing.push(count);
+        }
+    }
+    (window..=values.len())
+        .filter(|end| missing[*end] == missing[end - window])
+        .map(|end| (sums[end] - sums[end - window]) / window as f64)
+        .reduce(f64::max)
+}
+
+/// Best averages held for one duration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BestEffort {
+    pub duration: Duration,
+    /// Watts.
+    pub power: Option<f64>,
+    /// Meters per second.
+    pub speed: Option<f64>,
+    /// Beats per minute.
+    pub heart_rate: Option<f64>,
+}
+
+impl BestEffort {
+    /// Time per kilometer at the best speed.
+    pub fn pace(&self) -> Option<Duration> {
+        self.speed
+            .filter(|speed| *speed > 0.0)
+            .map(|speed| Duration::from_secs_f64(1000.0 / speed))
+    }
+}
+
+/// Best efforts of the series for each of the `durations`, rounded to whole
+/// seconds. The series is resampled to one value per second, see
+/// [`RecordSeries::resample_seconds`], and efforts do not span pauses.
+pub fn best_efforts(
+    series: &RecordSeries,
+    durations: &[Duration],
+) -> Result<Vec<BestEffort>, ResampleError> {
+    let resampled = series.resample_seconds()?;
+    Ok(durations
+        .iter()
+        .map(|duration| {
+            let window = duration.as_secs_f64().round() as usize;
+            BestEffort {
+                duration: *duration,
+                power: mean_maximal(&resampled.power, window),
+                speed: mean_maximal(&resampled.speed, window),
+                heart_rate: mean_maximal(&resampled.heart_rate, window),
+            }
+        })
+        .collect())
+}
+
+impl FitFile {
+    /// Best efforts of the record messages of the file, see [`best_efforts`].
+    pub fn best_efforts(&self, durations: &[Duration]) -> Result<Vec<BestEffort>, ResampleError> {
+        best_efforts(&self.record_series(), durations)
+    }
+}
+
+#[cfg(test)]
+use crate::series::test_series;
+
+#[test]
+fn test_mean_maximal() {
+    let values = [100.0, 300.0, 200.0, f64::NAN, 400.0, 400.0];
+    assert_eq!(mean_maximal(&values, 1), Some(400.0));
+    assert_eq!(mean_maximal(&values, 2), Some(400.0));
+    assert_eq!(mean_maximal(&values, 3), Some(200.0));
+    assert_eq!(mean_maximal(&values, 4), None);
+    assert_eq!(mean_maximal(&values, 7), None);
+}
+
+#[test]
+fn test_best_efforts() {
+    let series = RecordSeries {
+        heart_rate: vec![120.0, 130.0, 150.0, 140.0],
+        power: vec![100.0, 200.0, 300.0, 100.0],
+        speed: vec![2.0, 4.0, 5.0, 4.0],
+        ..test_series(&[0, 1, 2, 4])
+    };
+    let efforts =
+        best_efforts(&series, &[Duration::from_secs(2), Duration::from_secs(10)]).unwrap();
+    assert_eq!(
+        efforts[0],
+        BestEffort {
+            duration: Duration::from_secs(2),
+            power: Some(300.0),
+            speed: Some(5.0),
+            heart_rate: Some(150.0),
+        }
+    );
+    assert_eq!(efforts[0].pace(), Some(Duration::from_secs(200)));
+    assert_eq!(efforts[1].power, None);
+}
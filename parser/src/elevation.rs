@@ -0,0 +1,127 @@
+//! Total ascent and descent from noisy altitude samples.
+
+use crate::series::RecordSeries;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ElevationOptions {
+    /// Number of samples in the centered moving average applied to the
+    /// altitudes, 1 for no smoothing.
+    pub smoothing_window: usize,
+    /// Meters the altitude has to change from the last counted altitude
+    /// before the change is counted as ascent or descent.
+    pub hysteresis: f64,
+}
+
+impl Default for ElevationOptions {
+    fn default() -> Self {
+        ElevationOptions {
+            smoothing_window: 5,
+            hysteresis: 3.0,
+        }
+    }
+}
+
+/// Total ascent and descent in meters.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ElevationGain {
+    pub ascent: f64,
+    pub descent: f64,
+}
+
+/// Centered moving average over `window` values, shrinking the window at the
+/// ends.
+pub fn smooth(values: &[f64], window: usize) -> Vec<f64> {
+    let half = window.max(1) / 2;
+    (0..values.len())
+        .map(|idx| {
+            let start = idx.saturating_sub(half);
+            let end = values.len().min(idx + half + 1);
+            values[start..end].iter().sum::<f64>() / (end - start) as f64
+        })
+        .collect()
+}
+
+/// Ascent and descent of the altitudes after smoothing and hysteresis, see
+/// [`ElevationOptions`]. NaN altitudes are skipped.
+pub fn elevation_gain(altitude: &[f64], options: &ElevationOptions) -> ElevationGain {
+    let altitude = altitude
+        .iter()
+        .filter(|a| !a.is_nan())
+        .copied()
+        .collect::<Vec<f64>>();
+    let smoothed = smooth(&altitude, options.smoothing_window);
+    let mut gain = ElevationGain::default();
+    let mut reference = match smoothed.first() {
+        Some(first) => *first,
+        None => return gain,
+    };
+    for altitude in smoothed {
+        let change = altitude - reference;
+        if change.abs() >= options.hysteresis {
+            if change > 0.0 {
+                gain.ascent += change;
+            } else {
+                gain.descent -= change;
+            }
+            reference = altitude;
+        }
+    }
+    gain
+}
+
+impl RecordSeries {
+    /// Ascent and descent of the altitude column, see [`elevation_gain`].
+    pub fn elevation_gain(&self, options: &ElevationOptions) -> ElevationGain {
+        elevation_gain(&self.altitude, options)
+    }
+}
+
+#[test]
+fn test_smooth() {
+    assert_eq!(smooth(&[0.0, 3.0, 0.0, 3.0], 3), vec![1.5, 1.0, 2.0, 1.5]);
+    assert_eq!(smooth(&[1.0, 2.0], 1), vec![1.0, 2.0]);
+}
+
+#[test]
+fn test_elevation_gain_ignores_noise() {
+    let noisy = [
+        100.0,
+        101.0,
+        100.0,
+        101.0,
+        100.0,
+        f64::NAN,
+        105.0,
+        110.0,
+        104.0,
+        100.0,
+    ];
+    let raw = elevation_gain(
+        &noisy,
+        &ElevationOptions {
+            smoothing_window: 1,
+            hysteresis: 0.0,
+        },
+    );
+    assert_eq!(
+        raw,
+        ElevationGain {
+            ascent: 12.0,
+            descent: 12.0
+        }
+    );
+    let filtered = elevation_gain(
+        &noisy,
+        &ElevationOptions {
+            smoothing_window: 1,
+            hysteresis: 3.0,
+        },
+    );
+    assert_eq!(
+        filtered,
+        ElevationGain {
+            ascent: 10.0,
+            descent: 10.0
+        }
+    );
+}
@@ -0,0 +1,1025 @@
+//! Encoding of data messages into FIT files.
+//!
+//! Field values are written with the base type, scale and offset of their
+//! profile field, or of the active subfield named by the field value. Fields
+//! not in the profile are written with the base type matching their value.
+//! Developer fields are written with the base type of the `field_description`
+//! message written for them before, see [`FitWriter::describe_developer_field`].
+//!
+//! Values of variable size array fields longer than the 255 bytes a field
+//! can have are split over repeated definitions of the field, which the
+//! decoder joins again.
+//!
+//! Files target protocol 2.0 by default. Targeting 1.0 refuses the 64-bit
+//! base types and developer fields it does not have.
+
+use std::fmt;
+
+use crate::crc::FitCrc;
+use crate::developer::DeveloperFieldInfo;
+use crate::edit::write_bits;
+use crate::fit_header::FitFileHeader;
+use crate::fit_messages::{DataMessage, FieldValue, Value, TIMESTAMP_FIELD_NUMBER};
+use crate::fit_records::{
+    base_type_info_by_name, get_base_type_info, BaseType, BaseTypeInfo, RawDeveloperField,
+};
+use crate::profile::messages::{DeveloperDataId, FieldDescription};
+use crate::profile::{
+    message_info, type_info, FieldArray, DEVELOPER_DATA_ID_MESSAGE, FIELD_DESCRIPTION_MESSAGE,
+    PROFILE_VERSION,
+};
+
+pub use crate::fit_records::Endianness;
+
+/// Protocol version written in the header by default, 2.0.
+pub const PROTOCOL_VERSION: u8 = 0x20;
+
+/// Protocol version a file is written for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ProtocolVersion {
+    /// 1.0, without 64-bit base types and developer fields.
+    V1,
+    #[default]
+    V2,
+}
+
+impl ProtocolVersion {
+    /// The version byte of the file header.
+    pub fn byte(self) -> u8 {
+        match self {
+            ProtocolVersion::V1 => 0x10,
+            ProtocolVersion::V2 => PROTOCOL_VERSION,
+        }
+    }
+}
+
+/// Why a message could not be encoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EncodeError {
+    /// The encoded value is longer than the 255 bytes a field can have.
+    FieldTooLarge {
+        global_message_number: u16,
+        field_definition_number: u8,
+    },
+    /// The value does not fit the base type of the field.
+    InvalidValue {
+        global_message_number: u16,
+        field_definition_number: u8,
+    },
+    /// All local message types are pinned to other layouts.
+    NoFreeLocalMessageType { global_message_number: u16 },
+    /// The field has a 64-bit base type, which protocol 1.0 does not have.
+    UnsupportedBaseType {
+        global_message_number: u16,
+        field_definition_number: u8,
+    },
+    /// The message has developer fields, which protocol 1.0 does not have.
+    UnsupportedDeveloperFields { global_message_number: u16 },
+    /// No `field_description` message was written for the developer field.
+    UndescribedDeveloperField {
+        global_message_number: u16,
+        developer_data_index: u8,
+        field_number: u8,
+    },
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::FieldTooLarge {
+                global_message_number,
+                field_definition_number,
+            } => write!(
+                f,
+                "Field {} of message {} is longer than 255 bytes",
+                field_definition_number, global_message_number
+            ),
+            Self::InvalidValue {
+                global_message_number,
+                field_definition_number,
+            } => write!(
+                f,
+                "Value of field {} of message {} does not fit its base type",
+                field_definition_number, global_message_number
+            ),
+            Self::NoFreeLocalMessageType {
+                global_message_number,
+            } => write!(
+                f,
+                "No local message type is free for message {}",
+                global_message_number
+            ),
+            Self::UnsupportedBaseType {
+                global_message_number,
+                field_definition_number,
+            } => write!(
+                f,
+                "Field {} of message {} has a 64-bit base type, not in protocol 1.0",
+                field_definition_number, global_message_number
+            ),
+            Self::UnsupportedDeveloperFields {
+                global_message_number,
+            } => write!(
+                f,
+                "Message {} has developer fields, not in protocol 1.0",
+                global_message_number
+            ),
+            Self::UndescribedDeveloperField {
+                global_message_number,
+                developer_data_index,
+                field_number,
+            } => write!(
+                f,
+                "Developer field {} of developer {} of message {} has no description",
+                field_number, developer_data_index, global_message_number
+            ),
+        }
+    }
+}
+
+impl std::error::Error for EncodeError {}
+
+// A field value encoded with its base type.
+#[derive(Debug, Clone, PartialEq)]
+struct EncodedField {
+    field_definition_number: u8,
+    base_type: &'static BaseTypeInfo,
+    bytes: Vec<u8>,
+}
+
+// Base type and scale of a field value, from the profile when the field is
+// in it.
+fn interpretation(
+    global_message_number: u16,
+    field: &FieldValue,
+) -> Option<(&'static BaseTypeInfo, f64, f64)> {
+    let info = message_info(global_message_number)?.field(field.field_definition_number)?;
+    let (field_type, scale, offset) = match field.name.and_then(|name| info.subfield(name)) {
+        Some(subfield) => (subfield.field_type, subfield.scale, subfield.offset),
+        None => (info.field_type, info.scale, info.offset),
+    };
+    let base_type = type_info(field_type).map_or(field_type, |t| t.base_type);
+    Some((base_type_info_by_name(base_type)?, scale, offset))
+}
+
+// Base type of a value of a field not in the profile.
+fn value_base_type(value: &Value) -> &'static BaseTypeInfo {
+    let base_type = match value {
+        Value::U8(_) => BaseType::Uint8,
+        Value::U16(_) => BaseType::Uint16,
+        Value::U32(_) => BaseType::Uint32,
+        Value::U64(_) => BaseType::Uint64,
+        Value::I8(_) => BaseType::Sint8,
+        Value::I16(_) => BaseType::Sint16,
+        Value::I32(_) => BaseType::Sint32,
+        Value::I64(_) => BaseType::Sint64,
+        Value::F32(_) => BaseType::Float32,
+        Value::F64(_) => BaseType::Float64,
+        Value::String(_) => BaseType::String,
+        Value::Bytes(_) => BaseType::Byte,
+        Value::Array(values) => {
+            return values
+                .first()
+                .map_or(get_base_type_info(BaseType::Byte.into()), value_base_type)
+        }
+        Value::Enum(v) => {
+            return type_info(v.type_name)
+                .and_then(|t| base_type_info_by_name(t.base_type))
+                .unwrap_or(get_base_type_info(BaseType::Enum.into()))
+        }
+    };
+    get_base_type_info(base_type.into())
+}
+
+// Raw bits of one numeric value in the base type, after removing the scale
+// and offset.
+fn raw_bits(base_type: &BaseTypeInfo, value: &Value, scale: f64, offset: f64) -> Option<u64> {
+    let scaled = scale != 1.0 || offset != 0.0;
+    match base_type.base_type {
+        BaseType::Float32 => {
+            let v = (value.as_f64()? + offset) * scale;
+            Some((v as f32).to_bits() as u64)
+        }
+        BaseType::Float64 => {
+            let v = (value.as_f64()? + offset) * scale;
+            Some(v.to_bits())
+        }
+        _ => {
+            let v = if scaled {
+                ((value.as_f64()? + offset) * scale).round() as i64
+            } else {
+                match value {
+                    Value::U64(v) => return Some(*v),
+                    Value::F32(_) | Value::F64(_) => value.as_f64()?.round() as i64,
+                    _ => value.as_i64()?,
+                }
+            };
+            let fits = match base_type.base_type {
+                BaseType::Sint8 => i8::try_from(v).is_ok(),
+                BaseType::Sint16 => i16::try_from(v).is_ok(),
+                BaseType::Sint32 => i32::try_from(v).is_ok(),
+                BaseType::Sint64 => true,
+                _ => v >= 0 && (base_type.size == 8 || v < 1 << (base_type.size as u32 * 8)),
+            };
+            fits.then_some(v as u64)
+        }
+    }
+}
+
+// The encoded field, split in parts of at most 255 bytes if it is a variable
+// size array field.
+fn encode_field(
+    global_message_number: u16,
+    field: &FieldValue,
+    options: &EncodeOptions,
+) -> Result<Vec<EncodedField>, EncodeError> {
+    let invalid = EncodeError::InvalidValue {
+        global_message_number,
+        field_definition_number: field.field_definition_number,
+    };
+    let (base_type, scale, offset) = interpretation(global_message_number, field)
+        .unwrap_or_else(|| (value_base_type(&field.value), 1.0, 0.0));
+    if options.protocol_version == ProtocolVersion::V1
+        && matches!(
+            base_type.base_type,
+            BaseType::Sint64 | BaseType::Uint64 | BaseType::Uint64z
+        )
+    {
+        return Err(EncodeError::UnsupportedBaseType {
+            global_message_number,
+            field_definition_number: field.field_definition_number,
+        });
+    }
+    let bytes = match (&field.value, &base_type.base_type) {
+        (Value::String(s), BaseType::String) => {
+            let mut bytes = s.as_bytes().to_vec();
+            bytes.push(0);
+            bytes
+        }
+        (Value::Bytes(bytes), _) => bytes.clone(),
+        // string arrays, such as the names of a field description
+        (Value::Array(values), BaseType::String) => {
+            let mut bytes = Vec::new();
+            for value in values {
+                match value {
+                    Value::String(s) => bytes.extend(s.as_bytes()),
+                    _ => return Err(invalid),
+                }
+                bytes.push(0);
+            }
+            bytes
+        }
+        (Value::String(_), _) | (_, BaseType::String) => return Err(invalid),
+        (value, _) => {
+            let values = match value {
+                Value::Array(values) => values.as_slice(),
+                value => std::slice::from_ref(value),
+            };
+            let size = base_type.size as usize;
+            let mut bytes = vec![0; values.len() * size];
+            for (value, chunk) in values.iter().zip(bytes.chunks_exact_mut(size)) {
+                let bits = raw_bits(base_type, value, scale, offset).ok_or(invalid.clone())?;
+                write_bits(chunk, &options.architecture, bits);
+            }
+            bytes
+        }
+    };
+    if bytes.len() <= u8::MAX as usize {
+        return Ok(vec![EncodedField {
+            field_definition_number: field.field_definition_number,
+            base_type,
+            bytes,
+        }]);
+    }
+    let variable = message_info(global_message_number)
+        .and_then(|m| m.field(field.field_definition_number))
+        .is_some_and(|f| f.array == FieldArray::Variable);
+    if !variable {
+        return Err(EncodeError::FieldTooLarge {
+            global_message_number,
+            field_definition_number: field.field_definition_number,
+        });
+    }
+    let part_size = u8::MAX as usize / base_type.size as usize * base_type.size as usize;
+    Ok(bytes
+        .chunks(part_size)
+        .map(|part| EncodedField {
+            field_definition_number: field.field_definition_number,
+            base_type,
+            bytes: part.to_vec(),
+        })
+        .collect())
+}
+
+// Field layout of a definition.
+#[derive(Debug, Clone, PartialEq)]
+struct Layout {
+    global_message_number: u16,
+    architecture: Endianness,
+    // Number, size and base type of each field.
+    fields: Vec<(u8, u8, u8)>,
+    // Number, size and developer data index of each developer field.
+    developer_fields: Vec<(u8, u8, u8)>,
+}
+
+// Number of local message types a writer can use.
+const SLOT_COUNT: usize = 16;
+
+// Local message types a compressed timestamp header can refer to.
+const COMPRESSED_SLOT_COUNT: usize = 4;
+
+// Largest time offset a compressed timestamp header can have.
+const MAX_TIME_OFFSET: u32 = 31;
+
+/// Options of a [`FitWriter`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EncodeOptions {
+    /// Write messages timestamped at most 31 seconds after the previous
+    /// timestamp with a compressed timestamp header instead of a timestamp
+    /// field, like devices do. Larger gaps get a full timestamp.
+    pub compressed_timestamps: bool,
+    /// Byte order of the definitions written, see
+    /// [`FitWriter::set_architecture`] for changing it between messages.
+    pub architecture: Endianness,
+    pub protocol_version: ProtocolVersion,
+}
+
+// A local message type with its current definition.
+#[derive(Debug, Clone, PartialEq)]
+struct Slot {
+    layout: Layout,
+    // Value of the writer's clock when the slot was last used.
+    last_used: u64,
+    pinned: bool,
+}
+
+/// Writes data messages as a FIT file. The 16 local message types are
+/// managed automatically: a message reuses the local message type already
+/// defined with its layout, and otherwise gets a free one or the least
+/// recently used one that is not pinned, with a new definition record.
+#[derive(Debug, Default)]
+pub struct FitWriter {
+    options: EncodeOptions,
+    records: Vec<u8>,
+    slots: [Option<Slot>; SLOT_COUNT],
+    clock: u64,
+    // Timestamp of the last timestamped message written.
+    last_timestamp: Option<u32>,
+    // Developer data indices with a `developer_data_id` message written.
+    developers: Vec<u8>,
+    // Developer fields with a `field_description` message written.
+    developer_fields: Vec<DeveloperFieldInfo>,
+}
+
+impl FitWriter {
+    pub fn new() -> FitWriter {
+        FitWriter::default()
+    }
+
+    pub fn with_options(options: EncodeOptions) -> FitWriter {
+        FitWriter {
+            options,
+            ..FitWriter::default()
+        }
+    }
+
+    /// Sets the byte order of the messages written after this, with new
+    /// definitions where needed.
+    pub fn set_architecture(&mut self, architecture: Endianness) {
+        self.options.architecture = architecture;
+    }
+
+    /// Writes the `field_description` message of a developer field, and
+    /// the `developer_data_id` message of its developer unless written
+    /// before, so that messages with the developer field can be written.
+    /// The scale must be a whole number from 1 to 255 and the offset one
+    /// from -128 to 127.
+    pub fn describe_developer_field(
+        &mut self,
+        info: &DeveloperFieldInfo,
+    ) -> Result<(), EncodeError> {
+        let invalid = |field_definition_number| EncodeError::InvalidValue {
+            global_message_number: FIELD_DESCRIPTION_MESSAGE.number,
+            field_definition_number,
+        };
+        if info.scale.fract() != 0.0 || !(1.0..=255.0).contains(&info.scale) {
+            return Err(invalid(6));
+        }
+        if info.offset.fract() != 0.0 || !(-128.0..=127.0).contains(&info.offset) {
+            return Err(invalid(7));
+        }
+        if !self.developers.contains(&info.developer_data_index) {
+            let developer = DeveloperDataId {
+                application_id: info.application_id.clone(),
+                developer_data_index: Some(info.developer_data_index),
+                ..DeveloperDataId::default()
+            };
+            self.write(&developer.to_message())?;
+        }
+        let description = FieldDescription {
+            developer_data_index: Some(info.developer_data_index),
+            field_definition_number: Some(info.field_definition_number),
+            fit_base_type_id: Some(info.base_type.base_type_field),
+            field_name: Some(vec![info.name.clone()]),
+            scale: (info.scale != 1.0).then_some(info.scale as u8),
+            offset: (info.offset != 0.0).then_some(info.offset as i8),
+            units: info.units.clone().map(|units| vec![units]),
+            native_mesg_num: info.native_message_number,
+            native_field_num: info.native_field_number,
+            ..FieldDescription::default()
+        };
+        self.write(&description.to_message())
+    }
+
+    // Remembers the developers and developer fields described by a written
+    // message.
+    fn register_developer_message(&mut self, message: &DataMessage) {
+        if message.global_message_number == DEVELOPER_DATA_ID_MESSAGE.number {
+            if let Some(index) = DeveloperDataId::from_message(message).developer_data_index {
+                self.developers.push(index);
+            }
+        } else if message.global_message_number == FIELD_DESCRIPTION_MESSAGE.number {
+            let description = FieldDescription::from_message(message);
+            if let Some(info) = DeveloperFieldInfo::from_description(description) {
+                self.developer_fields.retain(|f| {
+                    (f.developer_data_index, f.field_definition_number)
+                        != (info.developer_data_index, info.field_definition_number)
+                });
+                self.developer_fields.push(info);
+            }
+        }
+    }
+
+    // Layout of the message and the bytes of its data record after the
+    // record header.
+    fn encode_fields(&self, message: &DataMessage) -> Result<(Layout, Vec<u8>), EncodeError> {
+        if self.options.protocol_version == ProtocolVersion::V1
+            && !message.developer_fields.is_empty()
+        {
+            return Err(EncodeError::UnsupportedDeveloperFields {
+                global_message_number: message.global_message_number,
+            });
+        }
+        let fields = message
+            .fields
+            .iter()
+            .map(|f| encode_field(message.global_message_number, f, &self.options))
+            .collect::<Result<Vec<Vec<EncodedField>>, EncodeError>>()?
+            .into_iter()
+            .flatten()
+            .collect::<Vec<EncodedField>>();
+        let mut layout = Layout {
+            global_message_number: message.global_message_number,
+            architecture: self.options.architecture.clone(),
+            fields: fields
+                .iter()
+                .map(|f| {
+                    (
+                        f.field_definition_number,
+                        f.bytes.len() as u8,
+                        f.base_type.base_type_field,
+                    )
+                })
+                .collect(),
+            developer_fields: Vec::new(),
+        };
+        let mut bytes = fields
+            .into_iter()
+            .flat_map(|f| f.bytes)
+            .collect::<Vec<u8>>();
+        for field in &message.developer_fields {
+            let info = self
+                .developer_fields
+                .iter()
+                .find(|f| {
+                    (f.developer_data_index, f.field_definition_number)
+                        == (field.developer_data_index, field.field_number)
+                })
+                .ok_or(EncodeError::UndescribedDeveloperField {
+                    global_message_number: message.global_message_number,
+                    developer_data_index: field.developer_data_index,
+                    field_number: field.field_number,
+                })?;
+            if field.data.len() > u8::MAX as usize {
+                return Err(EncodeError::FieldTooLarge {
+                    global_message_number: message.global_message_number,
+                    field_definition_number: field.field_number,
+                });
+            }
+            let start = bytes.len();
+            bytes.extend(&field.data);
+            if field.architecture != self.options.architecture {
+                for value in bytes[start..].chunks_exact_mut(info.base_type.size as usize) {
+                    value.reverse();
+                }
+            }
+            layout.developer_fields.push((
+                field.field_number,
+                field.data.len() as u8,
+                field.developer_data_index,
+            ));
+        }
+        Ok((layout, bytes))
+    }
+
+    // Local message type below `count` defined with the layout, defining one
+    // if needed.
+    fn slot(&mut self, layout: Layout, count: usize) -> Result<u8, EncodeError> {
+        self.clock += 1;
+        let slots = &self.slots[..count];
+        let defined = slots
+            .iter()
+            .position(|s| s.as_ref().is_some_and(|s| s.layout == layout));
+        let local = match defined {
+            Some(local) => local,
+            None => {
+                let free = slots.iter().position(Option::is_none);
+                let local = free
+                    .or_else(|| {
+                        slots
+                            .iter()
+                            .enumerate()
+                            .filter_map(|(i, s)| s.as_ref().map(|s| (i, s)))
+                            .filter(|(_, s)| !s.pinned)
+                            .min_by_key(|(_, s)| s.last_used)
+                            .map(|(i, _)| i)
+                    })
+                    .ok_or(EncodeError::NoFreeLocalMessageType {
+                        global_message_number: layout.global_message_number,
+                    })?;
+                let big_endian = layout.architecture == Endianness::BigEndian;
+                let header = match layout.developer_fields.is_empty() {
+                    true => 0x40,
+                    false => 0x60,
+                };
+                self.records
+                    .extend_from_slice(&[header | local as u8, 0x00, big_endian as u8]);
+                self.records.extend_from_slice(&match layout.architecture {
+                    Endianness::LittleEndian => layout.global_message_number.to_le_bytes(),
+                    Endianness::BigEndian => layout.global_message_number.to_be_bytes(),
+                });
+                self.records.push(layout.fields.len() as u8);
+                for (number, size, base_type) in &layout.fields {
+                    self.records
+                        .extend_from_slice(&[*number, *size, *base_type]);
+                }
+                if !layout.developer_fields.is_empty() {
+                    self.records.push(layout.developer_fields.len() as u8);
+                    for (number, size, index) in &layout.developer_fields {
+                        self.records.extend_from_slice(&[*number, *size, *index]);
+                    }
+                }
+                self.slots[local] = Some(Slot {
+                    layout,
+                    last_used: 0,
+                    pinned: false,
+                });
+                local
+            }
+        };
+        self.slots[local].as_mut().unwrap().last_used = self.clock;
+        Ok(local as u8)
+    }
+
+    pub fn write(&mut self, message: &DataMessage) -> Result<(), EncodeError> {
+        let timestamp = message.timestamp();
+        let compressible = self.options.compressed_timestamps
+            && timestamp
+                .zip(self.last_timestamp)
+                .and_then(|(t, previous)| t.checked_sub(previous))
+                .is_some_and(|delta| delta <= MAX_TIME_OFFSET);
+        if let (true, Some(timestamp)) = (compressible, timestamp) {
+            let mut untimed = message.clone();
+            untimed
+                .fields
+                .retain(|f| f.field_definition_number != TIMESTAMP_FIELD_NUMBER);
+            let (layout, bytes) = self.encode_fields(&untimed)?;
+            // all compressible local message types pinned falls back to a
+            // full timestamp
+            if let Ok(local) = self.slot(layout, COMPRESSED_SLOT_COUNT) {
+                self.records
+                    .push(0x80 | local << 5 | (timestamp & MAX_TIME_OFFSET) as u8);
+                self.records.extend(bytes);
+                self.register_developer_message(message);
+                self.last_timestamp = Some(timestamp);
+                return Ok(());
+            }
+        }
+        let (layout, bytes) = self.encode_fields(message)?;
+        let local = self.slot(layout, SLOT_COUNT)?;
+        self.records.push(local);
+        self.records.extend(bytes);
+        self.register_developer_message(message);
+        self.last_timestamp = timestamp.or(self.last_timestamp);
+        Ok(())
+    }
+
+    /// Defines the layout of the message, if it is not defined yet, and
+    /// keeps its local message type from being reused for other layouts
+    /// until unpinned. Returns the local message type.
+    pub fn pin(&mut self, message: &DataMessage) -> Result<u8, EncodeError> {
+        let (layout, _) = self.encode_fields(message)?;
+        let local = self.slot(layout, SLOT_COUNT)?;
+        self.slots[local as usize].as_mut().unwrap().pinned = true;
+        Ok(local)
+    }
+
+    pub fn unpin(&mut self, local_message_type: u8) {
+        if let Some(Some(slot)) = self.slots.get_mut(local_message_type as usize) {
+            slot.pinned = false;
+        }
+    }
+
+    /// The complete file: header, the written records and the file CRC.
+    pub fn finish(self) -> Vec<u8> {
+        let mut data = FitFileHeader::new(
+            self.options.protocol_version.byte(),
+            PROFILE_VERSION,
+            self.records.len() as u32,
+        )
+        .to_bytes();
+        data.extend(self.records);
+        let crc = FitCrc::checksum(&data, 0);
+        data.extend_from_slice(&crc.to_le_bytes());
+        data
+    }
+}
+
+impl DataMessage {
+    /// Sets the value of a developer field, encoded with the base type,
+    /// scale and offset of its description. Replaces an earlier value of the
+    /// field.
+    pub fn set_developer_field(
+        &mut self,
+        info: &DeveloperFieldInfo,
+        value: &Value,
+    ) -> Result<(), EncodeError> {
+        let invalid = EncodeError::InvalidValue {
+            global_message_number: self.global_message_number,
+            field_definition_number: info.field_definition_number,
+        };
+        let values = match value {
+            Value::Array(values) => values.as_slice(),
+            value => std::slice::from_ref(value),
+        };
+        let data = match (value, &info.base_type.base_type) {
+            (Value::String(s), BaseType::String) => {
+                let mut data = s.as_bytes().to_vec();
+                data.push(0);
+                data
+            }
+            (Value::Bytes(bytes), _) => bytes.clone(),
+            (Value::String(_), _) | (_, BaseType::String) => return Err(invalid),
+            _ => {
+                let size = info.base_type.size as usize;
+                let mut data = vec![0; values.len() * size];
+                for (value, chunk) in values.iter().zip(data.chunks_exact_mut(size)) {
+                    let bits = raw_bits(info.base_type, value, info.scale, info.offset)
+                        .ok_or(invalid.clone())?;
+                    write_bits(chunk, &Endianness::LittleEndian, bits);
+                }
+                data
+            }
+        };
+        self.developer_fields.retain(|f| {
+            (f.developer_data_index, f.field_number)
+                != (info.developer_data_index, info.field_definition_number)
+        });
+        self.developer_fields.push(RawDeveloperField {
+            field_number: info.field_definition_number,
+            developer_data_index: info.developer_data_index,
+            architecture: Endianness::LittleEndian,
+            data,
+        });
+        Ok(())
+    }
+}
+
+/// Encodes the messages as a FIT file, see [`FitWriter`].
+pub fn encode(messages: &[DataMessage]) -> Result<Vec<u8>, EncodeError> {
+    encode_with_options(messages, &EncodeOptions::default())
+}
+
+pub fn encode_with_options(
+    messages: &[DataMessage],
+    options: &EncodeOptions,
+) -> Result<Vec<u8>, EncodeError> {
+    let mut writer = FitWriter::with_options(options.clone());
+    for message in messages {
+        writer.write(message)?;
+    }
+    Ok(writer.finish())
+}
+
+#[cfg(test)]
+use crate::developer::DeveloperFields;
+#[cfg(test)]
+use crate::fit_file::FitFile;
+#[cfg(test)]
+use crate::fit_messages::apply_scale_and_offset;
+#[cfg(test)]
+use crate::profile::messages::{FileId, Record};
+
+#[test]
+fn test_encode_round_trip() {
+    let file_id = FileId {
+        r#type: Some(4),
+        manufacturer: Some(1),
+        serial_number: Some(1234),
+        ..FileId::default()
+    };
+    let record = |t: u32, speed: f64| Record {
+        timestamp: Some(t),
+        heart_rate: Some(150),
+        speed: Some(speed),
+        altitude: Some(120.0),
+        ..Record::default()
+    };
+    let messages = vec![
+        file_id.to_message(),
+        record(1000, 3.5).to_message(),
+        record(1001, 3.75).to_message(),
+    ];
+    let data = encode(&messages).unwrap();
+    let file = FitFile::parse(&data).unwrap();
+    assert_eq!(file.header.header_size(), 14);
+    assert!(file.diagnostics.is_empty());
+    assert_eq!(FileId::from_message(&file.messages[0]), file_id);
+    // speed and altitude are expanded to their enhanced fields on decoding
+    assert_eq!(
+        Record::from_message(&file.messages[2]),
+        Record {
+            enhanced_speed: Some(3.75),
+            enhanced_altitude: Some(120.0),
+            ..record(1001, 3.75)
+        }
+    );
+    // the second record reuses the definition of the first
+    let single = encode(&messages[..2]).unwrap();
+    assert_eq!(data.len() - single.len(), 1 + 4 + 1 + 2 + 2);
+    let data_end = data.len() - 2;
+    assert_eq!(
+        FitCrc::checksum(&data[..data_end], 0).to_le_bytes(),
+        data[data_end..]
+    );
+}
+
+#[test]
+fn test_float_scale_and_offset_round_trip() {
+    for base_type in [BaseType::Float32, BaseType::Float64] {
+        let info = get_base_type_info(base_type.into());
+        let bits = raw_bits(info, &Value::F64(-12.5), 4.0, 100.0).unwrap();
+        let raw = match info.base_type {
+            BaseType::Float32 => Value::F32(f32::from_bits(bits as u32)),
+            _ => Value::F64(f64::from_bits(bits)),
+        };
+        assert_eq!(raw.as_f64(), Some(350.0));
+        assert_eq!(apply_scale_and_offset(raw, 4.0, 100.0), Value::F64(-12.5));
+    }
+}
+
+#[test]
+fn test_encode_errors() {
+    let mut message = DataMessage::new(20);
+    message.set_typed_field(3, "heart_rate", &Some(300u16));
+    assert_eq!(
+        encode(&[message]),
+        Err(EncodeError::InvalidValue {
+            global_message_number: 20,
+            field_definition_number: 3
+        })
+    );
+    let mut message = DataMessage::new(0xFF00);
+    message.set_typed_field(0, "text", &Some("x".repeat(255)));
+    assert_eq!(
+        encode(&[message]),
+        Err(EncodeError::FieldTooLarge {
+            global_message_number: 0xFF00,
+            field_definition_number: 0
+        })
+    );
+}
+
+#[test]
+fn test_writer_local_message_types() {
+    let message = |number: u16| {
+        let mut message = DataMessage::new(0xFF00 + number);
+        message.set_typed_field(0, "", &Some(number as u8));
+        message
+    };
+    let mut writer = FitWriter::new();
+    assert_eq!(writer.pin(&message(0)).unwrap(), 0);
+    for number in 1..=17 {
+        writer.write(&message(number)).unwrap();
+    }
+    // alternating with a defined layout needs no new definition
+    let len = writer.records.len();
+    writer.write(&message(17)).unwrap();
+    writer.write(&message(16)).unwrap();
+    writer.write(&message(0)).unwrap();
+    assert_eq!(writer.records.len(), len + 3 * 2);
+    // the least recently used unpinned slot is replaced, not the pinned one
+    writer.write(&message(18)).unwrap();
+    assert_eq!(writer.records[writer.records.len() - 2], 3);
+
+    for local in 1..SLOT_COUNT as u8 {
+        writer.slots[local as usize].as_mut().unwrap().pinned = true;
+    }
+    assert_eq!(
+        writer.write(&message(19)),
+        Err(EncodeError::NoFreeLocalMessageType {
+            global_message_number: 0xFF13
+        })
+    );
+    writer.unpin(5);
+    writer.write(&message(19)).unwrap();
+
+    let file = FitFile::parse(&writer.finish()).unwrap();
+    let numbers = file
+        .messages
+        .iter()
+        .map(|m| m.global_message_number - 0xFF00)
+        .collect::<Vec<u16>>();
+    let mut expected = (1..=17).collect::<Vec<u16>>();
+    expected.extend([17, 16, 0, 18, 19]);
+    assert_eq!(numbers, expected);
+}
+
+#[test]
+fn test_encode_compressed_timestamps() {
+    let record = |t: u32| Record {
+        timestamp: Some(t),
+        heart_rate: Some(150),
+        ..Record::default()
+    };
+    let messages = [1000, 1001, 1031, 1063, 1064]
+        .into_iter()
+        .map(|t| record(t).to_message())
+        .collect::<Vec<DataMessage>>();
+    let options = EncodeOptions {
+        compressed_timestamps: true,
+        ..EncodeOptions::default()
+    };
+    let compressed = encode_with_options(&messages, &options).unwrap();
+    let full = encode(&messages).unwrap();
+    // 3 compressed records without the 4 timestamp bytes, and the 9 bytes
+    // of the definition without the timestamp field
+    assert_eq!(full.len() - compressed.len(), 3 * 4 - 9);
+    let file = FitFile::parse(&compressed).unwrap();
+    let timestamps = file
+        .messages
+        .iter()
+        .map(|m| Record::from_message(m).timestamp.unwrap())
+        .collect::<Vec<u32>>();
+    assert_eq!(timestamps, vec![1000, 1001, 1031, 1063, 1064]);
+    // the 32 s gap gets a full timestamp
+    assert_eq!(file.messages[3].fields[0].field_definition_number, 253);
+
+    // a message that fails to encode does not move the timestamps on
+    let mut writer = FitWriter::with_options(options.clone());
+    writer.write(&messages[0]).unwrap();
+    let mut invalid = record(5000).to_message();
+    invalid.set_typed_field(3, "heart_rate", &Some(300u16));
+    assert!(writer.write(&invalid).is_err());
+    writer.write(&messages[1]).unwrap();
+    assert_eq!(
+        writer.finish(),
+        encode_with_options(&messages[..2], &options).unwrap()
+    );
+}
+
+#[test]
+fn test_encode_architecture_and_protocol_version() {
+    let record = Record {
+        timestamp: Some(1000),
+        heart_rate: Some(150),
+        speed: Some(3.5),
+        ..Record::default()
+    };
+    let mut writer = FitWriter::new();
+    writer.write(&record.to_message()).unwrap();
+    writer.set_architecture(Endianness::BigEndian);
+    writer.write(&record.to_message()).unwrap();
+    let data = writer.finish();
+    // the big-endian record gets its own definition
+    assert_eq!(data[14 + 6 + 3 * 3 + 1 + 4 + 1 + 2], 0x40 | 1);
+    assert_eq!(data[14 + 6 + 3 * 3 + 1 + 4 + 1 + 2 + 2], 1);
+    let file = FitFile::parse(&data).unwrap();
+    assert_eq!(file.header.protocol_version(), 0x20);
+    assert_eq!(file.messages[0].fields, file.messages[1].fields);
+
+    let options = EncodeOptions {
+        protocol_version: ProtocolVersion::V1,
+        ..EncodeOptions::default()
+    };
+    let data = encode_with_options(&[record.to_message()], &options).unwrap();
+    assert_eq!(
+        FitFile::parse(&data).unwrap().header.protocol_version(),
+        0x10
+    );
+    let mut message = DataMessage::new(0xFF00);
+    message.set_typed_field(0, "", &Some(1u64));
+    assert_eq!(
+        encode_with_options(&[message], &options),
+        Err(EncodeError::UnsupportedBaseType {
+            global_message_number: 0xFF00,
+            field_definition_number: 0
+        })
+    );
+    let mut message = record.to_message();
+    message.developer_fields.push(RawDeveloperField {
+        field_number: 0,
+        developer_data_index: 0,
+        architecture: Endianness::LittleEndian,
+        data: vec![1],
+    });
+    assert_eq!(
+        encode_with_options(&[message], &options),
+        Err(EncodeError::UnsupportedDeveloperFields {
+            global_message_number: 20
+        })
+    );
+}
+
+#[test]
+fn test_encode_developer_fields() {
+    let info = DeveloperFieldInfo {
+        developer_data_index: 0,
+        field_definition_number: 1,
+        name: String::from("Power"),
+        base_type: get_base_type_info(BaseType::Uint16.into()),
+        units: Some(String::from("W")),
+        scale: 10.0,
+        offset: 5.0,
+        native_message_number: Some(20),
+        native_field_number: Some(7),
+        application_id: Some(vec![0xAB; 16]),
+    };
+    let mut record = Record {
+        timestamp: Some(1000),
+        heart_rate: Some(150),
+        ..Record::default()
+    }
+    .to_message();
+    record
+        .set_developer_field(&info, &Value::F64(245.0))
+        .unwrap();
+    let mut writer = FitWriter::new();
+    assert_eq!(
+        writer.write(&record),
+        Err(EncodeError::UndescribedDeveloperField {
+            global_message_number: 20,
+            developer_data_index: 0,
+            field_number: 1
+        })
+    );
+    writer.describe_developer_field(&info).unwrap();
+    writer.write(&record).unwrap();
+    writer.set_architecture(Endianness::BigEndian);
+    writer.write(&record).unwrap();
+
+    let file = FitFile::parse(&writer.finish()).unwrap();
+    assert!(file.diagnostics.is_empty());
+    assert_eq!(file.messages.len(), 4);
+    let developer = DeveloperFields::from_file(&file);
+    assert_eq!(developer.iter().collect::<Vec<_>>(), vec![&info]);
+    for message in &file.messages[2..] {
+        assert_eq!(developer.native_value(message, 7), Some(Value::F64(245.0)));
+    }
+    assert!(FitWriter::new()
+        .describe_developer_field(&DeveloperFieldInfo { scale: 0.5, ..info })
+        .is_err());
+}
+
+#[test]
+fn test_encode_split_fields() {
+    // hrv: time is a variable size array of uint16
+    let times = (0..200)
+        .map(|i| 0.5 + i as f64 / 1000.0)
+        .collect::<Vec<f64>>();
+    let mut hrv = DataMessage::new(78);
+    hrv.set_typed_field(0, "time", &Some(times.clone()));
+    let data = encode(&[hrv]).unwrap();
+    // 400 bytes are split in parts of 254 and 146 bytes
+    assert_eq!(&data[14..22], &[0x40, 0, 0, 78, 0, 2, 0, 254]);
+    let file = FitFile::parse(&data).unwrap();
+    let message = &file.messages[0];
+    assert_eq!(message.fields.len(), 1);
+    let decoded = message.typed_field::<Vec<f64>>(0).unwrap();
+    let milliseconds = |times: &[f64]| {
+        times
+            .iter()
+            .map(|t| (t * 1000.0).round() as u32)
+            .collect::<Vec<u32>>()
+    };
+    assert_eq!(milliseconds(&decoded), milliseconds(&times));
+
+    let name = "x".repeat(400);
+    let description = FieldDescription {
+        field_name: Some(vec![name.clone()]),
+        ..FieldDescription::default()
+    };
+    let file = FitFile::parse(&encode(&[description.to_message()]).unwrap()).unwrap();
+    let decoded = FieldDescription::from_message(&file.messages[0]);
+    assert_eq!(decoded.field_name, Some(vec![name]));
+}
@@ -0,0 +1,95 @@
+use std::fmt;
+
+use crate::fit_header::{format_protocol_version, ProtocolViolation};
+use crate::limits::ResourceLimit;
+
+/// Errors that abort parsing a FIT file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FitError {
+    /// Header size is smaller than the smallest valid header.
+    InvalidHeaderSize { header_size: u8 },
+    /// Header CRC does not match the header contents.
+    HeaderCrcMismatch { expected: u16, calculated: u16 },
+    /// File CRC does not match the header and data section, when the parse
+    /// enforces it.
+    FileCrcMismatch { expected: u16, calculated: u16 },
+    /// A field definition has a base type not in the FIT protocol.
+    UnknownBaseType { base_type: u8, offset: usize },
+    /// The data ended in the middle of a record.
+    UnexpectedEndOfData { offset: usize },
+    /// A data record refers to a local message type that has no definition.
+    UndefinedLocalMessageType {
+        local_message_type: u8,
+        offset: usize,
+    },
+    /// The file exceeds one of the configured parse limits.
+    ResourceLimitExceeded { limit: ResourceLimit, offset: usize },
+    /// The parse was cancelled before the record at the offset.
+    Cancelled { offset: usize },
+    /// A definition record uses a feature that the protocol version of the
+    /// file does not allow, when the parse enforces it.
+    ProtocolViolation {
+        violation: ProtocolViolation,
+        protocol_version: u8,
+        offset: usize,
+    },
+}
+
+impl fmt::Display for FitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidHeaderSize { header_size } => {
+                write!(f, "Invalid header size {}", header_size)
+            }
+            Self::HeaderCrcMismatch {
+                expected,
+                calculated,
+            } => write!(
+                f,
+                "Header CRC {:#06x} does not match calculated CRC {:#06x}",
+                expected, calculated
+            ),
+            Self::FileCrcMismatch {
+                expected,
+                calculated,
+            } => write!(
+                f,
+                "File CRC {:#06x} does not match calculated CRC {:#06x}",
+                expected, calculated
+            ),
+            Self::UnknownBaseType { base_type, offset } => write!(
+                f,
+                "Definition record at offset {} has unknown base type {:#04x}",
+                offset, base_type
+            ),
+            Self::UnexpectedEndOfData { offset } => {
+                write!(f, "Unexpected end of data in record at offset {}", offset)
+            }
+            Self::UndefinedLocalMessageType {
+                local_message_type,
+                offset,
+            } => write!(
+                f,
+                "Data record at offset {} refers to undefined local message type {}",
+                offset, local_message_type
+            ),
+            Self::ResourceLimitExceeded { limit, offset } => {
+                write!(f, "Limit of {} exceeded at offset {}", limit, offset)
+            }
+            Self::Cancelled { offset } => write!(f, "Parse cancelled at offset {}", offset),
+            Self::ProtocolViolation {
+                violation,
+                protocol_version,
+                offset,
+            } => write!(
+                f,
+                "Definition record at offset {} has {}, not allowed in protocol version {}",
+                offset,
+                violation,
+                format_protocol_version(*protocol_version)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FitError {}
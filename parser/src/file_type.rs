@@ -0,0 +1,166 @@
+//! The type of a file from its `file_id` message, and decoding a file to
+//! the model of its type.
+
+use crate::activity::SessionLaps;
+use crate::course::Course;
+use crate::error::FitError;
+use crate::fit_file::FitFile;
+#[cfg(feature = "monitoring")]
+use crate::monitoring::MonitoringSample;
+use crate::profile::messages::{FileId, Record};
+use crate::profile::{File, FILE_ID_MESSAGE, FILE_TYPE};
+use crate::segment::Segment;
+use crate::settings::Settings;
+use crate::workout::Workout;
+
+/// The `type` of the `file_id` message of a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FileType {
+    Activity,
+    Course,
+    Workout,
+    Settings,
+    Sport,
+    Goals,
+    Schedules,
+    MonitoringA,
+    MonitoringB,
+    MonitoringDaily,
+    Segment,
+    /// Any other type by its `file` value.
+    Other(u8),
+}
+
+// File types with a variant by their `file` value.
+const FILE_TYPES: [(File, FileType); 11] = [
+    (File::Activity, FileType::Activity),
+    (File::Course, FileType::Course),
+    (File::Workout, FileType::Workout),
+    (File::Settings, FileType::Settings),
+    (File::Sport, FileType::Sport),
+    (File::Goals, FileType::Goals),
+    (File::Schedules, FileType::Schedules),
+    (File::MonitoringA, FileType::MonitoringA),
+    (File::MonitoringB, FileType::MonitoringB),
+    (File::MonitoringDaily, FileType::MonitoringDaily),
+    (File::Segment, FileType::Segment),
+];
+
+impl FileType {
+    pub fn from_number(number: u8) -> FileType {
+        FILE_TYPES
+            .into_iter()
+            .find_map(|(file, file_type)| (file as u8 == number).then_some(file_type))
+            .unwrap_or(FileType::Other(number))
+    }
+
+    /// The `file` value of the type.
+    pub fn number(&self) -> u8 {
+        match self {
+            FileType::Other(number) => *number,
+            file_type => FILE_TYPES
+                .into_iter()
+                .find_map(|(file, t)| (t == *file_type).then_some(file as u8))
+                .unwrap(),
+        }
+    }
+
+    /// Profile name of the type, such as `activity`.
+    pub fn name(&self) -> Option<&'static str> {
+        FILE_TYPE.value_name(self.number().into())
+    }
+}
+
+/// A file decoded to the model of its type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileModel {
+    Activity {
+        sessions: Vec<SessionLaps>,
+        /// Ordered by timestamp.
+        records: Vec<Record>,
+    },
+    Course(Course),
+    Workout(Workout),
+    /// Settings, sport, goals and schedules files.
+    Settings(Settings),
+    #[cfg(feature = "monitoring")]
+    Monitoring(Vec<MonitoringSample>),
+    Segment(Segment),
+    /// Files of other types and files without a type.
+    Other(Option<FileType>),
+}
+
+impl FitFile {
+    /// Type of the file from its first `file_id` message.
+    pub fn file_type(&self) -> Option<FileType> {
+        self.typed_messages(FILE_ID_MESSAGE.number, FileId::from_message)
+            .next()
+            .and_then(|file_id| file_id.r#type)
+            .map(FileType::from_number)
+    }
+
+    /// The model of the file's type.
+    pub fn model(&self) -> FileModel {
+        match self.file_type() {
+            Some(FileType::Activity) => FileModel::Activity {
+                sessions: self.sessions(),
+                records: self.records(),
+            },
+            Some(FileType::Course) => FileModel::Course(self.course()),
+            Some(FileType::Workout) => FileModel::Workout(self.workout()),
+            Some(FileType::Settings | FileType::Sport | FileType::Goals | FileType::Schedules) => {
+                FileModel::Settings(self.settings())
+            }
+            #[cfg(feature = "monitoring")]
+            Some(FileType::MonitoringA | FileType::MonitoringB | FileType::MonitoringDaily) => {
+                FileModel::Monitoring(self.monitoring_samples())
+            }
+            Some(FileType::Segment) => FileModel::Segment(self.segment()),
+            file_type => FileModel::Other(file_type),
+        }
+    }
+}
+
+/// Parses a file and decodes it to the model of its type.
+pub fn decode_model(fit_data: &[u8]) -> Result<FileModel, FitError> {
+    Ok(FitFile::parse(fit_data)?.model())
+}
+
+#[cfg(test)]
+use crate::fit_file::{test_fit_file, test_message};
+#[cfg(test)]
+use crate::fit_messages::Value;
+
+#[test]
+fn test_file_type_dispatch() {
+    let file_id = |file_type: u8| test_message(0, vec![(0, "type", Value::U8(file_type))]);
+    let workout = test_fit_file(vec![
+        file_id(5),
+        test_message(
+            26,
+            vec![(8, "wkt_name", Value::String("Intervals".to_string()))],
+        ),
+    ]);
+    assert_eq!(workout.file_type(), Some(FileType::Workout));
+    assert_eq!(FileType::Workout.name(), Some("workout"));
+    match workout.model() {
+        FileModel::Workout(w) => assert_eq!(w.name.as_deref(), Some("Intervals")),
+        model => panic!("unexpected model {:?}", model),
+    }
+
+    let course = test_fit_file(vec![
+        file_id(6),
+        test_message(32, vec![(254, "message_index", Value::U16(1))]),
+        test_message(32, vec![(254, "message_index", Value::U16(0))]),
+    ]);
+    match course.model() {
+        FileModel::Course(c) => assert_eq!(c.points[0].message_index, Some(0)),
+        model => panic!("unexpected model {:?}", model),
+    }
+
+    assert_eq!(FileType::from_number(40), FileType::Other(40));
+    assert_eq!(FileType::Other(40).name(), Some("exd_configuration"));
+    let other = test_fit_file(vec![file_id(40)]);
+    assert_eq!(other.model(), FileModel::Other(Some(FileType::Other(40))));
+    assert_eq!(test_fit_file(Vec::new()).model(), FileModel::Other(None));
+}
@@ -0,0 +1,542 @@
+use crate::crc::FitCrc;
+use crate::decoder::{CrcPolicy, DecoderState, ParserOptions, Progress};
+use crate::diagnostics::{DiagnosticKind, Diagnostics};
+use crate::error::FitError;
+use crate::fit_header::{
+    try_parse_fit_header_from_data, FitFileHeader, ProfileCompatibility, ProtocolViolation,
+};
+use crate::fit_messages::{DataMessage, FromFitValue, MessageDecoder, Value};
+use crate::fit_records::{DefinitionRecord, Record, RecordIterator};
+use crate::limits::{ParseLimits, ResourceLimit};
+use crate::trace::{event, RECORD_EVENT_INTERVAL};
+
+// Typical size in bytes of a data record in activity files, definition
+// records amortized, used to estimate the message count from the data size.
+const TYPICAL_RECORD_SIZE: usize = 32;
+
+// Bytes of the data section between progress reports.
+const PROGRESS_INTERVAL: usize = 64 * 1024;
+
+/// A parsed FIT file: the header, the decoded data messages and the
+/// non-fatal issues found while decoding them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FitFile {
+    pub header: FitFileHeader,
+    pub messages: Vec<DataMessage>,
+    pub diagnostics: Diagnostics,
+}
+
+// Smallest average data record size accepted from untrusted sources, a
+// record header and a few bytes of fields. Caps the message count, and so
+// the memory use, by the input size.
+const UNTRUSTED_BYTES_PER_MESSAGE: usize = 4;
+
+/// Parses a FIT file from an untrusted source. Malformed input of any kind
+/// results in an error rather than a panic. Memory use grows linearly with
+/// the input size: files of more than one data message per 4 bytes fail with
+/// a resource limit error.
+pub fn parse_untrusted(fit_data: &[u8]) -> Result<FitFile, FitError> {
+    let limits = ParseLimits {
+        max_messages: fit_data.len() / UNTRUSTED_BYTES_PER_MESSAGE,
+        ..ParseLimits::default()
+    };
+    FitFile::parse_with_limits(fit_data, &limits)
+}
+
+impl FitFile {
+    /// Parses a whole FIT file. Data records referring to an undefined local
+    /// message type end the data section with a diagnostic, a malformed
+    /// header or other malformed records fail the parse.
+    pub fn parse(fit_data: &[u8]) -> Result<FitFile, FitError> {
+        FitFile::parse_with_limits(fit_data, &ParseLimits::default())
+    }
+
+    /// Parses a whole FIT file like [`FitFile::parse`], failing as soon as
+    /// the file exceeds one of `limits`.
+    pub fn parse_with_limits(fit_data: &[u8], limits: &ParseLimits) -> Result<FitFile, FitError> {
+        let options = ParserOptions {
+            limits: limits.clone(),
+            ..ParserOptions::default()
+        };
+        FitFile::parse_impl(fit_data, &options, &mut |_| {}, &mut DecoderState::new())
+    }
+
+    pub(crate) fn parse_impl(
+        fit_data: &[u8],
+        options: &ParserOptions,
+        progress: &mut dyn FnMut(Progress),
+        state: &mut DecoderState,
+    ) -> Result<FitFile, FitError> {
+        let limits = &options.limits;
+        let header = try_parse_fit_header_from_data(fit_data)?;
+        if header.data_size() > limits.max_data_size {
+            return Err(FitError::ResourceLimitExceeded {
+                limit: ResourceLimit::DataSize,
+                offset: 0,
+            });
+        }
+        let data_start = header.header_size() as usize;
+        let data_end = fit_data.len().min(data_start + header.data_size() as usize);
+        let mut decoder = MessageDecoder::with_options(options.apply_scale, options.strings);
+        decoder.set_state(state.decode.clone());
+        let mut diagnostics = Diagnostics::new();
+        check_file_crc(fit_data, data_end, options.crc, &mut diagnostics)?;
+        if header.profile_compatibility() == ProfileCompatibility::Newer {
+            diagnostics.push(
+                0,
+                DiagnosticKind::NewerProfile {
+                    profile_version: header.profile_version(),
+                },
+            );
+        }
+        // Estimated from the data actually present rather than the size the
+        // header claims, so a bogus header can not cause a huge allocation.
+        // Records are at least one byte, which bounds the hint.
+        let data_len = data_end - data_start;
+        let capacity = options
+            .capacity_hint
+            .unwrap_or(data_len / TYPICAL_RECORD_SIZE)
+            .min(data_len)
+            .min(limits.max_messages);
+        let mut messages = Vec::with_capacity(capacity);
+        let total_bytes = data_len;
+        let mut next_report = 0;
+        let mut records = RecordIterator::with_local_messages(
+            &fit_data[..data_end],
+            data_start,
+            state.local_messages.clone(),
+        );
+        loop {
+            let record_offset = records.offset();
+            let record_index = records.record_index();
+            if options
+                .cancellation
+                .as_ref()
+                .is_some_and(|c| c.is_cancelled())
+            {
+                return Err(FitError::Cancelled {
+                    offset: record_offset,
+                });
+            }
+            let bytes_processed = record_offset - data_start;
+            if bytes_processed >= next_report {
+                progress(Progress {
+                    bytes_processed,
+                    total_bytes,
+                });
+                next_report = bytes_processed + PROGRESS_INTERVAL;
+            }
+            if record_index > 0 && record_index.is_multiple_of(RECORD_EVENT_INTERVAL) {
+                event!(
+                    Debug,
+                    "Read {} records, {} of {} data bytes",
+                    record_index,
+                    bytes_processed,
+                    total_bytes
+                );
+            }
+            match records.next() {
+                None => break,
+                Some(Ok(Record::Definition(definition))) => {
+                    event!(
+                        Trace,
+                        "Definition of local message type {} at offset {}: \
+                         global message {}, {} fields, {} developer fields",
+                        definition.header.local_message_type,
+                        record_offset,
+                        definition.global_message_number,
+                        definition.field_definitions.len(),
+                        definition.developer_field_definitions.len()
+                    );
+                    check_definition_limits(&definition, record_offset, limits)?;
+                    if options.strict_protocol {
+                        check_protocol(&definition, record_offset, header.protocol_version())?;
+                    }
+                }
+                Some(Ok(Record::Data(record))) => {
+                    if messages.len() >= limits.max_messages {
+                        return Err(FitError::ResourceLimitExceeded {
+                            limit: ResourceLimit::Messages,
+                            offset: record_offset,
+                        });
+                    }
+                    // Filtered messages are still decoded for the compressed
+                    // timestamps and accumulated components of later ones.
+                    let mut message =
+                        decoder.decode(record, record_offset, record_index, &mut diagnostics);
+                    if !options.includes_message(message.global_message_number) {
+                        continue;
+                    }
+                    if !options.developer_fields {
+                        message.developer_fields.clear();
+                    }
+                    check_message_limits(&message, limits)?;
+                    messages.push(message);
+                }
+                Some(Err(FitError::UndefinedLocalMessageType {
+                    local_message_type,
+                    offset,
+                })) => {
+                    diagnostics.push(
+                        offset,
+                        DiagnosticKind::UndefinedLocalMessageType { local_message_type },
+                    );
+                    break;
+                }
+                Some(Err(e @ FitError::ResourceLimitExceeded { .. })) => return Err(e),
+                Some(Err(error)) if options.lenient => {
+                    diagnostics.push(record_offset, DiagnosticKind::MalformedRecord { error });
+                    break;
+                }
+                Some(Err(e)) => return Err(e),
+            }
+        }
+        if let Some(rules) = &options.plausibility {
+            rules.check(&messages, &mut diagnostics);
+        }
+        progress(Progress {
+            bytes_processed: total_bytes,
+            total_bytes,
+        });
+        *state = DecoderState {
+            local_messages: records.local_messages().clone(),
+            decode: decoder.state().clone(),
+        };
+        event!(
+            Debug,
+            "Parsed {} messages with {} diagnostics",
+            messages.len(),
+            diagnostics.len()
+        );
+        Ok(FitFile {
+            header,
+            messages,
+            diagnostics,
+        })
+    }
+
+    /// Profile version of the file, encoded as major * 100 + minor.
+    pub fn profile_version(&self) -> u16 {
+        self.header.profile_version()
+    }
+
+    /// Compares the file's profile version against the compiled profile.
+    /// Files of a newer profile have a [`DiagnosticKind::NewerProfile`]
+    /// diagnostic.
+    pub fn profile_compatibility(&self) -> ProfileCompatibility {
+        self.header.profile_compatibility()
+    }
+
+    /// Values of a field of the messages with the global message number,
+    /// in file order, converted to `T`. Messages without the field or with
+    /// a value that does not convert are skipped.
+    pub fn field_values<'a, T: FromFitValue + 'a>(
+        &'a self,
+        global_message_number: u16,
+        field_definition_number: u8,
+    ) -> impl Iterator<Item = T> + 'a {
+        self.messages
+            .iter()
+            .filter(move |m| m.global_message_number == global_message_number)
+            .filter_map(move |m| m.typed_field(field_definition_number))
+    }
+}
+
+// Checks the file CRC following the data section according to `policy`.
+fn check_file_crc(
+    fit_data: &[u8],
+    data_end: usize,
+    policy: CrcPolicy,
+    diagnostics: &mut Diagnostics,
+) -> Result<(), FitError> {
+    if policy == CrcPolicy::Ignore {
+        return Ok(());
+    }
+    let expected = match fit_data.get(data_end..data_end + 2) {
+        Some(bytes) => u16::from_le_bytes([bytes[0], bytes[1]]),
+        None if policy == CrcPolicy::Enforce => {
+            return Err(FitError::UnexpectedEndOfData { offset: data_end })
+        }
+        None => return Ok(()),
+    };
+    let calculated = FitCrc::checksum(&fit_data[..data_end], 0);
+    if expected == calculated {
+        return Ok(());
+    }
+    match policy {
+        CrcPolicy::Enforce => Err(FitError::FileCrcMismatch {
+            expected,
+            calculated,
+        }),
+        _ => {
+            diagnostics.push(
+                data_end,
+                DiagnosticKind::FileCrcMismatch {
+                    expected,
+                    calculated,
+                },
+            );
+            Ok(())
+        }
+    }
+}
+
+fn check_definition_limits(
+    definition: &DefinitionRecord,
+    offset: usize,
+    limits: &ParseLimits,
+) -> Result<(), FitError> {
+    let field_count =
+        definition.field_definitions.len() + definition.developer_field_definitions.len();
+    if field_count > limits.max_fields_per_definition {
+        return Err(FitError::ResourceLimitExceeded {
+            limit: ResourceLimit::FieldsPerDefinition,
+            offset,
+        });
+    }
+    Ok(())
+}
+
+fn check_protocol(
+    definition: &DefinitionRecord,
+    offset: usize,
+    protocol_version: u8,
+) -> Result<(), FitError> {
+    match ProtocolViolation::of_definition(definition, protocol_version) {
+        Some(violation) => Err(FitError::ProtocolViolation {
+            violation,
+            protocol_version,
+            offset,
+        }),
+        None => Ok(()),
+    }
+}
+
+fn check_message_limits(message: &DataMessage, limits: &ParseLimits) -> Result<(), FitError> {
+    let too_long = message.fields.iter().any(|f| match &f.value {
+        Value::String(s) => s.len() > limits.max_string_length,
+        _ => false,
+    });
+    if too_long {
+        return Err(FitError::ResourceLimitExceeded {
+            limit: ResourceLimit::StringLength,
+            offset: message.offset,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) fn test_file(records: &[u8]) -> Vec<u8> {
+    let mut data = vec![
+        12, 0x10, 0x6F, 0x08, // header size, protocol and profile version
+    ];
+    data.extend_from_slice(&(records.len() as u32).to_le_bytes());
+    data.extend_from_slice(b".FIT");
+    data.extend_from_slice(records);
+    data.extend_from_slice(&[0x00, 0x00]); // file CRC, not checked
+    data
+}
+
+#[cfg(test)]
+use crate::fit_header::parse_fit_header_from_data;
+#[cfg(test)]
+use crate::fit_messages::FieldValue;
+
+/// A data message with the fields as `(number, name, value)`.
+#[cfg(test)]
+pub(crate) fn test_message(
+    global_message_number: u16,
+    fields: Vec<(u8, &'static str, Value)>,
+) -> DataMessage {
+    DataMessage {
+        fields: fields
+            .into_iter()
+            .map(|(number, name, value)| FieldValue {
+                field_definition_number: number,
+                name: Some(name),
+                value,
+                units: "",
+            })
+            .collect(),
+        ..DataMessage::new(global_message_number)
+    }
+}
+
+/// A file of the messages with the header of [`test_file`].
+#[cfg(test)]
+pub(crate) fn test_fit_file(messages: Vec<DataMessage>) -> FitFile {
+    FitFile {
+        header: parse_fit_header_from_data(&test_file(&[])),
+        messages,
+        diagnostics: Diagnostics::new(),
+    }
+}
+
+#[test]
+fn test_parse_decodes_messages() {
+    let data = test_file(&[
+        0x40, 0x00, 0x00, 0x14, 0x00, 0x01, 0x03, 0x01, 0x02, // record: heart_rate
+        0x00, 0x96, // heart_rate 150
+    ]);
+    let fit_file = FitFile::parse(&data).unwrap();
+    assert_eq!(fit_file.messages.len(), 1);
+    assert_eq!(fit_file.messages[0].name(), Some("record"));
+    assert_eq!(fit_file.messages[0].offset, 21);
+    assert_eq!(fit_file.messages[0].record_index, 1);
+    assert!(fit_file.diagnostics.is_empty());
+}
+
+#[test]
+fn test_parse_newer_profile_is_diagnostic() {
+    let mut data = test_file(&[]);
+    assert_eq!(
+        FitFile::parse(&data).unwrap().profile_compatibility(),
+        ProfileCompatibility::Older
+    );
+    data[2..4].copy_from_slice(&2200u16.to_le_bytes());
+    let fit_file = FitFile::parse(&data).unwrap();
+    assert_eq!(fit_file.profile_version(), 2200);
+    assert_eq!(
+        fit_file.profile_compatibility(),
+        ProfileCompatibility::Newer
+    );
+    let diagnostic = fit_file.diagnostics.iter().next().unwrap();
+    assert_eq!(
+        diagnostic.kind,
+        DiagnosticKind::NewerProfile {
+            profile_version: 2200
+        }
+    );
+    assert_eq!(
+        diagnostic.to_string(),
+        "Profile version 22.00 of the file is newer than supported profile version 21.60 (offset 0x0)"
+    );
+}
+
+#[test]
+fn test_parse_undefined_local_message_is_diagnostic() {
+    let data = test_file(&[
+        0x40, 0x00, 0x00, 0x14, 0x00, 0x01, 0x03, 0x01, 0x02, // record: heart_rate
+        0x00, 0x96, // heart_rate 150
+        0x01, 0x00, // local message type 1 is not defined
+    ]);
+    let fit_file = FitFile::parse(&data).unwrap();
+    assert_eq!(fit_file.messages.len(), 1);
+    assert_eq!(fit_file.diagnostics.len(), 1);
+    let diagnostic = fit_file.diagnostics.iter().next().unwrap();
+    assert_eq!(diagnostic.offset, 23);
+    assert_eq!(
+        diagnostic.kind,
+        DiagnosticKind::UndefinedLocalMessageType {
+            local_message_type: 1
+        }
+    );
+}
+
+#[test]
+fn test_parse_with_limits() {
+    let data = test_file(&[
+        0x40, 0x00, 0x00, 0x14, 0x00, 0x01, 0x03, 0x01, 0x02, // record: heart_rate
+        0x00, 0x96, // heart_rate 150
+        0x00, 0x97, // heart_rate 151
+    ]);
+    let limits = ParseLimits {
+        max_messages: 1,
+        ..ParseLimits::default()
+    };
+    assert_eq!(
+        FitFile::parse_with_limits(&data, &limits),
+        Err(FitError::ResourceLimitExceeded {
+            limit: ResourceLimit::Messages,
+            offset: 23
+        })
+    );
+    let limits = ParseLimits {
+        max_data_size: 12,
+        ..ParseLimits::default()
+    };
+    assert_eq!(
+        FitFile::parse_with_limits(&data, &limits),
+        Err(FitError::ResourceLimitExceeded {
+            limit: ResourceLimit::DataSize,
+            offset: 0
+        })
+    );
+    assert!(FitFile::parse_with_limits(&data, &ParseLimits::default()).is_ok());
+}
+
+#[test]
+fn test_parse_untrusted_malformed_input() {
+    assert!(parse_untrusted(&[]).is_err());
+    assert!(parse_untrusted(&[0xFF; 3]).is_err());
+    // header claims more data than there is, definition is cut short
+    let mut data = test_file(&[0x40, 0x00, 0x00, 0x14, 0x00, 0x05]);
+    data[4] = 0xFF;
+    assert_eq!(
+        parse_untrusted(&data),
+        Err(FitError::UnexpectedEndOfData { offset: 12 })
+    );
+    // one byte records of a definition without fields
+    let mut records = vec![0x40, 0x00, 0x00, 0x14, 0x00, 0x00];
+    records.extend_from_slice(&[0x00; 100]);
+    assert_eq!(
+        parse_untrusted(&test_file(&records)),
+        Err(FitError::ResourceLimitExceeded {
+            limit: ResourceLimit::Messages,
+            offset: 48
+        })
+    );
+}
+
+#[cfg(test)]
+use crate::decoder::FitDecoder;
+
+#[test]
+fn test_capacity_hint() {
+    let data = test_file(&[
+        0x40, 0x00, 0x00, 0x14, 0x00, 0x01, 0x03, 0x01, 0x02, // record: heart_rate
+        0x00, 0x96, // heart_rate 150
+    ]);
+    let decode = |hint| {
+        FitDecoder::builder()
+            .capacity_hint(hint)
+            .build()
+            .decode(&data)
+            .unwrap()
+    };
+    let fit_file = decode(4);
+    assert!(fit_file.messages.capacity() >= 4);
+    assert_eq!(fit_file, FitFile::parse(&data).unwrap());
+    // clamped to the 11 bytes of data
+    assert!(decode(usize::MAX).messages.capacity() >= 11);
+}
+
+#[test]
+fn test_field_values_from_fit_value() {
+    #[derive(Debug, PartialEq)]
+    struct HeartRate(u8);
+
+    impl FromFitValue for HeartRate {
+        fn from_fit_value(field: &FieldValue) -> Option<Self> {
+            if field.units != "bpm" {
+                return None;
+            }
+            u8::from_fit_value(field).map(HeartRate)
+        }
+    }
+
+    let data = test_file(&[
+        0x40, 0x00, 0x00, 0x14, 0x00, 0x01, 0x03, 0x01, 0x02, // record: heart_rate
+        0x00, 0x96, // heart_rate 150
+        0x00, 0xFF, // invalid heart_rate
+        0x00, 0x97, // heart_rate 151
+    ]);
+    let fit_file = FitFile::parse(&data).unwrap();
+    let heart_rates = fit_file
+        .field_values::<HeartRate>(20, 3)
+        .collect::<Vec<_>>();
+    assert_eq!(heart_rates, vec![HeartRate(150), HeartRate(151)]);
+    assert_eq!(fit_file.messages[0].typed_field(3), Some(HeartRate(150)));
+    assert_eq!(fit_file.field_values::<f64>(20, 3).sum::<f64>(), 301.0);
+}
@@ -1,5 +1,13 @@
+use std::fmt;
+
 use byteorder::{ByteOrder, LittleEndian};
 
+use crate::crc::FitCrc;
+use crate::error::FitError;
+use crate::fit_records::{BaseType, DefinitionRecord};
+use crate::profile::PROFILE_VERSION;
+use crate::trace::event;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FitFileHeader {
     header_size: u8,
@@ -8,6 +16,7 @@ pub struct FitFileHeader {
     data_size: u32,
     data_type: String,
     crc: Option<u16>,
+    extension: Vec<u8>,
 }
 
 struct FitFileHeaderOffsets {}
@@ -25,14 +34,83 @@ impl FitFileHeaderOffsets {
     const CRC_MSB: usize = 13;
 }
 
+// Smallest valid header size, a header without the header CRC.
+const MIN_HEADER_SIZE: u8 = 12;
+// Header size with the header CRC, the end of the fields of the spec.
+const HEADER_SIZE_WITH_CRC: u8 = 14;
+
+/// Parses the file header, panicking if it is malformed. Use
+/// [`try_parse_fit_header_from_data`] for data from untrusted sources.
 pub fn parse_fit_header_from_data(fit_data: &[u8]) -> FitFileHeader {
+    try_parse_fit_header_from_data(fit_data).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Parses the file header. A header CRC of zero means the CRC was not
+/// computed and is not checked.
+pub fn try_parse_fit_header_from_data(fit_data: &[u8]) -> Result<FitFileHeader, FitError> {
     FitFileHeader::from(fit_data)
 }
 
 impl FitFileHeader {
-    fn from(fit_data: &[u8]) -> FitFileHeader {
-        let header_size: u8 = fit_data[FitFileHeaderOffsets::HEADER_SIZE];
-        assert!(header_size as usize <= fit_data.len());
+    /// A 14 byte header of `.FIT` data with its header CRC.
+    pub fn new(protocol_version: u8, profile_version: u16, data_size: u32) -> FitFileHeader {
+        let mut header = FitFileHeader {
+            header_size: HEADER_SIZE_WITH_CRC,
+            protocol_version,
+            profile_version,
+            data_size,
+            data_type: String::from(".FIT"),
+            crc: None,
+            extension: Vec::new(),
+        };
+        header.crc = Some(fit_crc(&header.fields_to_bytes(), 0));
+        header
+    }
+
+    // The first 12 bytes of the header, covered by the header CRC.
+    fn fields_to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.header_size, self.protocol_version];
+        bytes.extend_from_slice(&self.profile_version.to_le_bytes());
+        bytes.extend_from_slice(&self.data_size.to_le_bytes());
+        let mut data_type = [0; 4];
+        for (b, c) in data_type.iter_mut().zip(self.data_type.bytes()) {
+            *b = c;
+        }
+        bytes.extend_from_slice(&data_type);
+        bytes
+    }
+
+    /// The header as written in files, with a recomputed header CRC for
+    /// headers of 14 bytes or more and the extension bytes after it.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.fields_to_bytes();
+        if self.header_size >= HEADER_SIZE_WITH_CRC {
+            let crc = fit_crc(&bytes, 0);
+            bytes.extend_from_slice(&crc.to_le_bytes());
+        }
+        bytes.extend_from_slice(&self.extension);
+        bytes
+    }
+
+    /// Sets the data size, such as after rewriting the records, updating the
+    /// header CRC of headers with one.
+    pub fn set_data_size(&mut self, data_size: u32) {
+        self.data_size = data_size;
+        if self.crc.is_some() {
+            self.crc = Some(fit_crc(&self.fields_to_bytes(), 0));
+        }
+    }
+
+    fn from(fit_data: &[u8]) -> Result<FitFileHeader, FitError> {
+        let header_size: u8 = *fit_data
+            .get(FitFileHeaderOffsets::HEADER_SIZE)
+            .ok_or(FitError::UnexpectedEndOfData { offset: 0 })?;
+        if header_size < MIN_HEADER_SIZE {
+            return Err(FitError::InvalidHeaderSize { header_size });
+        }
+        if header_size as usize > fit_data.len() {
+            return Err(FitError::UnexpectedEndOfData { offset: 0 });
+        }
         let protocol_version: u8 = fit_data[FitFileHeaderOffsets::PROTOCOL_VERSION];
         let profile_version: u16 = LittleEndian::read_u16(
             &fit_data[FitFileHeaderOffsets::PROFILE_VERSION_LSB
@@ -45,68 +123,181 @@ impl FitFileHeader {
         let data_size = LittleEndian::read_u32(
             &fit_data[FitFileHeaderOffsets::DATA_SIZE_LSB..=FitFileHeaderOffsets::DATA_SIZE_MSB],
         );
-        let data_type = String::from_utf8(
-            fit_data[FitFileHeaderOffsets::DATA_TYPE_START..=FitFileHeaderOffsets::DATA_TYPE_END]
-                .to_vec(),
+        let data_type = String::from_utf8_lossy(
+            &fit_data[FitFileHeaderOffsets::DATA_TYPE_START..=FitFileHeaderOffsets::DATA_TYPE_END],
         )
-        .unwrap();
+        .into_owned();
         let mut crc: Option<u16> = None;
+        let mut extension_start = MIN_HEADER_SIZE as usize;
 
-        if header_size > (FitFileHeaderOffsets::CRC_LSB as u8)
-            && header_size >= (FitFileHeaderOffsets::CRC_MSB as u8)
-        {
+        // The header CRC covers the first 12 bytes only, also of headers
+        // with extension bytes after it: the spec places the CRC in bytes 12
+        // and 13 and defines it over the bytes before it. Extension bytes are
+        // covered by the file CRC instead.
+        if header_size >= HEADER_SIZE_WITH_CRC {
             let crc_in_data = LittleEndian::read_u16(
                 &fit_data[FitFileHeaderOffsets::CRC_LSB..=FitFileHeaderOffsets::CRC_MSB],
             );
-            let calculated_crc = fit_crc_vec(fit_data[..FitFileHeaderOffsets::CRC_LSB].to_vec(), 0);
-            assert_eq!(crc_in_data, calculated_crc, "crc mismatch");
+            let calculated_crc = fit_crc(&fit_data[..FitFileHeaderOffsets::CRC_LSB], 0);
+            if crc_in_data != 0 && crc_in_data != calculated_crc {
+                return Err(FitError::HeaderCrcMismatch {
+                    expected: crc_in_data,
+                    calculated: calculated_crc,
+                });
+            }
             crc = Some(crc_in_data);
+            extension_start = HEADER_SIZE_WITH_CRC as usize;
         }
+        let extension = fit_data[extension_start..header_size as usize].to_vec();
 
-        FitFileHeader {
+        event!(
+            Debug,
+            "Parsed header of {} bytes: protocol version {}, profile version {}, data size {}",
+            header_size,
+            protocol_version,
+            format_profile_version(profile_version),
+            data_size
+        );
+        Ok(FitFileHeader {
             header_size,
             protocol_version,
             profile_version,
             data_size,
             data_type,
             crc,
-        }
+            extension,
+        })
     }
 }
 
-static CRC_TABLE: [u16; 16] = [
-    0x0000, 0xCC01, 0xD801, 0x1400, 0xF001, 0x3C00, 0x2800, 0xE401, 0xA001, 0x6C00, 0x7800, 0xB401,
-    0x5000, 0x9C01, 0x8801, 0x4400,
-];
+/// How a file's profile version relates to the profile compiled into this crate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfileCompatibility {
+    /// File was written with an older profile, all its messages are known.
+    Older,
+    /// File was written with the same profile version.
+    Same,
+    /// File was written with a newer profile and may contain messages,
+    /// fields or enum values unknown to this crate.
+    Newer,
+}
 
-pub fn fit_crc(data: &[u8], crc_in: u16) -> u16 {
-    let mut crc = crc_in;
-    let mut tmp: u16;
-    for byte in data {
-        tmp = CRC_TABLE[(crc & 0xF) as usize];
-        crc = (crc >> 4) & 0x0FFF;
-        crc = crc ^ tmp ^ CRC_TABLE[(byte & 0xF) as usize];
+impl FitFileHeader {
+    pub fn header_size(&self) -> u8 {
+        self.header_size
+    }
+
+    /// Protocol version of the file, encoded as major * 16 + minor.
+    pub fn protocol_version(&self) -> u8 {
+        self.protocol_version
+    }
+
+    pub fn protocol_major_version(&self) -> u8 {
+        self.protocol_version >> 4
+    }
+
+    pub fn protocol_minor_version(&self) -> u8 {
+        self.protocol_version & 0x0F
+    }
+
+    /// Profile version of the file, encoded as major * 100 + minor.
+    pub fn profile_version(&self) -> u16 {
+        self.profile_version
+    }
+
+    pub fn data_size(&self) -> u32 {
+        self.data_size
+    }
+
+    pub fn data_type(&self) -> &str {
+        &self.data_type
+    }
+
+    /// Header CRC of 14 byte and larger headers. It covers the first 12
+    /// bytes of the header only, not the extension bytes after it.
+    pub fn crc(&self) -> Option<u16> {
+        self.crc
+    }
+
+    /// Bytes of the header after the fields of the spec, such as vendor
+    /// extensions of headers larger than 14 bytes. Empty for 12 and 14 byte
+    /// headers. They are covered by the file CRC, not the header CRC.
+    pub fn extension(&self) -> &[u8] {
+        &self.extension
+    }
 
-        tmp = CRC_TABLE[(crc & 0xF) as usize];
-        crc = (crc >> 4) & 0x0FFF;
-        crc = crc ^ tmp ^ CRC_TABLE[((byte >> 4) & 0xF) as usize];
+    /// Compares the file's profile version against the compiled profile.
+    pub fn profile_compatibility(&self) -> ProfileCompatibility {
+        compare_profile_version(self.profile_version, PROFILE_VERSION)
     }
-    crc
 }
 
-fn fit_crc_vec(data: Vec<u8>, crc_in: u16) -> u16 {
-    let mut crc = crc_in;
-    let mut tmp: u16;
-    for byte in data {
-        tmp = CRC_TABLE[(crc & 0xF) as usize];
-        crc = (crc >> 4) & 0x0FFF;
-        crc = crc ^ tmp ^ CRC_TABLE[(byte & 0xF) as usize];
+fn compare_profile_version(file_version: u16, compiled_version: u16) -> ProfileCompatibility {
+    match file_version.cmp(&compiled_version) {
+        std::cmp::Ordering::Less => ProfileCompatibility::Older,
+        std::cmp::Ordering::Equal => ProfileCompatibility::Same,
+        std::cmp::Ordering::Greater => ProfileCompatibility::Newer,
+    }
+}
+
+/// Formats a profile version encoded as major * 100 + minor, e.g. `21.60`.
+pub fn format_profile_version(profile_version: u16) -> String {
+    format!("{}.{:02}", profile_version / 100, profile_version % 100)
+}
+
+/// Formats a protocol version encoded as major * 16 + minor, e.g. `2.0`.
+pub fn format_protocol_version(protocol_version: u8) -> String {
+    format!("{}.{}", protocol_version >> 4, protocol_version & 0x0F)
+}
+
+// Protocol major version of developer fields and 64-bit base types.
+const PROTOCOL_MAJOR_VERSION_2: u8 = 2;
+
+/// A record feature that the protocol version of the file does not allow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtocolViolation {
+    /// Developer fields came with protocol version 2.0.
+    DeveloperFields,
+    /// 64-bit base types came with protocol version 2.0.
+    Base64Bit { base_type: u8 },
+}
+
+impl fmt::Display for ProtocolViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DeveloperFields => write!(f, "developer fields"),
+            Self::Base64Bit { base_type } => write!(f, "64-bit base type {:#04x}", base_type),
+        }
+    }
+}
 
-        tmp = CRC_TABLE[(crc & 0xF) as usize];
-        crc = (crc >> 4) & 0x0FFF;
-        crc = crc ^ tmp ^ CRC_TABLE[((byte >> 4) & 0xF) as usize];
+impl ProtocolViolation {
+    /// The first feature of the definition that the protocol version does
+    /// not allow.
+    pub(crate) fn of_definition(
+        definition: &DefinitionRecord,
+        protocol_version: u8,
+    ) -> Option<ProtocolViolation> {
+        if protocol_version >> 4 >= PROTOCOL_MAJOR_VERSION_2 {
+            return None;
+        }
+        if definition.header.contains_extended_definitions {
+            return Some(ProtocolViolation::DeveloperFields);
+        }
+        definition
+            .field_definitions
+            .iter()
+            .find(|f| f.base_type.size == 8 && f.base_type.base_type != BaseType::Float64)
+            .map(|f| ProtocolViolation::Base64Bit {
+                base_type: f.base_type.base_type_field,
+            })
     }
-    crc
+}
+
+/// CRC of `data`, continuing from `crc_in`. See [`FitCrc`] for computing the
+/// CRC incrementally.
+pub fn fit_crc(data: &[u8], crc_in: u16) -> u16 {
+    FitCrc::checksum(data, crc_in)
 }
 
 #[test]
@@ -118,6 +309,7 @@ fn fit_file_header_from_data() {
         data_size: 0x0A0B0C0D,
         data_type: String::from(".FIT"),
         crc: Some(0xA7A3),
+        extension: Vec::new(),
     };
     let data: Vec<u8> = vec![
         14, // header size
@@ -128,10 +320,111 @@ fn fit_file_header_from_data() {
         0xA3, 0xA7, // crc
         0xA3, 0xA7, // file crc
     ];
-    let actual_header = FitFileHeader::from(&data);
+    let actual_header = FitFileHeader::from(&data).unwrap();
     assert_eq!(
         expected_header, actual_header,
         "expected: {:?}, actual: {:?}",
         expected_header, actual_header
     )
 }
+
+#[test]
+fn test_profile_compatibility() {
+    assert_eq!(
+        compare_profile_version(2150, 2160),
+        ProfileCompatibility::Older
+    );
+    assert_eq!(
+        compare_profile_version(2160, 2160),
+        ProfileCompatibility::Same
+    );
+    assert_eq!(
+        compare_profile_version(2178, 2160),
+        ProfileCompatibility::Newer
+    );
+    assert_eq!(format_profile_version(2108), "21.08");
+}
+
+#[test]
+fn test_try_parse_malformed_header() {
+    assert_eq!(
+        try_parse_fit_header_from_data(&[]),
+        Err(FitError::UnexpectedEndOfData { offset: 0 })
+    );
+    assert_eq!(
+        try_parse_fit_header_from_data(&[4, 0, 0, 0]),
+        Err(FitError::InvalidHeaderSize { header_size: 4 })
+    );
+    assert_eq!(
+        try_parse_fit_header_from_data(&[14, 0, 0, 0]),
+        Err(FitError::UnexpectedEndOfData { offset: 0 })
+    );
+    let data = [
+        14, 3, 0x0B, 0x0A, 0, 0, 0, 0, 0x2E, 0x46, 0x49, 0x54, 0x01, 0x00,
+    ];
+    assert!(matches!(
+        try_parse_fit_header_from_data(&data),
+        Err(FitError::HeaderCrcMismatch { expected: 1, .. })
+    ));
+}
+
+#[test]
+fn test_header_with_extension() {
+    let mut data = vec![
+        16, 0x20, 0x0B, 0x0A, 0, 0, 0, 0, 0x2E, 0x46, 0x49, 0x54, 0, 0, 0xAB, 0xCD, 0, 0,
+    ];
+    let crc = fit_crc(&data[..12], 0);
+    data[12..14].copy_from_slice(&crc.to_le_bytes());
+    let header = try_parse_fit_header_from_data(&data).unwrap();
+    assert_eq!(header.header_size(), 16);
+    assert_eq!(header.crc(), Some(crc));
+    assert_eq!(header.extension(), &[0xAB, 0xCD]);
+
+    // extension bytes are left out of the header CRC
+    data[14..16].copy_from_slice(&[0x12, 0x34]);
+    assert_eq!(
+        try_parse_fit_header_from_data(&data).unwrap().crc(),
+        Some(crc)
+    );
+    let mut covered = data[..12].to_vec();
+    covered.extend_from_slice(&data[14..16]);
+    let extended_crc = fit_crc(&covered, 0);
+    data[12..14].copy_from_slice(&extended_crc.to_le_bytes());
+    assert_eq!(
+        try_parse_fit_header_from_data(&data),
+        Err(FitError::HeaderCrcMismatch {
+            expected: extended_crc,
+            calculated: crc
+        })
+    );
+    data[12..14].copy_from_slice(&crc.to_le_bytes());
+
+    // a 13 byte header has no CRC
+    data[0] = 13;
+    let header = try_parse_fit_header_from_data(&data).unwrap();
+    assert_eq!(header.crc(), None);
+    assert_eq!(header.extension(), &data[12..13]);
+}
+
+#[test]
+fn test_header_to_bytes() {
+    let mut header = FitFileHeader::new(0x20, 2160, 100);
+    let bytes = header.to_bytes();
+    assert_eq!(bytes.len(), 14);
+    assert_eq!(try_parse_fit_header_from_data(&bytes).unwrap(), header);
+
+    header.set_data_size(200);
+    let bytes = header.to_bytes();
+    let parsed = try_parse_fit_header_from_data(&bytes).unwrap();
+    assert_eq!(parsed.data_size(), 200);
+    assert_eq!(parsed, header);
+
+    // extension bytes are written back after the header CRC
+    let mut data = bytes.clone();
+    data[0] = 16;
+    data.extend_from_slice(&[0xAB, 0xCD]);
+    let crc = fit_crc(&data[..12], 0);
+    data[12..14].copy_from_slice(&crc.to_le_bytes());
+    let header = try_parse_fit_header_from_data(&data).unwrap();
+    assert_eq!(header.to_bytes(), data);
+}
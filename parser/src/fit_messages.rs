@@ -0,0 +1,1280 @@
+use std::fmt;
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+use crate::accumulator::Accumulator;
+use crate::decoder::StringHandling;
+use crate::diagnostics::{DiagnosticKind, Diagnostics};
+use crate::fit_records::{
+    base_type_info_by_name, BaseType, BaseTypeInfo, DataRecord, Endianness, RawDeveloperField,
+    RawField, RecordHeader,
+};
+use crate::profile::{
+    message_info, subfield_name, type_info, ComponentInfo, FieldArray, FieldInfo, MessageInfo,
+};
+
+/// Field definition number of the `timestamp` field shared by all messages.
+pub const TIMESTAMP_FIELD_NUMBER: u8 = 253;
+
+/// A value of a profile enum type, such as `sport`, by its number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EnumValue {
+    /// Profile name of the type.
+    pub type_name: &'static str,
+    pub value: u32,
+}
+
+impl EnumValue {
+    /// Profile name of the value, such as `running`, `None` for values not
+    /// in the profile.
+    pub fn name(&self) -> Option<&'static str> {
+        type_info(self.type_name)?.value_name(self.value)
+    }
+}
+
+impl fmt::Display for EnumValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "{}", self.value),
+        }
+    }
+}
+
+/// A decoded field value. Fields of the profile enum types, such as `sport`,
+/// decode as [`Value::Enum`] and display as the name of the value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    String(String),
+    Bytes(Vec<u8>),
+    Array(Vec<Value>),
+    Enum(EnumValue),
+}
+
+impl Value {
+    /// The value as a float, for numeric non-array values.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::U8(v) => Some(*v as f64),
+            Value::U16(v) => Some(*v as f64),
+            Value::U32(v) => Some(*v as f64),
+            Value::U64(v) => Some(*v as f64),
+            Value::I8(v) => Some(*v as f64),
+            Value::I16(v) => Some(*v as f64),
+            Value::I32(v) => Some(*v as f64),
+            Value::I64(v) => Some(*v as f64),
+            Value::F32(v) => Some(*v as f64),
+            Value::F64(v) => Some(*v),
+            Value::Enum(v) => Some(v.value as f64),
+            Value::String(_) | Value::Bytes(_) | Value::Array(_) => None,
+        }
+    }
+
+    /// The value as an unsigned integer, for integer non-array values.
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Value::U8(v) => Some(*v as u64),
+            Value::U16(v) => Some(*v as u64),
+            Value::U32(v) => Some(*v as u64),
+            Value::U64(v) => Some(*v),
+            Value::I8(v) => u64::try_from(*v).ok(),
+            Value::I16(v) => u64::try_from(*v).ok(),
+            Value::I32(v) => u64::try_from(*v).ok(),
+            Value::I64(v) => u64::try_from(*v).ok(),
+            Value::Enum(v) => Some(v.value as u64),
+            _ => None,
+        }
+    }
+
+    /// The value as a signed integer, for integer non-array values.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::U8(v) => Some(*v as i64),
+            Value::U16(v) => Some(*v as i64),
+            Value::U32(v) => Some(*v as i64),
+            Value::U64(v) => i64::try_from(*v).ok(),
+            Value::I8(v) => Some(*v as i64),
+            Value::I16(v) => Some(*v as i64),
+            Value::I32(v) => Some(*v as i64),
+            Value::I64(v) => Some(*v),
+            Value::Enum(v) => Some(v.value as i64),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::U8(v) => write!(f, "{}", v),
+            Value::U16(v) => write!(f, "{}", v),
+            Value::U32(v) => write!(f, "{}", v),
+            Value::U64(v) => write!(f, "{}", v),
+            Value::I8(v) => write!(f, "{}", v),
+            Value::I16(v) => write!(f, "{}", v),
+            Value::I32(v) => write!(f, "{}", v),
+            Value::I64(v) => write!(f, "{}", v),
+            Value::F32(v) => write!(f, "{}", v),
+            Value::F64(v) => write!(f, "{}", v),
+            Value::String(v) => write!(f, "{}", v),
+            Value::Bytes(bytes) => {
+                for byte in bytes {
+                    write!(f, "{:02x}", byte)?;
+                }
+                Ok(())
+            }
+            Value::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            Value::Enum(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+/// A value could not be converted to the requested type.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueConversionError {
+    pub value: Value,
+    /// Name of the requested type, such as `f64`.
+    pub target: &'static str,
+}
+
+impl fmt::Display for ValueConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Value {} can not be converted to {}",
+            self.value, self.target
+        )
+    }
+}
+
+impl std::error::Error for ValueConversionError {}
+
+macro_rules! impl_try_from_value {
+    ($($t:ty => $target:literal, $convert:expr);*) => {
+        $(
+            impl TryFrom<&Value> for $t {
+                type Error = ValueConversionError;
+
+                fn try_from(value: &Value) -> Result<Self, Self::Error> {
+                    let convert: fn(&Value) -> Option<$t> = $convert;
+                    convert(value).ok_or_else(|| ValueConversionError {
+                        value: value.clone(),
+                        target: $target,
+                    })
+                }
+            }
+
+            impl TryFrom<Value> for $t {
+                type Error = ValueConversionError;
+
+                fn try_from(value: Value) -> Result<Self, Self::Error> {
+                    <$t>::try_from(&value)
+                }
+            }
+        )*
+    };
+}
+
+impl_try_from_value!(
+    f64 => "f64", Value::as_f64;
+    i64 => "i64", Value::as_i64;
+    // strings, and the names of enum values
+    String => "String", |value| match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Enum(v) => v.name().map(String::from),
+        _ => None,
+    }
+);
+
+/// Conversion of a decoded value to the Rust type of a profile message
+/// struct field.
+pub trait FromValue: Sized {
+    fn from_value(value: &Value) -> Option<Self>;
+}
+
+macro_rules! impl_from_value_for_integer {
+    ($($t:ty),*) => {
+        $(
+            impl FromValue for $t {
+                fn from_value(value: &Value) -> Option<Self> {
+                    match value {
+                        Value::F32(_) | Value::F64(_) => value
+                            .as_f64()
+                            .filter(|v| v.fract() == 0.0)
+                            .and_then(|v| <$t>::try_from(v as i64).ok()),
+                        _ => value.as_i64().and_then(|v| <$t>::try_from(v).ok()),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_from_value_for_integer!(u8, u16, u32, i8, i16, i32, i64);
+
+impl FromValue for u64 {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::F32(_) | Value::F64(_) => value
+                .as_f64()
+                .filter(|v| v.fract() == 0.0 && *v >= 0.0)
+                .map(|v| v as u64),
+            _ => value.as_u64(),
+        }
+    }
+}
+
+impl FromValue for f32 {
+    fn from_value(value: &Value) -> Option<Self> {
+        value.as_f64().map(|v| v as f32)
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: &Value) -> Option<Self> {
+        value.as_f64()
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Value) -> Option<Self> {
+        value.as_u64().map(|v| v != 0)
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::String(s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Array(values) => values.iter().map(T::from_value).collect(),
+            Value::Bytes(bytes) => bytes
+                .iter()
+                .map(|b| T::from_value(&Value::U8(*b)))
+                .collect(),
+            // arrays of one element decode as plain values
+            value => T::from_value(value).map(|v| vec![v]),
+        }
+    }
+}
+
+/// Conversion of a decoded field to a user type, such as a `HeartRate`
+/// newtype, with its name and units at hand. Implemented for all
+/// [`FromValue`] types by converting the value.
+pub trait FromFitValue: Sized {
+    fn from_fit_value(field: &FieldValue) -> Option<Self>;
+}
+
+impl<T: FromValue> FromFitValue for T {
+    fn from_fit_value(field: &FieldValue) -> Option<Self> {
+        T::from_value(&field.value)
+    }
+}
+
+/// Conversion of a profile message struct field to a value, the inverse of
+/// [`FromValue`].
+pub trait ToValue {
+    fn to_value(&self) -> Value;
+}
+
+macro_rules! impl_to_value {
+    ($($t:ty => $variant:ident),*) => {
+        $(
+            impl ToValue for $t {
+                fn to_value(&self) -> Value {
+                    Value::$variant(*self)
+                }
+            }
+        )*
+    };
+}
+
+impl_to_value!(
+    u8 => U8, u16 => U16, u32 => U32, u64 => U64, i8 => I8, i16 => I16, i32 => I32,
+    i64 => I64, f32 => F32, f64 => F64
+);
+
+impl ToValue for bool {
+    fn to_value(&self) -> Value {
+        Value::U8(*self as u8)
+    }
+}
+
+impl ToValue for String {
+    fn to_value(&self) -> Value {
+        Value::String(self.clone())
+    }
+}
+
+impl<T: ToValue> ToValue for Vec<T> {
+    fn to_value(&self) -> Value {
+        Value::Array(self.iter().map(T::to_value).collect())
+    }
+}
+
+/// A decoded field of a data message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldValue {
+    pub field_definition_number: u8,
+    /// Profile name of the field, or of its active subfield. `None` for
+    /// fields not in the profile.
+    pub name: Option<&'static str>,
+    /// Value with the profile scale and offset applied.
+    pub value: Value,
+    pub units: &'static str,
+}
+
+/// A decoded data message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DataMessage {
+    /// Byte offset of the data record from the start of the file.
+    pub offset: usize,
+    /// Index of the data record among all records of the data section,
+    /// definition records included.
+    pub record_index: usize,
+    pub global_message_number: u16,
+    pub fields: Vec<FieldValue>,
+    pub developer_fields: Vec<RawDeveloperField>,
+}
+
+impl DataMessage {
+    /// A message without fields, for building messages to encode.
+    pub fn new(global_message_number: u16) -> DataMessage {
+        DataMessage {
+            offset: 0,
+            record_index: 0,
+            global_message_number,
+            fields: Vec::new(),
+            developer_fields: Vec::new(),
+        }
+    }
+
+    /// Sets the field to the value, or removes it for `None`. The value is
+    /// scaled like decoded values are.
+    pub fn set_typed_field<T: ToValue>(
+        &mut self,
+        field_definition_number: u8,
+        name: &'static str,
+        value: &Option<T>,
+    ) {
+        self.fields
+            .retain(|f| f.field_definition_number != field_definition_number);
+        if let Some(value) = value {
+            let units = message_info(self.global_message_number)
+                .and_then(|m| m.field(field_definition_number))
+                .map_or("", |f| f.units);
+            self.fields.push(FieldValue {
+                field_definition_number,
+                name: Some(name),
+                value: value.to_value(),
+                units,
+            });
+        }
+    }
+
+    /// Profile name of the message, `None` for messages not in the profile.
+    pub fn name(&self) -> Option<&'static str> {
+        message_info(self.global_message_number).map(|m| m.name)
+    }
+
+    pub fn field(&self, field_definition_number: u8) -> Option<&FieldValue> {
+        self.fields
+            .iter()
+            .find(|f| f.field_definition_number == field_definition_number)
+    }
+
+    /// Value of a field of a profile type, such as `sport`, with its type.
+    /// `None` if the message does not have the field or the field is not of
+    /// a profile type.
+    pub fn enum_value(&self, field_definition_number: u8) -> Option<EnumValue> {
+        let field = self.field(field_definition_number)?;
+        let info = message_info(self.global_message_number)?.field(field_definition_number)?;
+        let field_type = field
+            .name
+            .and_then(|name| info.subfield(name))
+            .map_or(info.field_type, |s| s.field_type);
+        Some(EnumValue {
+            type_name: type_info(field_type)?.name,
+            value: u32::try_from(field.value.as_u64()?).ok()?,
+        })
+    }
+
+    /// Value of the field converted to `T`, `None` if the field is not
+    /// present or does not convert.
+    pub fn typed_field<T: FromFitValue>(&self, field_definition_number: u8) -> Option<T> {
+        self.field(field_definition_number)
+            .and_then(T::from_fit_value)
+    }
+
+    /// Field with the given profile name, or the active subfield name.
+    pub fn field_by_name(&self, name: &str) -> Option<&FieldValue> {
+        self.fields
+            .iter()
+            .find(|f| f.name == Some(name))
+            .or_else(|| {
+                let info = message_info(self.global_message_number)?;
+                self.field(info.fields.iter().find(|f| f.name == name)?.number)
+            })
+    }
+
+    /// Name, value and units of the fields in the profile, named by their
+    /// active subfield when one applies. Fields not in the profile are
+    /// skipped.
+    pub fn fields(&self) -> impl Iterator<Item = (&'static str, &Value, &'static str)> {
+        self.fields
+            .iter()
+            .filter_map(|f| Some((f.name?, &f.value, f.units)))
+    }
+
+    pub fn timestamp(&self) -> Option<u32> {
+        match self.field(TIMESTAMP_FIELD_NUMBER).map(|f| &f.value) {
+            Some(Value::U32(timestamp)) => Some(*timestamp),
+            _ => None,
+        }
+    }
+}
+
+fn read_bits(bytes: &[u8], architecture: &Endianness) -> u64 {
+    match (bytes.len(), architecture) {
+        (1, _) => bytes[0] as u64,
+        (2, Endianness::LittleEndian) => LittleEndian::read_u16(bytes) as u64,
+        (2, Endianness::BigEndian) => BigEndian::read_u16(bytes) as u64,
+        (4, Endianness::LittleEndian) => LittleEndian::read_u32(bytes) as u64,
+        (4, Endianness::BigEndian) => BigEndian::read_u32(bytes) as u64,
+        (8, Endianness::LittleEndian) => LittleEndian::read_u64(bytes),
+        (8, Endianness::BigEndian) => BigEndian::read_u64(bytes),
+        (size, _) => panic!("Unsupported base type size {}", size),
+    }
+}
+
+fn value_from_bits(base_type: &BaseType, bits: u64) -> Value {
+    match base_type {
+        BaseType::Enum | BaseType::Uint8 | BaseType::Uint8z | BaseType::Byte => {
+            Value::U8(bits as u8)
+        }
+        BaseType::Sint8 => Value::I8(bits as u8 as i8),
+        BaseType::Uint16 | BaseType::Uint16z => Value::U16(bits as u16),
+        BaseType::Sint16 => Value::I16(bits as u16 as i16),
+        BaseType::Uint32 | BaseType::Uint32z => Value::U32(bits as u32),
+        BaseType::Sint32 => Value::I32(bits as u32 as i32),
+        BaseType::Uint64 | BaseType::Uint64z => Value::U64(bits),
+        BaseType::Sint64 => Value::I64(bits as i64),
+        BaseType::Float32 => Value::F32(f32::from_bits(bits as u32)),
+        BaseType::Float64 => Value::F64(f64::from_bits(bits)),
+        BaseType::String => Value::U8(bits as u8),
+    }
+}
+
+// Byte fields are invalid only when all of their bytes are 0xFF.
+fn decode_bytes(bytes: &[u8]) -> Option<Value> {
+    if bytes.iter().all(|b| *b == 0xFF) {
+        return None;
+    }
+    if bytes.len() == 1 {
+        Some(Value::U8(bytes[0]))
+    } else {
+        Some(Value::Bytes(bytes.to_vec()))
+    }
+}
+
+/// Decodes a raw field value of the given base type. Returns `None` when the
+/// value is the invalid value of its base type, meaning the field is not set.
+pub fn decode_value(
+    base_type: &BaseTypeInfo,
+    architecture: &Endianness,
+    bytes: &[u8],
+) -> Option<Value> {
+    match base_type.base_type {
+        BaseType::String => {
+            let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+            if end == 0 {
+                return None;
+            }
+            Some(Value::String(
+                String::from_utf8_lossy(&bytes[..end]).into_owned(),
+            ))
+        }
+        BaseType::Byte => decode_bytes(bytes),
+        _ => {
+            let size = base_type.size as usize;
+            let bits = bytes
+                .chunks_exact(size)
+                .map(|chunk| read_bits(chunk, architecture))
+                .collect::<Vec<u64>>();
+            if bits.is_empty() || bits.iter().all(|b| *b == base_type.invalid_value) {
+                return None;
+            }
+            if bits.len() == 1 {
+                Some(value_from_bits(&base_type.base_type, bits[0]))
+            } else {
+                Some(Value::Array(
+                    bits.iter()
+                        .map(|b| value_from_bits(&base_type.base_type, *b))
+                        .collect(),
+                ))
+            }
+        }
+    }
+}
+
+// String of the bytes up to the first NUL, or the bytes themselves when
+// they are not valid UTF-8.
+fn string_value(bytes: &[u8]) -> Value {
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    match std::str::from_utf8(&bytes[..end]) {
+        Ok(text) => Value::String(text.to_string()),
+        Err(_) => Value::Bytes(bytes[..end].to_vec()),
+    }
+}
+
+pub(crate) fn apply_scale_and_offset(value: Value, scale: f64, offset: f64) -> Value {
+    if scale == 1.0 && offset == 0.0 {
+        return value;
+    }
+    match value {
+        Value::Array(values) => Value::Array(
+            values
+                .into_iter()
+                .map(|v| apply_scale_and_offset(v, scale, offset))
+                .collect(),
+        ),
+        other => match other.as_f64() {
+            Some(v) => Value::F64(v / scale - offset),
+            None => other,
+        },
+    }
+}
+
+fn raw_field_integer(field: &RawField, architecture: &Endianness) -> Option<u32> {
+    if field.data.len() != field.base_type.size as usize
+        || matches!(
+            field.base_type.base_type,
+            BaseType::Float32 | BaseType::Float64 | BaseType::String
+        )
+    {
+        return None;
+    }
+    let bits = read_bits(&field.data, architecture);
+    if bits == field.base_type.invalid_value {
+        return None;
+    }
+    u32::try_from(bits).ok()
+}
+
+// Name, type, scale, offset, units and components of a field, taking the
+// active subfield into account.
+struct FieldInterpretation {
+    name: &'static str,
+    field_type: &'static str,
+    scale: f64,
+    offset: f64,
+    units: &'static str,
+    components: &'static [ComponentInfo],
+}
+
+// Joins the values of variable size array fields that are split over
+// repeated definitions of the field, as fields are at most 255 bytes, into
+// the first of them.
+fn join_split_fields(record: &mut DataRecord, message: Option<&'static MessageInfo>) {
+    let mut index = 0;
+    while index < record.fields.len() {
+        let number = record.fields[index].field_definition_number;
+        let variable = message
+            .and_then(|m| m.field(number))
+            .is_some_and(|f| f.array == FieldArray::Variable);
+        if variable {
+            let mut next = index + 1;
+            while next < record.fields.len() {
+                let field = &record.fields[next];
+                if field.field_definition_number == number
+                    && field.base_type == record.fields[index].base_type
+                {
+                    let field = record.fields.remove(next);
+                    record.fields[index].data.extend(field.data);
+                } else {
+                    next += 1;
+                }
+            }
+        }
+        index += 1;
+    }
+}
+
+fn interpret_field(record: &DataRecord, field_info: &'static FieldInfo) -> FieldInterpretation {
+    let ref_field_value = |number: u8| {
+        record
+            .fields
+            .iter()
+            .find(|f| f.field_definition_number == number)
+            .and_then(|f| raw_field_integer(f, &record.architecture))
+    };
+    let subfield = if field_info.subfields.is_empty() {
+        None
+    } else {
+        subfield_name(
+            record.global_message_number,
+            field_info.number,
+            ref_field_value,
+        )
+        .and_then(|name| field_info.subfield(name))
+    };
+    match subfield {
+        Some(subfield) => FieldInterpretation {
+            name: subfield.name,
+            field_type: subfield.field_type,
+            scale: subfield.scale,
+            offset: subfield.offset,
+            units: subfield.units,
+            components: subfield.components,
+        },
+        None => FieldInterpretation {
+            name: field_info.name,
+            field_type: field_info.field_type,
+            scale: field_info.scale,
+            offset: field_info.offset,
+            units: field_info.units,
+            components: field_info.components,
+        },
+    }
+}
+
+// The integer value of a field of an enum type as `Value::Enum`, element
+// wise for arrays. Values of other types are returned as they are.
+fn enum_typed(value: Value, field_type: &str) -> Value {
+    let type_name = match type_info(field_type) {
+        Some(t) if t.base_type == "enum" => t.name,
+        _ => return value,
+    };
+    let to_enum = |value: Value| match value.as_u64().and_then(|v| u32::try_from(v).ok()) {
+        Some(v) => Value::Enum(EnumValue {
+            type_name,
+            value: v,
+        }),
+        None => value,
+    };
+    match value {
+        Value::Array(values) => Value::Array(values.into_iter().map(to_enum).collect()),
+        value => to_enum(value),
+    }
+}
+
+fn check_enum_value(
+    global_message_number: u16,
+    field_definition_number: u8,
+    field_type: &str,
+    value: &Value,
+    record_offset: usize,
+    diagnostics: &mut Diagnostics,
+) {
+    let enum_type = match type_info(field_type) {
+        Some(t) if t.base_type == "enum" => t,
+        _ => return,
+    };
+    let values = match value {
+        Value::Array(values) => values.iter().collect::<Vec<&Value>>(),
+        value => vec![value],
+    };
+    for raw in values.iter().filter_map(|v| v.as_u64()) {
+        let raw = raw as u32;
+        if enum_type.value_name(raw).is_none() {
+            diagnostics.push(
+                record_offset,
+                DiagnosticKind::UnknownEnumValue {
+                    global_message_number,
+                    field_definition_number,
+                    value: raw,
+                },
+            );
+        }
+    }
+}
+
+fn check_scaled_value(
+    global_message_number: u16,
+    field_definition_number: u8,
+    value: &Value,
+    record_offset: usize,
+    diagnostics: &mut Diagnostics,
+) {
+    let scaled = match value {
+        Value::F64(v) => vec![*v],
+        Value::Array(values) => values.iter().filter_map(|v| v.as_f64()).collect(),
+        _ => return,
+    };
+    for v in scaled.into_iter().filter(|v| !v.is_finite()) {
+        diagnostics.push(
+            record_offset,
+            DiagnosticKind::ScaledValueOutOfRange {
+                global_message_number,
+                field_definition_number,
+                value: v,
+            },
+        );
+    }
+}
+
+pub(crate) fn low_bits_mask(bits: u8) -> u64 {
+    if bits >= 64 {
+        u64::MAX
+    } else {
+        (1 << bits) - 1
+    }
+}
+
+// Raw bits of a field as one little endian number of its elements, the
+// source of its components.
+fn component_bits(field: &RawField, architecture: &Endianness) -> Option<u64> {
+    if matches!(
+        field.base_type.base_type,
+        BaseType::Float32 | BaseType::Float64 | BaseType::String
+    ) {
+        return None;
+    }
+    let size = field.base_type.size as usize;
+    let mut bits = 0;
+    for (index, chunk) in field.data.chunks_exact(size).take(8 / size).enumerate() {
+        bits |= read_bits(chunk, architecture) << (index * size * 8);
+    }
+    Some(bits)
+}
+
+/// What decoding a data record carries over to the following ones.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct DecodeState {
+    last_timestamp: Option<u32>,
+    // Accumulated raw values of accumulating components by global message
+    // number and target field.
+    accumulators: Accumulator,
+}
+
+impl DecodeState {
+    /// The state after a record with the full timestamp.
+    pub(crate) fn at_timestamp(timestamp: u32) -> DecodeState {
+        DecodeState {
+            last_timestamp: Some(timestamp),
+            accumulators: Accumulator::new(),
+        }
+    }
+}
+
+/// Decodes the fields of a data message according to the profile, resolving
+/// subfields, applying scale and offset and expanding components.
+pub struct MessageDecoder {
+    state: DecodeState,
+    apply_scale: bool,
+    strings: StringHandling,
+}
+
+impl MessageDecoder {
+    pub fn new() -> MessageDecoder {
+        MessageDecoder::with_options(true, StringHandling::default())
+    }
+
+    pub(crate) fn with_options(apply_scale: bool, strings: StringHandling) -> MessageDecoder {
+        MessageDecoder {
+            state: DecodeState::default(),
+            apply_scale,
+            strings,
+        }
+    }
+
+    pub(crate) fn state(&self) -> &DecodeState {
+        &self.state
+    }
+
+    /// Continues decoding from the state of an earlier decoder.
+    pub(crate) fn set_state(&mut self, state: DecodeState) {
+        self.state = state;
+    }
+
+    fn scale(&self, value: Value, scale: f64, offset: f64) -> Value {
+        if self.apply_scale {
+            apply_scale_and_offset(value, scale, offset)
+        } else {
+            value
+        }
+    }
+
+    // Expands the components packed in `bits` to the fields they name, unless
+    // the message already has the field.
+    fn expand_components(
+        &mut self,
+        message: &'static MessageInfo,
+        components: &'static [ComponentInfo],
+        bits: u64,
+        fields: &mut Vec<FieldValue>,
+    ) {
+        let mut shift = 0u32;
+        for component in components {
+            if shift >= 64 {
+                break;
+            }
+            let mut raw = (bits >> shift) & low_bits_mask(component.bits);
+            shift += component.bits as u32;
+            let target = match message.fields.iter().find(|f| f.name == component.name) {
+                Some(target) => target,
+                None => continue,
+            };
+            if component.accumulate {
+                raw = self.state.accumulators.accumulate(
+                    message.number,
+                    target.number,
+                    raw,
+                    component.bits,
+                );
+            }
+            if fields
+                .iter()
+                .any(|f| f.field_definition_number == target.number)
+            {
+                continue;
+            }
+            let base_type = type_info(target.field_type).map_or(target.field_type, |t| t.base_type);
+            let value = match base_type_info_by_name(base_type) {
+                Some(base_type) => value_from_bits(&base_type.base_type, raw),
+                None => Value::U64(raw),
+            };
+            fields.push(FieldValue {
+                field_definition_number: target.number,
+                name: Some(target.name),
+                value: self.scale(value, component.scale, component.offset),
+                units: if component.units.is_empty() {
+                    target.units
+                } else {
+                    component.units
+                },
+            });
+        }
+    }
+
+    // Full timestamp of a compressed timestamp header, relative to the last
+    // full timestamp seen.
+    fn compressed_timestamp(&self, time_offset: u8) -> Option<u32> {
+        let last_timestamp = self.state.last_timestamp?;
+        let last_offset = (last_timestamp & 0x1F) as u8;
+        let mut timestamp = (last_timestamp & !0x1F).wrapping_add(time_offset as u32);
+        if time_offset < last_offset {
+            timestamp = timestamp.wrapping_add(0x20);
+        }
+        Some(timestamp)
+    }
+
+    /// Decodes the data record found at `record_offset` with index
+    /// `record_index`, reporting issues to `diagnostics`.
+    pub fn decode(
+        &mut self,
+        mut record: DataRecord,
+        record_offset: usize,
+        record_index: usize,
+        diagnostics: &mut Diagnostics,
+    ) -> DataMessage {
+        let global_message_number = record.global_message_number;
+        let message: Option<&'static MessageInfo> = message_info(global_message_number);
+        join_split_fields(&mut record, message);
+        let mut fields = Vec::with_capacity(record.fields.len() + 1);
+        let mut expansions = Vec::new();
+        for raw_field in &record.fields {
+            let field_info = message.and_then(|m| m.field(raw_field.field_definition_number));
+            if !raw_field
+                .data
+                .len()
+                .is_multiple_of(raw_field.base_type.size as usize)
+            {
+                diagnostics.push(
+                    record_offset,
+                    DiagnosticKind::FieldSizeMismatch {
+                        global_message_number,
+                        field_definition_number: raw_field.field_definition_number,
+                        field_size: raw_field.data.len(),
+                        base_type_size: raw_field.base_type.size,
+                    },
+                );
+                if let Some(value) = decode_bytes(&raw_field.data) {
+                    fields.push(FieldValue {
+                        field_definition_number: raw_field.field_definition_number,
+                        name: field_info.map(|f| f.name),
+                        value,
+                        units: "",
+                    });
+                }
+                continue;
+            }
+            let value =
+                match decode_value(raw_field.base_type, &record.architecture, &raw_field.data) {
+                    Some(value) => value,
+                    None => continue,
+                };
+            let value = match (self.strings, value) {
+                (StringHandling::Bytes, Value::String(_)) => string_value(&raw_field.data),
+                (_, value) => value,
+            };
+            let field_value = match field_info {
+                Some(field_info) => {
+                    let interpretation = interpret_field(&record, field_info);
+                    if let (Some(message), false) = (message, interpretation.components.is_empty())
+                    {
+                        if let Some(bits) = component_bits(raw_field, &record.architecture) {
+                            expansions.push((message, interpretation.components, bits));
+                        }
+                    }
+                    check_enum_value(
+                        global_message_number,
+                        raw_field.field_definition_number,
+                        interpretation.field_type,
+                        &value,
+                        record_offset,
+                        diagnostics,
+                    );
+                    let value = self.scale(value, interpretation.scale, interpretation.offset);
+                    let value = enum_typed(value, interpretation.field_type);
+                    check_scaled_value(
+                        global_message_number,
+                        raw_field.field_definition_number,
+                        &value,
+                        record_offset,
+                        diagnostics,
+                    );
+                    FieldValue {
+                        field_definition_number: raw_field.field_definition_number,
+                        name: Some(interpretation.name),
+                        value,
+                        units: interpretation.units,
+                    }
+                }
+                None => FieldValue {
+                    field_definition_number: raw_field.field_definition_number,
+                    name: None,
+                    value,
+                    units: "",
+                },
+            };
+            fields.push(field_value);
+        }
+        for (message, components, bits) in expansions {
+            self.expand_components(message, components, bits, &mut fields);
+        }
+        let mut data_message = DataMessage {
+            offset: record_offset,
+            record_index,
+            global_message_number,
+            fields,
+            developer_fields: record.developer_fields,
+        };
+        match (&record.header, data_message.timestamp()) {
+            (RecordHeader::CompressedTimestamp(header), None) => {
+                if let Some(timestamp) = self.compressed_timestamp(header.time_offset) {
+                    data_message.fields.push(FieldValue {
+                        field_definition_number: TIMESTAMP_FIELD_NUMBER,
+                        name: Some("timestamp"),
+                        value: Value::U32(timestamp),
+                        units: "s",
+                    });
+                    self.state.last_timestamp = Some(timestamp);
+                }
+            }
+            (_, Some(timestamp)) => self.state.last_timestamp = Some(timestamp),
+            _ => {}
+        }
+        data_message
+    }
+}
+
+impl Default for MessageDecoder {
+    fn default() -> Self {
+        MessageDecoder::new()
+    }
+}
+
+#[cfg(test)]
+use crate::fit_records::{get_base_type_info, CompressedTimestampHeader, NormalDataHeader};
+#[cfg(test)]
+use crate::profile::{field_num_by_name, mesg_num_by_name, RECORD_MESSAGE};
+
+#[cfg(test)]
+fn test_raw_field(field_definition_number: u8, base_type: BaseType, data: Vec<u8>) -> RawField {
+    RawField {
+        field_definition_number,
+        base_type: get_base_type_info(base_type.into()),
+        data,
+    }
+}
+
+#[cfg(test)]
+fn test_data_record(global_message_number: u16, fields: Vec<RawField>) -> DataRecord {
+    DataRecord {
+        header: RecordHeader::NormalData(NormalDataHeader {
+            local_message_type: 0,
+        }),
+        architecture: Endianness::LittleEndian,
+        global_message_number,
+        fields,
+        developer_fields: vec![],
+    }
+}
+
+#[test]
+fn test_decode_value_invalid_and_arrays() {
+    let uint16 = get_base_type_info(BaseType::Uint16.into());
+    assert_eq!(
+        decode_value(uint16, &Endianness::LittleEndian, &[0xFF, 0xFF]),
+        None
+    );
+    assert_eq!(
+        decode_value(uint16, &Endianness::BigEndian, &[0x01, 0x02]),
+        Some(Value::U16(0x0102))
+    );
+    assert_eq!(
+        decode_value(uint16, &Endianness::LittleEndian, &[0x01, 0x00, 0xFF, 0xFF]),
+        Some(Value::Array(vec![Value::U16(1), Value::U16(0xFFFF)]))
+    );
+    let string = get_base_type_info(BaseType::String.into());
+    assert_eq!(
+        decode_value(string, &Endianness::LittleEndian, b"Edge\0\0"),
+        Some(Value::String(String::from("Edge")))
+    );
+}
+
+#[test]
+fn test_decode_record_message_scales_fields() {
+    // record: heart_rate (3) and distance (5, scale 100)
+    let record = test_data_record(
+        20,
+        vec![
+            test_raw_field(3, BaseType::Uint8, vec![150]),
+            test_raw_field(5, BaseType::Uint32, vec![0x10, 0x27, 0x00, 0x00]),
+        ],
+    );
+    let mut diagnostics = Diagnostics::new();
+    let message = MessageDecoder::new().decode(record, 0, 0, &mut diagnostics);
+    assert_eq!(message.name(), Some("record"));
+    assert_eq!(message.field(3).unwrap().value, Value::U8(150));
+    assert_eq!(message.field(3).unwrap().name, Some("heart_rate"));
+    assert_eq!(message.field(5).unwrap().value, Value::F64(100.0));
+    assert_eq!(message.field(5).unwrap().units, "m");
+    assert!(diagnostics.is_empty());
+}
+
+#[test]
+fn test_decode_resolves_subfield() {
+    // file_id: manufacturer garmin (1), product 2697
+    let record = test_data_record(
+        0,
+        vec![
+            test_raw_field(1, BaseType::Uint16, vec![0x01, 0x00]),
+            test_raw_field(2, BaseType::Uint16, vec![0x89, 0x0A]),
+        ],
+    );
+    let message = MessageDecoder::new().decode(record, 0, 0, &mut Diagnostics::new());
+    assert_eq!(message.field(2).unwrap().name, Some("garmin_product"));
+    // the field is found by the names of both the field and the subfield
+    assert_eq!(message.field_by_name("product"), message.field(2));
+    assert_eq!(message.field_by_name("garmin_product"), message.field(2));
+    let names = message
+        .fields()
+        .map(|(name, _, _)| name)
+        .collect::<Vec<&str>>();
+    assert_eq!(names, vec!["manufacturer", "garmin_product"]);
+}
+
+#[test]
+fn test_decode_reports_unknown_enum_value() {
+    // event: event (0) is an enum, 200 is not a known event
+    let record = test_data_record(21, vec![test_raw_field(0, BaseType::Enum, vec![200])]);
+    let mut diagnostics = Diagnostics::new();
+    let message = MessageDecoder::new().decode(record, 42, 3, &mut diagnostics);
+    let value = &message.field(0).unwrap().value;
+    assert_eq!(
+        *value,
+        Value::Enum(EnumValue {
+            type_name: "event",
+            value: 200
+        })
+    );
+    assert_eq!(value.to_string(), "200");
+    assert_eq!(diagnostics.len(), 1);
+    let diagnostic = diagnostics.iter().next().unwrap();
+    assert_eq!(diagnostic.offset, 42);
+    assert_eq!(
+        diagnostic.kind,
+        DiagnosticKind::UnknownEnumValue {
+            global_message_number: 21,
+            field_definition_number: 0,
+            value: 200
+        }
+    );
+}
+
+#[test]
+fn test_decode_compressed_timestamp() {
+    let mut decoder = MessageDecoder::new();
+    let mut diagnostics = Diagnostics::new();
+    let full = test_data_record(
+        20,
+        vec![test_raw_field(
+            253,
+            BaseType::Uint32,
+            vec![0x3E, 0x00, 0x00, 0x00],
+        )],
+    );
+    decoder.decode(full, 0, 0, &mut diagnostics);
+    let mut compressed = test_data_record(20, vec![]);
+    compressed.header = RecordHeader::CompressedTimestamp(CompressedTimestampHeader {
+        local_message_type: 0,
+        time_offset: 0x02,
+    });
+    let message = decoder.decode(compressed, 5, 1, &mut diagnostics);
+    assert_eq!((message.offset, message.record_index), (5, 1));
+    // 0x3E has offset 0x1E, offset 0x02 rolls over to the next 32 s window
+    assert_eq!(message.timestamp(), Some(0x42));
+}
+
+#[test]
+fn test_decode_size_mismatch_as_bytes() {
+    // record: altitude (2) is uint16 but declared with size 3
+    let record = test_data_record(
+        20,
+        vec![test_raw_field(2, BaseType::Uint16, vec![0x01, 0x02, 0x03])],
+    );
+    let mut diagnostics = Diagnostics::new();
+    let message = MessageDecoder::new().decode(record, 7, 0, &mut diagnostics);
+    assert_eq!(
+        message.field(2).unwrap().value,
+        Value::Bytes(vec![0x01, 0x02, 0x03])
+    );
+    assert_eq!(message.field(2).unwrap().name, Some("altitude"));
+    assert_eq!(
+        diagnostics.iter().next().unwrap().kind,
+        DiagnosticKind::FieldSizeMismatch {
+            global_message_number: 20,
+            field_definition_number: 2,
+            field_size: 3,
+            base_type_size: 2
+        }
+    );
+}
+
+#[test]
+fn test_profile_lookup_by_name() {
+    assert_eq!(mesg_num_by_name("record"), Some(RECORD_MESSAGE.number));
+    assert_eq!(mesg_num_by_name("no_such_message"), None);
+    assert_eq!(field_num_by_name(RECORD_MESSAGE.number, "power"), Some(7));
+    assert_eq!(field_num_by_name(0, "garmin_product"), Some(2));
+    assert_eq!(
+        field_num_by_name(RECORD_MESSAGE.number, "no_such_field"),
+        None
+    );
+}
+
+#[test]
+fn test_from_value() {
+    assert_eq!(u16::from_value(&Value::U8(7)), Some(7));
+    assert_eq!(u8::from_value(&Value::U16(300)), None);
+    assert_eq!(u32::from_value(&Value::F64(12.0)), Some(12));
+    assert_eq!(u32::from_value(&Value::F64(12.5)), None);
+    assert_eq!(f64::from_value(&Value::I16(-3)), Some(-3.0));
+    assert_eq!(
+        Vec::<u16>::from_value(&Value::Array(vec![Value::U16(1), Value::U16(2)])),
+        Some(vec![1, 2])
+    );
+    assert_eq!(Vec::<u8>::from_value(&Value::U8(4)), Some(vec![4]));
+    assert_eq!(
+        String::from_value(&Value::String(String::from("Edge"))),
+        Some(String::from("Edge"))
+    );
+}
+
+#[test]
+fn test_decode_expands_components() {
+    // event: rear_gear_change (43) with gear_change_data packing rear gear 5
+    // of 17 teeth and front gear 2 of 50 teeth
+    let record = test_data_record(
+        21,
+        vec![
+            test_raw_field(0, BaseType::Enum, vec![43]),
+            test_raw_field(1, BaseType::Enum, vec![3]),
+            test_raw_field(3, BaseType::Uint32, vec![5, 17, 2, 50]),
+        ],
+    );
+    let message = MessageDecoder::new().decode(record, 0, 0, &mut Diagnostics::new());
+    assert_eq!(message.field(3).unwrap().name, Some("gear_change_data"));
+    assert_eq!(message.field(11).unwrap().name, Some("rear_gear_num"));
+    assert_eq!(message.field(11).unwrap().value, Value::U8(5));
+    assert_eq!(message.field(12).unwrap().value, Value::U8(17));
+    assert_eq!(message.field(9).unwrap().value, Value::U8(2));
+    assert_eq!(message.field(10).unwrap().value, Value::U8(50));
+}
+
+#[test]
+fn test_decode_accumulates_components() {
+    // record: compressed_speed_distance packs a 12 bit speed and a 12 bit
+    // accumulating distance in 1/16 m
+    let compressed = |distance: u16| {
+        test_data_record(
+            20,
+            vec![test_raw_field(
+                8,
+                BaseType::Byte,
+                vec![0xF4, (distance << 4) as u8 | 0x01, (distance >> 4) as u8],
+            )],
+        )
+    };
+    let mut decoder = MessageDecoder::new();
+    let mut diagnostics = Diagnostics::new();
+    let first = decoder.decode(compressed(4000), 0, 0, &mut diagnostics);
+    assert_eq!(first.field(6).unwrap().value, Value::F64(5.0));
+    assert_eq!(first.field(5).unwrap().value, Value::F64(250.0));
+    // the distance rolls over from 4000 to 100
+    let second = decoder.decode(compressed(100), 0, 1, &mut diagnostics);
+    assert_eq!(second.field(5).unwrap().value, Value::F64(262.25));
+}
+
+#[test]
+fn test_value_conversions_and_display() {
+    let mut session = DataMessage::new(18);
+    session.set_typed_field(5, "sport", &Some(1u8));
+    let sport = session.enum_value(5).unwrap();
+    assert_eq!(sport.type_name, "sport");
+    assert_eq!(sport.to_string(), "running");
+    let value = Value::Enum(sport);
+    assert_eq!(String::try_from(&value), Ok(String::from("running")));
+    assert_eq!(i64::try_from(&value), Ok(1));
+    assert_eq!(f64::try_from(Value::U16(300)), Ok(300.0));
+    assert_eq!(
+        i64::try_from(Value::String(String::from("x"))),
+        Err(ValueConversionError {
+            value: Value::String(String::from("x")),
+            target: "i64"
+        })
+    );
+    assert_eq!(
+        Value::Array(vec![Value::U8(1), Value::F64(2.5)]).to_string(),
+        "[1, 2.5]"
+    );
+    assert_eq!(Value::Bytes(vec![0x0A, 0xFF]).to_string(), "0aff");
+}
@@ -1,27 +1,30 @@
 use byteorder::{BigEndian, ByteOrder, LittleEndian};
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 
-include!(concat!(env!("OUT_DIR"), "/fit.rs"));
+use crate::error::FitError;
 
-#[derive(Debug, Clone, PartialEq)]
-enum Endianness {
+/// Byte order of the multi-byte values of a message, set per definition.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Endianness {
     BigEndian,
+    #[default]
     LittleEndian,
 }
 
+/// Properties of a FIT base type as listed in the FIT protocol base type table.
 #[derive(Debug, Clone, PartialEq)]
-struct BaseTypeInfo {
-    base_type: BaseType,
-    endian_ability: bool,
-    base_type_field: u8,
-    type_name: String,
-    size: u8,
-    invalid_value: u64,
+pub struct BaseTypeInfo {
+    pub base_type: BaseType,
+    pub endian_ability: bool,
+    pub base_type_field: u8,
+    pub type_name: &'static str,
+    pub size: u8,
+    pub invalid_value: u64,
 }
 
 #[derive(Debug, Eq, Clone, PartialEq, IntoPrimitive, TryFromPrimitive)]
 #[repr(u8)]
-enum BaseType {
+pub enum BaseType {
     Enum = 0x00,
     Sint8 = 0x01,
     Uint8 = 0x02,
@@ -41,174 +44,195 @@ enum BaseType {
     Uint64z = 0x90,
 }
 
-fn get_base_type_info(number: u8) -> BaseTypeInfo {
+// Base types indexed by base type number, the lowest five bits of the base
+// type field.
+static BASE_TYPES: [BaseTypeInfo; 17] = [
+    BaseTypeInfo {
+        base_type: BaseType::Enum,
+        endian_ability: false,
+        base_type_field: 0x00,
+        type_name: "enum",
+        size: 1,
+        invalid_value: 0xFF,
+    },
+    BaseTypeInfo {
+        base_type: BaseType::Sint8,
+        endian_ability: false,
+        base_type_field: 0x01,
+        type_name: "sint8",
+        size: 1,
+        invalid_value: 0x7F,
+    },
+    BaseTypeInfo {
+        base_type: BaseType::Uint8,
+        endian_ability: false,
+        base_type_field: 0x02,
+        type_name: "uint8",
+        size: 1,
+        invalid_value: 0xFF,
+    },
+    BaseTypeInfo {
+        base_type: BaseType::Sint16,
+        endian_ability: true,
+        base_type_field: 0x83,
+        type_name: "sint16",
+        size: 2,
+        invalid_value: 0x7FFF,
+    },
+    BaseTypeInfo {
+        base_type: BaseType::Uint16,
+        endian_ability: true,
+        base_type_field: 0x84,
+        type_name: "uint16",
+        size: 2,
+        invalid_value: 0xFFFF,
+    },
+    BaseTypeInfo {
+        base_type: BaseType::Sint32,
+        endian_ability: true,
+        base_type_field: 0x85,
+        type_name: "sint32",
+        size: 4,
+        invalid_value: 0x7FFFFFFF,
+    },
+    BaseTypeInfo {
+        base_type: BaseType::Uint32,
+        endian_ability: true,
+        base_type_field: 0x86,
+        type_name: "uint32",
+        size: 4,
+        invalid_value: 0xFFFFFFFF,
+    },
+    BaseTypeInfo {
+        base_type: BaseType::String,
+        endian_ability: false,
+        base_type_field: 0x07,
+        type_name: "string",
+        size: 1,
+        invalid_value: 0x00,
+    },
+    BaseTypeInfo {
+        base_type: BaseType::Float32,
+        endian_ability: true,
+        base_type_field: 0x88,
+        type_name: "float32",
+        size: 4,
+        invalid_value: 0xFFFFFFFF,
+    },
+    BaseTypeInfo {
+        base_type: BaseType::Float64,
+        endian_ability: true,
+        base_type_field: 0x89,
+        type_name: "float64",
+        size: 8,
+        invalid_value: 0xFFFFFFFFFFFFFFFF,
+    },
+    BaseTypeInfo {
+        base_type: BaseType::Uint8z,
+        endian_ability: false,
+        base_type_field: 0x0A,
+        type_name: "uint8z",
+        size: 1,
+        invalid_value: 0x00,
+    },
+    BaseTypeInfo {
+        base_type: BaseType::Uint16z,
+        endian_ability: true,
+        base_type_field: 0x8B,
+        type_name: "uint16z",
+        size: 2,
+        invalid_value: 0x0000,
+    },
+    BaseTypeInfo {
+        base_type: BaseType::Uint32z,
+        endian_ability: true,
+        base_type_field: 0x8C,
+        type_name: "uint32z",
+        size: 4,
+        invalid_value: 0x00000000,
+    },
+    BaseTypeInfo {
+        base_type: BaseType::Byte,
+        endian_ability: false,
+        base_type_field: 0x0D,
+        type_name: "byte",
+        size: 1,
+        invalid_value: 0xFF,
+    },
+    BaseTypeInfo {
+        base_type: BaseType::Sint64,
+        endian_ability: true,
+        base_type_field: 0x8E,
+        type_name: "sint64",
+        size: 8,
+        invalid_value: 0x7FFFFFFFFFFFFFFF,
+    },
+    BaseTypeInfo {
+        base_type: BaseType::Uint64,
+        endian_ability: true,
+        base_type_field: 0x8F,
+        type_name: "uint64",
+        size: 8,
+        invalid_value: 0xFFFFFFFFFFFFFFFF,
+    },
+    BaseTypeInfo {
+        base_type: BaseType::Uint64z,
+        endian_ability: true,
+        base_type_field: 0x90,
+        type_name: "uint64z",
+        size: 8,
+        invalid_value: 0x0000000000000000,
+    },
+];
+
+/// Properties of the base type with the given base type field value.
+/// Panics if the value is not a base type of the FIT protocol.
+pub(crate) fn get_base_type_info(number: u8) -> &'static BaseTypeInfo {
     let base_type = BaseType::try_from(number).unwrap();
-    match base_type {
-        BaseType::Enum => BaseTypeInfo {
-            base_type,
-            endian_ability: false,
-            base_type_field: 0x00,
-            type_name: String::from("enum"),
-            invalid_value: 0xFF,
-            size: 1,
-        },
-        BaseType::Sint8 => BaseTypeInfo {
-            base_type,
-            endian_ability: false,
-            base_type_field: 0x01,
-            type_name: String::from("sint8"),
-            invalid_value: 0x7F,
-            size: 1,
-        },
-        BaseType::Uint8 => BaseTypeInfo {
-            base_type,
-            endian_ability: false,
-            base_type_field: 0x02,
-            type_name: String::from("uint8"),
-            invalid_value: 0xFF,
-            size: 1,
-        },
-        BaseType::Sint16 => BaseTypeInfo {
-            base_type,
-            endian_ability: true,
-            base_type_field: 0x83,
-            type_name: String::from("sint16"),
-            invalid_value: 0x7FFF,
-            size: 2,
-        },
-        BaseType::Uint16 => BaseTypeInfo {
-            base_type,
-            endian_ability: true,
-            base_type_field: 0x84,
-            type_name: String::from("uint16"),
-            invalid_value: 0xFFFF,
-            size: 2,
-        },
-        BaseType::Sint32 => BaseTypeInfo {
-            base_type,
-            endian_ability: true,
-            base_type_field: 0x85,
-            type_name: String::from("sint32"),
-            invalid_value: 0x7FFFFFFF,
-            size: 4,
-        },
-        BaseType::Uint32 => BaseTypeInfo {
-            base_type,
-            endian_ability: true,
-            base_type_field: 0x86,
-            type_name: String::from("uint32"),
-            invalid_value: 0xFFFFFFFF,
-            size: 4,
-        },
-        BaseType::String => BaseTypeInfo {
-            base_type,
-            endian_ability: false,
-            base_type_field: 0x07,
-            type_name: String::from("string"),
-            invalid_value: 0x00,
-            size: 1,
-        },
-        BaseType::Float32 => BaseTypeInfo {
-            base_type,
-            endian_ability: true,
-            base_type_field: 0x88,
-            type_name: String::from("float32"),
-            invalid_value: 0xFFFFFFFF,
-            size: 4,
-        },
-        BaseType::Float64 => BaseTypeInfo {
-            base_type,
-            endian_ability: true,
-            base_type_field: 0x89,
-            type_name: String::from("float64"),
-            invalid_value: 0xFFFFFFFFFFFFFFFF,
-            size: 8,
-        },
-        BaseType::Uint8z => BaseTypeInfo {
-            base_type,
-            endian_ability: false,
-            base_type_field: 0x0A,
-            type_name: String::from("uint8z"),
-            invalid_value: 0x00,
-            size: 1,
-        },
-        BaseType::Uint16z => BaseTypeInfo {
-            base_type,
-            endian_ability: true,
-            base_type_field: 0x8B,
-            type_name: String::from("uint16z"),
-            invalid_value: 0x0000,
-            size: 2,
-        },
-        BaseType::Uint32z => BaseTypeInfo {
-            base_type,
-            endian_ability: true,
-            base_type_field: 0x8C,
-            type_name: String::from("uint32z"),
-            invalid_value: 0x00000000,
-            size: 4,
-        },
-        BaseType::Byte => BaseTypeInfo {
-            base_type,
-            endian_ability: false,
-            base_type_field: 0x0D,
-            type_name: String::from("byte"),
-            invalid_value: 0xFF,
-            size: 1,
-        },
-        BaseType::Sint64 => BaseTypeInfo {
-            base_type,
-            endian_ability: true,
-            base_type_field: 0x8E,
-            type_name: String::from("sint64"),
-            invalid_value: 0x7FFFFFFFFFFFFFFF,
-            size: 8,
-        },
-        BaseType::Uint64 => BaseTypeInfo {
-            base_type,
-            endian_ability: true,
-            base_type_field: 0x8F,
-            type_name: String::from("uint64"),
-            invalid_value: 0xFFFFFFFFFFFFFFFF,
-            size: 8,
-        },
-        BaseType::Uint64z => BaseTypeInfo {
-            base_type,
-            endian_ability: true,
-            base_type_field: 0x90,
-            type_name: String::from("uint64z"),
-            invalid_value: 0x0000000000000000,
-            size: 8,
-        },
-    }
+    &BASE_TYPES[(u8::from(base_type) & 0x1F) as usize]
+}
+
+/// Properties of the base type with the given name, such as `uint16`.
+pub fn base_type_info_by_name(name: &str) -> Option<&'static BaseTypeInfo> {
+    BASE_TYPES.iter().find(|b| b.type_name == name)
 }
 
 #[derive(Debug, Clone, PartialEq)]
-struct NormalDefinitionHeader {
-    contains_extended_definitions: bool,
-    local_message_type: u8,
+pub struct NormalDefinitionHeader {
+    pub contains_extended_definitions: bool,
+    pub local_message_type: u8,
 }
 
 #[derive(Debug, Clone, PartialEq)]
-struct NormalDataHeader {
-    local_message_type: u8,
+pub struct NormalDataHeader {
+    pub local_message_type: u8,
 }
 
 #[derive(Debug, Clone, PartialEq)]
-struct CompressedTimestampHeader {
-    local_message_type: u8,
-    time_offset: u8,
+pub struct CompressedTimestampHeader {
+    pub local_message_type: u8,
+    /// Lowest five bits of the message timestamp in seconds.
+    pub time_offset: u8,
 }
 
+/// The one byte header preceding every record in the data section.
 #[derive(Debug, Clone, PartialEq)]
-enum RecordHeader {
+pub enum RecordHeader {
     NormalDefinition(NormalDefinitionHeader),
     NormalData(NormalDataHeader),
     CompressedTimestamp(CompressedTimestampHeader),
 }
 
 impl RecordHeader {
-    fn get_record_message_type(&self) -> RecordMessageType {
+    pub fn local_message_type(&self) -> u8 {
+        match self {
+            Self::NormalDefinition(h) => h.local_message_type,
+            Self::NormalData(h) => h.local_message_type,
+            Self::CompressedTimestamp(h) => h.local_message_type,
+        }
+    }
+
+    pub fn get_record_message_type(&self) -> RecordMessageType {
         match self {
             Self::NormalDefinition(NormalDefinitionHeader {
                 contains_extended_definitions: _,
@@ -226,61 +250,59 @@ impl RecordHeader {
 }
 
 #[derive(Debug, Clone, PartialEq)]
-struct FieldDefinition {
-    field_definition_number: u8,
-    field_size: u8,
-    base_type: BaseTypeInfo,
+pub struct FieldDefinition {
+    pub field_definition_number: u8,
+    /// Declared size of the field in bytes. Not necessarily a multiple of the
+    /// base type size, such fields are decoded as byte arrays.
+    pub field_size: u8,
+    pub base_type: &'static BaseTypeInfo,
 }
 
 #[derive(Debug, Clone, PartialEq)]
-struct DeveloperFieldDefinition {
-    field_number: u8,
-    field_size: u8,
-    developer_data_index: u8,
+pub struct DeveloperFieldDefinition {
+    pub field_number: u8,
+    pub field_size: u8,
+    pub developer_data_index: u8,
 }
 
+/// Definition record describing the layout of the data records that use its
+/// local message type.
 #[derive(Debug, Clone, PartialEq)]
-struct DefinitionRecord {
-    header: NormalDefinitionHeader,
-    architecture: Endianness,
-    global_message_number: u16,
-    field_definitions: Vec<FieldDefinition>,
-    developer_field_definitions: Vec<DeveloperFieldDefinition>,
+pub struct DefinitionRecord {
+    pub header: NormalDefinitionHeader,
+    pub architecture: Endianness,
+    pub global_message_number: u16,
+    pub field_definitions: Vec<FieldDefinition>,
+    pub developer_field_definitions: Vec<DeveloperFieldDefinition>,
+}
+
+impl DefinitionRecord {
+    /// Size in bytes of the data records defined by this definition, excluding
+    /// the record header byte.
+    pub fn data_size(&self) -> usize {
+        self.field_definitions
+            .iter()
+            .map(|f| f.field_size as usize)
+            .chain(
+                self.developer_field_definitions
+                    .iter()
+                    .map(|f| f.field_size as usize),
+            )
+            .sum()
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
-enum RecordMessageType {
+pub enum RecordMessageType {
     Definition,
     Data,
     DataCompressedTimestamp,
 }
 
-#[derive(Debug, Clone, PartialEq)]
-struct RecordDefinitionHeaderMessage {
-    message_type: RecordMessageType,
-    local_message_type: u8,
-}
-
-#[derive(Debug, Clone, PartialEq)]
-struct DeveloperDataIdMessage {
-    application_id: u128,
-    developer_data_index: u8,
-}
-
-#[derive(Debug, Clone, PartialEq)]
-struct FieldDescriptionMessage {
-    developer_data_index: u8,
-    field_definition_number: u8,
-    fit_base_type_id: u8,
-    field_name: String,
-    units: String,
-    native_field_num: u8,
-}
-
-fn parse_record_header(b: u8) -> RecordHeader {
+pub fn parse_record_header(b: u8) -> RecordHeader {
     if b & 0b10000000 > 0 {
         // is compressed timestamp header and message
-        let local_message_type = b & 0b01100000;
+        let local_message_type = (b & 0b01100000) >> 5;
         let time_offset = b & 0b00011111;
         return RecordHeader::CompressedTimestamp(CompressedTimestampHeader {
             local_message_type,
@@ -337,7 +359,10 @@ fn test_parse_record_header() {
     );
 }
 
-fn parse_definition_record(
+/// Parses the definition record content starting at `data_start_offset`,
+/// the byte following the record header. Returns the record and the offset
+/// of the byte following it.
+pub(crate) fn parse_definition_record(
     data: &[u8],
     header: NormalDefinitionHeader,
     data_start_offset: usize,
@@ -360,18 +385,12 @@ fn parse_definition_record(
     let number_of_fields = data[curr_idx];
     curr_idx += 1;
 
-    let mut fields: Vec<FieldDefinition> = vec![];
+    let mut fields: Vec<FieldDefinition> = Vec::with_capacity(number_of_fields as usize);
     for _ in 0..number_of_fields {
         let field_definition_number = data[curr_idx];
         let size = data[curr_idx + 1];
         let base_type_number = data[curr_idx + 2];
         let base_type = get_base_type_info(base_type_number);
-        if size % base_type.size != 0 {
-            panic!(
-                "Size {:?} of message field not multiple of base type {:?} size {:?}",
-                size, base_type.type_name, base_type.size
-            );
-        }
         fields.push(FieldDefinition {
             field_definition_number,
             field_size: size,
@@ -384,6 +403,7 @@ fn parse_definition_record(
     if header.contains_extended_definitions {
         let number_of_developer_fields = data[curr_idx];
         curr_idx += 1;
+        developer_fields.reserve(number_of_developer_fields as usize);
         for _ in 0..number_of_developer_fields {
             let field_number = data[curr_idx];
             let size = data[curr_idx + 1];
@@ -407,6 +427,258 @@ fn parse_definition_record(
     (record, curr_idx)
 }
 
+/// A field value of a data record as raw bytes in the byte order of its
+/// definition.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawField {
+    pub field_definition_number: u8,
+    pub base_type: &'static BaseTypeInfo,
+    pub data: Vec<u8>,
+}
+
+/// A developer field value of a data record as raw bytes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawDeveloperField {
+    pub field_number: u8,
+    pub developer_data_index: u8,
+    /// Byte order of the definition, needed to decode the data with the
+    /// base type of the developer field's description.
+    pub architecture: Endianness,
+    pub data: Vec<u8>,
+}
+
+/// Data record with its field values split according to its definition but
+/// not yet interpreted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DataRecord {
+    pub header: RecordHeader,
+    pub architecture: Endianness,
+    pub global_message_number: u16,
+    pub fields: Vec<RawField>,
+    pub developer_fields: Vec<RawDeveloperField>,
+}
+
+/// Parses the data record content starting at `data_start_offset`, the byte
+/// following the record header, using the definition of its local message
+/// type. Returns the record and the offset of the byte following it.
+pub(crate) fn parse_data_record(
+    data: &[u8],
+    header: RecordHeader,
+    definition: &DefinitionRecord,
+    data_start_offset: usize,
+) -> (DataRecord, usize) {
+    let mut curr_idx = data_start_offset;
+    let mut fields: Vec<RawField> = Vec::with_capacity(definition.field_definitions.len());
+    for field_definition in &definition.field_definitions {
+        let size = field_definition.field_size as usize;
+        fields.push(RawField {
+            field_definition_number: field_definition.field_definition_number,
+            base_type: field_definition.base_type,
+            data: data[curr_idx..curr_idx + size].to_vec(),
+        });
+        curr_idx += size;
+    }
+    let mut developer_fields: Vec<RawDeveloperField> =
+        Vec::with_capacity(definition.developer_field_definitions.len());
+    for developer_field_definition in &definition.developer_field_definitions {
+        let size = developer_field_definition.field_size as usize;
+        developer_fields.push(RawDeveloperField {
+            field_number: developer_field_definition.field_number,
+            developer_data_index: developer_field_definition.developer_data_index,
+            architecture: definition.architecture.clone(),
+            data: data[curr_idx..curr_idx + size].to_vec(),
+        });
+        curr_idx += size;
+    }
+    let record = DataRecord {
+        header,
+        architecture: definition.architecture.clone(),
+        global_message_number: definition.global_message_number,
+        fields,
+        developer_fields,
+    };
+    (record, curr_idx)
+}
+
+/// Number of local message types a file can have defined at a time.
+pub const LOCAL_MESSAGE_TYPE_COUNT: usize = 16;
+
+/// The local message type table mapping the 16 local message types to their
+/// currently active definitions. Defining a local message type again replaces
+/// the earlier definition for all following data records.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LocalMessageTable {
+    definitions: [Option<DefinitionRecord>; LOCAL_MESSAGE_TYPE_COUNT],
+}
+
+impl LocalMessageTable {
+    pub fn new() -> LocalMessageTable {
+        LocalMessageTable::default()
+    }
+
+    /// Assigns the definition to its local message type, returning the
+    /// definition it replaced, if any.
+    pub fn define(&mut self, definition: DefinitionRecord) -> Option<DefinitionRecord> {
+        let slot = definition.header.local_message_type as usize % LOCAL_MESSAGE_TYPE_COUNT;
+        self.definitions[slot].replace(definition)
+    }
+
+    pub fn get(&self, local_message_type: u8) -> Option<&DefinitionRecord> {
+        self.definitions
+            .get(local_message_type as usize)
+            .and_then(|d| d.as_ref())
+    }
+}
+
+/// A record of the data section.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Record {
+    Definition(DefinitionRecord),
+    Data(DataRecord),
+}
+
+// Size of a definition record content starting at `data_start_offset`, or
+// `None` if the data ends before the record does.
+fn definition_record_len(
+    data: &[u8],
+    header: &NormalDefinitionHeader,
+    data_start_offset: usize,
+) -> Option<usize> {
+    const FIXED_CONTENT_SIZE: usize = 5;
+    let number_of_fields = *data.get(data_start_offset + FIXED_CONTENT_SIZE - 1)? as usize;
+    let mut len = FIXED_CONTENT_SIZE + number_of_fields * 3;
+    if header.contains_extended_definitions {
+        let number_of_developer_fields = *data.get(data_start_offset + len)? as usize;
+        len += 1 + number_of_developer_fields * 3;
+    }
+    if data_start_offset + len > data.len() {
+        return None;
+    }
+    Some(len)
+}
+
+// First base type of the field definitions of the definition record starting
+// at `data_start_offset` that is not in the FIT protocol. The record must be
+// known to be complete.
+fn unknown_base_type(data: &[u8], data_start_offset: usize) -> Option<u8> {
+    let fields_start = data_start_offset + 5;
+    let number_of_fields = data[data_start_offset + 4] as usize;
+    data[fields_start..fields_start + number_of_fields * 3]
+        .chunks_exact(3)
+        .map(|field| field[2])
+        .find(|base_type| BaseType::try_from(*base_type).is_err())
+}
+
+/// Iterator over the records of a data section, keeping track of the local
+/// message type definitions that data records refer to.
+#[derive(Debug, Clone)]
+pub struct RecordIterator<'a> {
+    data: &'a [u8],
+    offset: usize,
+    record_index: usize,
+    local_messages: LocalMessageTable,
+    failed: bool,
+}
+
+impl<'a> RecordIterator<'a> {
+    /// Iterates the records in `data`, which should contain exactly the data
+    /// section of a file, starting from `offset`.
+    pub fn new(data: &'a [u8], offset: usize) -> RecordIterator<'a> {
+        RecordIterator::with_local_messages(data, offset, LocalMessageTable::new())
+    }
+
+    /// Iterates the records like [`RecordIterator::new`], with the local
+    /// message types of `local_messages` defined up front.
+    pub fn with_local_messages(
+        data: &'a [u8],
+        offset: usize,
+        local_messages: LocalMessageTable,
+    ) -> RecordIterator<'a> {
+        RecordIterator {
+            data,
+            offset,
+            record_index: 0,
+            local_messages,
+            failed: false,
+        }
+    }
+
+    /// Offset of the next record to be read.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Index of the next record to be read, counting from the first record
+    /// the iterator read.
+    pub fn record_index(&self) -> usize {
+        self.record_index
+    }
+
+    pub fn local_messages(&self) -> &LocalMessageTable {
+        &self.local_messages
+    }
+
+    fn read_record(&mut self) -> Result<Record, FitError> {
+        let record_offset = self.offset;
+        let header = parse_record_header(self.data[record_offset]);
+        let content_offset = record_offset + 1;
+        match header {
+            RecordHeader::NormalDefinition(definition_header) => {
+                definition_record_len(self.data, &definition_header, content_offset).ok_or(
+                    FitError::UnexpectedEndOfData {
+                        offset: record_offset,
+                    },
+                )?;
+                if let Some(base_type) = unknown_base_type(self.data, content_offset) {
+                    return Err(FitError::UnknownBaseType {
+                        base_type,
+                        offset: record_offset,
+                    });
+                }
+                let (definition, next_offset) =
+                    parse_definition_record(self.data, definition_header, content_offset);
+                self.local_messages.define(definition.clone());
+                self.offset = next_offset;
+                Ok(Record::Definition(definition))
+            }
+            RecordHeader::NormalData(_) | RecordHeader::CompressedTimestamp(_) => {
+                let local_message_type = header.local_message_type();
+                let definition = self.local_messages.get(local_message_type).ok_or(
+                    FitError::UndefinedLocalMessageType {
+                        local_message_type,
+                        offset: record_offset,
+                    },
+                )?;
+                if content_offset + definition.data_size() > self.data.len() {
+                    return Err(FitError::UnexpectedEndOfData {
+                        offset: record_offset,
+                    });
+                }
+                let (data_record, next_offset) =
+                    parse_data_record(self.data, header, definition, content_offset);
+                self.offset = next_offset;
+                Ok(Record::Data(data_record))
+            }
+        }
+    }
+}
+
+impl Iterator for RecordIterator<'_> {
+    type Item = Result<Record, FitError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.offset >= self.data.len() {
+            return None;
+        }
+        let result = self.read_record();
+        self.failed = result.is_err();
+        if !self.failed {
+            self.record_index += 1;
+        }
+        Some(result)
+    }
+}
+
 #[test]
 fn test_parse_definition_record() {
     let header = NormalDefinitionHeader {
@@ -510,8 +782,7 @@ fn test_parse_definition_record_with_developer_fields() {
 }
 
 #[test]
-#[should_panic]
-fn test_parse_definition_record_invalid_size() {
+fn test_parse_definition_record_size_not_multiple_of_base_type() {
     let header = NormalDefinitionHeader {
         contains_extended_definitions: false,
         local_message_type: 1,
@@ -520,7 +791,172 @@ fn test_parse_definition_record_invalid_size() {
         0x00, 0x01, // architecture
         0x0A, 0x0B, // global message number
         0x01, //num of fields
-        0x01, 0x01, 0x09, // field definition
+        0x01, 0x01, 0x89, // field definition, float64 of size 1
     ];
-    parse_definition_record(&data, header, 0);
+    let (actual, _) = parse_definition_record(&data, header, 0);
+    assert_eq!(actual.field_definitions[0].field_size, 1);
+    assert_eq!(actual.data_size(), 1);
+}
+
+#[test]
+fn test_parse_compressed_timestamp_record_header() {
+    assert_eq!(
+        parse_record_header(0b11110101),
+        RecordHeader::CompressedTimestamp(CompressedTimestampHeader {
+            local_message_type: 3,
+            time_offset: 0b10101
+        })
+    );
+}
+
+#[test]
+fn test_parse_data_record() {
+    let definition = DefinitionRecord {
+        header: NormalDefinitionHeader {
+            contains_extended_definitions: true,
+            local_message_type: 0,
+        },
+        architecture: Endianness::LittleEndian,
+        global_message_number: 20,
+        field_definitions: vec![
+            FieldDefinition {
+                field_definition_number: 3,
+                field_size: 1,
+                base_type: get_base_type_info(BaseType::Uint8.into()),
+            },
+            FieldDefinition {
+                field_definition_number: 7,
+                field_size: 2,
+                base_type: get_base_type_info(BaseType::Uint16.into()),
+            },
+        ],
+        developer_field_definitions: vec![DeveloperFieldDefinition {
+            field_number: 0,
+            field_size: 1,
+            developer_data_index: 0,
+        }],
+    };
+    assert_eq!(definition.data_size(), 4);
+    let header = RecordHeader::NormalData(NormalDataHeader {
+        local_message_type: 0,
+    });
+    let data: Vec<u8> = vec![0x00, 0x8C, 0xC8, 0x00, 0x2A, 0xFF];
+    let (actual, new_idx) = parse_data_record(&data, header.clone(), &definition, 1);
+    assert_eq!(new_idx, 5);
+    assert_eq!(
+        actual,
+        DataRecord {
+            header,
+            architecture: Endianness::LittleEndian,
+            global_message_number: 20,
+            fields: vec![
+                RawField {
+                    field_definition_number: 3,
+                    base_type: get_base_type_info(BaseType::Uint8.into()),
+                    data: vec![0x8C],
+                },
+                RawField {
+                    field_definition_number: 7,
+                    base_type: get_base_type_info(BaseType::Uint16.into()),
+                    data: vec![0xC8, 0x00],
+                },
+            ],
+            developer_fields: vec![RawDeveloperField {
+                field_number: 0,
+                developer_data_index: 0,
+                architecture: Endianness::LittleEndian,
+                data: vec![0x2A],
+            }],
+        }
+    );
+}
+
+#[cfg(test)]
+fn test_definition_bytes(
+    local_message_type: u8,
+    global_message_number: u8,
+    field_size: u8,
+) -> Vec<u8> {
+    vec![
+        0b01000000 | local_message_type,
+        0x00,
+        0x00, // little endian
+        global_message_number,
+        0x00,
+        0x01, // num of fields
+        0x01,
+        field_size,
+        BaseType::Byte.into(),
+    ]
+}
+
+#[test]
+fn test_record_iterator_uses_latest_definition_of_local_message_type() {
+    let mut data = test_definition_bytes(0, 20, 1);
+    data.extend([0x00, 0xAA]);
+    // redefine local message type 0 with a wider field
+    data.extend(test_definition_bytes(0, 21, 2));
+    data.extend([0x00, 0xBB, 0xCC]);
+    let records = RecordIterator::new(&data, 0)
+        .collect::<Result<Vec<Record>, FitError>>()
+        .unwrap();
+    assert_eq!(records.len(), 4);
+    match (&records[1], &records[3]) {
+        (Record::Data(first), Record::Data(second)) => {
+            assert_eq!(first.global_message_number, 20);
+            assert_eq!(first.fields[0].data, vec![0xAA]);
+            assert_eq!(second.global_message_number, 21);
+            assert_eq!(second.fields[0].data, vec![0xBB, 0xCC]);
+        }
+        other => panic!("Expected data records, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_record_iterator_undefined_local_message_type() {
+    let mut data = test_definition_bytes(0, 20, 1);
+    data.extend([0x00, 0xAA, 0x03, 0xBB]);
+    let results = RecordIterator::new(&data, 0).collect::<Vec<Result<Record, FitError>>>();
+    assert_eq!(results.len(), 3);
+    assert_eq!(
+        results[2],
+        Err(FitError::UndefinedLocalMessageType {
+            local_message_type: 3,
+            offset: 11
+        })
+    );
+}
+
+#[test]
+fn test_record_iterator_truncated_data_record() {
+    let mut data = test_definition_bytes(0, 20, 4);
+    data.extend([0x00, 0xAA]);
+    let results = RecordIterator::new(&data, 0).collect::<Vec<Result<Record, FitError>>>();
+    assert_eq!(
+        results.last(),
+        Some(&Err(FitError::UnexpectedEndOfData { offset: 9 }))
+    );
+}
+
+#[test]
+fn test_record_iterator_unknown_base_type() {
+    let mut data = test_definition_bytes(0, 20, 1);
+    data[8] = 0x42;
+    let results = RecordIterator::new(&data, 0).collect::<Vec<Result<Record, FitError>>>();
+    assert_eq!(
+        results,
+        vec![Err(FitError::UnknownBaseType {
+            base_type: 0x42,
+            offset: 0
+        })]
+    );
+}
+
+#[test]
+fn test_base_type_table_matches_base_type_fields() {
+    for info in &BASE_TYPES {
+        let info_by_number = get_base_type_info(info.base_type_field);
+        assert_eq!(info_by_number, info);
+        assert_eq!(u8::from(info.base_type.clone()), info.base_type_field);
+    }
 }
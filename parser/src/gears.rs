@@ -0,0 +1,132 @@
+//! Electronic shifting (Di2, AXS) gear changes from `event` messages.
+//!
+//! The gears are packed into the `gear_change_data` subfield of `data` and
+//! expanded to the gear number and teeth fields by the decoder.
+
+use crate::fit_file::FitFile;
+use crate::profile::messages::Event as EventMessage;
+use crate::profile::{Event, EVENT_MESSAGE};
+
+/// The gear combination after a shift. Gear numbers start from 1 for the
+/// innermost chainring or sprocket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GearChange {
+    pub timestamp: u32,
+    /// Whether the shift was on the front derailleur.
+    pub front_shift: bool,
+    pub front_gear_num: Option<u8>,
+    pub front_teeth: Option<u8>,
+    pub rear_gear_num: Option<u8>,
+    pub rear_teeth: Option<u8>,
+}
+
+impl GearChange {
+    /// The ratio of front to rear teeth, if both are known.
+    pub fn ratio(&self) -> Option<f64> {
+        match (self.front_teeth?, self.rear_teeth?) {
+            (_, 0) => None,
+            (front, rear) => Some(front as f64 / rear as f64),
+        }
+    }
+
+    fn from_event(event: &EventMessage) -> Option<GearChange> {
+        let front_shift = match event.event? {
+            e if e == Event::FrontGearChange as u8 => true,
+            e if e == Event::RearGearChange as u8 => false,
+            _ => return None,
+        };
+        Some(GearChange {
+            timestamp: event.timestamp?,
+            front_shift,
+            front_gear_num: event.front_gear_num,
+            front_teeth: event.front_gear,
+            rear_gear_num: event.rear_gear_num,
+            rear_teeth: event.rear_gear,
+        })
+    }
+}
+
+/// Seconds spent in a front and rear gear combination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GearUsage {
+    pub front_gear_num: Option<u8>,
+    pub rear_gear_num: Option<u8>,
+    pub seconds: u32,
+}
+
+impl FitFile {
+    /// Gear changes in time order.
+    pub fn gear_changes(&self) -> Vec<GearChange> {
+        let mut changes = self
+            .messages
+            .iter()
+            .filter(|m| m.global_message_number == EVENT_MESSAGE.number)
+            .filter_map(|m| GearChange::from_event(&EventMessage::from_message(m)))
+            .collect::<Vec<GearChange>>();
+        changes.sort_by_key(|c| c.timestamp);
+        changes
+    }
+
+    /// Seconds spent in each gear combination, from each gear change to the
+    /// next one or to `end` for the last one, in order of first use.
+    pub fn gear_usage(&self, end: u32) -> Vec<GearUsage> {
+        let changes = self.gear_changes();
+        let mut usage: Vec<GearUsage> = Vec::new();
+        for (idx, change) in changes.iter().enumerate() {
+            let until = changes.get(idx + 1).map_or(end, |c| c.timestamp);
+            let seconds = until.saturating_sub(change.timestamp);
+            match usage.iter_mut().find(|u| {
+                u.front_gear_num == change.front_gear_num && u.rear_gear_num == change.rear_gear_num
+            }) {
+                Some(u) => u.seconds += seconds,
+                None => usage.push(GearUsage {
+                    front_gear_num: change.front_gear_num,
+                    rear_gear_num: change.rear_gear_num,
+                    seconds,
+                }),
+            }
+        }
+        usage
+    }
+}
+
+#[cfg(test)]
+use crate::fit_file::{test_fit_file, test_message};
+#[cfg(test)]
+use crate::fit_messages::Value;
+
+#[test]
+fn test_gear_changes() {
+    let shift = |t: u32, event: Event, front: u8, rear: u8| {
+        let front_teeth = if front == 2 { 50 } else { 34 };
+        test_message(
+            EVENT_MESSAGE.number,
+            vec![
+                (253, "timestamp", Value::U32(t)),
+                (0, "event", Value::U8(event as u8)),
+                (9, "front_gear_num", Value::U8(front)),
+                (10, "front_gear", Value::U8(front_teeth)),
+                (11, "rear_gear_num", Value::U8(rear)),
+                (12, "rear_gear", Value::U8(30 - rear * 2)),
+            ],
+        )
+    };
+    let file = test_fit_file(vec![
+        shift(1100, Event::RearGearChange, 2, 5),
+        shift(1000, Event::RearGearChange, 2, 4),
+        shift(1200, Event::FrontGearChange, 1, 5),
+        shift(1300, Event::RearGearChange, 2, 4),
+    ]);
+    let changes = file.gear_changes();
+    assert_eq!(changes.len(), 4);
+    assert_eq!(changes[0].timestamp, 1000);
+    assert_eq!(changes[0].rear_teeth, Some(22));
+    assert_eq!(changes[0].ratio(), Some(50.0 / 22.0));
+    assert!(changes[2].front_shift);
+    let usage = file.gear_usage(1400);
+    assert_eq!(usage.len(), 3);
+    assert_eq!(usage[0].rear_gear_num, Some(4));
+    assert_eq!(usage[0].seconds, 200);
+    assert_eq!(usage[2].front_gear_num, Some(1));
+    assert_eq!(usage[2].seconds, 100);
+}
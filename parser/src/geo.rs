@@ -0,0 +1,76 @@
+//! Distances between GPS positions.
+
+use crate::series::RecordSeries;
+
+/// Mean Earth radius in meters.
+pub const EARTH_RADIUS: f64 = 6_371_008.8;
+
+/// Great-circle distance in meters between two positions in degrees, using
+/// the haversine formula on a spherical Earth.
+pub fn haversine_distance(lat1: f64, long1: f64, lat2: f64, long2: f64) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let d_lat = lat2 - lat1;
+    let d_long = (long2 - long1).to_radians();
+    let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_long / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * a.sqrt().asin()
+}
+
+/// Cumulative distance in meters along the positions in degrees. Positions
+/// with a NaN coordinate get NaN and are skipped, as is the distance before
+/// the first position.
+pub fn cumulative_distance(lat: &[f64], long: &[f64]) -> Vec<f64> {
+    let mut total = 0.0;
+    let mut previous: Option<(f64, f64)> = None;
+    lat.iter()
+        .zip(long)
+        .map(|(lat, long)| {
+            if lat.is_nan() || long.is_nan() {
+                return f64::NAN;
+            }
+            if let Some((previous_lat, previous_long)) = previous {
+                total += haversine_distance(previous_lat, previous_long, *lat, *long);
+            }
+            previous = Some((*lat, *long));
+            total
+        })
+        .collect()
+}
+
+impl RecordSeries {
+    /// Derives the distance column from the positions when no record has a
+    /// distance, as in files converted from formats without one. Returns
+    /// whether the distance was derived.
+    pub fn derive_missing_distance(&mut self) -> bool {
+        if self.distance.iter().any(|d| !d.is_nan()) {
+            return false;
+        }
+        self.distance = cumulative_distance(&self.position_lat, &self.position_long);
+        true
+    }
+}
+
+#[cfg(test)]
+use crate::series::test_series;
+
+#[test]
+fn test_haversine_distance() {
+    // One degree of latitude along a meridian.
+    let distance = haversine_distance(60.0, 24.0, 61.0, 24.0);
+    assert!((distance - 111_195.0).abs() < 1.0);
+    assert_eq!(haversine_distance(60.0, 24.0, 60.0, 24.0), 0.0);
+}
+
+#[test]
+fn test_derive_missing_distance() {
+    let mut series = RecordSeries {
+        position_lat: vec![0.0, 0.0, f64::NAN, 0.0],
+        position_long: vec![0.0, 0.001, f64::NAN, 0.002],
+        ..test_series(&[0, 1, 2, 3])
+    };
+    assert!(series.derive_missing_distance());
+    assert_eq!(series.distance[0], 0.0);
+    assert!((series.distance[1] - 111.195).abs() < 0.001);
+    assert!(series.distance[2].is_nan());
+    assert!((series.distance[3] - 222.39).abs() < 0.001);
+    assert!(!series.derive_missing_distance());
+}
@@ -0,0 +1,112 @@
+//! Export of the track of an activity as GPX 1.1, for tools that read GPX
+//! rather than FIT. Heart rate and cadence are written in the Garmin track
+//! point extension most GPX readers understand.
+
+use std::io::{self, Write};
+
+use crate::fit_file::FitFile;
+use crate::profile::messages::Record;
+use crate::profile::RECORD_MESSAGE;
+use crate::series::SEMICIRCLES_TO_DEGREES;
+use crate::time::format_timestamp;
+
+const GPX_HEADER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gpx version="1.1" creator="fit_parser" xmlns="http://www.topografix.com/GPX/1/1" xmlns:gpxtpx="http://www.garmin.com/xmlschemas/TrackPointExtension/v1">"#;
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Writes the records of the file with a position as the track points of one
+/// track, typed by the sport of the file. Returns the number of track points
+/// written.
+pub fn write_gpx<W: Write>(file: &FitFile, mut writer: W) -> io::Result<usize> {
+    writeln!(writer, "{}", GPX_HEADER)?;
+    writeln!(writer, "  <trk>")?;
+    if let Some(sport) = file.line_tags().sport {
+        writeln!(writer, "    <type>{}</type>", escape_xml(&sport))?;
+    }
+    writeln!(writer, "    <trkseg>")?;
+    let mut points = 0;
+    for record in file.typed_messages(RECORD_MESSAGE.number, Record::from_message) {
+        let (lat, lon) = match (record.position_lat, record.position_long) {
+            (Some(lat), Some(lon)) => (
+                lat as f64 * SEMICIRCLES_TO_DEGREES,
+                lon as f64 * SEMICIRCLES_TO_DEGREES,
+            ),
+            _ => continue,
+        };
+        writeln!(
+            writer,
+            "      <trkpt lat=\"{:.7}\" lon=\"{:.7}\">",
+            lat, lon
+        )?;
+        if let Some(altitude) = record.enhanced_altitude.or(record.altitude) {
+            writeln!(writer, "        <ele>{:.1}</ele>", altitude)?;
+        }
+        if let Some(timestamp) = record.timestamp {
+            writeln!(
+                writer,
+                "        <time>{}</time>",
+                format_timestamp(timestamp)
+            )?;
+        }
+        if record.heart_rate.is_some() || record.cadence.is_some() {
+            writeln!(writer, "        <extensions><gpxtpx:TrackPointExtension>")?;
+            if let Some(heart_rate) = record.heart_rate {
+                writeln!(writer, "          <gpxtpx:hr>{}</gpxtpx:hr>", heart_rate)?;
+            }
+            if let Some(cadence) = record.cadence {
+                writeln!(writer, "          <gpxtpx:cad>{}</gpxtpx:cad>", cadence)?;
+            }
+            writeln!(writer, "        </gpxtpx:TrackPointExtension></extensions>")?;
+        }
+        writeln!(writer, "      </trkpt>")?;
+        points += 1;
+    }
+    writeln!(writer, "    </trkseg>")?;
+    writeln!(writer, "  </trk>")?;
+    writeln!(writer, "</gpx>")?;
+    Ok(points)
+}
+
+#[cfg(test)]
+use crate::fit_file::{test_fit_file, test_message};
+#[cfg(test)]
+use crate::fit_messages::Value;
+
+#[test]
+fn test_write_gpx() {
+    let file = test_fit_file(vec![
+        test_message(
+            RECORD_MESSAGE.number,
+            vec![
+                (253, "timestamp", Value::U32(1_073_001_599)),
+                (0, "position_lat", Value::I32(1 << 29)),
+                (1, "position_long", Value::I32(-(1 << 30))),
+                (2, "altitude", Value::F64(12.5)),
+                (3, "heart_rate", Value::U8(150)),
+            ],
+        ),
+        test_message(
+            RECORD_MESSAGE.number,
+            vec![(3, "heart_rate", Value::U8(151))],
+        ),
+        test_message(18, vec![(5, "sport", Value::U8(2))]),
+    ]);
+    let mut gpx = Vec::new();
+    assert_eq!(write_gpx(&file, &mut gpx).unwrap(), 1);
+    let gpx = String::from_utf8(gpx).unwrap();
+    assert!(gpx.contains("    <type>cycling</type>\n"));
+    assert!(gpx.contains(
+        "      <trkpt lat=\"45.0000000\" lon=\"-90.0000000\">\n\
+         \x20       <ele>12.5</ele>\n\
+         \x20       <time>2023-12-31T23:59:59Z</time>\n"
+    ));
+    assert!(gpx.contains("<gpxtpx:hr>150</gpxtpx:hr>"));
+    assert!(!gpx.contains("<gpxtpx:hr>151</gpxtpx:hr>"));
+    assert!(gpx.ends_with("</gpx>\n"));
+}
@@ -0,0 +1,80 @@
+//! Gradient and grade-adjusted pace derived from the distance and altitude
+//! columns.
+
+use crate::series::RecordSeries;
+
+/// Metabolic cost of running in J/kg/m at `gradient`, from Minetti et al.
+/// (2002). Gradients are clamped to the measured range of ±45 %.
+pub fn running_cost(gradient: f64) -> f64 {
+    let i = gradient.clamp(-0.45, 0.45);
+    155.4 * i.powi(5) - 30.4 * i.powi(4) - 43.3 * i.powi(3) + 46.3 * i.powi(2) + 19.5 * i + 3.6
+}
+
+impl RecordSeries {
+    /// Gradient at each sample as rise over run, from the altitude change
+    /// since the latest earlier sample at least `min_distance` meters back.
+    /// NaN when there is no such sample or a value is missing.
+    pub fn gradient(&self, min_distance: f64) -> Vec<f64> {
+        let samples = (0..self.len())
+            .filter(|idx| !self.distance[*idx].is_nan() && !self.altitude[*idx].is_nan())
+            .collect::<Vec<usize>>();
+        let mut gradient = vec![f64::NAN; self.len()];
+        let mut back = 0;
+        for (pos, idx) in samples.iter().enumerate() {
+            // The latest sample at least `min_distance` back is found by
+            // advancing while the next one would also be far enough.
+            while back + 1 < pos
+                && self.distance[*idx] - self.distance[samples[back + 1]] >= min_distance
+            {
+                back += 1;
+            }
+            let run = self.distance[*idx] - self.distance[samples[back]];
+            if back < pos && run >= min_distance && run > 0.0 {
+                gradient[*idx] = (self.altitude[*idx] - self.altitude[samples[back]]) / run;
+            }
+        }
+        gradient
+    }
+
+    /// Speed in meters per second on flat ground that takes the same effort
+    /// as the recorded speed at the gradient of [`RecordSeries::gradient`],
+    /// for running activities. Samples without a gradient keep their speed.
+    pub fn grade_adjusted_speed(&self, min_distance: f64) -> Vec<f64> {
+        let flat = running_cost(0.0);
+        self.gradient(min_distance)
+            .iter()
+            .zip(&self.speed)
+            .map(|(gradient, speed)| {
+                if gradient.is_nan() {
+                    *speed
+                } else {
+                    speed * running_cost(*gradient) / flat
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+use crate::series::test_series;
+
+#[test]
+fn test_gradient_and_grade_adjusted_speed() {
+    let series = RecordSeries {
+        altitude: vec![100.0, 100.5, 101.0, f64::NAN, 101.0],
+        speed: vec![3.0; 5],
+        distance: vec![0.0, 5.0, 10.0, 15.0, 20.0],
+        ..test_series(&[0, 1, 2, 3, 4])
+    };
+    let gradient = series.gradient(10.0);
+    assert!(gradient[0].is_nan());
+    assert!(gradient[1].is_nan());
+    assert_eq!(gradient[2], 0.1);
+    assert!(gradient[3].is_nan());
+    assert_eq!(gradient[4], 0.0);
+
+    let adjusted = series.grade_adjusted_speed(10.0);
+    assert_eq!(adjusted[0], 3.0);
+    assert!(adjusted[2] > 3.0);
+    assert_eq!(adjusted[4], 3.0);
+}
@@ -0,0 +1,156 @@
+//! Beat-to-beat (RR) intervals from `hrv` messages and heart rate
+//! variability metrics.
+
+use std::time::Duration;
+
+use crate::fit_file::FitFile;
+use crate::profile::messages::Hrv;
+use crate::profile::HRV_MESSAGE;
+
+// Decoded value of the invalid value of the uint16 RR intervals, which
+// devices use to pad the unused slots of the arrays.
+const INVALID_INTERVAL: f64 = 65.535;
+
+/// An interval between two heart beats.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RrInterval {
+    /// Seconds since the FIT epoch of the beat ending the interval.
+    pub time: f64,
+    /// Seconds.
+    pub interval: f64,
+}
+
+/// Root mean square of the successive differences of the intervals, in the
+/// unit of the intervals. `None` for fewer than two intervals.
+pub fn rmssd(intervals: &[f64]) -> Option<f64> {
+    if intervals.len() < 2 {
+        return None;
+    }
+    let sum = intervals
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]).powi(2))
+        .sum::<f64>();
+    Some((sum / (intervals.len() - 1) as f64).sqrt())
+}
+
+/// Standard deviation of the intervals, in their unit. `None` for fewer than
+/// two intervals.
+pub fn sdnn(intervals: &[f64]) -> Option<f64> {
+    if intervals.len() < 2 {
+        return None;
+    }
+    let mean = intervals.iter().sum::<f64>() / intervals.len() as f64;
+    let sum = intervals.iter().map(|i| (i - mean).powi(2)).sum::<f64>();
+    Some((sum / (intervals.len() - 1) as f64).sqrt())
+}
+
+/// Heart rate variability of the intervals ending within a window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HrvWindow {
+    /// Seconds since the FIT epoch.
+    pub start: f64,
+    pub intervals: usize,
+    /// Seconds.
+    pub rmssd: Option<f64>,
+    /// Seconds.
+    pub sdnn: Option<f64>,
+}
+
+/// HRV metrics over consecutive windows of length `window` starting from the
+/// first interval.
+pub fn hrv_windows(intervals: &[RrInterval], window: Duration) -> Vec<HrvWindow> {
+    assert!(!window.is_zero(), "HRV window must be positive");
+    let first = match intervals.first() {
+        Some(first) => first.time,
+        None => return Vec::new(),
+    };
+    let length = window.as_secs_f64();
+    let mut windows: Vec<HrvWindow> = Vec::new();
+    let mut current: Vec<f64> = Vec::new();
+    let mut start = first;
+    let close = |start: f64, current: &[f64]| HrvWindow {
+        start,
+        intervals: current.len(),
+        rmssd: rmssd(current),
+        sdnn: sdnn(current),
+    };
+    for rr in intervals {
+        while rr.time >= start + length {
+            windows.push(close(start, &current));
+            current.clear();
+            start += length;
+        }
+        current.push(rr.interval);
+    }
+    windows.push(close(start, &current));
+    windows
+}
+
+impl FitFile {
+    /// RR intervals of the `hrv` messages in file order. The beat times are
+    /// counted from the first timestamp of the file, as `hrv` messages have
+    /// no timestamps of their own.
+    pub fn rr_intervals(&self) -> Vec<RrInterval> {
+        let mut time = match self.messages.iter().find_map(|m| m.timestamp()) {
+            Some(timestamp) => timestamp as f64,
+            None => 0.0,
+        };
+        let mut intervals = Vec::new();
+        for hrv in self.typed_messages(HRV_MESSAGE.number, Hrv::from_message) {
+            for interval in hrv.time.unwrap_or_default() {
+                if interval >= INVALID_INTERVAL {
+                    continue;
+                }
+                time += interval;
+                intervals.push(RrInterval { time, interval });
+            }
+        }
+        intervals
+    }
+}
+
+#[cfg(test)]
+use crate::fit_file::{test_fit_file, test_message};
+#[cfg(test)]
+use crate::fit_messages::Value;
+
+#[test]
+fn test_rmssd_and_sdnn() {
+    let intervals = [0.8, 0.9, 0.7, 0.8];
+    assert!((rmssd(&intervals).unwrap() - (0.06f64 / 3.0).sqrt()).abs() < 1e-12);
+    assert!((sdnn(&intervals).unwrap() - (0.02f64 / 3.0).sqrt()).abs() < 1e-12);
+    assert_eq!(rmssd(&[0.8]), None);
+}
+
+#[test]
+fn test_rr_intervals_and_windows() {
+    let fit_file = test_fit_file(vec![
+        test_message(20, vec![(253, "timestamp", Value::U32(1000))]),
+        test_message(
+            78,
+            vec![(
+                0,
+                "time",
+                Value::Array(vec![
+                    Value::F64(0.5),
+                    Value::F64(0.6),
+                    Value::F64(INVALID_INTERVAL),
+                ]),
+            )],
+        ),
+        test_message(78, vec![(0, "time", Value::F64(0.7))]),
+    ]);
+    let intervals = fit_file.rr_intervals();
+    assert_eq!(
+        intervals.iter().map(|rr| rr.interval).collect::<Vec<f64>>(),
+        vec![0.5, 0.6, 0.7]
+    );
+    assert_eq!(intervals[0].time, 1000.5);
+    assert!((intervals[2].time - 1001.8).abs() < 1e-9);
+
+    let windows = hrv_windows(&intervals, Duration::from_secs(1));
+    assert_eq!(windows.len(), 2);
+    assert_eq!(windows[0].intervals, 2);
+    assert_eq!(windows[1].start, 1001.5);
+    assert_eq!(windows[1].rmssd, None);
+}
@@ -0,0 +1,242 @@
+//! An index of the timestamps of a file for decoding long files, such as
+//! monitoring files, from a point in time without reading the records
+//! before it.
+//!
+//! The index is built in one pass over the file and can be stored alongside
+//! it. Each entry records the offset of a timestamped data record and the
+//! offsets of the definitions active at it, so decoding can resume there.
+
+use std::io::{self, Read, Write};
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::diagnostics::Diagnostics;
+use crate::error::FitError;
+use crate::fit_header::try_parse_fit_header_from_data;
+use crate::fit_messages::{DataMessage, DecodeState, MessageDecoder};
+use crate::fit_records::{LocalMessageTable, Record, RecordIterator, LOCAL_MESSAGE_TYPE_COUNT};
+
+const INDEX_MAGIC: &[u8; 4] = b"FITX";
+const INDEX_VERSION: u8 = 1;
+// Timestamp, record offset and record index, then the definition offsets.
+const ENTRY_SIZE: usize = 4 + 8 + 8 + LOCAL_MESSAGE_TYPE_COUNT * 8;
+// Stored offset of an undefined local message type.
+const NO_DEFINITION: u64 = u64::MAX;
+
+/// A point of the file decoding can resume from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexEntry {
+    /// Timestamp of the data record, seconds since the FIT epoch.
+    pub timestamp: u32,
+    /// Offset of the data record in the file.
+    pub offset: usize,
+    pub record_index: usize,
+    /// Offsets of the definition records active at the data record by
+    /// local message type.
+    pub definitions: [Option<usize>; LOCAL_MESSAGE_TYPE_COUNT],
+}
+
+/// Entries of a file in file order, at most one per `interval` seconds.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TimestampIndex {
+    pub interval: u32,
+    pub entries: Vec<IndexEntry>,
+}
+
+impl TimestampIndex {
+    /// Builds the index of a file with an entry at the first timestamped
+    /// data record and then at most one per `interval` seconds. Fails like
+    /// [`crate::fit_file::FitFile::parse`] on malformed records.
+    pub fn build(fit_data: &[u8], interval: u32) -> Result<TimestampIndex, FitError> {
+        let header = try_parse_fit_header_from_data(fit_data)?;
+        let data_start = header.header_size() as usize;
+        let data_end = fit_data.len().min(data_start + header.data_size() as usize);
+        let mut decoder = MessageDecoder::new();
+        let mut diagnostics = Diagnostics::new();
+        let mut definitions = [None; LOCAL_MESSAGE_TYPE_COUNT];
+        let mut entries: Vec<IndexEntry> = Vec::new();
+        let mut records = RecordIterator::new(&fit_data[..data_end], data_start);
+        loop {
+            let offset = records.offset();
+            let record_index = records.record_index();
+            match records.next() {
+                None => break,
+                Some(Ok(Record::Definition(definition))) => {
+                    definitions[definition.header.local_message_type as usize] = Some(offset);
+                }
+                Some(Ok(Record::Data(record))) => {
+                    let message = decoder.decode(record, offset, record_index, &mut diagnostics);
+                    let timestamp = match message.timestamp() {
+                        Some(timestamp) => timestamp,
+                        None => continue,
+                    };
+                    let due = entries
+                        .last()
+                        .is_none_or(|e| timestamp >= e.timestamp.saturating_add(interval));
+                    if due {
+                        entries.push(IndexEntry {
+                            timestamp,
+                            offset,
+                            record_index,
+                            definitions,
+                        });
+                    }
+                }
+                Some(Err(FitError::UndefinedLocalMessageType { .. })) => break,
+                Some(Err(e)) => return Err(e),
+            }
+        }
+        Ok(TimestampIndex { interval, entries })
+    }
+
+    /// The last entry at or before the timestamp.
+    pub fn entry_before(&self, timestamp: u32) -> Option<&IndexEntry> {
+        let after = self.entries.partition_point(|e| e.timestamp <= timestamp);
+        after.checked_sub(1).map(|i| &self.entries[i])
+    }
+
+    /// Decodes the data messages of the file from the first one timestamped
+    /// at or after `timestamp` to the end, starting from the entry before
+    /// it. Components accumulated over the records before the entry are
+    /// missing.
+    pub fn seek_to(&self, fit_data: &[u8], timestamp: u32) -> Result<Vec<DataMessage>, FitError> {
+        let entry = match self.entry_before(timestamp).or(self.entries.first()) {
+            Some(entry) => entry,
+            None => return Ok(Vec::new()),
+        };
+        let header = try_parse_fit_header_from_data(fit_data)?;
+        let data_start = header.header_size() as usize;
+        let data_end = fit_data.len().min(data_start + header.data_size() as usize);
+        let data = &fit_data[..data_end];
+        let mut local_messages = LocalMessageTable::new();
+        for offset in entry.definitions.iter().flatten() {
+            match RecordIterator::new(data, *offset).next() {
+                Some(Ok(Record::Definition(definition))) => {
+                    local_messages.define(definition);
+                }
+                Some(Err(e)) => return Err(e),
+                _ => return Err(FitError::UnexpectedEndOfData { offset: *offset }),
+            }
+        }
+        let mut decoder = MessageDecoder::new();
+        decoder.set_state(DecodeState::at_timestamp(entry.timestamp));
+        let mut diagnostics = Diagnostics::new();
+        let mut records = RecordIterator::with_local_messages(data, entry.offset, local_messages);
+        let mut messages = Vec::new();
+        loop {
+            let offset = records.offset();
+            let record_index = entry.record_index + records.record_index();
+            match records.next() {
+                None => break,
+                Some(Ok(Record::Definition(_))) => {}
+                Some(Ok(Record::Data(record))) => {
+                    let message = decoder.decode(record, offset, record_index, &mut diagnostics);
+                    if !messages.is_empty() || message.timestamp().is_some_and(|t| t >= timestamp) {
+                        messages.push(message);
+                    }
+                }
+                Some(Err(FitError::UndefinedLocalMessageType { .. })) => break,
+                Some(Err(e)) => return Err(e),
+            }
+        }
+        Ok(messages)
+    }
+
+    /// Writes the index in its binary format.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let mut head = [0u8; 13];
+        head[..4].copy_from_slice(INDEX_MAGIC);
+        head[4] = INDEX_VERSION;
+        LittleEndian::write_u32(&mut head[5..9], self.interval);
+        LittleEndian::write_u32(&mut head[9..13], self.entries.len() as u32);
+        writer.write_all(&head)?;
+        for entry in &self.entries {
+            let mut bytes = [0u8; ENTRY_SIZE];
+            LittleEndian::write_u32(&mut bytes[..4], entry.timestamp);
+            LittleEndian::write_u64(&mut bytes[4..12], entry.offset as u64);
+            LittleEndian::write_u64(&mut bytes[12..20], entry.record_index as u64);
+            for (i, definition) in entry.definitions.iter().enumerate() {
+                let start = 20 + i * 8;
+                LittleEndian::write_u64(
+                    &mut bytes[start..start + 8],
+                    definition.map_or(NO_DEFINITION, |d| d as u64),
+                );
+            }
+            writer.write_all(&bytes)?;
+        }
+        Ok(())
+    }
+
+    /// Reads an index written by [`TimestampIndex::write_to`].
+    pub fn read_from<R: Read>(mut reader: R) -> io::Result<TimestampIndex> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut head = [0u8; 13];
+        reader.read_exact(&mut head)?;
+        if &head[..4] != INDEX_MAGIC || head[4] != INDEX_VERSION {
+            return Err(invalid("not a timestamp index"));
+        }
+        let interval = LittleEndian::read_u32(&head[5..9]);
+        let count = LittleEndian::read_u32(&head[9..13]) as usize;
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            let mut bytes = [0u8; ENTRY_SIZE];
+            reader.read_exact(&mut bytes)?;
+            let read_offset = |start: usize| {
+                usize::try_from(LittleEndian::read_u64(&bytes[start..start + 8]))
+                    .map_err(|_| invalid("offset out of range"))
+            };
+            let mut definitions = [None; LOCAL_MESSAGE_TYPE_COUNT];
+            for (i, definition) in definitions.iter_mut().enumerate() {
+                let start = 20 + i * 8;
+                if LittleEndian::read_u64(&bytes[start..start + 8]) != NO_DEFINITION {
+                    *definition = Some(read_offset(start)?);
+                }
+            }
+            entries.push(IndexEntry {
+                timestamp: LittleEndian::read_u32(&bytes[..4]),
+                offset: read_offset(4)?,
+                record_index: read_offset(12)?,
+                definitions,
+            });
+        }
+        Ok(TimestampIndex { interval, entries })
+    }
+}
+
+#[cfg(test)]
+use crate::fit_file::{test_file, FitFile};
+
+#[test]
+fn test_timestamp_index() {
+    let mut records = vec![
+        0x40, 0x00, 0x00, 0x14, 0x00, 0x02, // record, 2 fields
+        0xFD, 0x04, 0x86, // timestamp
+        0x03, 0x01, 0x02, // heart_rate
+        0x41, 0x00, 0x00, 0x14, 0x00, 0x01, // record, heart_rate only
+        0x03, 0x01, 0x02,
+    ];
+    for t in 0..100u32 {
+        records.push(0x00);
+        records.extend_from_slice(&(1000 + t * 10).to_le_bytes());
+        records.push(100 + t as u8);
+        // compressed timestamp record 5 s later
+        records.extend_from_slice(&[0xA0 | ((1005 + t * 10) & 0x1F) as u8, 50]);
+    }
+    let data = test_file(&records);
+    let index = TimestampIndex::build(&data, 60).unwrap();
+    assert_eq!(index.entries.len(), 17);
+    assert_eq!(index.entries[1].timestamp, 1060);
+    assert_eq!(index.entry_before(1075).unwrap().timestamp, 1060);
+
+    let mut stored = Vec::new();
+    index.write_to(&mut stored).unwrap();
+    let index = TimestampIndex::read_from(&stored[..]).unwrap();
+    assert_eq!(index, TimestampIndex::build(&data, 60).unwrap());
+
+    let messages = index.seek_to(&data, 1075).unwrap();
+    let expected = FitFile::parse(&data).unwrap().messages;
+    assert_eq!(messages, expected[expected.len() - messages.len()..]);
+    assert_eq!(messages[0].timestamp(), Some(1075));
+    assert_eq!(messages.len(), 185);
+    assert!(TimestampIndex::read_from(&b"FITX"[..]).is_err());
+}
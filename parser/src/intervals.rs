@@ -0,0 +1,246 @@
+//! Detecting work and rest intervals in activities without a structured
+//! workout, from the changes in power or speed of the records.
+//!
+//! The records are resampled to one value per second and split by binary
+//! segmentation: each segment is split where the split most reduces the sum
+//! of squared deviations from the segment means, as long as both parts last
+//! the minimum duration and their means differ by the minimum change.
+
+use crate::analysis::SummaryStats;
+use crate::fit_file::FitFile;
+use crate::series::{RecordSeries, ResampleError};
+
+/// The record value intervals are detected from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntervalMetric {
+    Power,
+    /// Speed, for detecting intervals by pace.
+    Speed,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntervalOptions {
+    pub metric: IntervalMetric,
+    /// Seconds an interval lasts at least.
+    pub min_duration: u32,
+    /// Difference of the means of consecutive intervals, in watts or meters
+    /// per second.
+    pub min_change: f64,
+    /// Value above which an interval is work. Defaults to the midpoint of
+    /// the lowest and highest interval means.
+    pub threshold: Option<f64>,
+}
+
+impl IntervalOptions {
+    pub fn power() -> IntervalOptions {
+        IntervalOptions {
+            metric: IntervalMetric::Power,
+            min_duration: 30,
+            min_change: 30.0,
+            threshold: None,
+        }
+    }
+
+    pub fn pace() -> IntervalOptions {
+        IntervalOptions {
+            metric: IntervalMetric::Speed,
+            min_duration: 30,
+            min_change: 0.5,
+            threshold: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntervalKind {
+    Work,
+    Rest,
+}
+
+/// A detected interval with the summary of its records.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Interval {
+    pub kind: IntervalKind,
+    /// Seconds since the FIT epoch.
+    pub start_time: u32,
+    pub end_time: u32,
+    pub stats: SummaryStats,
+}
+
+impl Interval {
+    /// Seconds.
+    pub fn duration(&self) -> u32 {
+        self.end_time - self.start_time
+    }
+}
+
+// Sums of the values and their squares for the sum of squared deviations of
+// any range of values.
+struct PrefixSums {
+    sums: Vec<f64>,
+    squares: Vec<f64>,
+}
+
+impl PrefixSums {
+    fn new(values: &[f64]) -> PrefixSums {
+        let mut sums = vec![0.0];
+        let mut squares = vec![0.0];
+        for value in values {
+            sums.push(sums[sums.len() - 1] + value);
+            squares.push(squares[squares.len() - 1] + value * value);
+        }
+        PrefixSums { sums, squares }
+    }
+
+    fn mean(&self, start: usize, end: usize) -> f64 {
+        (self.sums[end] - self.sums[start]) / (end - start) as f64
+    }
+
+    fn cost(&self, start: usize, end: usize) -> f64 {
+        let sum = self.sums[end] - self.sums[start];
+        self.squares[end] - self.squares[start] - sum * sum / (end - start) as f64
+    }
+}
+
+// Indices where the values of `start..end` change, in order.
+fn change_points(
+    sums: &PrefixSums,
+    start: usize,
+    end: usize,
+    min_len: usize,
+    min_change: f64,
+) -> Vec<usize> {
+    if end - start < 2 * min_len {
+        return Vec::new();
+    }
+    let total = sums.cost(start, end);
+    let split = (start + min_len..=end - min_len)
+        .map(|k| (k, total - sums.cost(start, k) - sums.cost(k, end)))
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(k, _)| k)
+        .filter(|k| (sums.mean(start, *k) - sums.mean(*k, end)).abs() >= min_change);
+    match split {
+        Some(k) => {
+            let mut points = change_points(sums, start, k, min_len, min_change);
+            points.push(k);
+            points.extend(change_points(sums, k, end, min_len, min_change));
+            points
+        }
+        None => Vec::new(),
+    }
+}
+
+/// Work and rest intervals of the series in time order. Missing power or
+/// speed counts as zero. Consecutive intervals of the same kind are merged.
+pub fn detect_intervals(
+    series: &RecordSeries,
+    options: &IntervalOptions,
+) -> Result<Vec<Interval>, ResampleError> {
+    let resampled = series.resample_seconds()?;
+    if resampled.is_empty() {
+        return Ok(Vec::new());
+    }
+    let values = match options.metric {
+        IntervalMetric::Power => &resampled.power,
+        IntervalMetric::Speed => &resampled.speed,
+    }
+    .iter()
+    .map(|v| if v.is_nan() { 0.0 } else { *v })
+    .collect::<Vec<f64>>();
+    let sums = PrefixSums::new(&values);
+    let min_len = options.min_duration.max(1) as usize;
+    let mut bounds = vec![0];
+    bounds.extend(change_points(
+        &sums,
+        0,
+        values.len(),
+        min_len,
+        options.min_change,
+    ));
+    bounds.push(values.len());
+    let segments = bounds
+        .windows(2)
+        .map(|pair| (pair[0], pair[1], sums.mean(pair[0], pair[1])))
+        .collect::<Vec<(usize, usize, f64)>>();
+    let threshold = options.threshold.unwrap_or_else(|| {
+        let means = segments.iter().map(|(_, _, mean)| *mean);
+        let lowest = means.clone().fold(f64::INFINITY, f64::min);
+        let highest = means.fold(f64::NEG_INFINITY, f64::max);
+        (lowest + highest) / 2.0
+    });
+    let mut merged: Vec<(IntervalKind, usize, usize)> = Vec::new();
+    for (start, end, mean) in segments {
+        let kind = if mean >= threshold {
+            IntervalKind::Work
+        } else {
+            IntervalKind::Rest
+        };
+        match merged.last_mut() {
+            Some(last) if last.0 == kind => last.2 = end,
+            _ => merged.push((kind, start, end)),
+        }
+    }
+    let time = |idx: usize| resampled.timestamp[idx].unwrap();
+    Ok(merged
+        .into_iter()
+        .map(|(kind, start, end)| Interval {
+            kind,
+            start_time: time(start),
+            end_time: resampled
+                .timestamp
+                .get(end)
+                .map_or(time(end - 1), |t| t.unwrap()),
+            stats: SummaryStats::from_series(&resampled.slice(start..end)),
+        })
+        .collect())
+}
+
+impl FitFile {
+    /// Work and rest intervals of the record messages of the file, see
+    /// [`detect_intervals`].
+    pub fn intervals(&self, options: &IntervalOptions) -> Result<Vec<Interval>, ResampleError> {
+        detect_intervals(&self.record_series(), options)
+    }
+}
+
+#[cfg(test)]
+use crate::series::test_series;
+
+#[test]
+fn test_detect_intervals() {
+    let power = [100.0, 300.0, 120.0, 320.0];
+    let series = RecordSeries {
+        power: (0..240)
+            .map(|t| power[t / 60] + (t % 3) as f64 * 10.0)
+            .collect(),
+        heart_rate: (0..240).map(|t| 120.0 + (t / 60) as f64).collect(),
+        ..test_series(&(1000..1240).collect::<Vec<u32>>())
+    };
+    let intervals = detect_intervals(&series, &IntervalOptions::power()).unwrap();
+    let kinds = intervals
+        .iter()
+        .map(|i| i.kind)
+        .collect::<Vec<IntervalKind>>();
+    assert_eq!(
+        kinds,
+        vec![
+            IntervalKind::Rest,
+            IntervalKind::Work,
+            IntervalKind::Rest,
+            IntervalKind::Work
+        ]
+    );
+    assert_eq!(intervals[1].start_time, 1060);
+    assert_eq!(intervals[1].end_time, 1120);
+    assert_eq!(intervals[1].duration(), 60);
+    assert_eq!(intervals[1].stats.avg_power, Some(310.0));
+    assert_eq!(intervals[2].stats.max_heart_rate, Some(122.0));
+    assert_eq!(intervals[3].end_time, 1239);
+
+    // changes smaller than the minimum change are not split
+    let options = IntervalOptions {
+        min_change: 500.0,
+        ..IntervalOptions::power()
+    };
+    assert_eq!(detect_intervals(&series, &options).unwrap().len(), 1);
+}
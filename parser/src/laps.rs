@@ -0,0 +1,401 @@
+//! Regenerating the laps of an activity from its records, by auto lap
+//! triggers or at chosen split times, and re-encoding the file with the new
+//! laps and corrected session totals.
+
+use std::ops::Range;
+
+use crate::analysis::SummaryStats;
+use crate::encode::{encode, EncodeError};
+use crate::fit_file::FitFile;
+use crate::fit_messages::DataMessage;
+use crate::geo::haversine_distance;
+use crate::profile::messages::{Lap, Session};
+use crate::profile::{Event, EventType, LapTrigger, LAP_MESSAGE, RECORD_MESSAGE, SESSION_MESSAGE};
+use crate::series::{RecordSeries, SEMICIRCLES_TO_DEGREES};
+
+/// When a new lap starts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AutoLap {
+    /// Every this many meters.
+    Distance(f64),
+    /// Every this many seconds.
+    Time(u32),
+    /// On returning to within `radius` meters of a position in degrees,
+    /// after having been further away.
+    Position { lat: f64, long: f64, radius: f64 },
+}
+
+impl AutoLap {
+    fn lap_trigger(&self) -> u8 {
+        match self {
+            AutoLap::Distance(_) => LapTrigger::Distance as u8,
+            AutoLap::Time(_) => LapTrigger::Time as u8,
+            AutoLap::Position { .. } => LapTrigger::PositionLap as u8,
+        }
+    }
+}
+
+// The samples of `range`.
+fn semicircles(degrees: f64) -> Option<i32> {
+    (!degrees.is_nan()).then(|| (degrees / SEMICIRCLES_TO_DEGREES).round() as i32)
+}
+
+// The records of the file with a timestamp, in time order.
+fn timed_series(file: &FitFile) -> RecordSeries {
+    let series = file.record_series();
+    let mut order = (0..series.len())
+        .filter(|idx| series.timestamp[*idx].is_some())
+        .collect::<Vec<usize>>();
+    order.sort_by_key(|idx| series.timestamp[*idx]);
+    let column = |values: &[f64]| order.iter().map(|idx| values[*idx]).collect();
+    RecordSeries {
+        timestamp: order.iter().map(|idx| series.timestamp[*idx]).collect(),
+        position_lat: column(&series.position_lat),
+        position_long: column(&series.position_long),
+        altitude: column(&series.altitude),
+        heart_rate: column(&series.heart_rate),
+        power: column(&series.power),
+        cadence: column(&series.cadence),
+        speed: column(&series.speed),
+        distance: column(&series.distance),
+        temperature: column(&series.temperature),
+    }
+}
+
+// Lap message of the records in `range`, ending at the record `end`.
+fn lap(series: &RecordSeries, range: Range<usize>, end: usize, trigger: u8) -> Lap {
+    let start = range.start;
+    let stats = SummaryStats::from_series(&series.slice(range));
+    let start_time = series.timestamp[start];
+    let end_time = series.timestamp[end];
+    let elapsed = match (start_time, end_time) {
+        (Some(start), Some(end)) => Some((end - start) as f64),
+        _ => None,
+    };
+    let (start_distance, end_distance) = (series.distance[start], series.distance[end]);
+    Lap {
+        timestamp: end_time,
+        event: Some(Event::Lap as u8),
+        event_type: Some(EventType::Stop as u8),
+        start_time,
+        start_position_lat: semicircles(series.position_lat[start]),
+        start_position_long: semicircles(series.position_long[start]),
+        end_position_lat: semicircles(series.position_lat[end]),
+        end_position_long: semicircles(series.position_long[end]),
+        total_elapsed_time: elapsed,
+        total_timer_time: elapsed,
+        total_distance: (!start_distance.is_nan() && !end_distance.is_nan())
+            .then_some(end_distance - start_distance),
+        avg_speed: stats.avg_speed,
+        max_speed: stats.max_speed,
+        enhanced_avg_speed: stats.avg_speed,
+        enhanced_max_speed: stats.max_speed,
+        avg_heart_rate: stats.avg_heart_rate.map(|v| v.round() as u8),
+        max_heart_rate: stats.max_heart_rate.map(|v| v.round() as u8),
+        avg_power: stats.avg_power.map(|v| v.round() as u16),
+        max_power: stats.max_power.map(|v| v.round() as u16),
+        total_ascent: stats.total_ascent.map(|v| v.round() as u16),
+        total_descent: stats.total_descent.map(|v| v.round() as u16),
+        lap_trigger: Some(trigger),
+        ..Lap::default()
+    }
+}
+
+// Session message with its totals recomputed from the records of the
+// session, keeping its other fields.
+fn corrected_session(message: &DataMessage, series: &RecordSeries, laps: &[Lap]) -> DataMessage {
+    let mut session = Session::from_message(message);
+    let start = session.start_time.unwrap_or(0);
+    let end = session.total_elapsed_time.map_or(u32::MAX, |t| {
+        start.saturating_add(t.round().clamp(0.0, u32::MAX as f64) as u32)
+    });
+    let in_session = |t: Option<u32>| t.is_some_and(|t| (start..=end).contains(&t));
+    let records = series
+        .timestamp
+        .iter()
+        .position(|t| in_session(*t))
+        .map_or(0..0, |first| {
+            first
+                ..series
+                    .timestamp
+                    .iter()
+                    .rposition(|t| in_session(*t))
+                    .unwrap()
+                    + 1
+        });
+    let stats = SummaryStats::from_series(&series.slice(records));
+    session.first_lap_index = laps
+        .iter()
+        .position(|l| in_session(l.start_time))
+        .map(|idx| idx as u16);
+    session.num_laps = Some(laps.iter().filter(|l| in_session(l.start_time)).count() as u16);
+    session.total_distance = stats.total_distance.or(session.total_distance);
+    session.avg_speed = stats.avg_speed.or(session.avg_speed);
+    session.max_speed = stats.max_speed.or(session.max_speed);
+    session.enhanced_avg_speed = stats.avg_speed.or(session.enhanced_avg_speed);
+    session.enhanced_max_speed = stats.max_speed.or(session.enhanced_max_speed);
+    session.avg_heart_rate = stats
+        .avg_heart_rate
+        .map(|v| v.round() as u8)
+        .or(session.avg_heart_rate);
+    session.max_heart_rate = stats
+        .max_heart_rate
+        .map(|v| v.round() as u8)
+        .or(session.max_heart_rate);
+    session.avg_power = stats
+        .avg_power
+        .map(|v| v.round() as u16)
+        .or(session.avg_power);
+    session.max_power = stats
+        .max_power
+        .map(|v| v.round() as u16)
+        .or(session.max_power);
+    session.total_ascent = stats
+        .total_ascent
+        .map(|v| v.round() as u16)
+        .or(session.total_ascent);
+    session.total_descent = stats
+        .total_descent
+        .map(|v| v.round() as u16)
+        .or(session.total_descent);
+    let updated = session.to_message();
+    let mut corrected = message.clone();
+    corrected.fields.retain(|f| {
+        !updated
+            .fields
+            .iter()
+            .any(|u| u.field_definition_number == f.field_definition_number)
+    });
+    corrected.fields.extend(updated.fields);
+    corrected
+}
+
+impl FitFile {
+    /// Start times of the laps after the first one by the auto lap trigger.
+    pub fn auto_lap_splits(&self, auto_lap: &AutoLap) -> Vec<u32> {
+        let series = timed_series(self);
+        let mut splits = Vec::new();
+        let timestamps = series.timestamp.iter().flatten().copied();
+        match *auto_lap {
+            AutoLap::Distance(lap_distance) if lap_distance > 0.0 => {
+                let mut next = series.distance.iter().find(|d| !d.is_nan()).copied();
+                for (t, distance) in timestamps.zip(&series.distance) {
+                    if let Some(n) = next.filter(|_| !distance.is_nan()) {
+                        if *distance >= n + lap_distance {
+                            splits.push(t);
+                            next = Some(n + lap_distance * ((distance - n) / lap_distance).floor());
+                        }
+                    }
+                }
+            }
+            AutoLap::Time(lap_time) if lap_time > 0 => {
+                let mut start = None;
+                for t in timestamps {
+                    match start {
+                        None => start = Some(t),
+                        Some(s) if t >= s + lap_time => {
+                            splits.push(t);
+                            start = Some(t);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            AutoLap::Position { lat, long, radius } => {
+                let mut left = false;
+                let positions = series.position_lat.iter().zip(&series.position_long);
+                for (t, (p_lat, p_long)) in timestamps.zip(positions) {
+                    if p_lat.is_nan() || p_long.is_nan() {
+                        continue;
+                    }
+                    let near = haversine_distance(lat, long, *p_lat, *p_long) <= radius;
+                    if near && left {
+                        splits.push(t);
+                    }
+                    left = !near;
+                }
+            }
+            _ => {}
+        }
+        splits
+    }
+
+    /// Laps of the records split at the given start times, with their
+    /// summaries computed from the records. `lap_trigger` is the
+    /// `lap_trigger` value of the laps.
+    pub fn laps_at(&self, splits: &[u32], lap_trigger: u8) -> Vec<Lap> {
+        let series = timed_series(self);
+        if series.is_empty() {
+            return Vec::new();
+        }
+        let mut starts = vec![0];
+        for split in splits {
+            let idx = series.timestamp.partition_point(|t| *t < Some(*split));
+            if idx > *starts.last().unwrap() && idx < series.len() {
+                starts.push(idx);
+            }
+        }
+        let mut laps = starts
+            .iter()
+            .enumerate()
+            .map(|(lap_index, start)| {
+                let (end, range_end) = match starts.get(lap_index + 1) {
+                    Some(next) => (*next, *next),
+                    None => (series.len() - 1, series.len()),
+                };
+                lap(&series, *start..range_end, end, lap_trigger)
+            })
+            .collect::<Vec<Lap>>();
+        for (idx, lap) in laps.iter_mut().enumerate() {
+            lap.message_index = Some(idx as u16);
+        }
+        if let Some(last) = laps.last_mut() {
+            last.lap_trigger = Some(LapTrigger::SessionEnd as u8);
+        }
+        laps
+    }
+
+    /// Laps of the records by the auto lap trigger.
+    pub fn auto_laps(&self, auto_lap: &AutoLap) -> Vec<Lap> {
+        self.laps_at(&self.auto_lap_splits(auto_lap), auto_lap.lap_trigger())
+    }
+
+    /// Encodes the file with its lap messages replaced by `laps` and the lap
+    /// counts and totals of its sessions recomputed. Each lap is written
+    /// after its records. Developer fields are written with the descriptions
+    /// of the file.
+    pub fn encode_with_laps(&self, laps: &[Lap]) -> Result<Vec<u8>, EncodeError> {
+        let series = timed_series(self);
+        // position of the first lap not yet written
+        let mut pending = 0;
+        let mut messages = Vec::with_capacity(self.messages.len() + laps.len());
+        for message in &self.messages {
+            match message.global_message_number {
+                n if n == LAP_MESSAGE.number => continue,
+                n if n == SESSION_MESSAGE.number => {
+                    messages.extend(laps[pending..].iter().map(Lap::to_message));
+                    pending = laps.len();
+                    messages.push(corrected_session(message, &series, laps));
+                    continue;
+                }
+                n if n == RECORD_MESSAGE.number => {
+                    // a lap ends where the next one starts
+                    while let Some(next) = laps.get(pending + 1) {
+                        let ended = next
+                            .start_time
+                            .is_some_and(|next| message.timestamp() >= Some(next));
+                        if !ended {
+                            break;
+                        }
+                        messages.push(laps[pending].to_message());
+                        pending += 1;
+                    }
+                }
+                _ => {}
+            }
+            messages.push(message.clone());
+        }
+        messages.extend(laps[pending..].iter().map(Lap::to_message));
+        encode(&messages)
+    }
+
+    /// Encodes the file with its laps regenerated by the auto lap trigger,
+    /// see [`FitFile::encode_with_laps`].
+    pub fn relap(&self, auto_lap: &AutoLap) -> Result<Vec<u8>, EncodeError> {
+        self.encode_with_laps(&self.auto_laps(auto_lap))
+    }
+}
+
+#[cfg(test)]
+use crate::profile::messages::Record;
+
+#[test]
+fn test_relap_by_distance() {
+    let mut messages = (0..10u32)
+        .map(|idx| {
+            Record {
+                timestamp: Some(1000 + idx * 60),
+                distance: Some(idx as f64 * 250.0),
+                heart_rate: Some(120 + idx as u8),
+                ..Record::default()
+            }
+            .to_message()
+        })
+        .collect::<Vec<DataMessage>>();
+    messages.insert(
+        5,
+        Lap {
+            message_index: Some(0),
+            start_time: Some(1000),
+            total_distance: Some(1.0),
+            ..Lap::default()
+        }
+        .to_message(),
+    );
+    messages.push(
+        Session {
+            start_time: Some(1000),
+            total_elapsed_time: Some(540.0),
+            sport: Some(1),
+            num_laps: Some(1),
+            ..Session::default()
+        }
+        .to_message(),
+    );
+    let file = FitFile::parse(&encode(&messages).unwrap()).unwrap();
+
+    assert_eq!(
+        file.auto_lap_splits(&AutoLap::Distance(1000.0)),
+        vec![1240, 1480]
+    );
+    assert_eq!(file.auto_lap_splits(&AutoLap::Time(300)), vec![1300]);
+
+    let relapped = FitFile::parse(&file.relap(&AutoLap::Distance(1000.0)).unwrap()).unwrap();
+    let laps = relapped.laps();
+    assert_eq!(laps.len(), 3);
+    assert_eq!(laps[0].start_time, Some(1000));
+    assert_eq!(laps[0].timestamp, Some(1240));
+    assert_eq!(laps[0].total_distance, Some(1000.0));
+    assert_eq!(laps[0].avg_heart_rate, Some(122));
+    assert_eq!(laps[0].lap_trigger, Some(LapTrigger::Distance as u8));
+    assert_eq!(laps[2].total_distance, Some(250.0));
+    assert_eq!(laps[2].lap_trigger, Some(LapTrigger::SessionEnd as u8));
+    // the first lap is written after its records
+    let first_lap = relapped
+        .messages
+        .iter()
+        .position(|m| m.global_message_number == LAP_MESSAGE.number)
+        .unwrap();
+    assert_eq!(first_lap, 4);
+    let session = relapped.sessions().remove(0).session;
+    assert_eq!(session.num_laps, Some(3));
+    assert_eq!(session.first_lap_index, Some(0));
+    assert_eq!(session.total_distance, Some(2250.0));
+    assert_eq!(session.max_heart_rate, Some(129));
+    assert_eq!(session.sport, Some(1));
+
+    // laps are written in order, whatever their message indexes
+    let mut unindexed = file.auto_laps(&AutoLap::Distance(1000.0));
+    unindexed[0].message_index = None;
+    unindexed[1].message_index = Some(u16::MAX);
+    let relapped = FitFile::parse(&file.encode_with_laps(&unindexed).unwrap()).unwrap();
+    assert_eq!(relapped.laps().len(), 3);
+    assert_eq!(
+        relapped
+            .messages
+            .iter()
+            .position(|m| m.global_message_number == LAP_MESSAGE.number),
+        Some(4)
+    );
+
+    // a session ending past the last timestamp
+    let late = vec![Session {
+        start_time: Some(u32::MAX - 10),
+        total_elapsed_time: Some(1_000_000.0),
+        ..Session::default()
+    }
+    .to_message()];
+    let file = FitFile::parse(&encode(&late).unwrap()).unwrap();
+    let relapped = FitFile::parse(&file.encode_with_laps(&[]).unwrap()).unwrap();
+    assert_eq!(relapped.sessions().len(), 1);
+}
@@ -1,2 +1,72 @@
+pub mod accumulator;
+pub mod activity;
+pub mod analysis;
+#[cfg(feature = "batch")]
+pub mod batch;
+pub mod chain;
+pub mod cleaning;
+#[cfg(feature = "compression")]
+pub mod compression;
+pub mod conformance;
+pub mod connect_iq;
+pub mod course;
+pub mod crc;
+pub mod cycling;
+pub mod debug;
+pub mod decoder;
+pub mod dedup;
+pub mod developer;
+pub mod devices;
+pub mod diagnostics;
+#[cfg(feature = "dive")]
+pub mod dive;
+pub mod edit;
+pub mod efforts;
+pub mod elevation;
+pub mod encode;
+pub mod error;
+pub mod file_type;
+pub mod fit_file;
 pub mod fit_header;
-pub mod fit_records;
+pub mod fit_messages;
+mod fit_records;
+pub mod gears;
+pub mod geo;
+pub mod gpx;
+pub mod grade;
+pub mod hrv;
+pub mod index;
+pub mod intervals;
+pub mod laps;
+pub mod limits;
+pub mod lines;
+pub mod merge;
+#[cfg(feature = "monitoring")]
+pub mod monitoring;
+pub mod mtb;
+pub mod plausibility;
+pub mod power;
+pub mod privacy;
+#[rustfmt::skip]
+pub mod profile;
+pub mod raw;
+pub mod running;
+pub mod segment;
+#[cfg(feature = "sensor-data")]
+pub mod sensors;
+pub mod series;
+pub mod settings;
+#[cfg(feature = "monitoring")]
+pub mod sleep;
+pub mod sqlite;
+pub mod structure;
+pub mod swim;
+pub mod tail;
+pub mod time;
+pub mod timeline;
+pub mod timeshift;
+pub mod trace;
+pub mod workout;
+pub mod zones;
+
+pub use fit_file::parse_untrusted;
@@ -0,0 +1,50 @@
+use std::fmt;
+
+/// Caps on the resources a parse may use, for parsing files from untrusted
+/// sources. The default limits are the largest values a valid file can have,
+/// so they only need tightening when input size or memory use must be bounded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseLimits {
+    /// Largest data section size honored from the file header.
+    pub max_data_size: u32,
+    /// Largest number of fields, developer fields included, in a definition.
+    pub max_fields_per_definition: usize,
+    /// Largest number of data messages in a file.
+    pub max_messages: usize,
+    /// Largest length in bytes of a decoded string value, joined from the
+    /// parts of a field split over repeated definitions of it.
+    pub max_string_length: usize,
+}
+
+impl Default for ParseLimits {
+    fn default() -> Self {
+        ParseLimits {
+            max_data_size: u32::MAX,
+            max_fields_per_definition: 2 * u8::MAX as usize,
+            max_messages: usize::MAX,
+            // a definition of 255 fields of 255 bytes of one string
+            max_string_length: u8::MAX as usize * u8::MAX as usize,
+        }
+    }
+}
+
+/// The limit of [`ParseLimits`] that a file exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourceLimit {
+    DataSize,
+    FieldsPerDefinition,
+    Messages,
+    StringLength,
+}
+
+impl fmt::Display for ResourceLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::DataSize => "data size",
+            Self::FieldsPerDefinition => "fields per definition",
+            Self::Messages => "message count",
+            Self::StringLength => "string length",
+        };
+        write!(f, "{}", name)
+    }
+}
@@ -0,0 +1,446 @@
+//! Streaming output of the records of a file one line per record, in
+//! InfluxDB line protocol or as JSON Lines, for loading activities into
+//! time-series databases.
+//!
+//! Lines are tagged with the sport of the activity and the device that
+//! recorded it. Numeric fields of the records are written under their
+//! profile names, or `field_<number>` for fields not in the profile.
+//! Records without a timestamp are skipped.
+//!
+//! Chosen fields of any message can be written as CSV, TSV or JSON Lines
+//! rows with [`write_rows`].
+
+use std::io::{self, Write};
+
+use crate::fit_file::FitFile;
+use crate::fit_messages::{DataMessage, Value, TIMESTAMP_FIELD_NUMBER};
+use crate::profile::messages::{FileId, Session};
+use crate::profile::{
+    FILE_ID_MESSAGE, MANUFACTURER_TYPE, RECORD_MESSAGE, SESSION_MESSAGE, SPORT_TYPE,
+};
+use crate::time::unix_seconds;
+
+// `device_index` of the device that created the file.
+const CREATOR_DEVICE_INDEX: u8 = 0;
+
+/// Tags of the lines of a file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LineTags {
+    /// Profile name of the sport of the first session, such as `running`.
+    pub sport: Option<String>,
+    /// Product name of the device that created the file, or its
+    /// manufacturer's name.
+    pub device: Option<String>,
+}
+
+impl LineTags {
+    fn pairs(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        [("sport", &self.sport), ("device", &self.device)]
+            .into_iter()
+            .filter_map(|(key, value)| Some((key, value.as_deref()?)))
+    }
+}
+
+impl FitFile {
+    /// Tags of the lines of the file.
+    pub fn line_tags(&self) -> LineTags {
+        let sport = self
+            .typed_messages(SESSION_MESSAGE.number, Session::from_message)
+            .find_map(|s| s.sport)
+            .and_then(|sport| SPORT_TYPE.value_name(sport.into()))
+            .map(String::from);
+        let creator = self
+            .devices()
+            .into_iter()
+            .find(|d| d.device_index == Some(CREATOR_DEVICE_INDEX));
+        let manufacturer = || {
+            self.typed_messages(FILE_ID_MESSAGE.number, FileId::from_message)
+                .find_map(|f| f.manufacturer)
+                .and_then(|m| MANUFACTURER_TYPE.value_name(m.into()))
+                .map(String::from)
+        };
+        let device = creator
+            .and_then(|d| d.product_name.or(d.manufacturer_name.map(String::from)))
+            .or_else(manufacturer);
+        LineTags { sport, device }
+    }
+}
+
+// Numeric fields of a record message other than the timestamp.
+fn numeric_fields(message: &DataMessage) -> impl Iterator<Item = (String, &Value)> {
+    message
+        .fields
+        .iter()
+        .filter(|f| f.field_definition_number != TIMESTAMP_FIELD_NUMBER)
+        .filter(|f| f.value.as_f64().is_some_and(f64::is_finite))
+        .map(|f| {
+            let name = f
+                .name
+                .map(String::from)
+                .unwrap_or_else(|| format!("field_{}", f.field_definition_number));
+            (name, &f.value)
+        })
+}
+
+// Records with their Unix timestamps in seconds.
+fn timed_records(file: &FitFile) -> impl Iterator<Item = (u64, &DataMessage)> {
+    file.messages
+        .iter()
+        .filter(|m| m.global_message_number == RECORD_MESSAGE.number)
+        .filter_map(|m| Some((unix_seconds(m.timestamp()?) as u64, m)))
+}
+
+fn is_float(value: &Value) -> bool {
+    matches!(value, Value::F32(_) | Value::F64(_))
+}
+
+// Escapes commas, equals signs and spaces of line protocol keys and tag
+// values, and only commas and spaces of measurements.
+fn escape_influx(text: &str, equals: bool) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c == ',' || c == ' ' || (equals && c == '=') || c == '\\' {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// The text as a quoted JSON string.
+pub fn json_string(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len() + 2);
+    escaped.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+// Number literal of a finite numeric value.
+fn number(value: &Value) -> String {
+    if is_float(value) {
+        format!("{:?}", value.as_f64().unwrap())
+    } else {
+        match value.as_i64() {
+            Some(v) => v.to_string(),
+            None => value.as_u64().unwrap().to_string(),
+        }
+    }
+}
+
+/// Writes the records of the file in InfluxDB line protocol under the
+/// `measurement`, with nanosecond timestamps. Integer fields are written as
+/// integers. Returns the number of lines written.
+pub fn write_influx_lines<W: Write>(
+    file: &FitFile,
+    measurement: &str,
+    mut writer: W,
+) -> io::Result<usize> {
+    let mut prefix = escape_influx(measurement, false);
+    for (key, value) in file.line_tags().pairs() {
+        prefix.push_str(&format!(",{}={}", key, escape_influx(value, true)));
+    }
+    let mut lines = 0;
+    for (time, record) in timed_records(file) {
+        let fields = numeric_fields(record)
+            .map(|(name, value)| {
+                // integers above i64::MAX are unsigned integers
+                let suffix = match value.as_i64() {
+                    _ if is_float(value) => "",
+                    Some(_) => "i",
+                    None => "u",
+                };
+                format!("{}={}{}", escape_influx(&name, true), number(value), suffix)
+            })
+            .collect::<Vec<String>>();
+        // line protocol requires at least one field
+        if fields.is_empty() {
+            continue;
+        }
+        writeln!(
+            writer,
+            "{} {} {}",
+            prefix,
+            fields.join(","),
+            time * 1_000_000_000
+        )?;
+        lines += 1;
+    }
+    Ok(lines)
+}
+
+/// Writes the records of the file as JSON Lines: one object per record with
+/// the Unix `time` in seconds, the tags and the fields. Returns the number
+/// of lines written.
+pub fn write_json_lines<W: Write>(file: &FitFile, mut writer: W) -> io::Result<usize> {
+    let tags = file
+        .line_tags()
+        .pairs()
+        .map(|(key, value)| format!("{}:{}", json_string(key), json_string(value)))
+        .collect::<Vec<String>>();
+    let mut lines = 0;
+    for (time, record) in timed_records(file) {
+        let mut members = vec![format!("\"time\":{}", time)];
+        members.extend(tags.iter().cloned());
+        members.extend(
+            numeric_fields(record)
+                .map(|(name, value)| format!("{}:{}", json_string(&name), number(value))),
+        );
+        writeln!(writer, "{{{}}}", members.join(","))?;
+        lines += 1;
+    }
+    Ok(lines)
+}
+
+/// Format of the rows written by [`write_rows`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RowFormat {
+    Csv,
+    Tsv,
+    JsonLines,
+}
+
+impl RowFormat {
+    /// The format by name: `csv`, `tsv` or `jsonl`.
+    pub fn from_name(name: &str) -> Option<RowFormat> {
+        match name {
+            "csv" => Some(RowFormat::Csv),
+            "tsv" => Some(RowFormat::Tsv),
+            "jsonl" => Some(RowFormat::JsonLines),
+            _ => None,
+        }
+    }
+
+    // Text of a field of a row, empty or `null` for missing fields.
+    fn cell(&self, value: Option<&Value>) -> String {
+        match (self, value) {
+            (RowFormat::JsonLines, None) => "null".to_string(),
+            (RowFormat::JsonLines, Some(value))
+                if value.as_f64().is_some_and(f64::is_finite)
+                    && !matches!(value, Value::Enum(_)) =>
+            {
+                number(value)
+            }
+            (RowFormat::JsonLines, Some(value)) => json_string(&value.to_string()),
+            (_, None) => String::new(),
+            (RowFormat::Csv, Some(value)) => {
+                let text = value.to_string();
+                if text.contains([',', '"', '\n', '\r']) {
+                    format!("\"{}\"", text.replace('"', "\"\""))
+                } else {
+                    text
+                }
+            }
+            (RowFormat::Tsv, Some(value)) => value.to_string().replace(['\t', '\n', '\r'], " "),
+        }
+    }
+}
+
+/// Rows of [`write_rows`] to write: the rows of the messages timestamped
+/// from `from` to `to`, both included, and of those the `first` rows and
+/// then the `last` rows. Bounds that are `None` are open. Messages without a
+/// timestamp are outside of the window when `from` or `to` is set.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RowWindow {
+    pub first: Option<usize>,
+    pub last: Option<usize>,
+    /// Seconds since the FIT epoch.
+    pub from: Option<u32>,
+    /// Seconds since the FIT epoch.
+    pub to: Option<u32>,
+}
+
+impl RowWindow {
+    fn contains_time(&self, message: &DataMessage) -> bool {
+        if self.from.is_none() && self.to.is_none() {
+            return true;
+        }
+        message.timestamp().is_some_and(|t| {
+            self.from.is_none_or(|from| t >= from) && self.to.is_none_or(|to| t <= to)
+        })
+    }
+
+    /// The messages within the window, in order.
+    pub fn select<'a, I>(&self, messages: I) -> Vec<&'a DataMessage>
+    where
+        I: IntoIterator<Item = &'a DataMessage>,
+    {
+        let mut selected = messages
+            .into_iter()
+            .filter(|m| self.contains_time(m))
+            .collect::<Vec<&DataMessage>>();
+        if let Some(first) = self.first {
+            selected.truncate(first);
+        }
+        if let Some(last) = self.last {
+            selected.drain(..selected.len().saturating_sub(last));
+        }
+        selected
+    }
+}
+
+/// Writes the named fields of the messages with the global message number
+/// within the window, one row per message, with a header row of the field
+/// names in CSV and TSV. Fields are looked up by
+/// [`DataMessage::field_by_name`], and messages with none of the fields are
+/// skipped. Returns the number of rows written, without the header.
+pub fn write_rows<W: Write>(
+    messages: &[DataMessage],
+    global_message_number: u16,
+    fields: &[&str],
+    window: &RowWindow,
+    format: RowFormat,
+    mut writer: W,
+) -> io::Result<usize> {
+    match format {
+        RowFormat::Csv => writeln!(writer, "{}", fields.join(","))?,
+        RowFormat::Tsv => writeln!(writer, "{}", fields.join("\t"))?,
+        RowFormat::JsonLines => {}
+    }
+    let rows = window
+        .select(
+            messages
+                .iter()
+                .filter(|m| m.global_message_number == global_message_number)
+                .filter(|m| fields.iter().any(|name| m.field_by_name(name).is_some())),
+        )
+        .into_iter()
+        .map(|message| {
+            fields
+                .iter()
+                .map(|name| message.field_by_name(name).map(|f| &f.value))
+                .collect::<Vec<Option<&Value>>>()
+        })
+        .collect::<Vec<Vec<Option<&Value>>>>();
+    for values in &rows {
+        let cells = values.iter().map(|value| format.cell(*value));
+        match format {
+            RowFormat::Csv => writeln!(writer, "{}", cells.collect::<Vec<String>>().join(","))?,
+            RowFormat::Tsv => writeln!(writer, "{}", cells.collect::<Vec<String>>().join("\t"))?,
+            RowFormat::JsonLines => {
+                let members = fields
+                    .iter()
+                    .zip(cells)
+                    .map(|(name, cell)| format!("{}:{}", json_string(name), cell))
+                    .collect::<Vec<String>>();
+                writeln!(writer, "{{{}}}", members.join(","))?;
+            }
+        }
+    }
+    Ok(rows.len())
+}
+
+#[cfg(test)]
+use crate::fit_file::{test_fit_file, test_message};
+
+#[test]
+fn test_line_output() {
+    let file = test_fit_file(vec![
+        test_message(
+            FILE_ID_MESSAGE.number,
+            vec![(1, "manufacturer", Value::U16(1))],
+        ),
+        test_message(
+            RECORD_MESSAGE.number,
+            vec![
+                (253, "timestamp", Value::U32(1000)),
+                (3, "heart_rate", Value::U8(150)),
+                (6, "speed", Value::F64(3.5)),
+            ],
+        ),
+        test_message(RECORD_MESSAGE.number, vec![(3, "heart_rate", Value::U8(1))]),
+        test_message(SESSION_MESSAGE.number, vec![(5, "sport", Value::U8(1))]),
+    ]);
+    assert_eq!(
+        file.line_tags(),
+        LineTags {
+            sport: Some("running".to_string()),
+            device: Some("garmin".to_string()),
+        }
+    );
+
+    let mut influx = Vec::new();
+    assert_eq!(
+        write_influx_lines(&file, "my activity", &mut influx).unwrap(),
+        1
+    );
+    assert_eq!(
+        String::from_utf8(influx).unwrap(),
+        "my\\ activity,sport=running,device=garmin \
+         heart_rate=150i,speed=3.5 631066600000000000\n"
+    );
+
+    let mut json = Vec::new();
+    assert_eq!(write_json_lines(&file, &mut json).unwrap(), 1);
+    assert_eq!(
+        String::from_utf8(json).unwrap(),
+        "{\"time\":631066600,\"sport\":\"running\",\"device\":\"garmin\",\
+         \"heart_rate\":150,\"speed\":3.5}\n"
+    );
+
+    let mut csv = Vec::new();
+    let fields = ["timestamp", "heart_rate", "speed"];
+    let all = RowWindow::default();
+    let records = RECORD_MESSAGE.number;
+    assert_eq!(
+        write_rows(
+            &file.messages,
+            records,
+            &fields,
+            &all,
+            RowFormat::Csv,
+            &mut csv
+        )
+        .unwrap(),
+        2
+    );
+    assert_eq!(
+        String::from_utf8(csv).unwrap(),
+        "timestamp,heart_rate,speed\n1000,150,3.5\n,1,\n"
+    );
+    let mut rows = Vec::new();
+    let format = RowFormat::from_name("jsonl").unwrap();
+    write_rows(&file.messages, records, &fields, &all, format, &mut rows).unwrap();
+    assert_eq!(
+        String::from_utf8(rows).unwrap().lines().nth(1),
+        Some("{\"timestamp\":null,\"heart_rate\":1,\"speed\":null}")
+    );
+    let last = RowWindow {
+        last: Some(1),
+        ..RowWindow::default()
+    };
+    let timed = RowWindow {
+        from: Some(1000),
+        to: Some(1000),
+        ..RowWindow::default()
+    };
+    let count = |window| {
+        write_rows(
+            &file.messages,
+            records,
+            &["heart_rate"],
+            window,
+            format,
+            io::sink(),
+        )
+    };
+    assert_eq!(count(&last).unwrap(), 1);
+    assert_eq!(count(&timed).unwrap(), 1);
+}
+
+#[test]
+fn test_number() {
+    assert_eq!(number(&Value::I64(-(1 << 53) - 1)), "-9007199254740993");
+    assert_eq!(number(&Value::U64(u64::MAX)), "18446744073709551615");
+    assert_eq!(number(&Value::F32(0.5)), "0.5");
+}
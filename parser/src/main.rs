@@ -1,26 +1,488 @@
-use byteorder::{ByteOrder, LittleEndian};
-use fit_parser::fit_header::{fit_crc, parse_fit_header_from_data};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::{Duration, SystemTime};
 
-use std::io::Read;
+use fit_parser::analysis::ActivitySummary;
+use fit_parser::debug::annotate_range;
+use fit_parser::decoder::{CrcPolicy, FitDecoder};
+use fit_parser::fit_header::format_protocol_version;
+use fit_parser::gpx::write_gpx;
+use fit_parser::lines::{json_string, write_json_lines, write_rows, RowFormat, RowWindow};
+use fit_parser::profile::{message_info, message_info_by_name, SPORT_TYPE};
+use fit_parser::time::format_timestamp;
 
-fn main() {
-    let input_path = "../7427193981_ACTIVITY.fit";
-    let mut input_file = std::fs::File::open(input_path).unwrap();
-    let data_size = input_file.metadata().unwrap().len() as usize;
-    println!("File size: {}", data_size);
-    let mut fit_content: Vec<u8> = vec![0; data_size];
+const USAGE: &str =
+    "Usage: fit_parser <file> [--first <n>] [--last <n>] [--from <timestamp>] [--to <timestamp>]
+       fit_parser grep <file> --mesg <message> [--fields <field>,...] [--format csv|tsv|jsonl]
+                       [--first <n>] [--last <n>] [--from <timestamp>] [--to <timestamp>]
+       fit_parser inspect <file> [--offset <offset>] [--len <bytes>]
+       fit_parser watch <dir> --to gpx|jsonl --out <dir> [--interval <seconds>]
+       fit_parser stats <file>... [--json]
+
+Timestamps are seconds since the FIT epoch, as printed by grep. Offsets
+and lengths can be hexadecimal with a 0x prefix.";
+
+// Bytes inspect prints without `--len`.
+const DEFAULT_INSPECT_LEN: usize = 256;
+
+// Seconds between the directory scans of watch without `--interval`.
+const DEFAULT_WATCH_INTERVAL: u64 = 2;
+
+fn exit_with_usage(message: &str) -> ! {
+    eprintln!("{}\n{}", message, USAGE);
+    process::exit(2);
+}
+
+fn parse_number<T: std::str::FromStr>(option: &str, value: &str) -> T {
+    value
+        .parse()
+        .unwrap_or_else(|_| exit_with_usage(&format!("Invalid {} {}", option, value)))
+}
+
+fn parse_size(option: &str, value: &str) -> usize {
+    match value.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16)
+            .unwrap_or_else(|_| exit_with_usage(&format!("Invalid {} {}", option, value))),
+        None => parse_number(option, value),
+    }
+}
+
+fn read_file(path: &str) -> Vec<u8> {
+    std::fs::read(path).unwrap_or_else(|e| {
+        eprintln!("Reading {} failed: {}", path, e);
+        process::exit(1);
+    })
+}
+
+fn set_window(window: &mut RowWindow, option: &str, value: &str) {
+    match option {
+        "--first" => window.first = Some(parse_number(option, value)),
+        "--last" => window.last = Some(parse_number(option, value)),
+        "--from" => window.from = Some(parse_number(option, value)),
+        _ => window.to = Some(parse_number(option, value)),
+    }
+}
+
+// Prints the named fields of the messages of a file, such as
+// `grep activity.fit --mesg record --fields timestamp,power,heart_rate`.
+// Without `--fields`, all fields of the message in the profile are printed.
+fn grep(args: &[String]) {
+    let mut path = None;
+    let mut mesg = None;
+    let mut fields = None;
+    let mut format = RowFormat::Csv;
+    let mut window = RowWindow::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .unwrap_or_else(|| exit_with_usage(&format!("Missing value of {}", arg)))
+        };
+        match arg.as_str() {
+            "--mesg" => mesg = Some(value()),
+            "--fields" => fields = Some(value()),
+            "--format" => {
+                let name = value();
+                format = RowFormat::from_name(name)
+                    .unwrap_or_else(|| exit_with_usage(&format!("Unknown format {}", name)));
+            }
+            "--first" | "--last" | "--from" | "--to" => set_window(&mut window, arg, value()),
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(arg),
+            _ => exit_with_usage(&format!("Unexpected argument {}", arg)),
+        }
+    }
+    let path = path.unwrap_or_else(|| exit_with_usage("Missing file"));
+    let mesg = mesg.unwrap_or_else(|| exit_with_usage("Missing --mesg"));
+    let global_message_number = match mesg.parse::<u16>() {
+        Ok(number) => number,
+        Err(_) => message_info_by_name(mesg)
+            .map(|info| info.number)
+            .unwrap_or_else(|| exit_with_usage(&format!("Unknown message {}", mesg))),
+    };
+    let info = message_info(global_message_number);
+    let fields = match (fields, info) {
+        (Some(fields), _) => fields.split(',').collect::<Vec<&str>>(),
+        (None, Some(info)) => info.fields.iter().map(|f| f.name).collect(),
+        (None, None) => exit_with_usage("Messages not in the profile need --fields"),
+    };
 
-    input_file.read_exact(&mut *fit_content).unwrap();
+    let data = read_file(path);
+    let decoder = FitDecoder::builder()
+        .messages([global_message_number])
+        .build();
+    let messages = decoder
+        .decode(&data)
+        .unwrap_or_else(|e| {
+            eprintln!("Parsing {} failed: {}", path, e);
+            process::exit(1);
+        })
+        .messages;
+    let stdout = io::stdout().lock();
+    match write_rows(
+        &messages,
+        global_message_number,
+        &fields,
+        &window,
+        format,
+        stdout,
+    ) {
+        // output piped to a command that quit, such as `head`
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {}
+        Err(e) => {
+            eprintln!("Writing rows failed: {}", e);
+            process::exit(1);
+        }
+        Ok(_) => {}
+    }
+}
+
+// Prints the annotated hexdump of the bytes from `--offset`, such as
+// `inspect activity.fit --offset 0x1A0 --len 256`.
+fn inspect(args: &[String]) {
+    let mut path = None;
+    let mut offset = 0;
+    let mut len = DEFAULT_INSPECT_LEN;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .unwrap_or_else(|| exit_with_usage(&format!("Missing value of {}", arg)))
+        };
+        match arg.as_str() {
+            "--offset" => offset = parse_size(arg, value()),
+            "--len" => len = parse_size(arg, value()),
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(arg),
+            _ => exit_with_usage(&format!("Unexpected argument {}", arg)),
+        }
+    }
+    let path = path.unwrap_or_else(|| exit_with_usage("Missing file"));
+    let data = read_file(path);
+    print!(
+        "{}",
+        annotate_range(&data, offset..offset.saturating_add(len))
+    );
+}
+
+#[derive(Clone, Copy)]
+enum ExportFormat {
+    Gpx,
+    JsonLines,
+}
+
+impl ExportFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Gpx => "gpx",
+            ExportFormat::JsonLines => "jsonl",
+        }
+    }
+}
 
-    let header = parse_fit_header_from_data(&fit_content);
+// Size and modification time of a file, which change while it is copied.
+type FileVersion = (u64, SystemTime);
+
+fn file_version(path: &Path) -> io::Result<FileVersion> {
+    let metadata = fs::metadata(path)?;
+    Ok((metadata.len(), metadata.modified()?))
+}
+
+fn export(path: &Path, out_path: &Path, format: ExportFormat) -> io::Result<()> {
+    let fit_file = FitDecoder::new()
+        .decode(&fs::read(path)?)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let mut writer = BufWriter::new(File::create(out_path)?);
+    match format {
+        ExportFormat::Gpx => write_gpx(&fit_file, &mut writer)?,
+        ExportFormat::JsonLines => write_json_lines(&fit_file, &mut writer)?,
+    };
+    writer.flush()
+}
+
+// Converts the FIT files of a directory, such as a mounted watch, as they
+// appear. A file is converted once its size and modification time are the
+// same in two scans in a row, so files still being copied are not. Files
+// whose output is newer than them are not converted again.
+fn watch(args: &[String]) {
+    let mut dir = None;
+    let mut format = None;
+    let mut out_dir = None;
+    let mut interval = DEFAULT_WATCH_INTERVAL;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .unwrap_or_else(|| exit_with_usage(&format!("Missing value of {}", arg)))
+        };
+        match arg.as_str() {
+            "--to" => {
+                format = match value().as_str() {
+                    "gpx" => Some(ExportFormat::Gpx),
+                    "jsonl" => Some(ExportFormat::JsonLines),
+                    name => exit_with_usage(&format!("Unknown format {}", name)),
+                }
+            }
+            "--out" => out_dir = Some(Path::new(value())),
+            "--interval" => interval = parse_number(arg, value()),
+            _ if dir.is_none() && !arg.starts_with("--") => dir = Some(Path::new(arg)),
+            _ => exit_with_usage(&format!("Unexpected argument {}", arg)),
+        }
+    }
+    let dir = dir.unwrap_or_else(|| exit_with_usage("Missing directory"));
+    let format = format.unwrap_or_else(|| exit_with_usage("Missing --to"));
+    let out_dir = out_dir.unwrap_or_else(|| exit_with_usage("Missing --out"));
+    if let Err(e) = fs::create_dir_all(out_dir) {
+        eprintln!("Creating {} failed: {}", out_dir.display(), e);
+        process::exit(1);
+    }
+
+    let mut seen: HashMap<PathBuf, FileVersion> = HashMap::new();
+    let mut converted: HashMap<PathBuf, FileVersion> = HashMap::new();
+    loop {
+        let entries = fs::read_dir(dir).unwrap_or_else(|e| {
+            eprintln!("Reading {} failed: {}", dir.display(), e);
+            process::exit(1);
+        });
+        for path in entries.filter_map(|entry| Some(entry.ok()?.path())) {
+            let is_fit = path
+                .extension()
+                .is_some_and(|e| e.eq_ignore_ascii_case("fit"));
+            let version = match file_version(&path) {
+                Ok(version) if is_fit && path.is_file() => version,
+                _ => continue,
+            };
+            let stable = seen.insert(path.clone(), version) == Some(version);
+            if !stable || converted.get(&path) == Some(&version) {
+                continue;
+            }
+            converted.insert(path.clone(), version);
+            // a file of the same name in the output directory
+            let out_path = out_dir
+                .join(path.file_stem().unwrap_or_default())
+                .with_extension(format.extension());
+            if file_version(&out_path).is_ok_and(|(_, modified)| modified >= version.1) {
+                continue;
+            }
+            match export(&path, &out_path, format) {
+                Ok(()) => println!("{} -> {}", path.display(), out_path.display()),
+                Err(e) => eprintln!("Converting {} failed: {}", path.display(), e),
+            }
+        }
+        thread::sleep(Duration::from_secs(interval));
+    }
+}
 
-    let file_crc_slice = &fit_content[data_size - 2..];
-    let file_crc = LittleEndian::read_u16(file_crc_slice);
-    let calculated_file_crc = fit_crc(&fit_content[..data_size - 2], 0);
+fn sport_name(summary: &ActivitySummary) -> Option<&'static str> {
+    SPORT_TYPE.value_name(summary.sport? as u32)
+}
+
+fn format_duration(seconds: u32) -> String {
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+fn print_summary(name: &str, summary: &ActivitySummary) {
+    let or_dash = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+    let number = |value: Option<f64>| or_dash(value.map(|v| format!("{:.0}", v)));
+    let stats = &summary.stats;
+    println!("{}", name);
+    println!("  sport: {}", sport_name(summary).unwrap_or("-"));
+    println!(
+        "  start: {}",
+        or_dash(summary.start_time.map(format_timestamp))
+    );
+    println!(
+        "  duration: {} (timer {})",
+        format_duration(summary.elapsed_time),
+        format_duration(summary.timer_time)
+    );
     println!(
-        "CRC in file: {}, calculated CRC: {}",
-        file_crc, calculated_file_crc
+        "  distance: {} km",
+        or_dash(stats.total_distance.map(|d| format!("{:.2}", d / 1000.0)))
     );
-    assert_eq!(file_crc, calculated_file_crc);
-    println!("Header: {:#?}", header);
+    println!(
+        "  heart rate: avg {} max {} bpm",
+        number(stats.avg_heart_rate),
+        number(stats.max_heart_rate)
+    );
+    println!(
+        "  power: avg {} max {} W",
+        number(stats.avg_power),
+        number(stats.max_power)
+    );
+    println!("  ascent: {} m", number(stats.total_ascent));
+}
+
+fn summary_json(summary: &ActivitySummary) -> String {
+    let number = |value: Option<f64>| match value.filter(|v| v.is_finite()) {
+        Some(value) => format!("{}", value),
+        None => "null".to_string(),
+    };
+    let text = |value: Option<String>| value.map_or("null".to_string(), |v| json_string(&v));
+    let stats = &summary.stats;
+    format!(
+        "\"sport\":{},\"start_time\":{},\"elapsed_time\":{},\"timer_time\":{},\
+         \"distance\":{},\"avg_heart_rate\":{},\"max_heart_rate\":{},\
+         \"avg_power\":{},\"max_power\":{},\"ascent\":{}",
+        text(sport_name(summary).map(String::from)),
+        text(summary.start_time.map(format_timestamp)),
+        summary.elapsed_time,
+        summary.timer_time,
+        number(stats.total_distance),
+        number(stats.avg_heart_rate),
+        number(stats.max_heart_rate),
+        number(stats.avg_power),
+        number(stats.max_power),
+        number(stats.total_ascent)
+    )
+}
+
+// Prints the summaries of the files and, for several files, their total.
+// Files that can not be parsed are reported and left out.
+fn stats(args: &[String]) {
+    let json = args.iter().any(|a| a == "--json");
+    let paths = args
+        .iter()
+        .filter(|a| *a != "--json")
+        .collect::<Vec<&String>>();
+    if paths.is_empty() {
+        exit_with_usage("Missing file");
+    }
+    let mut failed = false;
+    let mut summaries = Vec::new();
+    for path in paths {
+        match FitDecoder::new().decode(&read_file(path)) {
+            Ok(fit_file) => summaries.push((path, ActivitySummary::from_file(&fit_file))),
+            Err(e) => {
+                eprintln!("Parsing {} failed: {}", path, e);
+                failed = true;
+            }
+        }
+    }
+    let total = ActivitySummary::total(
+        &summaries
+            .iter()
+            .map(|(_, s)| s.clone())
+            .collect::<Vec<ActivitySummary>>(),
+    );
+    if json {
+        let files = summaries
+            .iter()
+            .map(|(path, s)| format!("{{\"path\":{},{}}}", json_string(path), summary_json(s)))
+            .collect::<Vec<String>>();
+        println!(
+            "{{\"files\":[{}],\"total\":{{{}}}}}",
+            files.join(","),
+            summary_json(&total)
+        );
+    } else {
+        for (path, summary) in &summaries {
+            print_summary(path, summary);
+        }
+        if summaries.len() > 1 {
+            print_summary(&format!("total of {} files", summaries.len()), &total);
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+}
+
+// Prints the header and the diagnostics of a file and, with any of
+// `--first`, `--last`, `--from` or `--to`, dumps the messages of the window.
+fn summary(args: &[String]) {
+    let mut path = None;
+    let mut window = RowWindow::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--first" | "--last" | "--from" | "--to" => {
+                let value = args
+                    .next()
+                    .unwrap_or_else(|| exit_with_usage(&format!("Missing value of {}", arg)));
+                set_window(&mut window, arg, value);
+            }
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(arg.as_str()),
+            _ => exit_with_usage(&format!("Unexpected argument {}", arg)),
+        }
+    }
+    let path = path.unwrap_or_else(|| exit_with_usage("Missing file"));
+    let fit_file = FitDecoder::builder()
+        .crc_policy(CrcPolicy::Enforce)
+        .build()
+        .decode(&read_file(path))
+        .unwrap_or_else(|e| {
+            eprintln!("Parsing {} failed: {}", path, e);
+            process::exit(1);
+        });
+    println!(
+        "Protocol version: {}",
+        format_protocol_version(fit_file.header.protocol_version())
+    );
+    println!("Data messages: {}", fit_file.messages.len());
+    for diagnostic in &fit_file.diagnostics {
+        println!(
+            "{} {}: {}",
+            diagnostic.kind.code(),
+            diagnostic.kind.severity(),
+            diagnostic
+        );
+    }
+    if window != RowWindow::default() {
+        for message in window.select(&fit_file.messages) {
+            let fields = message
+                .fields
+                .iter()
+                .map(|f| match f.name {
+                    Some(name) => format!("{}={}", name, f.value),
+                    None => format!("{}={}", f.field_definition_number, f.value),
+                })
+                .collect::<Vec<String>>();
+            match message.name() {
+                Some(name) => println!("{} {}", name, fields.join(" ")),
+                None => println!("{} {}", message.global_message_number, fields.join(" ")),
+            }
+        }
+    }
+}
+
+// Writes the parse events of the `trace` feature to stderr.
+#[cfg(feature = "trace")]
+struct StderrLogger;
+
+#[cfg(feature = "trace")]
+impl log::Log for StderrLogger {
+    fn enabled(&self, _: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        eprintln!("{} {}: {}", record.level(), record.target(), record.args());
+    }
+
+    fn flush(&self) {}
+}
+
+fn main() {
+    #[cfg(feature = "trace")]
+    {
+        log::set_logger(&StderrLogger).unwrap();
+        log::set_max_level(log::LevelFilter::Debug);
+    }
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    match args.first().map(String::as_str) {
+        Some("grep") => grep(&args[1..]),
+        Some("inspect") => inspect(&args[1..]),
+        Some("watch") => watch(&args[1..]),
+        Some("stats") => stats(&args[1..]),
+        Some("-h" | "--help") => println!("{}", USAGE),
+        _ => summary(&args),
+    }
 }
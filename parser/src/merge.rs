@@ -0,0 +1,344 @@
+//! Merging record fields recorded by another device into a file.
+
+use std::fmt;
+
+use crate::crc::FitCrc;
+use crate::edit::write_bits;
+use crate::error::FitError;
+use crate::fit_file::FitFile;
+use crate::fit_header::try_parse_fit_header_from_data;
+use crate::fit_records::{
+    base_type_info_by_name, BaseType, BaseTypeInfo, DefinitionRecord, Endianness, Record,
+    RecordIterator, LOCAL_MESSAGE_TYPE_COUNT,
+};
+use crate::profile::{type_info, FieldInfo, RECORD_MESSAGE};
+
+/// Seconds a secondary sample may be away from a primary record to be merged
+/// into it.
+pub const MAX_ALIGNMENT_GAP: u32 = 2;
+
+/// Why merging two files failed.
+#[derive(Debug)]
+pub enum MergeError {
+    Parse(FitError),
+    /// The field is not a record field or not a single numeric value.
+    UnsupportedField(String),
+    /// The definition record at the offset can not take the merged fields,
+    /// a definition has at most 255 fields.
+    TooManyFields {
+        offset: usize,
+    },
+}
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parse(e) => write!(f, "Parsing file failed: {}", e),
+            Self::UnsupportedField(name) => write!(f, "Field {} can not be merged", name),
+            Self::TooManyFields { offset } => write!(
+                f,
+                "Definition record at offset {} has too many fields to merge into",
+                offset
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MergeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Parse(e) => Some(e),
+            Self::UnsupportedField(_) | Self::TooManyFields { .. } => None,
+        }
+    }
+}
+
+impl From<FitError> for MergeError {
+    fn from(e: FitError) -> Self {
+        MergeError::Parse(e)
+    }
+}
+
+// A merged field with the raw values of the secondary file by timestamp.
+struct Stream {
+    field: &'static FieldInfo,
+    base_type: &'static BaseTypeInfo,
+    samples: Vec<(u32, u64)>,
+}
+
+impl Stream {
+    // Raw value of the secondary sample nearest to `timestamp`.
+    fn value_at(&self, timestamp: u32) -> Option<u64> {
+        let idx = self.samples.partition_point(|(t, _)| *t < timestamp);
+        let before = idx.checked_sub(1).map(|idx| self.samples[idx]);
+        let after = self.samples.get(idx).copied();
+        [before, after]
+            .into_iter()
+            .flatten()
+            .filter(|(t, _)| t.abs_diff(timestamp) <= MAX_ALIGNMENT_GAP)
+            .min_by_key(|(t, _)| t.abs_diff(timestamp))
+            .map(|(_, raw)| raw)
+    }
+}
+
+fn field_base_type(field: &FieldInfo) -> Option<&'static BaseTypeInfo> {
+    let name = type_info(field.field_type)
+        .map(|t| t.base_type)
+        .unwrap_or(field.field_type);
+    base_type_info_by_name(name).filter(|b| {
+        !matches!(
+            b.base_type,
+            BaseType::String | BaseType::Byte | BaseType::Float32 | BaseType::Float64
+        )
+    })
+}
+
+fn stream(secondary: &FitFile, name: &str) -> Result<Stream, MergeError> {
+    let unsupported = || MergeError::UnsupportedField(name.to_string());
+    let field = RECORD_MESSAGE
+        .fields
+        .iter()
+        .find(|f| f.name == name)
+        .ok_or_else(unsupported)?;
+    let base_type = field_base_type(field).ok_or_else(unsupported)?;
+    let mut samples = secondary
+        .messages
+        .iter()
+        .filter(|m| m.global_message_number == RECORD_MESSAGE.number)
+        .filter_map(|m| {
+            let value = m.field(field.number)?.value.as_f64()?;
+            let raw = ((value + field.offset) * field.scale).round() as i64;
+            Some((m.timestamp()?, raw as u64))
+        })
+        .collect::<Vec<(u32, u64)>>();
+    samples.sort_by_key(|(t, _)| *t);
+    Ok(Stream {
+        field,
+        base_type,
+        samples,
+    })
+}
+
+// Definition record bytes at `offset` with the field definitions of `extra`
+// appended.
+fn extend_definition(
+    bytes: &[u8],
+    offset: usize,
+    definition: &DefinitionRecord,
+    extra: &[&Stream],
+) -> Result<Vec<u8>, MergeError> {
+    let fields_end = 6 + definition.field_definitions.len() * 3;
+    let mut extended = bytes[..fields_end].to_vec();
+    extended[5] = u8::try_from(extra.len())
+        .ok()
+        .and_then(|extra| extended[5].checked_add(extra))
+        .ok_or(MergeError::TooManyFields { offset })?;
+    for stream in extra {
+        extended.extend_from_slice(&[
+            stream.field.number,
+            stream.base_type.size,
+            stream.base_type.base_type_field,
+        ]);
+    }
+    extended.extend_from_slice(&bytes[fields_end..]);
+    Ok(extended)
+}
+
+/// Injects the named record fields, such as `heart_rate`, of the `secondary`
+/// file into the record messages of the `primary` file, aligning the records
+/// by timestamp. Fields already in the primary records are overwritten where
+/// the secondary file has a value and missing ones are added to the record
+/// definitions. Returns the re-encoded primary file.
+pub fn merge_streams(
+    primary: &[u8],
+    secondary: &[u8],
+    fields: &[&str],
+) -> Result<Vec<u8>, MergeError> {
+    let secondary = FitFile::parse(secondary)?;
+    let streams = fields
+        .iter()
+        .map(|name| stream(&secondary, name))
+        .collect::<Result<Vec<Stream>, MergeError>>()?;
+    let parsed = FitFile::parse(primary)?;
+    let mut header = try_parse_fit_header_from_data(primary)?;
+    let data_start = header.header_size() as usize;
+    let data_end = data_start + header.data_size() as usize;
+    if data_end > primary.len() {
+        return Err(FitError::UnexpectedEndOfData {
+            offset: primary.len(),
+        }
+        .into());
+    }
+
+    let mut merged = primary[..data_start].to_vec();
+    let mut messages = parsed.messages.iter().peekable();
+    // Streams appended to the definition of each local message type, and
+    // the byte order and field layout of its records.
+    let mut appended: [Vec<&Stream>; LOCAL_MESSAGE_TYPE_COUNT] = Default::default();
+    let mut layouts: [Vec<(u8, usize)>; LOCAL_MESSAGE_TYPE_COUNT] = Default::default();
+    let mut architectures: [Endianness; LOCAL_MESSAGE_TYPE_COUNT] =
+        std::array::from_fn(|_| Endianness::LittleEndian);
+    let mut records = RecordIterator::new(&primary[..data_end], data_start);
+    loop {
+        let record_offset = records.offset();
+        let record = match records.next() {
+            None => break,
+            Some(record) => record?,
+        };
+        let bytes = &primary[record_offset..records.offset()];
+        match record {
+            Record::Definition(definition) => {
+                let local = definition.header.local_message_type as usize;
+                appended[local].clear();
+                if definition.global_message_number == RECORD_MESSAGE.number {
+                    appended[local] = streams
+                        .iter()
+                        .filter(|s| {
+                            !definition
+                                .field_definitions
+                                .iter()
+                                .any(|f| f.field_definition_number == s.field.number)
+                        })
+                        .collect();
+                }
+                layouts[local] = definition
+                    .field_definitions
+                    .iter()
+                    .map(|f| (f.field_definition_number, f.field_size as usize))
+                    .collect();
+                architectures[local] = definition.architecture.clone();
+                merged.extend(extend_definition(
+                    bytes,
+                    record_offset,
+                    &definition,
+                    &appended[local],
+                )?);
+            }
+            Record::Data(record) => {
+                let local = record.header.local_message_type() as usize;
+                while messages.peek().is_some_and(|m| m.offset < record_offset) {
+                    messages.next();
+                }
+                let timestamp = messages
+                    .peek()
+                    .filter(|m| m.offset == record_offset)
+                    .and_then(|m| m.timestamp());
+                let mut record_bytes = bytes.to_vec();
+                let architecture = &architectures[local];
+                if record.global_message_number == RECORD_MESSAGE.number {
+                    let mut start = 1;
+                    for (number, size) in &layouts[local] {
+                        let stream = streams.iter().find(|s| s.field.number == *number);
+                        if let Some(stream) = stream {
+                            let value = timestamp.and_then(|t| stream.value_at(t));
+                            if let Some(raw) =
+                                value.filter(|_| *size == stream.base_type.size as usize)
+                            {
+                                write_bits(
+                                    &mut record_bytes[start..start + size],
+                                    architecture,
+                                    raw,
+                                );
+                            }
+                        }
+                        start += size;
+                    }
+                    let mut values = Vec::new();
+                    for stream in &appended[local] {
+                        let mut value = vec![0; stream.base_type.size as usize];
+                        let raw = timestamp
+                            .and_then(|t| stream.value_at(t))
+                            .unwrap_or(stream.base_type.invalid_value);
+                        write_bits(&mut value, architecture, raw);
+                        values.extend(value);
+                    }
+                    record_bytes.splice(start..start, values);
+                }
+                merged.extend(record_bytes);
+            }
+        }
+    }
+    header.set_data_size((merged.len() - data_start) as u32);
+    merged[..data_start].copy_from_slice(&header.to_bytes());
+    let crc = FitCrc::checksum(&merged, 0);
+    merged.extend_from_slice(&crc.to_le_bytes());
+    Ok(merged)
+}
+
+#[cfg(test)]
+use crate::fit_file::test_file;
+#[cfg(test)]
+use crate::fit_messages::Value;
+
+#[test]
+fn test_merge_streams() {
+    let mut primary = vec![
+        0x40, 0x00, 0x00, 0x14, 0x00, 0x02, // record, 2 fields
+        0xFD, 0x04, 0x86, // timestamp
+        0x07, 0x02, 0x84, // power
+    ];
+    for (t, power) in [
+        (0x3000_0000u32, 200u16),
+        (0x3000_0001, 210),
+        (0x3000_0010, 220),
+    ] {
+        primary.push(0x00);
+        primary.extend_from_slice(&t.to_le_bytes());
+        primary.extend_from_slice(&power.to_le_bytes());
+    }
+    let mut secondary = vec![
+        0x40, 0x00, 0x00, 0x14, 0x00, 0x02, // record, 2 fields
+        0xFD, 0x04, 0x86, // timestamp
+        0x03, 0x01, 0x02, // heart_rate
+    ];
+    for (t, heart_rate) in [(0x3000_0000u32, 120u8), (0x3000_0002, 125)] {
+        secondary.push(0x00);
+        secondary.extend_from_slice(&t.to_le_bytes());
+        secondary.push(heart_rate);
+    }
+    let merged = merge_streams(
+        &test_file(&primary),
+        &test_file(&secondary),
+        &["heart_rate"],
+    )
+    .unwrap();
+    let fit_file = FitFile::parse(&merged).unwrap();
+    let heart_rates = fit_file
+        .messages
+        .iter()
+        .map(|m| m.field(3).map(|f| f.value.clone()))
+        .collect::<Vec<Option<Value>>>();
+    assert_eq!(
+        heart_rates,
+        vec![Some(Value::U8(120)), Some(Value::U8(120)), None]
+    );
+    assert_eq!(
+        fit_file.messages[2].field(7).unwrap().value,
+        Value::U16(220)
+    );
+    assert!(matches!(
+        merge_streams(&test_file(&primary), &test_file(&secondary), &["nope"]),
+        Err(MergeError::UnsupportedField(_))
+    ));
+
+    // a primary file with fewer records than its header says
+    let mut truncated = test_file(&primary);
+    truncated[4..8].copy_from_slice(&1000u32.to_le_bytes());
+    assert!(matches!(
+        merge_streams(&truncated, &test_file(&secondary), &["heart_rate"]),
+        Err(MergeError::Parse(FitError::UnexpectedEndOfData { .. }))
+    ));
+
+    // a record definition of 255 fields has no room for heart_rate
+    let mut full = vec![0x40, 0x00, 0x00, 0x14, 0x00, 0xFF];
+    for number in (0..=255u8).filter(|n| *n != 3) {
+        match number {
+            253 => full.extend_from_slice(&[number, 0x04, 0x86]),
+            _ => full.extend_from_slice(&[number, 0x01, 0x02]),
+        }
+    }
+    assert!(matches!(
+        merge_streams(&test_file(&full), &test_file(&secondary), &["heart_rate"]),
+        Err(MergeError::TooManyFields { offset: 12 })
+    ));
+}
@@ -0,0 +1,261 @@
+//! Daily monitoring files: all-day steps, heart rate and stress.
+
+use std::collections::BTreeMap;
+
+use crate::fit_file::FitFile;
+use crate::profile::messages::{Monitoring, MonitoringInfo, StressLevel};
+use crate::profile::{MONITORING_INFO_MESSAGE, MONITORING_MESSAGE, STRESS_LEVEL_MESSAGE};
+use crate::time::SECONDS_PER_DAY;
+
+pub use crate::timeline::resolve_timestamp_16;
+
+// `activity_type` values whose cycles are steps.
+const RUNNING: u8 = 1;
+const WALKING: u8 = 6;
+
+/// A monitoring message with its timestamp resolved.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MonitoringSample {
+    /// Seconds since the FIT epoch.
+    pub timestamp: u32,
+    pub monitoring: Monitoring,
+}
+
+/// A stress level measurement.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StressSample {
+    /// Seconds since the FIT epoch.
+    pub timestamp: u32,
+    /// Stress from 0 to 100. Negative values mean the stress could not be
+    /// measured, such as during activity.
+    pub stress: i16,
+}
+
+/// Totals and ranges of the monitoring data of one local calendar day.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DailySummary {
+    /// Days since the FIT epoch in local time.
+    pub day: i64,
+    pub steps: u32,
+    /// Meters.
+    pub distance: f64,
+    /// Kilocalories.
+    pub calories: u32,
+    /// Seconds.
+    pub active_time: f64,
+    pub moderate_activity_minutes: u16,
+    pub vigorous_activity_minutes: u16,
+    /// Beats per minute.
+    pub min_heart_rate: Option<u8>,
+    pub max_heart_rate: Option<u8>,
+    pub avg_heart_rate: Option<f64>,
+    pub avg_stress: Option<f64>,
+    pub max_stress: Option<i16>,
+}
+
+// Highest accumulated values of each activity type within a day. Monitoring
+// values accumulate over the day, so the day's total is the last value.
+#[derive(Default)]
+struct DayAccumulator {
+    cycles: BTreeMap<u8, f64>,
+    distance: BTreeMap<u8, f64>,
+    calories: BTreeMap<u8, u16>,
+    active_time: BTreeMap<u8, f64>,
+    moderate_activity_minutes: u16,
+    vigorous_activity_minutes: u16,
+    heart_rates: Vec<u8>,
+    stress: Vec<i16>,
+}
+
+fn keep_max<T: PartialOrd + Copy>(map: &mut BTreeMap<u8, T>, key: u8, value: Option<T>) {
+    if let Some(value) = value {
+        let entry = map.entry(key).or_insert(value);
+        if value > *entry {
+            *entry = value;
+        }
+    }
+}
+
+impl DayAccumulator {
+    fn summary(self, day: i64) -> DailySummary {
+        let heart_rates = self.heart_rates;
+        let stress = self.stress;
+        DailySummary {
+            day,
+            steps: self
+                .cycles
+                .iter()
+                .filter(|(activity_type, _)| [RUNNING, WALKING].contains(activity_type))
+                .map(|(_, cycles)| *cycles as u32)
+                .sum(),
+            distance: self.distance.values().sum(),
+            calories: self.calories.values().map(|c| *c as u32).sum(),
+            active_time: self.active_time.values().sum(),
+            moderate_activity_minutes: self.moderate_activity_minutes,
+            vigorous_activity_minutes: self.vigorous_activity_minutes,
+            min_heart_rate: heart_rates.iter().min().copied(),
+            max_heart_rate: heart_rates.iter().max().copied(),
+            avg_heart_rate: (!heart_rates.is_empty()).then(|| {
+                heart_rates.iter().map(|hr| *hr as f64).sum::<f64>() / heart_rates.len() as f64
+            }),
+            avg_stress: (!stress.is_empty())
+                .then(|| stress.iter().map(|s| *s as f64).sum::<f64>() / stress.len() as f64),
+            max_stress: stress.iter().max().copied(),
+        }
+    }
+}
+
+impl FitFile {
+    /// Monitoring messages with their timestamps, resolving `timestamp_16`
+    /// values against the latest full timestamp. Messages before any full
+    /// timestamp are skipped.
+    pub fn monitoring_samples(&self) -> Vec<MonitoringSample> {
+        let mut reference: Option<u32> = None;
+        let mut samples = Vec::new();
+        for message in &self.messages {
+            if message.global_message_number != MONITORING_MESSAGE.number {
+                if let Some(timestamp) = message.timestamp() {
+                    reference = Some(timestamp);
+                }
+                continue;
+            }
+            let monitoring = Monitoring::from_message(message);
+            let timestamp = match (monitoring.timestamp, monitoring.timestamp_16, reference) {
+                (Some(timestamp), _, _) => timestamp,
+                (None, Some(timestamp_16), Some(reference)) => {
+                    resolve_timestamp_16(reference, timestamp_16)
+                }
+                _ => continue,
+            };
+            reference = Some(timestamp);
+            samples.push(MonitoringSample {
+                timestamp,
+                monitoring,
+            });
+        }
+        samples
+    }
+
+    /// Valid stress level measurements.
+    pub fn stress_levels(&self) -> Vec<StressSample> {
+        self.typed_messages(STRESS_LEVEL_MESSAGE.number, StressLevel::from_message)
+            .filter_map(|s| {
+                Some(StressSample {
+                    timestamp: s.stress_level_time?,
+                    stress: s.stress_level_value?,
+                })
+            })
+            .collect()
+    }
+
+    /// Seconds from UTC to local time, from the `monitoring_info` message.
+    pub fn monitoring_utc_offset(&self) -> Option<i64> {
+        self.typed_messages(MONITORING_INFO_MESSAGE.number, MonitoringInfo::from_message)
+            .find_map(|info| Some(info.local_timestamp? as i64 - info.timestamp? as i64))
+    }
+
+    /// Daily totals of the monitoring data by local calendar day. Times are
+    /// converted to local time with [`FitFile::monitoring_utc_offset`], or
+    /// taken as UTC without it.
+    pub fn daily_summaries(&self) -> Vec<DailySummary> {
+        let utc_offset = self.monitoring_utc_offset().unwrap_or(0);
+        let day = |timestamp: u32| (timestamp as i64 + utc_offset).div_euclid(SECONDS_PER_DAY);
+        let mut days: BTreeMap<i64, DayAccumulator> = BTreeMap::new();
+        for sample in self.monitoring_samples() {
+            let m = &sample.monitoring;
+            let acc = days.entry(day(sample.timestamp)).or_default();
+            let activity_type = m.activity_type.unwrap_or(0);
+            keep_max(&mut acc.cycles, activity_type, m.cycles);
+            keep_max(&mut acc.distance, activity_type, m.distance);
+            keep_max(&mut acc.calories, activity_type, m.calories);
+            keep_max(&mut acc.active_time, activity_type, m.active_time);
+            acc.moderate_activity_minutes = acc
+                .moderate_activity_minutes
+                .max(m.moderate_activity_minutes.unwrap_or(0));
+            acc.vigorous_activity_minutes = acc
+                .vigorous_activity_minutes
+                .max(m.vigorous_activity_minutes.unwrap_or(0));
+            acc.heart_rates.extend(m.heart_rate.filter(|hr| *hr > 0));
+        }
+        for sample in self.stress_levels().iter().filter(|s| s.stress >= 0) {
+            days.entry(day(sample.timestamp))
+                .or_default()
+                .stress
+                .push(sample.stress);
+        }
+        days.into_iter()
+            .map(|(day, acc)| acc.summary(day))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+use crate::fit_file::{test_fit_file, test_message};
+#[cfg(test)]
+use crate::fit_messages::Value;
+
+#[test]
+fn test_resolve_timestamp_16() {
+    assert_eq!(resolve_timestamp_16(0x1234_FFF0, 0xFFF5), 0x1234_FFF5);
+    assert_eq!(resolve_timestamp_16(0x1234_FFF0, 0x0005), 0x1235_0005);
+}
+
+#[test]
+fn test_daily_summaries() {
+    let day = 86400 * 10000;
+    let file = test_fit_file(vec![
+        test_message(
+            103,
+            vec![
+                (253, "timestamp", Value::U32(day)),
+                (0, "local_timestamp", Value::U32(day + 7200)),
+            ],
+        ),
+        test_message(
+            55,
+            vec![
+                (253, "timestamp", Value::U32(day + 3600)),
+                (5, "activity_type", Value::U8(WALKING)),
+                (3, "cycles", Value::F64(1000.0)),
+            ],
+        ),
+        test_message(
+            55,
+            vec![
+                (26, "timestamp_16", Value::U16((day + 3660) as u16)),
+                (27, "heart_rate", Value::U8(60)),
+            ],
+        ),
+        test_message(
+            55,
+            vec![
+                (26, "timestamp_16", Value::U16((day + 7200) as u16)),
+                (5, "activity_type", Value::U8(WALKING)),
+                (3, "cycles", Value::F64(2500.0)),
+                (27, "heart_rate", Value::U8(80)),
+            ],
+        ),
+        test_message(
+            227,
+            vec![
+                (0, "stress_level_value", Value::I16(30)),
+                (1, "stress_level_time", Value::U32(day + 3600)),
+            ],
+        ),
+        test_message(
+            227,
+            vec![
+                (0, "stress_level_value", Value::I16(-1)),
+                (1, "stress_level_time", Value::U32(day + 3700)),
+            ],
+        ),
+    ]);
+    let samples = file.monitoring_samples();
+    assert_eq!(samples[1].timestamp, day + 3660);
+    let summaries = file.daily_summaries();
+    assert_eq!(summaries.len(), 1);
+    assert_eq!(summaries[0].day, 10000);
+    assert_eq!(summaries[0].steps, 2500);
+    assert_eq!(summaries[0].avg_heart_rate, Some(70.0));
+    assert_eq!(summaries[0].avg_stress, Some(30.0));
+}
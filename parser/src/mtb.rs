@@ -0,0 +1,159 @@
+//! Mountain bike jumps from `jump` messages and the grit and flow scores
+//! written by Edge devices.
+
+use crate::fit_file::FitFile;
+use crate::profile::messages::{Jump, Session};
+use crate::profile::{JUMP_MESSAGE, SESSION_MESSAGE};
+use crate::series::SEMICIRCLES_TO_DEGREES;
+
+/// One jump. Missing values are `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct JumpSummary {
+    /// Seconds since the FIT epoch.
+    pub timestamp: Option<u32>,
+    /// Meters.
+    pub distance: Option<f64>,
+    /// Meters.
+    pub height: Option<f64>,
+    /// Seconds in the air.
+    pub hang_time: Option<f64>,
+    pub rotations: Option<u8>,
+    /// Score of the device from the hang time, rotations and distance.
+    pub score: Option<f64>,
+    /// Meters per second, from `enhanced_speed` when present.
+    pub speed: Option<f64>,
+    /// Degrees.
+    pub position_lat: Option<f64>,
+    /// Degrees.
+    pub position_long: Option<f64>,
+}
+
+impl JumpSummary {
+    pub fn from_jump(jump: &Jump) -> JumpSummary {
+        JumpSummary {
+            timestamp: jump.timestamp,
+            distance: jump.distance.map(f64::from),
+            height: jump.height.map(f64::from),
+            hang_time: jump.hang_time.map(f64::from),
+            rotations: jump.rotations,
+            score: jump.score.map(f64::from),
+            speed: jump.enhanced_speed.or(jump.speed),
+            position_lat: jump.position_lat.map(|p| p as f64 * SEMICIRCLES_TO_DEGREES),
+            position_long: jump
+                .position_long
+                .map(|p| p as f64 * SEMICIRCLES_TO_DEGREES),
+        }
+    }
+}
+
+/// Grit, flow and jumps of an activity.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MtbSummary {
+    /// kGrit, summed over the sessions.
+    pub total_grit: Option<f64>,
+    /// kGrit.
+    pub avg_grit: Option<f64>,
+    /// Summed over the sessions.
+    pub total_flow: Option<f64>,
+    pub avg_flow: Option<f64>,
+    pub jumps: Vec<JumpSummary>,
+}
+
+impl MtbSummary {
+    pub fn jump_count(&self) -> usize {
+        self.jumps.len()
+    }
+
+    /// The longest jump by hang time.
+    pub fn longest_jump(&self) -> Option<&JumpSummary> {
+        self.jumps
+            .iter()
+            .filter(|j| j.hang_time.is_some())
+            .max_by(|a, b| a.hang_time.partial_cmp(&b.hang_time).unwrap())
+    }
+
+    /// Meters of the longest jump by distance.
+    pub fn max_jump_distance(&self) -> Option<f64> {
+        self.jumps
+            .iter()
+            .filter_map(|j| j.distance)
+            .reduce(f64::max)
+    }
+}
+
+// Sum of the present values, `None` if there are none.
+fn sum(values: impl Iterator<Item = Option<f32>>) -> Option<f64> {
+    values.flatten().map(f64::from).reduce(|a, b| a + b)
+}
+
+impl FitFile {
+    /// Jumps in time order.
+    pub fn jumps(&self) -> Vec<JumpSummary> {
+        let mut jumps = self
+            .typed_messages(JUMP_MESSAGE.number, Jump::from_message)
+            .map(|j| JumpSummary::from_jump(&j))
+            .collect::<Vec<JumpSummary>>();
+        jumps.sort_by_key(|j| j.timestamp);
+        jumps
+    }
+
+    /// Grit and flow of the sessions with the jumps. Averages are those of
+    /// the first session with one.
+    pub fn mtb_summary(&self) -> MtbSummary {
+        let sessions = self
+            .typed_messages(SESSION_MESSAGE.number, Session::from_message)
+            .collect::<Vec<Session>>();
+        MtbSummary {
+            total_grit: sum(sessions.iter().map(|s| s.total_grit)),
+            avg_grit: sessions.iter().find_map(|s| s.avg_grit).map(f64::from),
+            total_flow: sum(sessions.iter().map(|s| s.total_flow)),
+            avg_flow: sessions.iter().find_map(|s| s.avg_flow).map(f64::from),
+            jumps: self.jumps(),
+        }
+    }
+}
+
+#[cfg(test)]
+use crate::fit_file::{test_fit_file, test_message};
+#[cfg(test)]
+use crate::fit_messages::Value;
+
+#[test]
+fn test_mtb_summary() {
+    let jump = |t: u32, distance: f32, hang_time: f32| {
+        test_message(
+            JUMP_MESSAGE.number,
+            vec![
+                (253, "timestamp", Value::U32(t)),
+                (0, "distance", Value::F32(distance)),
+                (3, "hang_time", Value::F32(hang_time)),
+                (5, "position_lat", Value::I32(1 << 30)),
+                (7, "speed", Value::F64(4.5)),
+                (8, "enhanced_speed", Value::F64(4.5)),
+            ],
+        )
+    };
+    let file = test_fit_file(vec![
+        jump(1200, 3.5, 0.6),
+        jump(1100, 4.25, 0.5),
+        test_message(
+            SESSION_MESSAGE.number,
+            vec![
+                (181, "total_grit", Value::F32(12.5)),
+                (182, "total_flow", Value::F32(4.0)),
+                (186, "avg_grit", Value::F32(1.5)),
+            ],
+        ),
+    ]);
+    let summary = file.mtb_summary();
+    assert_eq!(summary.jump_count(), 2);
+    assert_eq!(summary.jumps[0].timestamp, Some(1100));
+    assert_eq!(summary.jumps[0].position_lat, Some(90.0));
+    assert_eq!(summary.jumps[0].speed, Some(4.5));
+    assert_eq!(summary.longest_jump().unwrap().timestamp, Some(1200));
+    assert_eq!(summary.max_jump_distance(), Some(4.25));
+    assert_eq!(summary.total_grit, Some(12.5));
+    assert_eq!(summary.total_flow, Some(4.0));
+    assert_eq!(summary.avg_grit, Some(1.5));
+    assert_eq!(summary.avg_flow, None);
+}
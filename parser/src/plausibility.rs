@@ -0,0 +1,177 @@
+//! Plausibility checks of field values, flagging values outside the range a
+//! sensor can plausibly measure as diagnostics, for ingestion pipelines that
+//! quarantine suspect data. Set the rules to check while parsing with
+//! [`crate::decoder::FitDecoderBuilder::plausibility`].
+
+use crate::diagnostics::{DiagnosticKind, Diagnostics};
+use crate::fit_messages::DataMessage;
+use crate::profile::{Sport, RECORD_MESSAGE, SESSION_MESSAGE, SPORT_MESSAGE};
+
+// Semicircles of 90 degrees.
+const QUARTER_CIRCLE: f64 = (1u32 << 30) as f64;
+
+/// Range of plausible values of a field, in the units of its decoded value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlausibilityRule {
+    pub global_message_number: u16,
+    pub field_definition_number: u8,
+    /// The `sport` value of the files the rule applies to, all files when
+    /// `None`.
+    pub sport: Option<u8>,
+    pub min: f64,
+    pub max: f64,
+}
+
+/// Rules checked together. The default rules check heart rates from 20 to
+/// 250 bpm, cycling speeds up to 150 km/h and latitudes within ±90°.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlausibilityRules {
+    pub rules: Vec<PlausibilityRule>,
+}
+
+impl Default for PlausibilityRules {
+    fn default() -> Self {
+        let record = |field_definition_number, sport, min, max| PlausibilityRule {
+            global_message_number: RECORD_MESSAGE.number,
+            field_definition_number,
+            sport,
+            min,
+            max,
+        };
+        let cycling = Some(Sport::Cycling as u8);
+        let max_cycling_speed = 150.0 / 3.6;
+        PlausibilityRules {
+            rules: vec![
+                record(3, None, 20.0, 250.0),
+                record(6, cycling, 0.0, max_cycling_speed),
+                record(73, cycling, 0.0, max_cycling_speed),
+                record(0, None, -QUARTER_CIRCLE, QUARTER_CIRCLE),
+            ],
+        }
+    }
+}
+
+// Sport of the file from its first `sport` or `session` message.
+fn file_sport(messages: &[DataMessage]) -> Option<u8> {
+    messages.iter().find_map(|m| {
+        if m.global_message_number == SPORT_MESSAGE.number {
+            m.typed_field(0)
+        } else if m.global_message_number == SESSION_MESSAGE.number {
+            m.typed_field(5)
+        } else {
+            None
+        }
+    })
+}
+
+impl PlausibilityRules {
+    /// No rules, to add rules to.
+    pub fn new() -> PlausibilityRules {
+        PlausibilityRules { rules: Vec::new() }
+    }
+
+    pub fn with_rule(mut self, rule: PlausibilityRule) -> PlausibilityRules {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Adds a diagnostic for each field value of the messages outside the
+    /// range of a rule. Values of arrays are not checked.
+    pub fn check(&self, messages: &[DataMessage], diagnostics: &mut Diagnostics) {
+        let sport = file_sport(messages);
+        let rules = self
+            .rules
+            .iter()
+            .filter(|r| r.sport.is_none() || r.sport == sport)
+            .collect::<Vec<&PlausibilityRule>>();
+        for message in messages {
+            for rule in rules
+                .iter()
+                .filter(|r| r.global_message_number == message.global_message_number)
+            {
+                let value = match message
+                    .field(rule.field_definition_number)
+                    .and_then(|f| f.value.as_f64())
+                {
+                    Some(value) => value,
+                    None => continue,
+                };
+                if !(rule.min..=rule.max).contains(&value) {
+                    diagnostics.push(
+                        message.offset,
+                        DiagnosticKind::ImplausibleValue {
+                            global_message_number: message.global_message_number,
+                            field_definition_number: rule.field_definition_number,
+                            value,
+                        },
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+use crate::decoder::FitDecoder;
+#[cfg(test)]
+use crate::fit_file::test_file;
+
+#[test]
+fn test_plausibility_rules() {
+    let data = test_file(&[
+        0x40, 0x00, 0x00, 0x14, 0x00, 0x02, // record, 2 fields
+        0x03, 0x01, 0x02, // heart_rate
+        0x06, 0x02, 0x84, // speed
+        0x00, 0x96, 0x10, 0x27, // 150 bpm, 10 m/s
+        0x00, 0x0A, 0x50, 0xC3, // 10 bpm, 50 m/s
+        0x41, 0x00, 0x00, 0x0C, 0x00, 0x01, // sport, 1 field
+        0x00, 0x01, 0x00, // sport
+        0x01, 0x02, // cycling
+    ]);
+    let decoder = FitDecoder::builder()
+        .plausibility(PlausibilityRules::default())
+        .build();
+    let file = decoder.decode(&data).unwrap();
+    let kinds = file
+        .diagnostics
+        .iter()
+        .map(|d| d.kind.clone())
+        .collect::<Vec<DiagnosticKind>>();
+    assert_eq!(
+        kinds,
+        vec![
+            DiagnosticKind::ImplausibleValue {
+                global_message_number: 20,
+                field_definition_number: 3,
+                value: 10.0
+            },
+            DiagnosticKind::ImplausibleValue {
+                global_message_number: 20,
+                field_definition_number: 6,
+                value: 50.0
+            },
+            DiagnosticKind::ImplausibleValue {
+                global_message_number: 20,
+                field_definition_number: 73,
+                value: 50.0
+            },
+        ]
+    );
+    assert_eq!(file.diagnostics.iter().next().unwrap().offset, 28);
+    // speed is only checked for cycling
+    let mut messages = file.messages.clone();
+    messages.truncate(2);
+    let mut diagnostics = Diagnostics::new();
+    PlausibilityRules::default().check(&messages, &mut diagnostics);
+    assert_eq!(diagnostics.len(), 1);
+    let strict = PlausibilityRules::new().with_rule(PlausibilityRule {
+        global_message_number: 20,
+        field_definition_number: 3,
+        sport: None,
+        min: 0.0,
+        max: 100.0,
+    });
+    let mut diagnostics = Diagnostics::new();
+    strict.check(&messages, &mut diagnostics);
+    assert_eq!(diagnostics.len(), 1);
+}
@@ -0,0 +1,147 @@
+//! Cycling power metrics: normalized power, intensity factor and training
+//! stress score.
+
+use crate::fit_file::FitFile;
+use crate::series::{RecordSeries, ResampleError};
+
+/// Seconds in the rolling average that normalized power is computed from.
+pub const NORMALIZED_POWER_WINDOW: usize = 30;
+
+/// Trailing averages over `window` values. The first `window - 1` averages,
+/// whose windows are not yet full, are NaN.
+pub fn rolling_average(values: &[f64], window: usize) -> Vec<f64> {
+    assert!(window > 0, "Rolling average window must be positive");
+    let mut averages = Vec::with_capacity(values.len());
+    let mut sum = 0.0;
+    for (idx, value) in values.iter().enumerate() {
+        sum += value;
+        if idx >= window {
+            sum -= values[idx - window];
+        }
+        if idx + 1 >= window {
+            averages.push(sum / window as f64);
+        } else {
+            averages.push(f64::NAN);
+        }
+    }
+    averages
+}
+
+/// Power of an activity as one value per second.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PowerAnalysis {
+    /// Watts. Seconds without power data and pauses count as zero.
+    pub power: Vec<f64>,
+}
+
+impl PowerAnalysis {
+    /// Resamples the power of the series to one value per second, see
+    /// [`RecordSeries::resample_seconds`].
+    pub fn from_series(series: &RecordSeries) -> Result<PowerAnalysis, ResampleError> {
+        let resampled = series.resample_seconds()?;
+        Ok(PowerAnalysis {
+            power: resampled
+                .power
+                .into_iter()
+                .map(|p| if p.is_nan() { 0.0 } else { p })
+                .collect(),
+        })
+    }
+
+    /// Seconds of power data.
+    pub fn duration(&self) -> u32 {
+        self.power.len() as u32
+    }
+
+    pub fn average_power(&self) -> Option<f64> {
+        if self.power.is_empty() {
+            return None;
+        }
+        Some(self.power.iter().sum::<f64>() / self.power.len() as f64)
+    }
+
+    /// Fourth root of the mean of the fourth powers of the 30 second rolling
+    /// average power. `None` for activities shorter than 30 seconds.
+    pub fn normalized_power(&self) -> Option<f64> {
+        if self.power.len() < NORMALIZED_POWER_WINDOW {
+            return None;
+        }
+        let rolling = rolling_average(&self.power, NORMALIZED_POWER_WINDOW);
+        let full = &rolling[NORMALIZED_POWER_WINDOW - 1..];
+        let mean = full.iter().map(|p| p.powi(4)).sum::<f64>() / full.len() as f64;
+        Some(mean.powf(0.25))
+    }
+
+    /// Normalized power relative to the functional threshold power `ftp` in
+    /// watts.
+    pub fn intensity_factor(&self, ftp: f64) -> Option<f64> {
+        self.normalized_power().map(|np| np / ftp)
+    }
+
+    /// Training stress score: 100 for one hour at the functional threshold
+    /// power `ftp` in watts.
+    pub fn training_stress_score(&self, ftp: f64) -> Option<f64> {
+        let np = self.normalized_power()?;
+        Some(self.duration() as f64 * np * (np / ftp) / (ftp * 3600.0) * 100.0)
+    }
+}
+
+impl FitFile {
+    /// Power analysis of the record messages of the file.
+    pub fn power_analysis(&self) -> Result<PowerAnalysis, ResampleError> {
+        PowerAnalysis::from_series(&self.record_series())
+    }
+}
+
+#[cfg(test)]
+use crate::series::test_series;
+
+#[test]
+fn test_rolling_average() {
+    let averages = rolling_average(&[1.0, 2.0, 3.0, 4.0], 2);
+    assert!(averages[0].is_nan());
+    assert_eq!(averages[1..], [1.5, 2.5, 3.5]);
+}
+
+#[test]
+fn test_normalized_power_and_tss() {
+    let steady = PowerAnalysis {
+        power: vec![200.0; 3600],
+    };
+    assert_eq!(steady.normalized_power(), Some(200.0));
+    assert_eq!(steady.intensity_factor(250.0), Some(0.8));
+    assert!((steady.training_stress_score(200.0).unwrap() - 100.0).abs() < 1e-9);
+
+    let mut intervals = vec![100.0; 600];
+    intervals.extend(vec![300.0; 600]);
+    let intervals = PowerAnalysis { power: intervals };
+    assert_eq!(intervals.average_power(), Some(200.0));
+    assert!(intervals.normalized_power().unwrap() > 200.0);
+
+    let short = PowerAnalysis {
+        power: vec![200.0; 29],
+    };
+    assert_eq!(short.normalized_power(), None);
+}
+
+#[test]
+fn test_power_analysis_from_series() {
+    let series = RecordSeries {
+        power: vec![100.0, f64::NAN, 300.0],
+        ..test_series(&[0, 2, 3])
+    };
+    assert_eq!(
+        PowerAnalysis::from_series(&series).unwrap().power,
+        vec![100.0, 100.0, 0.0, 300.0]
+    );
+
+    // an hour long stop is not held at the power before it
+    let stopped = RecordSeries {
+        power: vec![300.0, 100.0],
+        ..test_series(&[0, 3600])
+    };
+    assert_eq!(
+        PowerAnalysis::from_series(&stopped).unwrap().power,
+        vec![300.0, 0.0, 100.0]
+    );
+}
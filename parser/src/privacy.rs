@@ -0,0 +1,119 @@
+//! Removal of GPS positions near private places before sharing a file.
+
+use crate::edit::{edit_records, EditableRecord};
+use crate::error::FitError;
+use crate::geo::haversine_distance;
+use crate::profile::message_info;
+use crate::series::SEMICIRCLES_TO_DEGREES;
+
+/// A circle around a private place such as home or work.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrivacyZone {
+    /// Degrees.
+    pub latitude: f64,
+    /// Degrees.
+    pub longitude: f64,
+    /// Meters.
+    pub radius: f64,
+}
+
+impl PrivacyZone {
+    pub fn contains(&self, latitude: f64, longitude: f64) -> bool {
+        haversine_distance(self.latitude, self.longitude, latitude, longitude) <= self.radius
+    }
+}
+
+// Field numbers of the latitude and longitude field pairs of a message, such
+// as `position_lat` and `position_long` or `start_position_lat` and
+// `start_position_long`.
+fn position_fields(global_message_number: u16) -> Vec<(u8, u8)> {
+    let message = match message_info(global_message_number) {
+        Some(message) => message,
+        None => return Vec::new(),
+    };
+    message
+        .fields
+        .iter()
+        .filter_map(|lat| {
+            let prefix = lat.name.strip_suffix("_lat")?;
+            let long = message
+                .fields
+                .iter()
+                .find(|f| f.name.strip_suffix("_long") == Some(prefix))?;
+            Some((lat.number, long.number))
+        })
+        .collect()
+}
+
+fn strip_record(record: &mut EditableRecord, zones: &[PrivacyZone]) -> usize {
+    let mut stripped = 0;
+    for (lat, long) in position_fields(record.global_message_number) {
+        let position = match (record.field(lat), record.field(long)) {
+            (Some(lat), Some(long)) => lat.as_f64().zip(long.as_f64()),
+            _ => None,
+        };
+        let inside = position.is_some_and(|(lat, long)| {
+            let (lat, long) = (lat * SEMICIRCLES_TO_DEGREES, long * SEMICIRCLES_TO_DEGREES);
+            zones.iter().any(|zone| zone.contains(lat, long))
+        });
+        if inside {
+            record.invalidate(lat);
+            record.invalidate(long);
+            stripped += 1;
+        }
+    }
+    stripped
+}
+
+/// Invalidates the positions of all messages that fall inside one of the
+/// `zones`, returning the edited file and the number of positions removed.
+/// The rest of the file, including distances, is kept as is.
+pub fn strip_privacy_zones(
+    fit_data: &[u8],
+    zones: &[PrivacyZone],
+) -> Result<(Vec<u8>, usize), FitError> {
+    let mut stripped = 0;
+    let edited = edit_records(fit_data, |record| {
+        stripped += strip_record(record, zones);
+    })?;
+    Ok((edited, stripped))
+}
+
+#[cfg(test)]
+use crate::fit_file::{test_file, FitFile};
+
+#[test]
+fn test_position_fields() {
+    assert_eq!(position_fields(20), vec![(0, 1)]);
+    assert!(position_fields(19).contains(&(3, 4)));
+    assert!(position_fields(0xFF00).is_empty());
+}
+
+#[test]
+fn test_strip_privacy_zones() {
+    let record = |lat: i32, long: i32| {
+        let mut bytes = vec![0x00];
+        bytes.extend_from_slice(&lat.to_le_bytes());
+        bytes.extend_from_slice(&long.to_le_bytes());
+        bytes
+    };
+    let degrees = |d: f64| (d / SEMICIRCLES_TO_DEGREES) as i32;
+    let mut records = vec![
+        0x40, 0x00, 0x00, 0x14, 0x00, 0x02, // record, 2 fields
+        0x00, 0x04, 0x85, // position_lat
+        0x01, 0x04, 0x85, // position_long
+    ];
+    records.extend(record(degrees(60.0), degrees(24.0)));
+    records.extend(record(degrees(60.1), degrees(24.0)));
+    let zones = [PrivacyZone {
+        latitude: 60.0,
+        longitude: 24.001,
+        radius: 200.0,
+    }];
+    let (edited, stripped) = strip_privacy_zones(&test_file(&records), &zones).unwrap();
+    assert_eq!(stripped, 1);
+    let fit_file = FitFile::parse(&edited).unwrap();
+    assert!(fit_file.messages[0].field(0).is_none());
+    assert!(fit_file.messages[0].field(1).is_none());
+    assert!(fit_file.messages[1].field(0).is_some());
+}
@@ -0,0 +1,13 @@
+//! Low-level view of the FIT data section wire structure.
+//!
+//! Exposes the record headers, definition records and data records exactly
+//! as they appear in the file, including local message types and declared
+//! field sizes, for tools that need to inspect the encoding rather than the
+//! decoded message values.
+
+pub use crate::fit_records::{
+    parse_record_header, BaseType, BaseTypeInfo, CompressedTimestampHeader, DataRecord,
+    DefinitionRecord, DeveloperFieldDefinition, Endianness, FieldDefinition, LocalMessageTable,
+    NormalDataHeader, NormalDefinitionHeader, RawDeveloperField, RawField, Record, RecordHeader,
+    RecordIterator, RecordMessageType, LOCAL_MESSAGE_TYPE_COUNT,
+};
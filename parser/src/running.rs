@@ -0,0 +1,187 @@
+//! Running dynamics from record messages, from the profile fields written by
+//! Garmin running sensors or the developer fields of Connect IQ foot pods.
+
+use crate::developer::{DeveloperFieldInfo, DeveloperFields};
+use crate::fit_file::FitFile;
+use crate::fit_messages::{DataMessage, Value};
+use crate::profile::RECORD_MESSAGE;
+
+/// Running dynamics of one record. Missing values are `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RunningDynamics {
+    pub timestamp: Option<u32>,
+    /// Millimeters.
+    pub vertical_oscillation: Option<f64>,
+    /// Milliseconds.
+    pub ground_contact_time: Option<f64>,
+    /// Percent of ground contact time on the left foot.
+    pub ground_contact_balance: Option<f64>,
+    /// Millimeters.
+    pub step_length: Option<f64>,
+    /// Vertical oscillation relative to step length, percent.
+    pub vertical_ratio: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Metric {
+    VerticalOscillation,
+    GroundContactTime,
+    GroundContactBalance,
+    StepLength,
+    VerticalRatio,
+}
+
+// Record field numbers of the profile fields.
+const NATIVE_FIELDS: [(u8, Metric); 5] = [
+    (39, Metric::VerticalOscillation),
+    (41, Metric::GroundContactTime),
+    (84, Metric::GroundContactBalance),
+    (85, Metric::StepLength),
+    (83, Metric::VerticalRatio),
+];
+
+// Developer field names used by popular foot pod apps such as Stryd and
+// RunScribe.
+const DEVELOPER_FIELD_NAMES: [(&str, Metric); 7] = [
+    ("Vertical Oscillation", Metric::VerticalOscillation),
+    ("Ground Time", Metric::GroundContactTime),
+    ("Contact Time", Metric::GroundContactTime),
+    ("Ground Contact Balance", Metric::GroundContactBalance),
+    ("Stride Length", Metric::StepLength),
+    ("Step Length", Metric::StepLength),
+    ("Vertical Ratio", Metric::VerticalRatio),
+];
+
+// Converts a developer field value to the unit of the metric, millimeters
+// for lengths and milliseconds for times, from the units of its description.
+fn convert(metric: Metric, value: f64, units: Option<&str>) -> f64 {
+    let factor = match (metric, units.map(str::trim)) {
+        (Metric::VerticalOscillation | Metric::StepLength, Some("m")) => 1000.0,
+        (Metric::VerticalOscillation | Metric::StepLength, Some("cm")) => 10.0,
+        (Metric::GroundContactTime, Some("s")) => 1000.0,
+        (_, _) => 1.0,
+    };
+    value * factor
+}
+
+fn developer_metric(info: &DeveloperFieldInfo) -> Option<Metric> {
+    if info.native_message_number == Some(RECORD_MESSAGE.number) {
+        let native = NATIVE_FIELDS
+            .iter()
+            .find(|(number, _)| Some(*number) == info.native_field_number);
+        if let Some((_, metric)) = native {
+            return Some(*metric);
+        }
+    }
+    DEVELOPER_FIELD_NAMES
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(&info.name))
+        .map(|(_, metric)| *metric)
+}
+
+impl RunningDynamics {
+    fn set(&mut self, metric: Metric, value: f64) {
+        let slot = match metric {
+            Metric::VerticalOscillation => &mut self.vertical_oscillation,
+            Metric::GroundContactTime => &mut self.ground_contact_time,
+            Metric::GroundContactBalance => &mut self.ground_contact_balance,
+            Metric::StepLength => &mut self.step_length,
+            Metric::VerticalRatio => &mut self.vertical_ratio,
+        };
+        slot.get_or_insert(value);
+    }
+
+    /// Running dynamics of a record message. Profile fields take precedence
+    /// over developer fields for the same metric.
+    pub fn from_record(record: &DataMessage, developer: &DeveloperFields) -> RunningDynamics {
+        let mut dynamics = RunningDynamics {
+            timestamp: record.timestamp(),
+            ..RunningDynamics::default()
+        };
+        for (number, metric) in NATIVE_FIELDS {
+            if let Some(value) = record.field(number).and_then(|f| f.value.as_f64()) {
+                dynamics.set(metric, value);
+            }
+        }
+        for (info, value) in developer.values(record) {
+            let metric = match developer_metric(info) {
+                Some(metric) => metric,
+                None => continue,
+            };
+            let value = match value {
+                Value::Array(_) | Value::String(_) | Value::Bytes(_) => continue,
+                value => value.as_f64(),
+            };
+            if let Some(value) = value {
+                dynamics.set(metric, convert(metric, value, info.units.as_deref()));
+            }
+        }
+        dynamics
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vertical_oscillation.is_none()
+            && self.ground_contact_time.is_none()
+            && self.ground_contact_balance.is_none()
+            && self.step_length.is_none()
+            && self.vertical_ratio.is_none()
+    }
+}
+
+impl FitFile {
+    /// Running dynamics of the record messages that have any, in file order.
+    pub fn running_dynamics(&self) -> Vec<RunningDynamics> {
+        let developer = DeveloperFields::from_file(self);
+        self.messages
+            .iter()
+            .filter(|m| m.global_message_number == RECORD_MESSAGE.number)
+            .map(|m| RunningDynamics::from_record(m, &developer))
+            .filter(|d| !d.is_empty())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+use crate::fit_file::test_file;
+
+#[test]
+fn test_native_running_dynamics() {
+    let file = FitFile::parse(&test_file(&[
+        0x40, 0x00, 0x00, 0x14, 0x00, 0x03, // record, 3 fields
+        0x27, 0x02, 0x84, // vertical_oscillation
+        0x29, 0x02, 0x84, // stance_time
+        0x54, 0x02, 0x84, // stance_time_balance
+        0x00, 0xE8, 0x03, 0xA0, 0x0F, 0x8A, 0x13, // 100 mm, 400 ms, 50.02 %
+    ]))
+    .unwrap();
+    let dynamics = file.running_dynamics();
+    assert_eq!(dynamics.len(), 1);
+    assert_eq!(dynamics[0].vertical_oscillation, Some(100.0));
+    assert_eq!(dynamics[0].ground_contact_time, Some(400.0));
+    assert_eq!(dynamics[0].ground_contact_balance, Some(50.02));
+    assert_eq!(dynamics[0].step_length, None);
+}
+
+#[test]
+fn test_developer_running_dynamics() {
+    let mut records = vec![
+        0x40, 0x00, 0x00, 0xCE, 0x00, 0x05, // field_description, 5 fields
+        0x00, 0x01, 0x02, // developer_data_index
+        0x01, 0x01, 0x02, // field_definition_number
+        0x02, 0x01, 0x02, // fit_base_type_id
+        0x03, 0x15, 0x07, // field_name
+        0x08, 0x03, 0x07, // units
+        0x00, 0x00, 0x00, 0x88,
+    ];
+    records.extend_from_slice(b"Vertical Oscillation\0cm\0");
+    records.extend_from_slice(&[
+        0x61, 0x00, 0x00, 0x14, 0x00, 0x00, // record with developer fields
+        0x01, 0x00, 0x04, 0x00, // developer field 0, 4 bytes, index 0
+        0x01,
+    ]);
+    records.extend_from_slice(&8.5f32.to_le_bytes());
+    let file = FitFile::parse(&test_file(&records)).unwrap();
+    let dynamics = file.running_dynamics();
+    assert_eq!(dynamics.len(), 1);
+    assert_eq!(dynamics[0].vertical_oscillation, Some(85.0));
+}
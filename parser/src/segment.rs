@@ -0,0 +1,126 @@
+//! Segment files: a segment's identity, its leaderboard, the points along it
+//! with the time each leader needed to reach them, and the laps recorded on
+//! it.
+
+use crate::encode::{encode, EncodeError};
+use crate::fit_file::FitFile;
+use crate::profile::messages::{
+    FileId, SegmentId, SegmentLap, SegmentLeaderboardEntry, SegmentPoint,
+};
+use crate::profile::{
+    FILE_ID_MESSAGE, SEGMENT_ID_MESSAGE, SEGMENT_LAP_MESSAGE, SEGMENT_LEADERBOARD_ENTRY_MESSAGE,
+    SEGMENT_POINT_MESSAGE,
+};
+
+// `file` value of segment files.
+const SEGMENT_FILE_TYPE: u8 = 34;
+
+/// The messages of a segment file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Segment {
+    pub file_id: Option<FileId>,
+    pub id: Option<SegmentId>,
+    pub leaderboard: Vec<SegmentLeaderboardEntry>,
+    /// Ordered by message index.
+    pub points: Vec<SegmentPoint>,
+    pub laps: Vec<SegmentLap>,
+}
+
+impl Segment {
+    pub fn from_file(file: &FitFile) -> Segment {
+        let mut points = file
+            .typed_messages(SEGMENT_POINT_MESSAGE.number, SegmentPoint::from_message)
+            .collect::<Vec<SegmentPoint>>();
+        points.sort_by_key(|p| p.message_index);
+        Segment {
+            file_id: file
+                .typed_messages(FILE_ID_MESSAGE.number, FileId::from_message)
+                .next(),
+            id: file
+                .typed_messages(SEGMENT_ID_MESSAGE.number, SegmentId::from_message)
+                .next(),
+            leaderboard: file
+                .typed_messages(
+                    SEGMENT_LEADERBOARD_ENTRY_MESSAGE.number,
+                    SegmentLeaderboardEntry::from_message,
+                )
+                .collect(),
+            points,
+            laps: file
+                .typed_messages(SEGMENT_LAP_MESSAGE.number, SegmentLap::from_message)
+                .collect(),
+        }
+    }
+
+    /// Encodes the segment as a FIT file. A `file_id` of type segment is
+    /// written when the segment has none.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, EncodeError> {
+        let file_id = self.file_id.clone().unwrap_or(FileId {
+            r#type: Some(SEGMENT_FILE_TYPE),
+            ..FileId::default()
+        });
+        let messages = std::iter::once(file_id.to_message())
+            .chain(self.id.iter().map(SegmentId::to_message))
+            .chain(
+                self.leaderboard
+                    .iter()
+                    .map(SegmentLeaderboardEntry::to_message),
+            )
+            .chain(self.points.iter().map(SegmentPoint::to_message))
+            .chain(self.laps.iter().map(SegmentLap::to_message))
+            .collect::<Vec<_>>();
+        encode(&messages)
+    }
+}
+
+impl FitFile {
+    pub fn segment(&self) -> Segment {
+        Segment::from_file(self)
+    }
+}
+
+#[test]
+fn test_segment_round_trip() {
+    let point = |index: u16, distance: f64, leader_time: Vec<f64>| SegmentPoint {
+        message_index: Some(index),
+        position_lat: Some(index as i32 * 1000),
+        position_long: Some(-(index as i32) * 1000),
+        distance: Some(distance),
+        leader_time: Some(leader_time),
+        ..SegmentPoint::default()
+    };
+    let segment = Segment {
+        file_id: None,
+        id: Some(SegmentId {
+            name: Some("Hill climb".to_string()),
+            uuid: Some("1234".to_string()),
+            sport: Some(2),
+            enabled: Some(true),
+            ..SegmentId::default()
+        }),
+        leaderboard: vec![SegmentLeaderboardEntry {
+            message_index: Some(0),
+            name: Some("KOM".to_string()),
+            r#type: Some(2),
+            segment_time: Some(301.5),
+            ..SegmentLeaderboardEntry::default()
+        }],
+        points: vec![point(0, 0.0, vec![0.0]), point(1, 1200.5, vec![301.5])],
+        laps: vec![SegmentLap {
+            message_index: Some(0),
+            total_elapsed_time: Some(320.0),
+            total_distance: Some(1200.5),
+            ..SegmentLap::default()
+        }],
+    };
+    let data = segment.to_bytes().unwrap();
+    let decoded = FitFile::parse(&data).unwrap().segment();
+    assert_eq!(decoded.file_id.unwrap().r#type, Some(SEGMENT_FILE_TYPE));
+    assert_eq!(
+        Segment {
+            file_id: None,
+            ..decoded
+        },
+        segment
+    );
+}
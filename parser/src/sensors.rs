@@ -0,0 +1,243 @@
+//! Raw accelerometer, gyroscope and magnetometer samples and their
+//! calibration from `three_d_sensor_calibration` messages.
+//!
+//! The data messages share their field layout: up to 30 samples per axis
+//! with `sample_time_offset` giving each sample's offset from the message's
+//! timestamp.
+
+use crate::fit_file::FitFile;
+use crate::fit_messages::DataMessage;
+use crate::profile::messages::ThreeDSensorCalibration;
+use crate::profile::{
+    SensorType, ACCELEROMETER_DATA_MESSAGE, GYROSCOPE_DATA_MESSAGE, MAGNETOMETER_DATA_MESSAGE,
+    THREE_D_SENSOR_CALIBRATION_MESSAGE,
+};
+
+// Field numbers shared by the sensor data messages.
+const TIMESTAMP_MS_FIELD_NUMBER: u8 = 0;
+const SAMPLE_TIME_OFFSET_FIELD_NUMBER: u8 = 1;
+const RAW_FIELD_NUMBERS: [u8; 3] = [2, 3, 4];
+const CALIBRATED_FIELD_NUMBERS: [u8; 3] = [5, 6, 7];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Sensor {
+    Accelerometer,
+    Gyroscope,
+    Magnetometer,
+}
+
+impl Sensor {
+    fn message_number(self) -> u16 {
+        match self {
+            Sensor::Accelerometer => ACCELEROMETER_DATA_MESSAGE.number,
+            Sensor::Gyroscope => GYROSCOPE_DATA_MESSAGE.number,
+            Sensor::Magnetometer => MAGNETOMETER_DATA_MESSAGE.number,
+        }
+    }
+
+    fn sensor_type(self) -> u8 {
+        match self {
+            Sensor::Accelerometer => SensorType::Accelerometer as u8,
+            Sensor::Gyroscope => SensorType::Gyroscope as u8,
+            Sensor::Magnetometer => SensorType::Compass as u8,
+        }
+    }
+}
+
+/// Conversion of raw ADC counts of a sensor to g, degrees per second or
+/// gauss.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SensorCalibration {
+    /// Seconds since the FIT epoch.
+    pub timestamp: Option<u32>,
+    pub sensor_type: u8,
+    pub calibration_factor: f64,
+    pub calibration_divisor: f64,
+    pub level_shift: f64,
+    /// Offsets of the x, y and z axes in counts.
+    pub offset_cal: [f64; 3],
+    /// Row major rotation from the sensor axes to the device axes.
+    pub orientation_matrix: [[f64; 3]; 3],
+}
+
+impl SensorCalibration {
+    pub fn from_message(calibration: &ThreeDSensorCalibration) -> Option<SensorCalibration> {
+        let divisor = calibration.calibration_divisor.filter(|d| *d != 0)?;
+        let offset_cal = match calibration.offset_cal.as_deref() {
+            Some([x, y, z]) => [*x as f64, *y as f64, *z as f64],
+            _ => [0.0; 3],
+        };
+        let orientation_matrix = match calibration.orientation_matrix.as_deref() {
+            Some(m) if m.len() == 9 => [[m[0], m[1], m[2]], [m[3], m[4], m[5]], [m[6], m[7], m[8]]],
+            _ => [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+        };
+        Some(SensorCalibration {
+            timestamp: calibration.timestamp,
+            sensor_type: calibration.sensor_type?,
+            calibration_factor: calibration.calibration_factor? as f64,
+            calibration_divisor: divisor as f64,
+            level_shift: calibration.level_shift.unwrap_or(0) as f64,
+            offset_cal,
+            orientation_matrix,
+        })
+    }
+
+    /// Calibrated x, y and z of raw counts: shifted by the level shift and
+    /// axis offsets, scaled by the calibration factor and divisor and rotated
+    /// by the orientation matrix.
+    pub fn apply(&self, raw: [u16; 3]) -> [f64; 3] {
+        let mut scaled = [0.0; 3];
+        for (axis, value) in scaled.iter_mut().enumerate() {
+            *value = (raw[axis] as f64 - self.level_shift - self.offset_cal[axis])
+                * self.calibration_factor
+                / self.calibration_divisor;
+        }
+        let mut rotated = [0.0; 3];
+        for (row, value) in rotated.iter_mut().enumerate() {
+            *value = (0..3)
+                .map(|col| self.orientation_matrix[row][col] * scaled[col])
+                .sum();
+        }
+        rotated
+    }
+}
+
+/// One sample of a three axis sensor.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SensorSample {
+    /// Milliseconds since the FIT epoch.
+    pub timestamp_ms: u64,
+    /// ADC counts of the x, y and z axes.
+    pub raw: [u16; 3],
+    /// Calibrated x, y and z, when the file has a calibration for the sensor
+    /// or calibrated values of its own.
+    pub calibrated: Option<[f64; 3]>,
+}
+
+// Samples of one data message, calibrated with `calibration` when given.
+fn message_samples(
+    message: &DataMessage,
+    calibration: Option<&SensorCalibration>,
+) -> Option<Vec<SensorSample>> {
+    let start = message.timestamp()? as u64 * 1000
+        + message
+            .typed_field::<u16>(TIMESTAMP_MS_FIELD_NUMBER)
+            .unwrap_or(0) as u64;
+    let offsets = message.typed_field::<Vec<u16>>(SAMPLE_TIME_OFFSET_FIELD_NUMBER)?;
+    let axes = RAW_FIELD_NUMBERS.map(|n| message.typed_field::<Vec<u16>>(n));
+    let calibrated = CALIBRATED_FIELD_NUMBERS.map(|n| message.typed_field::<Vec<f32>>(n));
+    let samples = (0..offsets.len())
+        .map_while(|idx| {
+            let raw = [
+                *axes[0].as_ref()?.get(idx)?,
+                *axes[1].as_ref()?.get(idx)?,
+                *axes[2].as_ref()?.get(idx)?,
+            ];
+            let device_calibrated = || {
+                Some([
+                    *calibrated[0].as_ref()?.get(idx)? as f64,
+                    *calibrated[1].as_ref()?.get(idx)? as f64,
+                    *calibrated[2].as_ref()?.get(idx)? as f64,
+                ])
+            };
+            Some(SensorSample {
+                timestamp_ms: start + offsets[idx] as u64,
+                raw,
+                calibrated: calibration.map(|c| c.apply(raw)).or_else(device_calibrated),
+            })
+        })
+        .collect();
+    Some(samples)
+}
+
+impl FitFile {
+    /// Calibrations of the three axis sensors in file order.
+    pub fn sensor_calibrations(&self) -> Vec<SensorCalibration> {
+        self.typed_messages(
+            THREE_D_SENSOR_CALIBRATION_MESSAGE.number,
+            ThreeDSensorCalibration::from_message,
+        )
+        .filter_map(|c| SensorCalibration::from_message(&c))
+        .collect()
+    }
+
+    /// Samples of the sensor in file order. Each message is calibrated with
+    /// the last calibration of the sensor before it, or the first one when
+    /// none precedes it.
+    pub fn sensor_samples(&self, sensor: Sensor) -> Vec<SensorSample> {
+        let calibrations = self
+            .sensor_calibrations()
+            .into_iter()
+            .filter(|c| c.sensor_type == sensor.sensor_type())
+            .collect::<Vec<SensorCalibration>>();
+        self.messages
+            .iter()
+            .filter(|m| m.global_message_number == sensor.message_number())
+            .filter_map(|m| {
+                let calibration = calibrations
+                    .iter()
+                    .rev()
+                    .find(|c| c.timestamp <= m.timestamp())
+                    .or_else(|| calibrations.first());
+                message_samples(m, calibration)
+            })
+            .flatten()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+use crate::fit_file::{test_fit_file, test_message};
+#[cfg(test)]
+use crate::fit_messages::Value;
+
+#[test]
+fn test_sensor_samples() {
+    let u16s = |values: &[u16]| Value::Array(values.iter().map(|v| Value::U16(*v)).collect());
+    let calibration = test_message(
+        THREE_D_SENSOR_CALIBRATION_MESSAGE.number,
+        vec![
+            (253, "timestamp", Value::U32(1000)),
+            (0, "sensor_type", Value::U8(SensorType::Accelerometer as u8)),
+            (1, "calibration_factor", Value::U32(2)),
+            (2, "calibration_divisor", Value::U32(1000)),
+            (3, "level_shift", Value::U32(2048)),
+            (
+                4,
+                "offset_cal",
+                Value::Array(vec![Value::I32(0), Value::I32(48), Value::I32(-52)]),
+            ),
+            (
+                5,
+                "orientation_matrix",
+                Value::Array(
+                    [0.0, 1.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, -1.0]
+                        .iter()
+                        .map(|v| Value::F64(*v))
+                        .collect(),
+                ),
+            ),
+        ],
+    );
+    let data = test_message(
+        ACCELEROMETER_DATA_MESSAGE.number,
+        vec![
+            (253, "timestamp", Value::U32(1001)),
+            (0, "timestamp_ms", Value::U16(250)),
+            (1, "sample_time_offset", u16s(&[0, 40])),
+            (2, "accel_x", u16s(&[2548, 2048])),
+            (3, "accel_y", u16s(&[2096, 2096])),
+            (4, "accel_z", u16s(&[1996, 2496])),
+        ],
+    );
+    let file = test_fit_file(vec![calibration, data]);
+    assert_eq!(file.sensor_calibrations().len(), 1);
+    let samples = file.sensor_samples(Sensor::Accelerometer);
+    assert_eq!(samples.len(), 2);
+    assert_eq!(samples[0].timestamp_ms, 1_001_250);
+    assert_eq!(samples[1].timestamp_ms, 1_001_290);
+    assert_eq!(samples[0].raw, [2548, 2096, 1996]);
+    assert_eq!(samples[0].calibrated, Some([0.0, 1.0, 0.0]));
+    assert_eq!(samples[1].calibrated, Some([0.0, 0.0, -1.0]));
+    assert!(file.sensor_samples(Sensor::Gyroscope).is_empty());
+}
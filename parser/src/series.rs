@@ -0,0 +1,567 @@
+//! Columnar views of the time series in decoded messages.
+
+use std::fmt;
+use std::ops::Range;
+
+use crate::fit_file::FitFile;
+use crate::fit_messages::DataMessage;
+use crate::profile::RECORD_MESSAGE;
+
+/// Degrees per semicircle, the unit of positions in FIT files.
+pub const SEMICIRCLES_TO_DEGREES: f64 = 180.0 / 2147483648.0;
+
+/// Values of the record messages of a file as aligned columns, one entry per
+/// record message in file order. Missing values are `None` for timestamps and
+/// NaN for the other columns.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RecordSeries {
+    /// Seconds since the FIT epoch, 1989-12-31T00:00:00Z.
+    pub timestamp: Vec<Option<u32>>,
+    /// Degrees.
+    pub position_lat: Vec<f64>,
+    /// Degrees.
+    pub position_long: Vec<f64>,
+    /// Meters, from `enhanced_altitude` when present.
+    pub altitude: Vec<f64>,
+    /// Beats per minute.
+    pub heart_rate: Vec<f64>,
+    /// Watts.
+    pub power: Vec<f64>,
+    /// Revolutions or strides per minute.
+    pub cadence: Vec<f64>,
+    /// Meters per second, from `enhanced_speed` when present.
+    pub speed: Vec<f64>,
+    /// Meters.
+    pub distance: Vec<f64>,
+    /// Degrees Celsius.
+    pub temperature: Vec<f64>,
+}
+
+impl RecordSeries {
+    pub fn len(&self) -> usize {
+        self.timestamp.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.timestamp.is_empty()
+    }
+
+    /// The samples in the `range` of indices.
+    pub fn slice(&self, range: Range<usize>) -> RecordSeries {
+        RecordSeries {
+            timestamp: self.timestamp[range.clone()].to_vec(),
+            position_lat: self.position_lat[range.clone()].to_vec(),
+            position_long: self.position_long[range.clone()].to_vec(),
+            altitude: self.altitude[range.clone()].to_vec(),
+            heart_rate: self.heart_rate[range.clone()].to_vec(),
+            power: self.power[range.clone()].to_vec(),
+            cadence: self.cadence[range.clone()].to_vec(),
+            speed: self.speed[range.clone()].to_vec(),
+            distance: self.distance[range.clone()].to_vec(),
+            temperature: self.temperature[range].to_vec(),
+        }
+    }
+
+    fn with_capacity(capacity: usize) -> RecordSeries {
+        RecordSeries {
+            timestamp: Vec::with_capacity(capacity),
+            position_lat: Vec::with_capacity(capacity),
+            position_long: Vec::with_capacity(capacity),
+            altitude: Vec::with_capacity(capacity),
+            heart_rate: Vec::with_capacity(capacity),
+            power: Vec::with_capacity(capacity),
+            cadence: Vec::with_capacity(capacity),
+            speed: Vec::with_capacity(capacity),
+            distance: Vec::with_capacity(capacity),
+            temperature: Vec::with_capacity(capacity),
+        }
+    }
+
+    fn push(&mut self, record: &DataMessage) {
+        self.timestamp.push(record.timestamp());
+        self.position_lat
+            .push(numeric(record, &["position_lat"]) * SEMICIRCLES_TO_DEGREES);
+        self.position_long
+            .push(numeric(record, &["position_long"]) * SEMICIRCLES_TO_DEGREES);
+        self.altitude
+            .push(numeric(record, &["enhanced_altitude", "altitude"]));
+        self.heart_rate.push(numeric(record, &["heart_rate"]));
+        self.power.push(numeric(record, &["power"]));
+        self.cadence.push(numeric(record, &["cadence"]));
+        self.speed
+            .push(numeric(record, &["enhanced_speed", "speed"]));
+        self.distance.push(numeric(record, &["distance"]));
+        self.temperature.push(numeric(record, &["temperature"]));
+    }
+}
+
+/// How values between two samples are filled in when resampling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Value of the latest sample at or before the time.
+    Hold,
+    /// Linear interpolation between the samples around the time.
+    Linear,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResampleOptions {
+    /// Seconds between resampled values.
+    pub interval: u32,
+    pub interpolation: Interpolation,
+    /// Longest time in seconds between two samples that is interpolated
+    /// over. The grid is split at longer gaps, such as pauses, with one row
+    /// of NaN values marking each split.
+    pub max_gap: Option<u32>,
+}
+
+impl Default for ResampleOptions {
+    fn default() -> Self {
+        ResampleOptions {
+            interval: 1,
+            interpolation: Interpolation::Linear,
+            max_gap: None,
+        }
+    }
+}
+
+/// Most values in a resampled series, about 48 days of seconds.
+pub const MAX_RESAMPLED_LEN: u64 = 1 << 22;
+
+/// Why a series could not be resampled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResampleError {
+    /// The grid would have more than [`MAX_RESAMPLED_LEN`] values, such as
+    /// for a series with a timestamp far off from the others.
+    TooLong { len: u64 },
+}
+
+impl fmt::Display for ResampleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooLong { len } => write!(
+                f,
+                "Resampled series of {} values exceeds the limit of {}",
+                len, MAX_RESAMPLED_LEN
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ResampleError {}
+
+// Where a resampled time falls among the source samples: the samples before
+// and after it and the relative position between them, or `None` for the
+// row marking a split of the grid.
+type SamplePosition = Option<(usize, usize, f64)>;
+
+fn resample_column(
+    column: &[f64],
+    positions: &[SamplePosition],
+    interpolation: Interpolation,
+) -> Vec<f64> {
+    positions
+        .iter()
+        .map(|position| match (position, interpolation) {
+            (None, _) => f64::NAN,
+            (Some((before, _, _)), Interpolation::Hold) => column[*before],
+            (Some((before, after, fraction)), Interpolation::Linear) => {
+                if *fraction == 0.0 {
+                    column[*before]
+                } else {
+                    column[*before] + (column[*after] - column[*before]) * fraction
+                }
+            }
+        })
+        .collect()
+}
+
+// Grid times of the samples `(time, index)` of one segment, in time order,
+// and their positions among the samples.
+fn resample_segment(
+    samples: &[(u32, usize)],
+    interval: u32,
+    grid: &mut Vec<u32>,
+    positions: &mut Vec<SamplePosition>,
+) {
+    let (first, last) = (samples[0].0, samples[samples.len() - 1].0);
+    let mut next = 0;
+    for t in (first..=last).step_by(interval as usize) {
+        while next + 1 < samples.len() && samples[next + 1].0 <= t {
+            next += 1;
+        }
+        let (before_time, before) = samples[next];
+        let position = match samples.get(next + 1) {
+            Some((after_time, after)) if before_time != t => Some((
+                before,
+                *after,
+                (t - before_time) as f64 / (after_time - before_time) as f64,
+            )),
+            _ => Some((before, before, 0.0)),
+        };
+        grid.push(t);
+        positions.push(position);
+    }
+}
+
+/// Longest time in seconds between records that a value is held over when
+/// resampling to seconds for power, effort and zone metrics. Longer gaps are
+/// pauses, such as auto-pauses and stops.
+pub const MAX_HOLD_GAP: u32 = 30;
+
+impl RecordSeries {
+    /// Resamples the series to one value per second, holding each value
+    /// until the next record over gaps up to [`MAX_HOLD_GAP`] seconds. Pauses
+    /// are left out, with one row of NaN values marking each.
+    pub fn resample_seconds(&self) -> Result<RecordSeries, ResampleError> {
+        self.resample(&ResampleOptions {
+            interval: 1,
+            interpolation: Interpolation::Hold,
+            max_gap: Some(MAX_HOLD_GAP),
+        })
+    }
+
+    /// Resamples the series to a grid of `options.interval` seconds from its
+    /// first to its last timestamp, split at gaps longer than
+    /// `options.max_gap`. Samples without a timestamp are ignored.
+    pub fn resample(&self, options: &ResampleOptions) -> Result<RecordSeries, ResampleError> {
+        assert!(options.interval > 0, "Resample interval must be positive");
+        let mut samples = self
+            .timestamp
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, t)| t.map(|t| (t, idx)))
+            .collect::<Vec<(u32, usize)>>();
+        samples.sort_by_key(|(t, _)| *t);
+        let mut segments = Vec::new();
+        let mut start = 0;
+        for idx in 1..=samples.len() {
+            let split = idx == samples.len()
+                || options
+                    .max_gap
+                    .is_some_and(|max_gap| samples[idx].0 - samples[idx - 1].0 > max_gap);
+            if split {
+                segments.push(&samples[start..idx]);
+                start = idx;
+            }
+        }
+        // each segment and the row marking the split after it
+        let len = segments
+            .iter()
+            .map(|s| (s[s.len() - 1].0 - s[0].0) as u64 / options.interval as u64 + 2)
+            .sum::<u64>()
+            .saturating_sub(1);
+        if len > MAX_RESAMPLED_LEN {
+            return Err(ResampleError::TooLong { len });
+        }
+        let mut grid = Vec::with_capacity(len as usize);
+        let mut positions: Vec<SamplePosition> = Vec::with_capacity(len as usize);
+        for (idx, segment) in segments.iter().enumerate() {
+            resample_segment(segment, options.interval, &mut grid, &mut positions);
+            if let Some(next) = segments.get(idx + 1) {
+                let end = grid[grid.len() - 1];
+                grid.push(end.saturating_add(options.interval).min(next[0].0 - 1));
+                positions.push(None);
+            }
+        }
+        let column = |values: &[f64]| resample_column(values, &positions, options.interpolation);
+        Ok(RecordSeries {
+            timestamp: grid.into_iter().map(Some).collect(),
+            position_lat: column(&self.position_lat),
+            position_long: column(&self.position_long),
+            altitude: column(&self.altitude),
+            heart_rate: column(&self.heart_rate),
+            power: column(&self.power),
+            cadence: column(&self.cadence),
+            speed: column(&self.speed),
+            distance: column(&self.distance),
+            temperature: column(&self.temperature),
+        })
+    }
+}
+
+/// Indices of `n_points` points of the series of `(x[i], y[i])` points chosen
+/// with the Largest-Triangle-Three-Buckets algorithm, which keeps the visual
+/// shape of the series. The first and last points are always included.
+/// Points with a NaN coordinate are only chosen when a bucket has nothing
+/// else.
+pub fn lttb_indices(x: &[f64], y: &[f64], n_points: usize) -> Vec<usize> {
+    let len = x.len().min(y.len());
+    match n_points {
+        _ if n_points >= len => return (0..len).collect(),
+        0 => return vec![],
+        1 => return vec![0],
+        2 => return vec![0, len - 1],
+        _ => {}
+    }
+    let mut indices = Vec::with_capacity(n_points);
+    indices.push(0);
+    let bucket_size = (len - 2) as f64 / (n_points - 2) as f64;
+    let bucket_start = |bucket: usize| 1 + (bucket as f64 * bucket_size) as usize;
+    let is_point = |idx: usize| !x[idx].is_nan() && !y[idx].is_nan();
+    // Last chosen point with both coordinates, the anchor of the triangles.
+    let mut previous = is_point(0).then_some((x[0], y[0]));
+    for bucket in 0..n_points - 2 {
+        let (start, end) = (bucket_start(bucket), bucket_start(bucket + 1));
+        // Average of the next bucket, or the last point for the last bucket.
+        let (next_start, next_end) = if bucket + 1 < n_points - 2 {
+            (end, bucket_start(bucket + 2))
+        } else {
+            (len - 1, len)
+        };
+        let next = (next_start..next_end)
+            .filter(|idx| is_point(*idx))
+            .collect::<Vec<usize>>();
+        let (avg_x, avg_y) = match next.len() {
+            0 => previous.unwrap_or((0.0, 0.0)),
+            count => (
+                next.iter().map(|idx| x[*idx]).sum::<f64>() / count as f64,
+                next.iter().map(|idx| y[*idx]).sum::<f64>() / count as f64,
+            ),
+        };
+        let (px, py) = previous.unwrap_or((avg_x, avg_y));
+        let mut chosen = start;
+        let mut max_area = -1.0;
+        for idx in (start..end).filter(|idx| is_point(*idx)) {
+            let area = ((px - avg_x) * (y[idx] - py) - (px - x[idx]) * (avg_y - py)).abs();
+            if area > max_area {
+                max_area = area;
+                chosen = idx;
+            }
+        }
+        indices.push(chosen);
+        if is_point(chosen) {
+            previous = Some((x[chosen], y[chosen]));
+        }
+    }
+    indices.push(len - 1);
+    indices
+}
+
+/// Downsamples `(x, y)` points to `n_points` points for charting, see
+/// [`lttb_indices`].
+pub fn downsample(series: &[(f64, f64)], n_points: usize) -> Vec<(f64, f64)> {
+    let (x, y): (Vec<f64>, Vec<f64>) = series.iter().copied().unzip();
+    lttb_indices(&x, &y, n_points)
+        .into_iter()
+        .map(|idx| series[idx])
+        .collect()
+}
+
+impl RecordSeries {
+    /// Keeps `n_points` samples chosen to preserve the shape of the `column`
+    /// over time, see [`lttb_indices`]. Samples without a timestamp are
+    /// dropped.
+    pub fn downsample<F>(&self, column: F, n_points: usize) -> RecordSeries
+    where
+        F: Fn(&RecordSeries) -> &[f64],
+    {
+        let rows = (0..self.len())
+            .filter(|idx| self.timestamp[*idx].is_some())
+            .collect::<Vec<usize>>();
+        let x = rows
+            .iter()
+            .map(|idx| self.timestamp[*idx].unwrap_or(0) as f64)
+            .collect::<Vec<f64>>();
+        let values = column(self);
+        let y = rows.iter().map(|idx| values[*idx]).collect::<Vec<f64>>();
+        let mut series = RecordSeries::with_capacity(n_points.min(rows.len()));
+        for idx in lttb_indices(&x, &y, n_points) {
+            series.push_row(self, rows[idx]);
+        }
+        series
+    }
+
+    fn push_row(&mut self, other: &RecordSeries, idx: usize) {
+        self.timestamp.push(other.timestamp[idx]);
+        self.position_lat.push(other.position_lat[idx]);
+        self.position_long.push(other.position_long[idx]);
+        self.altitude.push(other.altitude[idx]);
+        self.heart_rate.push(other.heart_rate[idx]);
+        self.power.push(other.power[idx]);
+        self.cadence.push(other.cadence[idx]);
+        self.speed.push(other.speed[idx]);
+        self.distance.push(other.distance[idx]);
+        self.temperature.push(other.temperature[idx]);
+    }
+}
+
+// Value of the first of the named fields present in the message, NaN if none
+// is present or numeric.
+fn numeric(message: &DataMessage, names: &[&str]) -> f64 {
+    names
+        .iter()
+        .find_map(|name| message.field_by_name(name).and_then(|f| f.value.as_f64()))
+        .unwrap_or(f64::NAN)
+}
+
+impl FitFile {
+    /// The record messages of the file as aligned columns.
+    pub fn record_series(&self) -> RecordSeries {
+        let records = self
+            .messages
+            .iter()
+            .filter(|m| m.global_message_number == RECORD_MESSAGE.number);
+        let mut series = RecordSeries::with_capacity(records.clone().count());
+        for record in records {
+            series.push(record);
+        }
+        series
+    }
+}
+
+#[cfg(test)]
+use crate::fit_file::{test_fit_file, test_message};
+#[cfg(test)]
+use crate::fit_messages::Value;
+
+#[cfg(test)]
+fn test_record(fields: Vec<(u8, &'static str, Value)>) -> DataMessage {
+    test_message(RECORD_MESSAGE.number, fields)
+}
+
+#[test]
+fn test_record_series() {
+    let fit_file = test_fit_file(vec![
+        test_record(vec![
+            (253, "timestamp", Value::U32(1000)),
+            (0, "position_lat", Value::I32(1 << 30)),
+            (3, "heart_rate", Value::U8(120)),
+        ]),
+        test_record(vec![
+            (6, "speed", Value::F64(2.5)),
+            (73, "enhanced_speed", Value::F64(2.75)),
+        ]),
+    ]);
+    let series = fit_file.record_series();
+    assert_eq!(series.len(), 2);
+    assert_eq!(series.timestamp, vec![Some(1000), None]);
+    assert_eq!(series.position_lat[0], 90.0);
+    assert!(series.position_lat[1].is_nan());
+    assert_eq!(series.heart_rate[0], 120.0);
+    assert!(series.heart_rate[1].is_nan());
+    assert_eq!(series.speed[1], 2.75);
+    let second = series.slice(1..2);
+    assert_eq!(second.timestamp, vec![None]);
+    assert_eq!(second.speed, vec![2.75]);
+    assert_eq!(second.temperature.len(), 1);
+}
+
+/// A series of samples at the timestamps with NaN in every column, for tests
+/// to set the columns they use with `..test_series(timestamps)`.
+#[cfg(test)]
+pub(crate) fn test_series(timestamps: &[u32]) -> RecordSeries {
+    let nan = vec![f64::NAN; timestamps.len()];
+    RecordSeries {
+        timestamp: timestamps.iter().copied().map(Some).collect(),
+        position_lat: nan.clone(),
+        position_long: nan.clone(),
+        altitude: nan.clone(),
+        heart_rate: nan.clone(),
+        power: nan.clone(),
+        cadence: nan.clone(),
+        speed: nan.clone(),
+        distance: nan.clone(),
+        temperature: nan,
+    }
+}
+
+#[test]
+fn test_resample_linear_and_hold() {
+    let series = RecordSeries {
+        heart_rate: vec![100.0, 110.0, 90.0],
+        ..test_series(&[10, 12, 13])
+    };
+    let linear = series.resample(&ResampleOptions::default()).unwrap();
+    assert_eq!(
+        linear.timestamp,
+        vec![Some(10), Some(11), Some(12), Some(13)]
+    );
+    assert_eq!(linear.heart_rate, vec![100.0, 105.0, 110.0, 90.0]);
+    let hold = series
+        .resample(&ResampleOptions {
+            interpolation: Interpolation::Hold,
+            ..ResampleOptions::default()
+        })
+        .unwrap();
+    assert_eq!(hold.heart_rate, vec![100.0, 100.0, 110.0, 90.0]);
+}
+
+#[test]
+fn test_resample_gap() {
+    let series = RecordSeries {
+        heart_rate: vec![100.0, 100.0, 120.0],
+        ..test_series(&[0, 1, 5])
+    };
+    let resampled = series
+        .resample(&ResampleOptions {
+            max_gap: Some(2),
+            ..ResampleOptions::default()
+        })
+        .unwrap();
+    assert_eq!(
+        resampled.timestamp,
+        vec![Some(0), Some(1), Some(2), Some(5)]
+    );
+    assert_eq!(resampled.heart_rate[1], 100.0);
+    assert!(resampled.heart_rate[2].is_nan());
+    assert_eq!(resampled.heart_rate[3], 120.0);
+
+    // a timestamp from before the clock was set does not fill the grid
+    let series = RecordSeries {
+        heart_rate: vec![100.0; 3],
+        ..test_series(&[1000, 1001, 1_000_000_000])
+    };
+    assert_eq!(
+        series.resample(&ResampleOptions::default()),
+        Err(ResampleError::TooLong { len: 999_999_001 })
+    );
+    let resampled = series
+        .resample(&ResampleOptions {
+            max_gap: Some(10),
+            ..ResampleOptions::default()
+        })
+        .unwrap();
+    assert_eq!(resampled.len(), 4);
+}
+
+#[test]
+fn test_downsample_keeps_peaks() {
+    let points = (0..100)
+        .map(|i| (i as f64, if i == 37 { 50.0 } else { 0.0 }))
+        .collect::<Vec<(f64, f64)>>();
+    let downsampled = downsample(&points, 10);
+    assert_eq!(downsampled.len(), 10);
+    assert_eq!(downsampled[0], (0.0, 0.0));
+    assert_eq!(downsampled[9], (99.0, 0.0));
+    assert!(downsampled.contains(&(37.0, 50.0)));
+    assert_eq!(downsample(&points[..5], 10), points[..5].to_vec());
+    assert_eq!(downsample(&points, 2), vec![(0.0, 0.0), (99.0, 0.0)]);
+}
+
+#[test]
+fn test_lttb_skips_nan() {
+    let x = (0..100).map(|i| i as f64).collect::<Vec<f64>>();
+    let mut y = (0..100)
+        .map(|i| if i == 44 { 50.0 } else { 0.0 })
+        .collect::<Vec<f64>>();
+    assert!(lttb_indices(&x, &y, 10).contains(&44));
+    y[55] = f64::NAN;
+    assert!(lttb_indices(&x, &y, 10).contains(&44));
+    y[44] = f64::NAN;
+    y[45] = 50.0;
+    let indices = lttb_indices(&x, &y, 10);
+    assert!(indices.contains(&45));
+    assert!(!indices.contains(&44));
+}
+
+#[test]
+fn test_record_series_downsample() {
+    let series = RecordSeries {
+        heart_rate: vec![100.0, 100.0, 150.0, 100.0, 100.0, 100.0],
+        ..test_series(&[0, 1, 2, 3, 4, 5])
+    };
+    let downsampled = series.downsample(|s| &s.heart_rate, 3);
+    assert_eq!(downsampled.timestamp, vec![Some(0), Some(2), Some(5)]);
+    assert_eq!(downsampled.heart_rate, vec![100.0, 150.0, 100.0]);
+}
@@ -0,0 +1,134 @@
+//! Settings, sport, goals and schedules files exported from devices, as
+//! typed messages that can be read and written back.
+
+use crate::encode::{encode, EncodeError};
+use crate::fit_file::FitFile;
+use crate::fit_messages::DataMessage;
+use crate::profile::messages::{
+    BikeProfile, FileId, Goal, HrZone, PowerZone, Schedule, Sport, ZonesTarget,
+};
+use crate::profile::{
+    BIKE_PROFILE_MESSAGE, FILE_ID_MESSAGE, GOAL_MESSAGE, HR_ZONE_MESSAGE, POWER_ZONE_MESSAGE,
+    SCHEDULE_MESSAGE, SPORT_MESSAGE, ZONES_TARGET_MESSAGE,
+};
+
+/// The settings messages of a file, each kind in file order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Settings {
+    pub file_id: Option<FileId>,
+    pub sports: Vec<Sport>,
+    pub zones_targets: Vec<ZonesTarget>,
+    pub hr_zones: Vec<HrZone>,
+    pub power_zones: Vec<PowerZone>,
+    pub bike_profiles: Vec<BikeProfile>,
+    pub goals: Vec<Goal>,
+    pub schedules: Vec<Schedule>,
+}
+
+impl Settings {
+    pub fn from_file(file: &FitFile) -> Settings {
+        Settings {
+            file_id: file
+                .typed_messages(FILE_ID_MESSAGE.number, FileId::from_message)
+                .next(),
+            sports: file
+                .typed_messages(SPORT_MESSAGE.number, Sport::from_message)
+                .collect(),
+            zones_targets: file
+                .typed_messages(ZONES_TARGET_MESSAGE.number, ZonesTarget::from_message)
+                .collect(),
+            hr_zones: file
+                .typed_messages(HR_ZONE_MESSAGE.number, HrZone::from_message)
+                .collect(),
+            power_zones: file
+                .typed_messages(POWER_ZONE_MESSAGE.number, PowerZone::from_message)
+                .collect(),
+            bike_profiles: file
+                .typed_messages(BIKE_PROFILE_MESSAGE.number, BikeProfile::from_message)
+                .collect(),
+            goals: file
+                .typed_messages(GOAL_MESSAGE.number, Goal::from_message)
+                .collect(),
+            schedules: file
+                .typed_messages(SCHEDULE_MESSAGE.number, Schedule::from_message)
+                .collect(),
+        }
+    }
+
+    /// The messages in the order devices write them, `file_id` first.
+    pub fn to_messages(&self) -> Vec<DataMessage> {
+        self.file_id
+            .iter()
+            .map(FileId::to_message)
+            .chain(self.zones_targets.iter().map(ZonesTarget::to_message))
+            .chain(self.sports.iter().map(Sport::to_message))
+            .chain(self.hr_zones.iter().map(HrZone::to_message))
+            .chain(self.power_zones.iter().map(PowerZone::to_message))
+            .chain(self.bike_profiles.iter().map(BikeProfile::to_message))
+            .chain(self.goals.iter().map(Goal::to_message))
+            .chain(self.schedules.iter().map(Schedule::to_message))
+            .collect()
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, EncodeError> {
+        encode(&self.to_messages())
+    }
+}
+
+impl FitFile {
+    pub fn settings(&self) -> Settings {
+        Settings::from_file(self)
+    }
+}
+
+#[test]
+fn test_settings_round_trip() {
+    let settings = Settings {
+        file_id: Some(FileId {
+            r#type: Some(3),
+            manufacturer: Some(1),
+            ..FileId::default()
+        }),
+        sports: vec![Sport {
+            sport: Some(2),
+            sub_sport: Some(0),
+            name: Some("Bike".to_string()),
+        }],
+        zones_targets: vec![ZonesTarget {
+            max_heart_rate: Some(190),
+            functional_threshold_power: Some(280),
+            ..ZonesTarget::default()
+        }],
+        hr_zones: vec![HrZone {
+            message_index: Some(0),
+            high_bpm: Some(120),
+            name: Some("Easy".to_string()),
+        }],
+        power_zones: vec![PowerZone {
+            message_index: Some(0),
+            high_value: Some(200),
+            name: None,
+        }],
+        bike_profiles: vec![BikeProfile {
+            message_index: Some(0),
+            name: Some("Road".to_string()),
+            odometer: Some(12345.67),
+            custom_wheelsize: Some(2.105),
+            ..BikeProfile::default()
+        }],
+        goals: vec![Goal {
+            message_index: Some(0),
+            sport: Some(1),
+            value: Some(100),
+            enabled: Some(true),
+            ..Goal::default()
+        }],
+        schedules: vec![Schedule {
+            completed: Some(false),
+            scheduled_time: Some(1_000_000_000),
+            ..Schedule::default()
+        }],
+    };
+    let data = settings.to_bytes().unwrap();
+    assert_eq!(FitFile::parse(&data).unwrap().settings(), settings);
+}
@@ -0,0 +1,157 @@
+//! Sleep stages from `sleep_level` messages of sleep monitoring files.
+//!
+//! `sleep_level` is newer than the bundled profile, so it is decoded by its
+//! message number. `sleep_assessment` scores are not decoded.
+
+use crate::fit_file::FitFile;
+
+/// Global message number of `sleep_level`.
+pub const SLEEP_LEVEL_MESSAGE_NUMBER: u16 = 275;
+
+// Field number of the stage in `sleep_level` messages.
+const SLEEP_LEVEL_FIELD_NUMBER: u8 = 0;
+
+/// Sleep sessions are split where consecutive stage samples are further
+/// apart than this many seconds.
+pub const MAX_SLEEP_GAP: u32 = 3600;
+
+// Length assumed for the last stage of a session without a following sample
+// when the session has only one sample.
+const DEFAULT_SAMPLE_INTERVAL: u32 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SleepStage {
+    Unmeasurable,
+    Awake,
+    Light,
+    Deep,
+    Rem,
+}
+
+impl SleepStage {
+    pub fn from_sleep_level(value: u8) -> Option<SleepStage> {
+        match value {
+            0 => Some(SleepStage::Unmeasurable),
+            1 => Some(SleepStage::Awake),
+            2 => Some(SleepStage::Light),
+            3 => Some(SleepStage::Deep),
+            4 => Some(SleepStage::Rem),
+            _ => None,
+        }
+    }
+}
+
+/// A period of one sleep stage, in seconds since the FIT epoch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SleepPeriod {
+    pub stage: SleepStage,
+    pub start: u32,
+    pub end: u32,
+}
+
+/// A night of sleep as a timeline of stages.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SleepSession {
+    pub stages: Vec<SleepPeriod>,
+}
+
+impl SleepSession {
+    pub fn start(&self) -> u32 {
+        self.stages.first().map_or(0, |p| p.start)
+    }
+
+    pub fn end(&self) -> u32 {
+        self.stages.last().map_or(0, |p| p.end)
+    }
+
+    /// Seconds spent in the stage.
+    pub fn time_in(&self, stage: SleepStage) -> u32 {
+        self.stages
+            .iter()
+            .filter(|p| p.stage == stage)
+            .map(|p| p.end - p.start)
+            .sum()
+    }
+}
+
+// Builds a session from stage samples, merging consecutive samples of the
+// same stage.
+fn session(samples: &[(u32, SleepStage)]) -> SleepSession {
+    let mut stages: Vec<SleepPeriod> = Vec::new();
+    for (idx, (start, stage)) in samples.iter().enumerate() {
+        let end = match samples.get(idx + 1) {
+            Some((next, _)) => *next,
+            None if idx > 0 => start + (start - samples[idx - 1].0),
+            None => start + DEFAULT_SAMPLE_INTERVAL,
+        };
+        match stages.last_mut() {
+            Some(last) if last.stage == *stage => last.end = end,
+            _ => stages.push(SleepPeriod {
+                stage: *stage,
+                start: *start,
+                end,
+            }),
+        }
+    }
+    SleepSession { stages }
+}
+
+impl FitFile {
+    /// Sleep sessions of the `sleep_level` messages, split at gaps longer
+    /// than [`MAX_SLEEP_GAP`].
+    pub fn sleep_sessions(&self) -> Vec<SleepSession> {
+        let mut samples = self
+            .messages
+            .iter()
+            .filter(|m| m.global_message_number == SLEEP_LEVEL_MESSAGE_NUMBER)
+            .filter_map(|m| {
+                let level = m.field(SLEEP_LEVEL_FIELD_NUMBER)?.value.as_u64()?;
+                let stage = SleepStage::from_sleep_level(u8::try_from(level).ok()?)?;
+                Some((m.timestamp()?, stage))
+            })
+            .collect::<Vec<(u32, SleepStage)>>();
+        samples.sort_by_key(|(t, _)| *t);
+        let mut sessions = Vec::new();
+        let mut start = 0;
+        for idx in 1..=samples.len() {
+            if idx == samples.len() || samples[idx].0 - samples[idx - 1].0 > MAX_SLEEP_GAP {
+                sessions.push(session(&samples[start..idx]));
+                start = idx;
+            }
+        }
+        sessions
+    }
+}
+
+#[cfg(test)]
+use crate::fit_file::{test_fit_file, test_message};
+#[cfg(test)]
+use crate::fit_messages::Value;
+
+#[test]
+fn test_sleep_sessions() {
+    let level = |t: u32, level: u8| {
+        test_message(
+            SLEEP_LEVEL_MESSAGE_NUMBER,
+            vec![
+                (253, "timestamp", Value::U32(t)),
+                (0, "sleep_level", Value::U8(level)),
+            ],
+        )
+    };
+    let file = test_fit_file(vec![
+        level(1000, 2),
+        level(1060, 2),
+        level(1120, 3),
+        level(1180, 4),
+        level(10000, 1),
+    ]);
+    let sessions = file.sleep_sessions();
+    assert_eq!(sessions.len(), 2);
+    assert_eq!(sessions[0].start(), 1000);
+    assert_eq!(sessions[0].end(), 1240);
+    assert_eq!(sessions[0].stages.len(), 3);
+    assert_eq!(sessions[0].time_in(SleepStage::Light), 120);
+    assert_eq!(sessions[0].time_in(SleepStage::Rem), 60);
+    assert_eq!(sessions[1].time_in(SleepStage::Awake), 60);
+}
@@ -0,0 +1,312 @@
+//! Export of parsed files as SQL for SQLite, for ad-hoc SQL analysis of an
+//! activity library: `sqlite3 library.db < export.sql`.
+//!
+//! Each exported file gets a row in the `fit_files` table and each message
+//! type a table named after the message, such as `record` or `session`,
+//! with a column per profile field named after the field. Fields not in the
+//! profile are `field_<number>` columns and messages not in the profile go
+//! to `message_<number>` tables. Every message row references its file by
+//! `fit_file_id` and, when its timestamp is within a session, the session by
+//! `session_id`. Fields named like these key columns get a `_field`
+//! suffix. Developer fields are not exported.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use crate::fit_file::FitFile;
+use crate::fit_messages::{DataMessage, Value};
+use crate::profile::messages::Session;
+use crate::profile::{message_info, SESSION_MESSAGE};
+
+const FILES_TABLE: &str = "fit_files";
+
+// Columns of every message table.
+const KEY_COLUMNS: [&str; 3] = ["id", "fit_file_id", "session_id"];
+
+fn table_name(message: &DataMessage) -> String {
+    message_info(message.global_message_number)
+        .map(|info| info.name.to_string())
+        .unwrap_or_else(|| format!("message_{}", message.global_message_number))
+}
+
+// Column of a field named after the field rather than an active subfield,
+// so that all values of the field share a column.
+fn column_name(global_message_number: u16, field_definition_number: u8) -> String {
+    message_info(global_message_number)
+        .and_then(|info| info.field(field_definition_number))
+        .map(|field| match field.name {
+            name if KEY_COLUMNS.contains(&name) => format!("{}_field", name),
+            name => name.to_string(),
+        })
+        .unwrap_or_else(|| format!("field_{}", field_definition_number))
+}
+
+fn column_type(value: &Value) -> &'static str {
+    match value {
+        Value::F32(_) | Value::F64(_) => "REAL",
+        Value::String(_) | Value::Array(_) => "TEXT",
+        Value::Bytes(_) => "BLOB",
+        _ => "INTEGER",
+    }
+}
+
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn quote_text(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+// Arrays are written as their values separated by commas.
+fn array_text(values: &[Value]) -> String {
+    values
+        .iter()
+        .map(|value| match value {
+            Value::String(s) => s.clone(),
+            value => value.as_f64().map_or_else(String::new, |v| v.to_string()),
+        })
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+// SQL literal of a value. Infinite and NaN floats are NULL. SQLite integers
+// are signed 64-bit, so unsigned values above `i64::MAX` keep their bits as
+// negative integers, as two's complement.
+fn literal(value: &Value) -> String {
+    match value {
+        Value::F32(_) | Value::F64(_) => match value.as_f64() {
+            Some(v) if v.is_finite() => format!("{:?}", v),
+            _ => "NULL".to_string(),
+        },
+        Value::String(s) => quote_text(s),
+        Value::Bytes(bytes) => {
+            let hex = bytes
+                .iter()
+                .map(|b| format!("{:02X}", b))
+                .collect::<String>();
+            format!("X'{}'", hex)
+        }
+        Value::Array(values) => quote_text(&array_text(values)),
+        value => value
+            .as_i64()
+            .or_else(|| value.as_u64().map(|v| v as i64))
+            .map_or_else(|| "NULL".to_string(), |v| v.to_string()),
+    }
+}
+
+/// Writes SQL that creates and fills the tables of the exported files. The
+/// SQL is meant for a new database: the ids of files and sessions count up
+/// from 1 within one exporter.
+pub struct SqliteExporter<W: Write> {
+    writer: W,
+    // Columns of the tables created so far.
+    tables: HashMap<String, Vec<String>>,
+    next_file_id: u64,
+    next_session_id: u64,
+}
+
+impl<W: Write> SqliteExporter<W> {
+    /// Writes the `fit_files` and `session` tables.
+    pub fn new(mut writer: W) -> io::Result<SqliteExporter<W>> {
+        writeln!(writer, "PRAGMA foreign_keys = ON;")?;
+        writeln!(
+            writer,
+            "CREATE TABLE IF NOT EXISTS {} (id INTEGER PRIMARY KEY, name TEXT);",
+            FILES_TABLE
+        )?;
+        let mut exporter = SqliteExporter {
+            writer,
+            tables: HashMap::new(),
+            next_file_id: 1,
+            next_session_id: 1,
+        };
+        exporter.create_table(SESSION_MESSAGE.name)?;
+        Ok(exporter)
+    }
+
+    fn create_table(&mut self, table: &str) -> io::Result<()> {
+        writeln!(
+            self.writer,
+            "CREATE TABLE IF NOT EXISTS {} (id INTEGER PRIMARY KEY, \
+             fit_file_id INTEGER NOT NULL REFERENCES {}(id), \
+             session_id INTEGER REFERENCES {}(id));",
+            quote_identifier(table),
+            FILES_TABLE,
+            SESSION_MESSAGE.name
+        )?;
+        self.tables.insert(table.to_string(), Vec::new());
+        Ok(())
+    }
+
+    // Creates the table of the message, or adds the columns it lacks.
+    fn ensure_table(&mut self, table: &str, message: &DataMessage) -> io::Result<()> {
+        if !self.tables.contains_key(table) {
+            self.create_table(table)?;
+        }
+        let columns = self.tables.get_mut(table).unwrap();
+        for field in &message.fields {
+            let column = column_name(message.global_message_number, field.field_definition_number);
+            if !columns.contains(&column) {
+                writeln!(
+                    self.writer,
+                    "ALTER TABLE {} ADD COLUMN {} {};",
+                    quote_identifier(table),
+                    quote_identifier(&column),
+                    column_type(&field.value)
+                )?;
+                columns.push(column);
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the messages of a file in one transaction, returning the id
+    /// of the file in `fit_files`. Sessions are written first for the other
+    /// messages to reference them, the rest in file order.
+    pub fn export(&mut self, name: &str, file: &FitFile) -> io::Result<u64> {
+        let file_id = self.next_file_id;
+        self.next_file_id += 1;
+        writeln!(self.writer, "BEGIN;")?;
+        writeln!(
+            self.writer,
+            "INSERT INTO {} (id, name) VALUES ({}, {});",
+            FILES_TABLE,
+            file_id,
+            quote_text(name)
+        )?;
+        // Ids and time ranges of the sessions in file order.
+        let sessions = file
+            .typed_messages(SESSION_MESSAGE.number, Session::from_message)
+            .map(|session| {
+                let id = self.next_session_id;
+                self.next_session_id += 1;
+                let start = session.start_time.unwrap_or(u32::MAX);
+                let end = session.total_elapsed_time.map_or(start, |elapsed| {
+                    start.saturating_add(elapsed.round() as u32)
+                });
+                (id, start, end)
+            })
+            .collect::<Vec<(u64, u32, u32)>>();
+        let mut session_messages = sessions.iter();
+        let (session_first, rest): (Vec<&DataMessage>, Vec<&DataMessage>) = file
+            .messages
+            .iter()
+            .partition(|m| m.global_message_number == SESSION_MESSAGE.number);
+        for message in session_first.into_iter().chain(rest) {
+            let table = table_name(message);
+            self.ensure_table(&table, message)?;
+            let (id, session_id) = if message.global_message_number == SESSION_MESSAGE.number {
+                let id = session_messages.next().map(|(id, _, _)| *id);
+                (id, id)
+            } else {
+                let session_id = message.timestamp().and_then(|t| {
+                    sessions
+                        .iter()
+                        .find(|(_, start, end)| (*start..=*end).contains(&t))
+                        .map(|(id, _, _)| *id)
+                });
+                (None, session_id)
+            };
+            let mut columns = vec![KEY_COLUMNS[1].to_string(), KEY_COLUMNS[2].to_string()];
+            let mut values = vec![
+                file_id.to_string(),
+                session_id.map_or_else(|| "NULL".to_string(), |id| id.to_string()),
+            ];
+            if let Some(id) = id {
+                columns.push(KEY_COLUMNS[0].to_string());
+                values.push(id.to_string());
+            }
+            for field in &message.fields {
+                let column =
+                    column_name(message.global_message_number, field.field_definition_number);
+                if !columns.contains(&column) {
+                    columns.push(column);
+                    values.push(literal(&field.value));
+                }
+            }
+            writeln!(
+                self.writer,
+                "INSERT INTO {} ({}) VALUES ({});",
+                quote_identifier(&table),
+                columns
+                    .iter()
+                    .map(|c| quote_identifier(c))
+                    .collect::<Vec<String>>()
+                    .join(", "),
+                values.join(", ")
+            )?;
+        }
+        writeln!(self.writer, "COMMIT;")?;
+        Ok(file_id)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(test)]
+use crate::fit_file::{test_fit_file, test_message};
+
+#[test]
+fn test_sqlite_export() {
+    let file = test_fit_file(vec![
+        test_message(
+            20,
+            vec![
+                (253, "timestamp", Value::U32(1010)),
+                (3, "heart_rate", Value::U8(150)),
+                (6, "speed", Value::F64(3.5)),
+            ],
+        ),
+        test_message(
+            20,
+            vec![
+                (253, "timestamp", Value::U32(2000)),
+                (7, "power", Value::U16(250)),
+            ],
+        ),
+        test_message(
+            SESSION_MESSAGE.number,
+            vec![
+                (2, "start_time", Value::U32(1000)),
+                (7, "total_elapsed_time", Value::F64(60.0)),
+            ],
+        ),
+        test_message(0xFF00, vec![(1, "", Value::String("it's".to_string()))]),
+    ]);
+    let mut exporter = SqliteExporter::new(Vec::new()).unwrap();
+    assert_eq!(exporter.export("a.fit", &file).unwrap(), 1);
+    assert_eq!(exporter.export("b.fit", &file).unwrap(), 2);
+    let sql = String::from_utf8(exporter.into_inner()).unwrap();
+    let lines = sql.lines().collect::<Vec<&str>>();
+    assert!(lines.contains(&"ALTER TABLE \"record\" ADD COLUMN \"heart_rate\" INTEGER;"));
+    assert!(lines.contains(&"ALTER TABLE \"record\" ADD COLUMN \"speed\" REAL;"));
+    assert!(lines.contains(
+        &"INSERT INTO \"record\" (\"fit_file_id\", \"session_id\", \"timestamp\", \"heart_rate\", \"speed\") VALUES (1, 1, 1010, 150, 3.5);"
+    ));
+    assert!(lines.contains(
+        &"INSERT INTO \"record\" (\"fit_file_id\", \"session_id\", \"timestamp\", \"power\") VALUES (2, NULL, 2000, 250);"
+    ));
+    assert!(lines.contains(
+        &"INSERT INTO \"session\" (\"fit_file_id\", \"session_id\", \"id\", \"start_time\", \"total_elapsed_time\") VALUES (2, 2, 2, 1000, 60.0);"
+    ));
+    assert!(lines.contains(
+        &"INSERT INTO \"message_65280\" (\"fit_file_id\", \"session_id\", \"field_1\") VALUES (1, NULL, 'it''s');"
+    ));
+    // tables and columns are created once
+    assert_eq!(
+        sql.matches("CREATE TABLE IF NOT EXISTS \"record\"").count(),
+        1
+    );
+    assert_eq!(sql.matches("ADD COLUMN \"power\"").count(), 1);
+}
+
+#[test]
+fn test_sqlite_literal() {
+    assert_eq!(literal(&Value::I64(i64::MIN + 1)), "-9223372036854775807");
+    assert_eq!(literal(&Value::U64((1 << 53) + 1)), "9007199254740993");
+    assert_eq!(literal(&Value::U64(u64::MAX - 1)), "-2");
+    assert_eq!(literal(&Value::F64(f64::NAN)), "NULL");
+}
@@ -0,0 +1,177 @@
+//! A summary of what a file contains and how it is encoded: message counts
+//! and sizes per message type, how definitions are reused and whether the
+//! file has developer data. For a first look at an unknown file.
+
+use std::collections::BTreeMap;
+
+use crate::error::FitError;
+use crate::fit_file::FitFile;
+use crate::fit_header::try_parse_fit_header_from_data;
+use crate::fit_records::{Record, RecordHeader, RecordIterator, LOCAL_MESSAGE_TYPE_COUNT};
+use crate::profile::{message_info, DEVELOPER_DATA_ID_MESSAGE, FIELD_DESCRIPTION_MESSAGE};
+
+/// Records of one global message type.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MessageTypeStats {
+    pub global_message_number: u16,
+    /// Profile name of the message, `None` for messages not in the profile.
+    pub name: Option<&'static str>,
+    /// Data records.
+    pub count: usize,
+    /// Bytes of the data records, record headers included.
+    pub bytes: usize,
+    /// Definition records.
+    pub definitions: usize,
+    /// Data records with developer fields.
+    pub with_developer_fields: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StructureReport {
+    pub header_size: u8,
+    pub data_size: u32,
+    /// Message types in order of global message number.
+    pub message_types: Vec<MessageTypeStats>,
+    pub definition_records: usize,
+    /// Bytes of the definition records, record headers included.
+    pub definition_bytes: usize,
+    /// Definition records repeating the previous definition of their local
+    /// message type.
+    pub repeated_definitions: usize,
+    /// Distinct local message types used.
+    pub local_message_types: usize,
+    /// Data records with a compressed timestamp header.
+    pub compressed_timestamp_records: usize,
+    /// Bytes of developer field values in data records.
+    pub developer_field_bytes: usize,
+}
+
+impl StructureReport {
+    pub fn data_records(&self) -> usize {
+        self.message_types.iter().map(|m| m.count).sum()
+    }
+
+    /// Average data records per definition record.
+    pub fn records_per_definition(&self) -> Option<f64> {
+        (self.definition_records > 0)
+            .then(|| self.data_records() as f64 / self.definition_records as f64)
+    }
+
+    pub fn message_type(&self, global_message_number: u16) -> Option<&MessageTypeStats> {
+        self.message_types
+            .iter()
+            .find(|m| m.global_message_number == global_message_number)
+    }
+
+    /// Whether the file describes developer fields or has data records with
+    /// them.
+    pub fn has_developer_data(&self) -> bool {
+        self.developer_field_bytes > 0
+            || [
+                DEVELOPER_DATA_ID_MESSAGE.number,
+                FIELD_DESCRIPTION_MESSAGE.number,
+            ]
+            .iter()
+            .any(|number| self.message_type(*number).is_some())
+    }
+}
+
+fn stats(types: &mut BTreeMap<u16, MessageTypeStats>, number: u16) -> &mut MessageTypeStats {
+    types.entry(number).or_insert_with(|| MessageTypeStats {
+        global_message_number: number,
+        name: message_info(number).map(|m| m.name),
+        ..MessageTypeStats::default()
+    })
+}
+
+impl FitFile {
+    /// Reports the structure of the data section of a file. Fails like
+    /// [`FitFile::parse`] on a malformed header or records.
+    pub fn structure_report(fit_data: &[u8]) -> Result<StructureReport, FitError> {
+        let header = try_parse_fit_header_from_data(fit_data)?;
+        let data_start = header.header_size() as usize;
+        let data_end = fit_data.len().min(data_start + header.data_size() as usize);
+        let mut report = StructureReport {
+            header_size: header.header_size(),
+            data_size: header.data_size(),
+            ..StructureReport::default()
+        };
+        let mut types = BTreeMap::<u16, MessageTypeStats>::new();
+        // Bytes of the latest definition of each local message type.
+        let mut definitions: [Option<&[u8]>; LOCAL_MESSAGE_TYPE_COUNT] = Default::default();
+        let mut records = RecordIterator::new(&fit_data[..data_end], data_start);
+        loop {
+            let offset = records.offset();
+            let record = match records.next() {
+                None => break,
+                Some(record) => record?,
+            };
+            let bytes = &fit_data[offset..records.offset()];
+            match record {
+                Record::Definition(definition) => {
+                    let local = definition.header.local_message_type as usize;
+                    report.definition_records += 1;
+                    report.definition_bytes += bytes.len();
+                    if definitions[local] == Some(bytes) {
+                        report.repeated_definitions += 1;
+                    }
+                    definitions[local] = Some(bytes);
+                    stats(&mut types, definition.global_message_number).definitions += 1;
+                }
+                Record::Data(record) => {
+                    if let RecordHeader::CompressedTimestamp(_) = record.header {
+                        report.compressed_timestamp_records += 1;
+                    }
+                    let developer_bytes = record
+                        .developer_fields
+                        .iter()
+                        .map(|f| f.data.len())
+                        .sum::<usize>();
+                    report.developer_field_bytes += developer_bytes;
+                    let stats = stats(&mut types, record.global_message_number);
+                    stats.count += 1;
+                    stats.bytes += bytes.len();
+                    if developer_bytes > 0 {
+                        stats.with_developer_fields += 1;
+                    }
+                }
+            }
+        }
+        report.local_message_types = definitions.iter().filter(|d| d.is_some()).count();
+        report.message_types = types.into_values().collect();
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+use crate::fit_file::test_file;
+
+#[test]
+fn test_structure_report() {
+    let data = test_file(&[
+        0x40, 0x00, 0x00, 0x14, 0x00, 0x01, // record, 1 field
+        0x03, 0x01, 0x02, // heart_rate
+        0x00, 0x96, // heart_rate 150
+        0x40, 0x00, 0x00, 0x14, 0x00, 0x01, // the same definition again
+        0x03, 0x01, 0x02, // heart_rate
+        0x80, 0x97, // compressed timestamp, heart_rate 151
+        0x41, 0x00, 0x00, 0xFF, 0xFF, 0x01, // unknown message 65535
+        0x00, 0x02, 0x84, // field 0, uint16
+        0x01, 0x01, 0x00, // value 1
+    ]);
+    let report = FitFile::structure_report(&data).unwrap();
+    assert_eq!(report.data_records(), 3);
+    assert_eq!(report.definition_records, 3);
+    assert_eq!(report.definition_bytes, 27);
+    assert_eq!(report.repeated_definitions, 1);
+    assert_eq!(report.local_message_types, 2);
+    assert_eq!(report.compressed_timestamp_records, 1);
+    assert_eq!(report.records_per_definition(), Some(1.0));
+    assert!(!report.has_developer_data());
+    let record = report.message_type(20).unwrap();
+    assert_eq!(record.name, Some("record"));
+    assert_eq!((record.count, record.bytes, record.definitions), (2, 4, 2));
+    let unknown = &report.message_types[1];
+    assert_eq!(unknown.name, None);
+    assert_eq!((unknown.count, unknown.bytes), (1, 3));
+}
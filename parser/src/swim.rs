@@ -0,0 +1,190 @@
+//! Pool swimming analysis from `length` messages and the laps and session
+//! they belong to.
+
+use crate::fit_file::FitFile;
+use crate::profile::messages::{Length, Session};
+use crate::profile::{LENGTH_MESSAGE, SESSION_MESSAGE};
+
+// `length_type` of lengths spent resting at the wall.
+const IDLE_LENGTH: u8 = 0;
+
+/// One pool length, swum or rested.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SwimLength {
+    /// Index of the lap the length belongs to.
+    pub lap_index: Option<usize>,
+    /// Seconds since the FIT epoch.
+    pub start_time: Option<u32>,
+    /// Seconds.
+    pub elapsed_time: Option<f64>,
+    /// Seconds of timer time.
+    pub timer_time: Option<f64>,
+    /// Whether the length was swum rather than rested.
+    pub active: bool,
+    /// `swim_stroke` value.
+    pub stroke: Option<u8>,
+    pub strokes: Option<u16>,
+}
+
+impl SwimLength {
+    /// Seconds per 100 meters in a pool of `pool_length` meters.
+    pub fn pace(&self, pool_length: f64) -> Option<f64> {
+        let time = self.timer_time.filter(|_| self.active)?;
+        Some(time / pool_length * 100.0)
+    }
+
+    /// Seconds plus strokes of the length, lower is more efficient.
+    pub fn swolf(&self) -> Option<f64> {
+        let time = self.timer_time.filter(|_| self.active)?;
+        Some(time + self.strokes? as f64)
+    }
+
+    fn end_time(&self) -> Option<f64> {
+        Some(self.start_time? as f64 + self.elapsed_time?)
+    }
+}
+
+/// A rest between lengths, in seconds since the FIT epoch.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rest {
+    pub start: f64,
+    pub end: f64,
+}
+
+impl Rest {
+    pub fn duration(&self) -> f64 {
+        self.end - self.start
+    }
+}
+
+/// The lengths of a pool swim.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PoolSwim {
+    /// Meters, from the session.
+    pub pool_length: Option<f64>,
+    pub lengths: Vec<SwimLength>,
+}
+
+impl PoolSwim {
+    /// Collects the lengths of the file ordered by their message index and
+    /// assigns them to laps by the laps' first length index and length
+    /// count.
+    pub fn from_file(file: &FitFile) -> PoolSwim {
+        let laps = file.laps();
+        let mut lengths = file
+            .typed_messages(LENGTH_MESSAGE.number, Length::from_message)
+            .enumerate()
+            .map(|(idx, length)| (length.message_index.map_or(idx, usize::from), length))
+            .collect::<Vec<(usize, Length)>>();
+        lengths.sort_by_key(|(idx, _)| *idx);
+        let lengths = lengths
+            .into_iter()
+            .map(|(idx, length)| SwimLength {
+                lap_index: laps.iter().position(|lap| {
+                    match (lap.first_length_index, lap.num_lengths) {
+                        (Some(first), Some(count)) => {
+                            (first as usize..first as usize + count as usize).contains(&idx)
+                        }
+                        _ => false,
+                    }
+                }),
+                start_time: length.start_time,
+                elapsed_time: length.total_elapsed_time,
+                timer_time: length.total_timer_time,
+                active: length.length_type != Some(IDLE_LENGTH),
+                stroke: length.swim_stroke,
+                strokes: length.total_strokes,
+            })
+            .collect();
+        let pool_length = file
+            .typed_messages(SESSION_MESSAGE.number, Session::from_message)
+            .find_map(|s| s.pool_length);
+        PoolSwim {
+            pool_length,
+            lengths,
+        }
+    }
+
+    pub fn active_lengths(&self) -> impl Iterator<Item = &SwimLength> {
+        self.lengths.iter().filter(|l| l.active)
+    }
+
+    /// Rests from idle lengths and from gaps between lengths, such as timer
+    /// pauses at the wall, merged where they touch.
+    pub fn rests(&self) -> Vec<Rest> {
+        let mut rests: Vec<Rest> = Vec::new();
+        let mut previous_end: Option<f64> = None;
+        for length in &self.lengths {
+            let (start, end) = match (length.start_time, length.end_time()) {
+                (Some(start), Some(end)) => (start as f64, end),
+                _ => continue,
+            };
+            let mut add = |rest: Rest| match rests.last_mut() {
+                Some(last) if last.end >= rest.start => last.end = last.end.max(rest.end),
+                _ => rests.push(rest),
+            };
+            if let Some(previous_end) = previous_end.filter(|e| start - e >= 1.0) {
+                add(Rest {
+                    start: previous_end,
+                    end: start,
+                });
+            }
+            if !length.active {
+                add(Rest { start, end });
+            }
+            previous_end = Some(end);
+        }
+        rests
+    }
+}
+
+#[cfg(test)]
+use crate::fit_file::{test_fit_file, test_message};
+#[cfg(test)]
+use crate::fit_messages::Value;
+
+#[test]
+fn test_pool_swim() {
+    let length = |index: u16, start: u32, time: f64, active: bool, strokes: u16| {
+        test_message(
+            101,
+            vec![
+                (254, "message_index", Value::U16(index)),
+                (2, "start_time", Value::U32(start)),
+                (3, "total_elapsed_time", Value::F64(time)),
+                (4, "total_timer_time", Value::F64(time)),
+                (5, "total_strokes", Value::U16(strokes)),
+                (12, "length_type", Value::U8(active as u8)),
+            ],
+        )
+    };
+    let file = test_fit_file(vec![
+        length(0, 1000, 30.0, true, 20),
+        length(1, 1030, 32.0, true, 22),
+        length(2, 1062, 20.0, false, 0),
+        length(3, 1090, 31.0, true, 21),
+        test_message(
+            19,
+            vec![
+                (35, "first_length_index", Value::U16(0)),
+                (32, "num_lengths", Value::U16(3)),
+            ],
+        ),
+        test_message(18, vec![(44, "pool_length", Value::F64(25.0))]),
+    ]);
+    let swim = PoolSwim::from_file(&file);
+    assert_eq!(swim.pool_length, Some(25.0));
+    assert_eq!(swim.active_lengths().count(), 3);
+    assert_eq!(swim.lengths[0].lap_index, Some(0));
+    assert_eq!(swim.lengths[3].lap_index, None);
+    assert_eq!(swim.lengths[0].pace(25.0), Some(120.0));
+    assert_eq!(swim.lengths[1].swolf(), Some(54.0));
+    assert_eq!(swim.lengths[2].swolf(), None);
+    assert_eq!(
+        swim.rests(),
+        vec![Rest {
+            start: 1062.0,
+            end: 1090.0
+        }]
+    );
+}
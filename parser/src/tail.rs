@@ -0,0 +1,137 @@
+//! Finding the session and activity messages of a file without decoding the
+//! records before them. Devices write the summary messages last, so for
+//! totals of huge files it is enough to decode the end of the data section.
+//!
+//! Records can not be read backwards, so the end of the data section is
+//! searched for a definition record from which the rest of the data section
+//! reads without errors. The search starts from the last few kilobytes and
+//! widens until the rest of the data section has a session message.
+
+use crate::diagnostics::Diagnostics;
+use crate::error::FitError;
+use crate::fit_file::FitFile;
+use crate::fit_header::try_parse_fit_header_from_data;
+use crate::fit_messages::{DataMessage, MessageDecoder};
+use crate::fit_records::{Record, RecordIterator};
+use crate::profile::{ACTIVITY_MESSAGE, SESSION_MESSAGE};
+
+// Bytes from the end of the data section searched first.
+const INITIAL_WINDOW: usize = 4 * 1024;
+
+// Whether a definition record plausibly starts at the offset: a definition
+// record header with the reserved bit clear, a zero reserved byte and a
+// known architecture.
+fn is_definition_start(data: &[u8], offset: usize) -> bool {
+    match data.get(offset..offset + 3) {
+        Some(&[header, reserved, architecture]) => {
+            header & 0xD0 == 0x40 && reserved == 0 && architecture <= 1
+        }
+        _ => false,
+    }
+}
+
+// Session and activity messages of the records from `start` to the end of
+// `data`, or `None` if the records do not read to the end without errors.
+fn summary_from(data: &[u8], start: usize) -> Option<Vec<DataMessage>> {
+    let mut decoder = MessageDecoder::new();
+    let mut diagnostics = Diagnostics::new();
+    let mut records = RecordIterator::new(data, start);
+    let mut summary = Vec::new();
+    loop {
+        let record_offset = records.offset();
+        let record_index = records.record_index();
+        match records.next()? {
+            Ok(Record::Definition(_)) => {}
+            Ok(Record::Data(record)) => {
+                if [SESSION_MESSAGE.number, ACTIVITY_MESSAGE.number]
+                    .contains(&record.global_message_number)
+                {
+                    summary.push(decoder.decode(
+                        record,
+                        record_offset,
+                        record_index,
+                        &mut diagnostics,
+                    ));
+                }
+            }
+            Err(_) => return None,
+        }
+        if records.offset() == data.len() {
+            return Some(summary);
+        }
+    }
+}
+
+impl FitFile {
+    /// Session and activity messages at the end of the file in file order,
+    /// decoded without the records before them. Timestamps compressed
+    /// relative to earlier records are missing and record indices count
+    /// from the first record decoded. Falls back to reading the
+    /// whole data section when the end of it has no session, and then fails
+    /// like [`FitFile::parse`] on malformed records.
+    pub fn trailing_summary(fit_data: &[u8]) -> Result<Vec<DataMessage>, FitError> {
+        let header = try_parse_fit_header_from_data(fit_data)?;
+        let data_start = header.header_size() as usize;
+        let data_end = fit_data.len().min(data_start + header.data_size() as usize);
+        let data = &fit_data[..data_end];
+        let mut window = INITIAL_WINDOW;
+        while data_end - data_start > window {
+            let summary = (data_end - window..data_end)
+                .filter(|offset| is_definition_start(data, *offset))
+                .find_map(|offset| summary_from(data, offset));
+            if let Some(summary) = summary {
+                if summary
+                    .iter()
+                    .any(|m| m.global_message_number == SESSION_MESSAGE.number)
+                {
+                    return Ok(summary);
+                }
+            }
+            window *= 4;
+        }
+        Ok(FitFile::parse(fit_data)?
+            .messages
+            .into_iter()
+            .filter(|m| {
+                [SESSION_MESSAGE.number, ACTIVITY_MESSAGE.number].contains(&m.global_message_number)
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+use crate::fit_file::test_file;
+
+#[test]
+fn test_trailing_summary() {
+    let mut records = vec![
+        0x40, 0x00, 0x00, 0x14, 0x00, 0x01, // record, 1 field
+        0x03, 0x01, 0x02, // heart_rate
+    ];
+    for _ in 0..5000 {
+        records.extend_from_slice(&[0x00, 0x96]);
+    }
+    records.extend_from_slice(&[
+        0x41, 0x00, 0x00, 0x12, 0x00, 0x01, // session, 1 field
+        0x05, 0x01, 0x00, // sport
+        0x01, 0x01, // running
+        0x42, 0x00, 0x00, 0x22, 0x00, 0x01, // activity, 1 field
+        0x01, 0x02, 0x84, // num_sessions
+        0x02, 0x01, 0x00, // 1 session
+    ]);
+    let data = test_file(&records);
+    let summary = FitFile::trailing_summary(&data).unwrap();
+    let parsed = FitFile::parse(&data).unwrap();
+    let expected = parsed.messages[5000..].iter();
+    assert_eq!(summary.len(), 2);
+    for (message, expected) in summary.iter().zip(expected) {
+        assert_eq!(message.offset, expected.offset);
+        assert_eq!(message.fields, expected.fields);
+    }
+    // the summary is found from the definitions after the records
+    assert_eq!(summary[0].record_index, 1);
+
+    // a short file is read whole
+    let short = test_file(&records[records.len() - 23..]);
+    assert_eq!(FitFile::trailing_summary(&short).unwrap().len(), 2);
+}
@@ -0,0 +1,74 @@
+//! Calendar dates of FIT timestamps, in the proleptic Gregorian calendar.
+
+/// Days from the Unix epoch to the FIT epoch.
+pub(crate) const FIT_EPOCH_UNIX_DAYS: i64 = 7304;
+
+pub(crate) const SECONDS_PER_DAY: i64 = 86400;
+
+/// Year, month and day of a day counted from the Unix epoch.
+pub(crate) fn civil_from_days(unix_day: i64) -> (i64, i64, i64) {
+    let z = unix_day + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
+
+/// Days from the Unix epoch to a year, month and day.
+pub fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let mp = (month + 9) % 12;
+    let day_of_year = (153 * mp + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Seconds since the Unix epoch of a timestamp in seconds since the FIT
+/// epoch.
+pub fn unix_seconds(timestamp: u32) -> i64 {
+    timestamp as i64 + FIT_EPOCH_UNIX_DAYS * SECONDS_PER_DAY
+}
+
+/// The timestamp, seconds since the FIT epoch, as an RFC 3339 UTC time
+/// such as `2024-01-01T12:00:00Z`.
+pub fn format_timestamp(timestamp: u32) -> String {
+    let seconds = timestamp as i64;
+    let (year, month, day) = civil_from_days(seconds / SECONDS_PER_DAY + FIT_EPOCH_UNIX_DAYS);
+    let time = seconds % SECONDS_PER_DAY;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+#[test]
+fn test_calendar_dates() {
+    assert_eq!(civil_from_days(FIT_EPOCH_UNIX_DAYS), (1989, 12, 31));
+    assert_eq!(civil_from_days(19_782), (2024, 2, 29));
+    assert_eq!(format_timestamp(0), "1989-12-31T00:00:00Z");
+    assert_eq!(format_timestamp(1_073_001_599), "2023-12-31T23:59:59Z");
+    assert_eq!(unix_seconds(0), 631_065_600);
+}
+
+#[test]
+fn test_days_from_civil_round_trip() {
+    assert_eq!(days_from_civil(1989, 12, 31), FIT_EPOCH_UNIX_DAYS);
+    assert_eq!(days_from_civil(1970, 1, 1), 0);
+    for unix_day in (-800_000..800_000).step_by(97) {
+        let (year, month, day) = civil_from_days(unix_day);
+        assert_eq!(days_from_civil(year, month, day), unix_day);
+    }
+}
@@ -0,0 +1,134 @@
+//! Records, events, `hrv` messages and lap boundaries on one timeline, for
+//! correlating the streams of an activity without resolving their
+//! timestamps by hand.
+
+use crate::fit_file::FitFile;
+use crate::fit_messages::DataMessage;
+use crate::profile::{EVENT_MESSAGE, HRV_MESSAGE, LAP_MESSAGE, RECORD_MESSAGE};
+
+// `start_time` field of lap messages.
+const LAP_START_TIME_FIELD: u8 = 2;
+
+/// Resolves a 16 bit timestamp, the lowest bits of the full timestamp, as
+/// the first time at or after `reference` with those bits.
+pub fn resolve_timestamp_16(reference: u32, timestamp_16: u16) -> u32 {
+    reference.wrapping_add((timestamp_16.wrapping_sub(reference as u16)) as u32)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimelineKind {
+    Record,
+    Event,
+    Hrv,
+    LapStart,
+    LapEnd,
+}
+
+/// A message at a point of the timeline. Lap messages are on it twice, at
+/// their start and at their end.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimelineEntry<'a> {
+    /// Seconds since the FIT epoch.
+    pub timestamp: u32,
+    pub kind: TimelineKind,
+    pub message: &'a DataMessage,
+}
+
+impl FitFile {
+    /// Records, events, `hrv` messages and lap starts and ends ordered by
+    /// timestamp, entries at the same time in file order. Timestamps of
+    /// compressed timestamp headers are resolved by the decoder and
+    /// `timestamp_16` fields against the last full timestamp. `hrv` messages
+    /// have no timestamps and are placed at the last timestamp before them,
+    /// or the first timestamp of the file. Messages without a timestamp
+    /// otherwise are left out.
+    pub fn timeline(&self) -> Vec<TimelineEntry<'_>> {
+        let mut last = self.messages.iter().find_map(|m| m.timestamp());
+        let mut entries = Vec::new();
+        for message in &self.messages {
+            let timestamp = message.timestamp().or_else(|| {
+                let timestamp_16 = message.field_by_name("timestamp_16")?.value.as_u64()?;
+                Some(resolve_timestamp_16(last?, timestamp_16 as u16))
+            });
+            if timestamp.is_some() {
+                last = timestamp;
+            }
+            let number = message.global_message_number;
+            let mut push = |timestamp: Option<u32>, kind| {
+                if let Some(timestamp) = timestamp {
+                    entries.push(TimelineEntry {
+                        timestamp,
+                        kind,
+                        message,
+                    });
+                }
+            };
+            if number == RECORD_MESSAGE.number {
+                push(timestamp, TimelineKind::Record);
+            } else if number == EVENT_MESSAGE.number {
+                push(timestamp, TimelineKind::Event);
+            } else if number == HRV_MESSAGE.number {
+                push(last, TimelineKind::Hrv);
+            } else if number == LAP_MESSAGE.number {
+                push(
+                    message.typed_field(LAP_START_TIME_FIELD),
+                    TimelineKind::LapStart,
+                );
+                push(timestamp, TimelineKind::LapEnd);
+            }
+        }
+        entries.sort_by_key(|e| e.timestamp);
+        entries
+    }
+}
+
+#[cfg(test)]
+use crate::fit_file::{test_fit_file, test_message};
+#[cfg(test)]
+use crate::fit_messages::Value;
+
+#[test]
+fn test_timeline() {
+    let record = |timestamp: u32| test_message(20, vec![(253, "timestamp", Value::U32(timestamp))]);
+    let fit_file = test_fit_file(vec![
+        test_message(78, vec![(0, "time", Value::F64(0.5))]),
+        record(1000),
+        record(1005),
+        test_message(78, vec![(0, "time", Value::F64(0.6))]),
+        test_message(
+            21,
+            vec![
+                (253, "timestamp", Value::U32(1003)),
+                (0, "event", Value::U8(0)),
+            ],
+        ),
+        test_message(
+            19,
+            vec![
+                (253, "timestamp", Value::U32(1010)),
+                (2, "start_time", Value::U32(1000)),
+            ],
+        ),
+        // hr message resolving its timestamp_16 to 1012
+        test_message(132, vec![(10, "timestamp_16", Value::U16(1012))]),
+        test_message(78, vec![(0, "time", Value::F64(0.7))]),
+    ]);
+    let timeline = fit_file
+        .timeline()
+        .iter()
+        .map(|e| (e.timestamp, e.kind))
+        .collect::<Vec<(u32, TimelineKind)>>();
+    assert_eq!(
+        timeline,
+        vec![
+            (1000, TimelineKind::Hrv),
+            (1000, TimelineKind::Record),
+            (1000, TimelineKind::LapStart),
+            (1003, TimelineKind::Event),
+            (1005, TimelineKind::Record),
+            (1005, TimelineKind::Hrv),
+            (1010, TimelineKind::LapEnd),
+            (1012, TimelineKind::Hrv),
+        ]
+    );
+}
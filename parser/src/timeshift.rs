@@ -0,0 +1,117 @@
+//! Correction of the timestamps of files recorded with a wrong device clock.
+
+use crate::edit::{edit_records, EditableRecord};
+use crate::error::FitError;
+use crate::fit_messages::TIMESTAMP_FIELD_NUMBER;
+use crate::profile::message_info;
+use crate::profile::ACTIVITY_MESSAGE;
+
+/// Date and time values below this are seconds since the device powered on
+/// rather than since the FIT epoch, and are not shifted.
+pub const MIN_DATE_TIME: u32 = 0x10000000;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TimeShift {
+    /// Seconds added to every UTC and local date and time.
+    pub offset: i64,
+    /// Seconds from UTC to the local time zone of the activity. When set, the
+    /// `local_timestamp` of the activity message is recomputed from its
+    /// shifted timestamp instead of being shifted.
+    pub utc_offset: Option<i32>,
+}
+
+impl TimeShift {
+    /// Shift that moves the activity start `from` a wrong device time `to`
+    /// the correct one, both in seconds since the FIT epoch.
+    pub fn between(from: u32, to: u32) -> TimeShift {
+        TimeShift {
+            offset: to as i64 - from as i64,
+            utc_offset: None,
+        }
+    }
+}
+
+// Field number of `local_timestamp` in the activity message.
+const LOCAL_TIMESTAMP_FIELD_NUMBER: u8 = 5;
+
+// Whether the field holds a UTC or local date and time.
+fn is_date_time(global_message_number: u16, field_definition_number: u8) -> bool {
+    if field_definition_number == TIMESTAMP_FIELD_NUMBER {
+        return true;
+    }
+    message_info(global_message_number)
+        .and_then(|m| m.field(field_definition_number))
+        .is_some_and(|f| f.field_type == "date_time" || f.field_type == "local_date_time")
+}
+
+fn shift_record(record: &mut EditableRecord, shift: &TimeShift) {
+    for number in record.field_numbers() {
+        if !is_date_time(record.global_message_number, number) {
+            continue;
+        }
+        let value = match record.field(number).and_then(|v| v.as_i64()) {
+            Some(value) if value >= MIN_DATE_TIME as i64 => value,
+            _ => continue,
+        };
+        record.set_integer(number, value + shift.offset);
+    }
+    if let Some(time_offset) = record.compressed_time_offset() {
+        let shifted = (time_offset as i64 + shift.offset).rem_euclid(32);
+        record.set_compressed_time_offset(shifted as u8);
+    }
+    if let Some(utc_offset) = shift.utc_offset {
+        if record.global_message_number == ACTIVITY_MESSAGE.number {
+            let timestamp = record
+                .field(TIMESTAMP_FIELD_NUMBER)
+                .and_then(|v| v.as_i64());
+            if let Some(timestamp) = timestamp {
+                record.set_integer(LOCAL_TIMESTAMP_FIELD_NUMBER, timestamp + utc_offset as i64);
+            }
+        }
+    }
+}
+
+/// Shifts all date and time fields of the file, including the timestamps
+/// of records with compressed timestamp headers, and optionally fixes the
+/// local time of the activity. Values that do not fit their field after
+/// shifting are left unchanged.
+pub fn shift_timestamps(fit_data: &[u8], shift: &TimeShift) -> Result<Vec<u8>, FitError> {
+    edit_records(fit_data, |record| shift_record(record, shift))
+}
+
+#[cfg(test)]
+use crate::fit_file::{test_file, FitFile};
+#[cfg(test)]
+use crate::profile::messages::Activity;
+
+#[test]
+fn test_shift_timestamps() {
+    let mut records = vec![
+        0x40, 0x00, 0x00, 0x22, 0x00, 0x02, // activity, 2 fields
+        0xFD, 0x04, 0x86, // timestamp
+        0x05, 0x04, 0x86, // local_timestamp
+        0x00,
+    ];
+    records.extend_from_slice(&0x3000_0000u32.to_le_bytes());
+    records.extend_from_slice(&0x3000_0000u32.to_le_bytes());
+    records.extend_from_slice(&[
+        0x41, 0x00, 0x00, 0x14, 0x00, 0x02, // record, 2 fields
+        0xFD, 0x04, 0x86, // timestamp
+        0x03, 0x01, 0x02, // heart_rate
+        0x01, 0x1E, 0x00, 0x00, 0x30, 0x96, // timestamp 0x3000001E, heart_rate 150
+        0x42, 0x00, 0x00, 0x14, 0x00, 0x01, // record, 1 field
+        0x03, 0x01, 0x02, // heart_rate
+        0xC3, 0x97, // compressed timestamp 0x30000023, heart_rate 151
+    ]);
+    let shift = TimeShift {
+        offset: 10,
+        utc_offset: Some(3600),
+    };
+    let edited = shift_timestamps(&test_file(&records), &shift).unwrap();
+    let fit_file = FitFile::parse(&edited).unwrap();
+    let activity = Activity::from_message(&fit_file.messages[0]);
+    assert_eq!(activity.timestamp, Some(0x3000_000A));
+    assert_eq!(activity.local_timestamp, Some(0x3000_000A + 3600));
+    assert_eq!(fit_file.messages[1].timestamp(), Some(0x3000_0028));
+    assert_eq!(fit_file.messages[2].timestamp(), Some(0x3000_002D));
+}
@@ -0,0 +1,62 @@
+//! Instrumentation of parsing with the `trace` feature: events for parsed
+//! headers, definition records, record progress and finished parses,
+//! emitted as [`log`](https://docs.rs/log) records with the module of the
+//! event as their target. Without the feature no events are created.
+
+/// Records between record progress events.
+pub(crate) const RECORD_EVENT_INTERVAL: usize = 10_000;
+
+// Emits a log record at a level, formatting the message only when the level
+// is enabled.
+macro_rules! event {
+    ($level:ident, $($arg:tt)+) => {{
+        #[cfg(feature = "trace")]
+        ::log::log!(::log::Level::$level, $($arg)+);
+    }};
+}
+
+pub(crate) use event;
+
+#[cfg(feature = "trace")]
+#[test]
+fn test_trace_events() {
+    use std::sync::Mutex;
+
+    use log::{LevelFilter, Log, Metadata, Record};
+
+    use crate::fit_file::{test_file, FitFile};
+
+    static EVENTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+    struct TestLogger;
+    impl Log for TestLogger {
+        fn enabled(&self, _: &Metadata) -> bool {
+            true
+        }
+        fn log(&self, record: &Record) {
+            EVENTS.lock().unwrap().push(format!(
+                "{} {}: {}",
+                record.level(),
+                record.target(),
+                record.args()
+            ));
+        }
+        fn flush(&self) {}
+    }
+    log::set_logger(&TestLogger).unwrap();
+    log::set_max_level(LevelFilter::Trace);
+    let data = test_file(&[
+        0x40, 0x00, 0x00, 0x14, 0x00, 0x01, // record, 1 field
+        0x03, 0x01, 0x02, // heart_rate
+        0x00, 0x96, // heart_rate 150
+    ]);
+    FitFile::parse(&data).unwrap();
+    let events = EVENTS.lock().unwrap();
+    assert!(events.contains(
+        &"TRACE fit_parser::fit_file: Definition of local message type 0 at offset 12: \
+          global message 20, 1 fields, 0 developer fields"
+            .to_string()
+    ));
+    assert!(events
+        .iter()
+        .any(|e| e.starts_with("DEBUG fit_parser::fit_file: Parsed 1 messages")));
+}
@@ -0,0 +1,383 @@
+//! Workout files as a list of steps whose durations, targets and repeats are
+//! resolved from the `duration_type` and `target_type` of each step.
+
+use crate::fit_file::FitFile;
+use crate::fit_messages::DataMessage;
+use crate::profile::messages::Workout as WorkoutMessage;
+use crate::profile::{WORKOUT_MESSAGE, WORKOUT_STEP_MESSAGE};
+
+/// A heart rate in a workout step. Values below 100 are a percentage of the
+/// maximum heart rate, others are beats per minute offset by 100.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeartRate {
+    PercentOfMax(u32),
+    Bpm(u32),
+}
+
+impl HeartRate {
+    pub fn from_raw(value: u32) -> HeartRate {
+        match value {
+            0..=99 => HeartRate::PercentOfMax(value),
+            _ => HeartRate::Bpm(value - 100),
+        }
+    }
+}
+
+/// A power in a workout step. Values below 1000 are a percentage of the
+/// functional threshold power, others are watts offset by 1000.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Power {
+    PercentOfFtp(u32),
+    Watts(u32),
+}
+
+impl Power {
+    pub fn from_raw(value: u32) -> Power {
+        match value {
+            0..=999 => Power::PercentOfFtp(value),
+            _ => Power::Watts(value - 1000),
+        }
+    }
+}
+
+/// When a step ends.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Duration {
+    Open,
+    /// Seconds.
+    Time(f64),
+    /// Meters.
+    Distance(f64),
+    Calories(u32),
+    HeartRateLessThan(HeartRate),
+    HeartRateGreaterThan(HeartRate),
+    PowerLessThan(Power),
+    PowerGreaterThan(Power),
+    Reps(u32),
+    /// A `wkt_step_duration` not modelled above.
+    Other {
+        duration_type: u8,
+        value: Option<f64>,
+    },
+}
+
+/// What a step aims for. Zones are numbers of the user's zones, ranges are
+/// custom low and high values.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    Open,
+    HeartRateZone(u32),
+    HeartRateRange {
+        low: HeartRate,
+        high: HeartRate,
+    },
+    /// Meters per second.
+    SpeedZone(u32),
+    SpeedRange {
+        low: f64,
+        high: f64,
+    },
+    /// Revolutions per minute.
+    CadenceZone(u32),
+    CadenceRange {
+        low: u32,
+        high: u32,
+    },
+    PowerZone(u32),
+    PowerRange {
+        low: Power,
+        high: Power,
+    },
+    /// `swim_stroke` value.
+    SwimStroke(u32),
+    /// A `wkt_step_target` not modelled above.
+    Other {
+        target_type: u8,
+        value: Option<f64>,
+        low: Option<f64>,
+        high: Option<f64>,
+    },
+}
+
+/// Condition ending a repeat of earlier steps.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RepeatUntil {
+    /// Total number of times the steps are done.
+    Count(u32),
+    /// Seconds.
+    Time(f64),
+    /// Meters.
+    Distance(f64),
+    Calories(u32),
+    HeartRateLessThan(HeartRate),
+    HeartRateGreaterThan(HeartRate),
+    PowerLessThan(Power),
+    PowerGreaterThan(Power),
+    /// A repeat `wkt_step_duration` not modelled above.
+    Other {
+        duration_type: u8,
+        value: Option<f64>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StepKind {
+    Step {
+        duration: Duration,
+        target: Target,
+    },
+    /// Repeats the steps from message index `from_step` up to this step.
+    Repeat {
+        from_step: u32,
+        until: RepeatUntil,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkoutStep {
+    pub message_index: Option<u16>,
+    pub name: Option<String>,
+    pub notes: Option<String>,
+    /// `intensity` value.
+    pub intensity: Option<u8>,
+    pub kind: StepKind,
+}
+
+fn number(message: &DataMessage, field_definition_number: u8) -> Option<f64> {
+    message.field(field_definition_number)?.value.as_f64()
+}
+
+fn integer(message: &DataMessage, field_definition_number: u8) -> Option<u32> {
+    number(message, field_definition_number)
+        .filter(|v| *v >= 0.0)
+        .map(|v| v.round() as u32)
+}
+
+fn repeat_until(duration_type: u8, message: &DataMessage) -> Option<RepeatUntil> {
+    let value = number(message, 4);
+    let int = integer(message, 4);
+    Some(match duration_type {
+        6 => RepeatUntil::Count(int?),
+        7 => RepeatUntil::Time(value?),
+        8 => RepeatUntil::Distance(value?),
+        9 => RepeatUntil::Calories(int?),
+        10 => RepeatUntil::HeartRateLessThan(HeartRate::from_raw(int?)),
+        11 => RepeatUntil::HeartRateGreaterThan(HeartRate::from_raw(int?)),
+        12 => RepeatUntil::PowerLessThan(Power::from_raw(int?)),
+        13 => RepeatUntil::PowerGreaterThan(Power::from_raw(int?)),
+        17 | 18 | 27 => RepeatUntil::Other {
+            duration_type,
+            value,
+        },
+        _ => return None,
+    })
+}
+
+fn duration(duration_type: Option<u8>, message: &DataMessage) -> Duration {
+    let value = number(message, 2);
+    let int = integer(message, 2);
+    let duration = match duration_type {
+        None | Some(5) => Some(Duration::Open),
+        Some(0) | Some(28) | Some(31) => value.map(Duration::Time),
+        Some(1) => value.map(Duration::Distance),
+        Some(2) => int.map(|v| Duration::HeartRateLessThan(HeartRate::from_raw(v))),
+        Some(3) => int.map(|v| Duration::HeartRateGreaterThan(HeartRate::from_raw(v))),
+        Some(4) => int.map(Duration::Calories),
+        Some(14) => int.map(|v| Duration::PowerLessThan(Power::from_raw(v))),
+        Some(15) => int.map(|v| Duration::PowerGreaterThan(Power::from_raw(v))),
+        Some(29) => int.map(Duration::Reps),
+        Some(_) => None,
+    };
+    duration.unwrap_or(Duration::Other {
+        duration_type: duration_type.unwrap_or(u8::MAX),
+        value,
+    })
+}
+
+fn target(target_type: Option<u8>, message: &DataMessage) -> Target {
+    let zone = integer(message, 4).filter(|zone| *zone != 0);
+    let (low, high) = (number(message, 5), number(message, 6));
+    let range = low.zip(high).filter(|_| zone.is_none());
+    let int_range = range.map(|(low, high)| (low.round() as u32, high.round() as u32));
+    let target = match target_type {
+        None | Some(2) => Some(Target::Open),
+        Some(0) => zone
+            .map(Target::SpeedZone)
+            .or(range.map(|(low, high)| Target::SpeedRange { low, high })),
+        Some(1) => zone
+            .map(Target::HeartRateZone)
+            .or(int_range.map(|(low, high)| Target::HeartRateRange {
+                low: HeartRate::from_raw(low),
+                high: HeartRate::from_raw(high),
+            })),
+        Some(3) => zone
+            .map(Target::CadenceZone)
+            .or(int_range.map(|(low, high)| Target::CadenceRange { low, high })),
+        Some(4) => {
+            zone.map(Target::PowerZone)
+                .or(int_range.map(|(low, high)| Target::PowerRange {
+                    low: Power::from_raw(low),
+                    high: Power::from_raw(high),
+                }))
+        }
+        Some(11) => zone.map(Target::SwimStroke),
+        Some(_) => None,
+    };
+    target.unwrap_or(Target::Other {
+        target_type: target_type.unwrap_or(u8::MAX),
+        value: number(message, 4),
+        low,
+        high,
+    })
+}
+
+impl WorkoutStep {
+    pub fn from_message(message: &DataMessage) -> WorkoutStep {
+        let duration_type = message.typed_field::<u8>(1);
+        let repeat = duration_type.and_then(|t| {
+            Some(StepKind::Repeat {
+                until: repeat_until(t, message)?,
+                from_step: integer(message, 2)?,
+            })
+        });
+        WorkoutStep {
+            message_index: message.typed_field(254),
+            name: message.typed_field(0),
+            notes: message.typed_field(8),
+            intensity: message.typed_field(7),
+            kind: repeat.unwrap_or_else(|| StepKind::Step {
+                duration: duration(duration_type, message),
+                target: target(message.typed_field(3), message),
+            }),
+        }
+    }
+}
+
+/// A workout and its steps ordered by message index.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Workout {
+    pub name: Option<String>,
+    /// `sport` value.
+    pub sport: Option<u8>,
+    /// `sub_sport` value.
+    pub sub_sport: Option<u8>,
+    /// Meters.
+    pub pool_length: Option<f64>,
+    pub steps: Vec<WorkoutStep>,
+}
+
+impl Workout {
+    pub fn from_file(file: &FitFile) -> Workout {
+        let workout = file
+            .typed_messages(WORKOUT_MESSAGE.number, WorkoutMessage::from_message)
+            .next()
+            .unwrap_or_default();
+        let mut steps = file
+            .typed_messages(WORKOUT_STEP_MESSAGE.number, WorkoutStep::from_message)
+            .collect::<Vec<WorkoutStep>>();
+        steps.sort_by_key(|s| s.message_index);
+        Workout {
+            name: workout.wkt_name,
+            sport: workout.sport,
+            sub_sport: workout.sub_sport,
+            pool_length: workout.pool_length,
+            steps,
+        }
+    }
+}
+
+impl FitFile {
+    pub fn workout(&self) -> Workout {
+        Workout::from_file(self)
+    }
+}
+
+#[cfg(test)]
+use crate::fit_file::{test_fit_file, test_message};
+#[cfg(test)]
+use crate::fit_messages::Value;
+
+#[test]
+fn test_workout() {
+    let step = |index: u16, fields: Vec<(u8, &'static str, Value)>| {
+        let mut fields = fields;
+        fields.push((254, "message_index", Value::U16(index)));
+        test_message(27, fields)
+    };
+    let file = test_fit_file(vec![
+        test_message(
+            26,
+            vec![
+                (8, "wkt_name", Value::String("Intervals".to_string())),
+                (4, "sport", Value::U8(2)),
+            ],
+        ),
+        step(
+            2,
+            vec![
+                (1, "duration_type", Value::U8(6)),
+                (2, "duration_step", Value::U32(1)),
+                (4, "repeat_steps", Value::U32(5)),
+            ],
+        ),
+        step(
+            0,
+            vec![
+                (1, "duration_type", Value::U8(0)),
+                (2, "duration_time", Value::F64(600.0)),
+                (3, "target_type", Value::U8(1)),
+                (4, "target_hr_zone", Value::U32(2)),
+            ],
+        ),
+        step(
+            1,
+            vec![
+                (1, "duration_type", Value::U8(1)),
+                (2, "duration_distance", Value::F64(400.0)),
+                (3, "target_type", Value::U8(4)),
+                (4, "target_power_zone", Value::U32(0)),
+                (5, "custom_target_power_low", Value::U32(1250)),
+                (6, "custom_target_power_high", Value::U32(110)),
+            ],
+        ),
+        step(
+            3,
+            vec![
+                (1, "duration_type", Value::U8(2)),
+                (2, "duration_hr", Value::U32(220)),
+            ],
+        ),
+    ]);
+    let workout = file.workout();
+    assert_eq!(workout.name.as_deref(), Some("Intervals"));
+    assert_eq!(workout.sport, Some(2));
+    let kinds = workout
+        .steps
+        .into_iter()
+        .map(|s| s.kind)
+        .collect::<Vec<StepKind>>();
+    assert_eq!(
+        kinds,
+        vec![
+            StepKind::Step {
+                duration: Duration::Time(600.0),
+                target: Target::HeartRateZone(2)
+            },
+            StepKind::Step {
+                duration: Duration::Distance(400.0),
+                target: Target::PowerRange {
+                    low: Power::Watts(250),
+                    high: Power::PercentOfFtp(110)
+                }
+            },
+            StepKind::Repeat {
+                from_step: 1,
+                until: RepeatUntil::Count(5)
+            },
+            StepKind::Step {
+                duration: Duration::HeartRateLessThan(HeartRate::Bpm(120)),
+                target: Target::Open
+            },
+        ]
+    );
+}
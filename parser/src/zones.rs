@@ -0,0 +1,123 @@
+//! Time spent in heart rate and power zones.
+
+use crate::fit_file::FitFile;
+use crate::profile::messages::{HrZone, PowerZone};
+use crate::profile::{HR_ZONE_MESSAGE, POWER_ZONE_MESSAGE};
+use crate::series::{RecordSeries, ResampleError};
+
+/// Index of the zone of `value` given the ascending upper `boundaries` of the
+/// zones. Values above the last boundary are in the zone after it.
+fn zone(value: f64, boundaries: &[f64]) -> usize {
+    boundaries
+        .iter()
+        .position(|boundary| value <= *boundary)
+        .unwrap_or(boundaries.len())
+}
+
+/// Seconds spent in each zone given the ascending upper `boundaries` of the
+/// zones, one more than there are boundaries, like the `time_in_zone`
+/// messages devices write. The series is resampled to one value per second,
+/// see [`RecordSeries::resample_seconds`]. Seconds without a value of the
+/// `column` and pauses are not counted.
+pub fn time_in_zones<F>(
+    series: &RecordSeries,
+    column: F,
+    boundaries: &[f64],
+) -> Result<Vec<u32>, ResampleError>
+where
+    F: Fn(&RecordSeries) -> &[f64],
+{
+    let resampled = series.resample_seconds()?;
+    let mut times = vec![0; boundaries.len() + 1];
+    for value in column(&resampled).iter().filter(|v| !v.is_nan()) {
+        times[zone(*value, boundaries)] += 1;
+    }
+    Ok(times)
+}
+
+// Zone boundaries ordered by the message index of the zone messages.
+fn boundaries<T>(mut zones: Vec<(Option<u16>, Option<T>)>) -> Vec<f64>
+where
+    f64: From<T>,
+{
+    zones.sort_by_key(|(message_index, _)| *message_index);
+    zones
+        .into_iter()
+        .filter_map(|(_, high)| high.map(f64::from))
+        .collect()
+}
+
+impl FitFile {
+    /// Upper heart rate boundaries of the zones in the `hr_zone` messages.
+    pub fn hr_zone_boundaries(&self) -> Vec<f64> {
+        boundaries(
+            self.typed_messages(HR_ZONE_MESSAGE.number, HrZone::from_message)
+                .map(|zone| (zone.message_index, zone.high_bpm))
+                .collect(),
+        )
+    }
+
+    /// Upper power boundaries of the zones in the `power_zone` messages.
+    pub fn power_zone_boundaries(&self) -> Vec<f64> {
+        boundaries(
+            self.typed_messages(POWER_ZONE_MESSAGE.number, PowerZone::from_message)
+                .map(|zone| (zone.message_index, zone.high_value))
+                .collect(),
+        )
+    }
+
+    /// Seconds in each heart rate zone of the `hr_zone` messages, see
+    /// [`time_in_zones`].
+    pub fn time_in_hr_zones(&self) -> Result<Vec<u32>, ResampleError> {
+        time_in_zones(
+            &self.record_series(),
+            |s| &s.heart_rate,
+            &self.hr_zone_boundaries(),
+        )
+    }
+
+    /// Seconds in each power zone of the `power_zone` messages, see
+    /// [`time_in_zones`].
+    pub fn time_in_power_zones(&self) -> Result<Vec<u32>, ResampleError> {
+        time_in_zones(
+            &self.record_series(),
+            |s| &s.power,
+            &self.power_zone_boundaries(),
+        )
+    }
+}
+
+#[cfg(test)]
+use crate::fit_file::{test_fit_file, test_message};
+#[cfg(test)]
+use crate::fit_messages::Value;
+#[cfg(test)]
+use crate::series::test_series;
+
+#[test]
+fn test_time_in_zones() {
+    let series = RecordSeries {
+        heart_rate: vec![100.0, 140.0, f64::NAN, 180.0],
+        ..test_series(&[0, 2, 3, 6])
+    };
+    assert_eq!(
+        time_in_zones(&series, |s| &s.heart_rate, &[120.0, 160.0]),
+        Ok(vec![2, 1, 1])
+    );
+}
+
+#[test]
+fn test_hr_zone_boundaries() {
+    let zone = |index: u16, high: u8| {
+        test_message(
+            8,
+            vec![
+                (254, "message_index", Value::U16(index)),
+                (1, "high_bpm", Value::U8(high)),
+            ],
+        )
+    };
+    let fit_file = test_fit_file(vec![zone(1, 150), zone(0, 120)]);
+    assert_eq!(fit_file.hr_zone_boundaries(), vec![120.0, 150.0]);
+    assert!(fit_file.power_zone_boundaries().is_empty());
+}